@@ -0,0 +1,166 @@
+use crate::wildmatch::{wildmatch, MatchFlags};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttrValue {
+    Set,
+    Unset,
+    Value(String),
+}
+
+// One line of a .gitattributes file, e.g. "*.png binary" or "docs/*.txt diff=text".
+#[derive(Debug)]
+struct AttrRule {
+    // The directory holding the .gitattributes file, relative to the workspace root ("" for the root).
+    base: String,
+    pattern: String,
+    // Patterns without a slash are matched against the file's name alone, in any directory below base.
+    basename_only: bool,
+    // None means the attribute was explicitly made unspecified again with "!name".
+    attrs: Vec<(String, Option<AttrValue>)>,
+}
+
+impl AttrRule {
+    fn parse(line: &str, base: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let mut fields = line.split_whitespace();
+        let mut pattern = fields.next()?.to_string();
+        let basename_only = !pattern.trim_end_matches('/').contains('/');
+        if let Some(anchored) = pattern.strip_prefix('/') {
+            pattern = anchored.to_string();
+        }
+
+        let mut attrs = Vec::new();
+        for field in fields {
+            if let Some(name) = field.strip_prefix('-') {
+                attrs.push((name.to_string(), Some(AttrValue::Unset)));
+            } else if let Some(name) = field.strip_prefix('!') {
+                attrs.push((name.to_string(), None));
+            } else if let Some((name, value)) = field.split_once('=') {
+                attrs.push((name.to_string(), Some(AttrValue::Value(value.to_string()))));
+            } else if field == "binary" {
+                // "binary" is a built-in macro for "-diff -merge -text".
+                attrs.push(("binary".to_string(), Some(AttrValue::Set)));
+                for name in ["diff", "merge", "text"] {
+                    attrs.push((name.to_string(), Some(AttrValue::Unset)));
+                }
+            } else {
+                attrs.push((field.to_string(), Some(AttrValue::Set)));
+            }
+        }
+        Some(AttrRule {
+            base: base.to_string(),
+            pattern,
+            basename_only,
+            attrs,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        // Patterns ending in a slash only apply to directories, and attributes are only looked up for files.
+        if self.pattern.ends_with('/') {
+            return false;
+        }
+        let Some(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let flags = MatchFlags {
+            pathname: true,
+            casefold: false,
+        };
+        if self.basename_only {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(&self.pattern, name, flags)
+        } else {
+            wildmatch(&self.pattern, relative, flags)
+        }
+    }
+}
+
+// Looks up gitattributes for workspace paths, reading the .gitattributes files in the path's directories and .git/info/attributes.
+pub struct Attributes {
+    root_path: PathBuf,
+    info_path: PathBuf,
+}
+
+impl Attributes {
+    pub fn new(root_path: PathBuf, git_path: &Path) -> Self {
+        Attributes {
+            root_path,
+            info_path: git_path.join("info").join("attributes"),
+        }
+    }
+
+    fn read_rules(file: &Path, base: &str) -> Vec<AttrRule> {
+        fs::read_to_string(file)
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(|line| AttrRule::parse(line, base))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Returns the value of the named attribute for the path, or None if it's unspecified.
+    pub fn get(&self, path: &Path, name: &str) -> Option<AttrValue> {
+        let path = path.to_string_lossy();
+
+        // Collect the files from lowest to highest precedence: the root .gitattributes, then deeper directories, then info/attributes.
+        let mut rules = Self::read_rules(&self.root_path.join(".gitattributes"), "");
+        let mut base = String::new();
+        let dirs: Vec<&str> = path.split('/').collect();
+        for dir in &dirs[..dirs.len() - 1] {
+            base.push_str(dir);
+            base.push('/');
+            rules.extend(Self::read_rules(
+                &self.root_path.join(&base).join(".gitattributes"),
+                &base,
+            ));
+        }
+        rules.extend(Self::read_rules(&self.info_path, ""));
+
+        // The last matching line that mentions the attribute wins.
+        rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.matches(&path))
+            .find_map(|rule| {
+                rule.attrs
+                    .iter()
+                    .rev()
+                    .find(|(attr, _)| attr == name)
+                    .map(|(_, value)| value.clone())
+            })
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_binary_macro() {
+        let rule = AttrRule::parse("*.png binary", "").unwrap();
+        assert!(rule.matches("images/logo.png"));
+        assert!(rule
+            .attrs
+            .contains(&("diff".to_string(), Some(AttrValue::Unset))));
+    }
+
+    #[test]
+    fn test_anchored_and_nested_patterns() {
+        let rule = AttrRule::parse("/docs/*.txt diff", "").unwrap();
+        assert!(rule.matches("docs/readme.txt"));
+        assert!(!rule.matches("src/docs/readme.txt"));
+
+        let nested = AttrRule::parse("*.dat -diff", "data/").unwrap();
+        assert!(nested.matches("data/a/b.dat"));
+        assert!(!nested.matches("other/b.dat"));
+    }
+}
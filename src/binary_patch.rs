@@ -0,0 +1,248 @@
+use flate2::{write::ZlibEncoder, Compression};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+// The alphabet git uses for base85 encoding binary patches.
+const BASE85_ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+// Each base85 line of a binary patch encodes at most this many bytes.
+const BYTES_PER_LINE: usize = 52;
+// The size of the source blocks we index when looking for copies in a delta (the same window size as git's diff-delta).
+const DELTA_BLOCK: usize = 16;
+// A single copy instruction can copy at most this many bytes.
+const MAX_COPY_SIZE: usize = 0x10000;
+// A single insert instruction can insert at most this many bytes.
+const MAX_INSERT_SIZE: usize = 0x7f;
+
+// Writes the "GIT binary patch" section for a change from a to b. It contains the forward change followed by the reverse change,
+// so that the patch can be applied in either direction.
+pub fn write_binary_patch(out: &mut impl Write, a: &[u8], b: &[u8]) -> io::Result<()> {
+    writeln!(out, "GIT binary patch")?;
+    write_binary_hunk(out, a, b)?;
+    write_binary_hunk(out, b, a)
+}
+
+// Writes a single hunk, picking whichever of a delta against the source or the literal target compresses smaller.
+fn write_binary_hunk(out: &mut impl Write, source: &[u8], target: &[u8]) -> io::Result<()> {
+    let literal = deflate(target);
+    let delta = (!source.is_empty() && !target.is_empty()).then(|| delta(source, target));
+
+    let data = match delta {
+        Some(delta) if deflate(&delta).len() < literal.len() => {
+            writeln!(out, "delta {}", delta.len())?;
+            deflate(&delta)
+        }
+        _ => {
+            writeln!(out, "literal {}", target.len())?;
+            literal
+        }
+    };
+
+    for chunk in data.chunks(BYTES_PER_LINE) {
+        // The first character of each line encodes its length: 'A'-'Z' for 1-26 bytes and 'a'-'z' for 27-52 bytes.
+        let length = if chunk.len() <= 26 {
+            b'A' + chunk.len() as u8 - 1
+        } else {
+            b'a' + chunk.len() as u8 - 27
+        };
+        out.write_all(&[length])?;
+        out.write_all(&encode_base85(chunk))?;
+        writeln!(out)?;
+    }
+    writeln!(out)
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .expect("Could not deflate binary patch data");
+    encoder
+        .finish()
+        .expect("Could not flush deflated binary patch data")
+}
+
+// Encodes every 4 bytes (zero padded at the end) as 5 base85 characters, most significant first.
+fn encode_base85(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len().div_ceil(4) * 5);
+    for chunk in data.chunks(4) {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(word);
+        let mut digits = [0; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = BASE85_ALPHABET[(value % 85) as usize];
+            value /= 85;
+        }
+        encoded.extend_from_slice(&digits);
+    }
+    encoded
+}
+
+// Sizes in a delta header are little-endian base-128 numbers, where the high bit says whether more bytes follow.
+fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+// Builds a git delta that turns source into target, using copy instructions for runs found in the source and inserts for the rest.
+fn delta(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_varint(&mut delta, source.len());
+    write_varint(&mut delta, target.len());
+
+    // Remember where each aligned block of the source starts, so we can find candidate copies quickly.
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for (i, block) in source.chunks_exact(DELTA_BLOCK).enumerate() {
+        blocks.entry(block).or_insert(i * DELTA_BLOCK);
+    }
+
+    let mut insert: Vec<u8> = Vec::new();
+    let mut pos = 0;
+    while pos < target.len() {
+        let found = target
+            .get(pos..pos + DELTA_BLOCK)
+            .and_then(|block| blocks.get(block));
+        let Some(&offset) = found else {
+            insert.push(target[pos]);
+            pos += 1;
+            continue;
+        };
+
+        // Extend the match as far forward as possible.
+        let mut len = DELTA_BLOCK;
+        while offset + len < source.len()
+            && pos + len < target.len()
+            && source[offset + len] == target[pos + len]
+        {
+            len += 1;
+        }
+        flush_insert(&mut delta, &mut insert);
+        let mut copied = 0;
+        while copied < len {
+            let size = (len - copied).min(MAX_COPY_SIZE);
+            write_copy(&mut delta, offset + copied, size);
+            copied += size;
+        }
+        pos += len;
+    }
+    flush_insert(&mut delta, &mut insert);
+    delta
+}
+
+fn flush_insert(delta: &mut Vec<u8>, insert: &mut Vec<u8>) {
+    for chunk in insert.chunks(MAX_INSERT_SIZE) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+    insert.clear();
+}
+
+// A copy instruction is a command byte with the high bit set, whose low bits say which offset and size bytes follow (zero bytes are left out).
+fn write_copy(delta: &mut Vec<u8>, offset: usize, size: usize) {
+    let mut command = 0x80;
+    let mut args = Vec::new();
+    for (i, byte) in (offset as u32).to_le_bytes().iter().enumerate() {
+        if *byte != 0 {
+            command |= 1 << i;
+            args.push(*byte);
+        }
+    }
+    // A size of 0x10000 is encoded as zero (i.e. no size bytes at all).
+    let size = if size == MAX_COPY_SIZE { 0 } else { size };
+    for (i, byte) in (size as u32).to_le_bytes()[..3].iter().enumerate() {
+        if *byte != 0 {
+            command |= 1 << (4 + i);
+            args.push(*byte);
+        }
+    }
+    delta.push(command);
+    delta.extend_from_slice(&args);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Applies a delta the same way git does, so we can check that what we produce round-trips.
+    fn apply_delta(source: &[u8], delta: &[u8]) -> Vec<u8> {
+        let mut pos = 0;
+        let mut read_varint = || {
+            let (mut n, mut shift) = (0, 0);
+            loop {
+                let byte = delta[pos];
+                pos += 1;
+                n |= ((byte & 0x7f) as usize) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    return n;
+                }
+            }
+        };
+        assert_eq!(read_varint(), source.len());
+        let target_size = read_varint();
+
+        let mut target = Vec::new();
+        while pos < delta.len() {
+            let command = delta[pos];
+            pos += 1;
+            if command & 0x80 == 0 {
+                target.extend_from_slice(&delta[pos..pos + command as usize]);
+                pos += command as usize;
+                continue;
+            }
+            let (mut offset, mut size) = (0, 0);
+            for i in 0..7 {
+                if command & (1 << i) != 0 {
+                    let value = (delta[pos] as usize) << (8 * (i % 4));
+                    pos += 1;
+                    if i < 4 {
+                        offset |= value;
+                    } else {
+                        size |= value;
+                    }
+                }
+            }
+            if size == 0 {
+                size = MAX_COPY_SIZE;
+            }
+            target.extend_from_slice(&source[offset..offset + size]);
+        }
+        assert_eq!(target.len(), target_size);
+        target
+    }
+
+    #[test]
+    fn test_encode_base85() {
+        assert_eq!(encode_base85(&[0, 0, 0, 0]), b"00000");
+        assert_eq!(encode_base85(&[0xff, 0xff, 0xff, 0xff]), b"|NsC0");
+        // Partial words are padded with zeros.
+        assert_eq!(encode_base85(&[0, 0, 0, 1, 0]), b"0000100000");
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let source: Vec<u8> = (0..5000_u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut target = source.clone();
+        target.splice(100..110, b"changed!!".iter().copied());
+        target.extend_from_slice(&source[..300]);
+        let delta = delta(&source, &target);
+        assert!(delta.len() < target.len() / 10);
+        assert_eq!(apply_delta(&source, &delta), target);
+    }
+
+    #[test]
+    fn test_binary_patch_literal_for_new_file() {
+        let mut out = Vec::new();
+        write_binary_patch(&mut out, b"", b"\0\x01bin").unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "GIT binary patch");
+        assert_eq!(lines[1], "literal 5");
+        assert_eq!(lines[3], "");
+        assert_eq!(lines[4], "literal 0");
+    }
+}
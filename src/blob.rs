@@ -1,9 +1,8 @@
 use std::fmt::Debug;
 use std::fmt::Formatter;
-use std::{path::Path, path::PathBuf};
+use std::path::PathBuf;
 
 use crate::object::Object;
-use is_executable::IsExecutable;
 use sha1_smol::{Digest, Sha1};
 
 // Technically, we only really care to sort by the path (there should never be two blobs with the same path, so the further sorting by oid and content won't matter).
@@ -30,14 +29,6 @@ impl Blob {
         let oid = Sha1::from(&content).digest();
         Blob { oid, content, path }
     }
-
-    pub fn get_path(&self) -> &Path {
-        &self.path
-    }
-
-    pub fn is_executable(&self) -> bool {
-        self.path.is_executable()
-    }
 }
 
 impl Object for Blob {
@@ -0,0 +1,579 @@
+use crate::attributes::{AttrValue, Attributes};
+use crate::database::{Database, DatabaseEntry, TreeDiff};
+use crate::diff::{self, EditKind, HUNK_CONTEXT};
+use sha1_smol::Digest;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+// A path that a merge changed compared to every one of its parents, with its entry in the merge and in each parent (None where
+// it's missing).
+pub struct CombinedPath {
+    pub path: PathBuf,
+    pub result: Option<DatabaseEntry>,
+    pub parents: Vec<Option<DatabaseEntry>>,
+}
+
+// Finds the paths where a merge's tree differs from the trees of all of its parents. Paths that match any one parent were
+// simply taken from it, so they aren't interesting in a combined diff.
+pub fn changed_paths(
+    database: &Database,
+    tree: &Digest,
+    parent_trees: &[Digest],
+) -> Vec<CombinedPath> {
+    let diffs: Vec<TreeDiff> = parent_trees
+        .iter()
+        .map(|parent| database.tree_diff(Some(parent), Some(tree)))
+        .collect();
+    let Some((first, rest)) = diffs.split_first() else {
+        return Vec::new();
+    };
+    let mut paths: Vec<CombinedPath> = first
+        .iter()
+        .filter(|(path, _)| rest.iter().all(|diff| diff.contains_key(*path)))
+        .map(|(path, (_, result))| CombinedPath {
+            path: path.clone(),
+            result: *result,
+            parents: diffs.iter().map(|diff| diff[path].0).collect(),
+        })
+        .collect();
+    paths.sort_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+    paths
+}
+
+// A line that one or more parents have but the merge result doesn't. `parents` has a bit set for each parent that lost it.
+#[derive(Debug, Clone)]
+struct LostLine {
+    text: Vec<u8>,
+    parents: u64,
+}
+
+// A line of the merge result, along with what the combined diff knows about it. There is one more of these than there are
+// lines, to hold what was lost after the last line.
+#[derive(Debug, Default)]
+struct Sline {
+    text: Vec<u8>,
+    // The lines lost right before this one, from all parents compared so far.
+    lost: Vec<LostLine>,
+    // The lines lost right before this one from the parent currently being compared, until they're merged into `lost`.
+    new_lost: Vec<LostLine>,
+    // Bits 0 to N-1 are set for each parent this line was added to. Bit N marks lines to show, and bit N+1 context lines before a
+    // hunk, whose lost lines aren't shown.
+    flag: u64,
+    // For each parent, the line number it's at when a hunk starts at this line.
+    parent_lines: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Match,
+    Base,
+    New,
+}
+
+// Merges lines lost from another parent into the lines lost so far, keeping the longest common run of them together so that a
+// line lost from several parents is only shown once.
+fn coalesce_lines(mut base: Vec<LostLine>, new: Vec<LostLine>, parent: usize) -> Vec<LostLine> {
+    if base.is_empty() {
+        return new;
+    }
+    let (base_len, new_len) = (base.len(), new.len());
+    let mut lcs = vec![vec![0; new_len + 1]; base_len + 1];
+    let mut directions = vec![vec![Direction::Base; new_len + 1]; base_len + 1];
+    for direction in directions[0].iter_mut().skip(1) {
+        *direction = Direction::New;
+    }
+    for i in 1..=base_len {
+        for j in 1..=new_len {
+            if base[i - 1].text == new[j - 1].text {
+                lcs[i][j] = lcs[i - 1][j - 1] + 1;
+                directions[i][j] = Direction::Match;
+            } else if lcs[i][j - 1] >= lcs[i - 1][j] {
+                lcs[i][j] = lcs[i][j - 1];
+                directions[i][j] = Direction::New;
+            } else {
+                lcs[i][j] = lcs[i - 1][j];
+                directions[i][j] = Direction::Base;
+            }
+        }
+    }
+
+    // Walk back from the end, marking matched lines as lost from this parent too and inserting the others where they belong.
+    let (mut i, mut j) = (base_len, new_len);
+    while i != 0 || j != 0 {
+        match directions[i][j] {
+            Direction::Match => {
+                base[i - 1].parents |= 1 << parent;
+                i -= 1;
+                j -= 1;
+            }
+            Direction::New => {
+                base.insert(i, new[j - 1].clone());
+                j -= 1;
+            }
+            Direction::Base => i -= 1,
+        }
+    }
+    base
+}
+
+// Records how the result differs from one parent: which lines were added compared to it, and which of its lines were lost.
+fn compare_with_parent(slines: &mut [Sline], result: &[u8], parent: &[u8], n: usize) {
+    let mask = 1 << n;
+    let cnt = slines.len() - 2;
+    let (result_lines, parent_lines) = (diff::lines(result), diff::lines(parent));
+    let edits = diff::diff(&parent_lines, &result_lines);
+
+    // Each run of changes is a hunk. Its lost lines are shown before its first added line, or before the line after it if it
+    // only removes lines.
+    let mut line = 0;
+    let mut i = 0;
+    while i < edits.len() {
+        if edits[i].kind == EditKind::Eql {
+            line += 1;
+            i += 1;
+            continue;
+        }
+        let mut lost = Vec::new();
+        let mut first_added = None;
+        while i < edits.len() && edits[i].kind != EditKind::Eql {
+            let text = edits[i].text();
+            if edits[i].kind == EditKind::Del {
+                lost.push(LostLine {
+                    text: text.strip_suffix(b"\n").unwrap_or(text).to_vec(),
+                    parents: mask,
+                });
+            } else {
+                first_added.get_or_insert(line);
+                slines[line].flag |= mask;
+                line += 1;
+            }
+            i += 1;
+        }
+        slines[first_added.unwrap_or(line)].new_lost.extend(lost);
+    }
+
+    let mut parent_line = 1;
+    for (lno, sline) in slines[..=cnt].iter_mut().enumerate() {
+        sline.parent_lines[n] = parent_line;
+        if !sline.new_lost.is_empty() {
+            let new_lost = std::mem::take(&mut sline.new_lost);
+            sline.lost = coalesce_lines(std::mem::take(&mut sline.lost), new_lost, n);
+        }
+        // Lines lost from this parent and lines it shares with the result both move through the parent.
+        parent_line += sline.lost.iter().filter(|l| l.parents & mask != 0).count();
+        if lno < cnt && sline.flag & mask == 0 {
+            parent_line += 1;
+        }
+    }
+    slines[cnt + 1].parent_lines[n] = parent_line;
+}
+
+// Parent i has the same contents as parent j, which was already compared, so copy what we found for it.
+fn reuse_comparison(slines: &mut [Sline], i: usize, j: usize) {
+    let (i_mask, j_mask) = (1 << i, 1 << j);
+    for sline in slines.iter_mut() {
+        sline.parent_lines[i] = sline.parent_lines[j];
+        for lost in sline.lost.iter_mut() {
+            if lost.parents & j_mask != 0 {
+                lost.parents |= i_mask;
+            }
+        }
+        if sline.flag & j_mask != 0 {
+            sline.flag |= i_mask;
+        }
+    }
+}
+
+// A line with changes compared to some parent, or with lines lost before it.
+fn is_interesting(sline: &Sline, all_mask: u64) -> bool {
+    sline.flag & all_mask != 0 || !sline.lost.is_empty()
+}
+
+// `i` is the first line after a hunk. If the hunk's last line only matters for the lines lost before it, that line already works
+// as context, so the hunk ends one line earlier.
+fn adjust_hunk_tail(slines: &[Sline], all_mask: u64, hunk_begin: usize, i: usize) -> usize {
+    if hunk_begin < i && slines[i - 1].flag & all_mask == 0 {
+        i - 1
+    } else {
+        i
+    }
+}
+
+// Finds the next line from `i` on that is (or with `unmarked`, isn't) marked to be shown.
+fn find_next(slines: &[Sline], mark: u64, mut i: usize, cnt: usize, unmarked: bool) -> usize {
+    while i <= cnt {
+        if (slines[i].flag & mark == 0) == unmarked {
+            return i;
+        }
+        i += 1;
+    }
+    i
+}
+
+// Marks context lines around the lines to show, joining groups of them that are close together. Returns whether there's
+// anything to show.
+fn give_context(slines: &mut [Sline], num_parents: usize) -> bool {
+    let cnt = slines.len() - 2;
+    let all_mask = (1 << num_parents) - 1;
+    let mark = 1 << num_parents;
+    let no_pre_delete = 2 << num_parents;
+
+    let mut i = find_next(slines, mark, 0, cnt, false);
+    if cnt < i {
+        return false;
+    }
+    while i <= cnt {
+        // Paint a few lines before the first interesting line.
+        for sline in &mut slines[i.saturating_sub(HUNK_CONTEXT)..i] {
+            if sline.flag & mark == 0 {
+                sline.flag |= no_pre_delete;
+            }
+            sline.flag |= mark;
+        }
+
+        loop {
+            let mut j = find_next(slines, mark, i, cnt, true);
+            if cnt < j {
+                // Everything to the end is shown.
+                return true;
+            }
+            let k = find_next(slines, mark, j, cnt, false);
+            j = adjust_hunk_tail(slines, all_mask, i, j);
+            if k < j + HUNK_CONTEXT {
+                // The gap to the next interesting line is small, so show it all.
+                for sline in &mut slines[j..k] {
+                    sline.flag |= mark;
+                }
+                i = k;
+                continue;
+            }
+            // Paint the trailing context.
+            i = k;
+            let end = (j + HUNK_CONTEXT).min(cnt + 1);
+            for sline in &mut slines[j..end] {
+                sline.flag |= mark;
+            }
+            break;
+        }
+    }
+    true
+}
+
+// Marks the lines to show. With `dense` (--cc), hunks that only differ from one parent, or where all the parents but one agree,
+// are left out since they were taken as they are from one side.
+fn make_hunks(slines: &mut [Sline], num_parents: usize, dense: bool) -> bool {
+    let cnt = slines.len() - 2;
+    let all_mask = (1 << num_parents) - 1;
+    let mark = 1 << num_parents;
+
+    for sline in slines[..=cnt].iter_mut() {
+        if is_interesting(sline, all_mask) {
+            sline.flag |= mark;
+        } else {
+            sline.flag &= !mark;
+        }
+    }
+    if !dense {
+        return give_context(slines, num_parents);
+    }
+
+    let mut i = 0;
+    while i <= cnt {
+        while i <= cnt && slines[i].flag & mark == 0 {
+            i += 1;
+        }
+        if cnt < i {
+            break;
+        }
+        let hunk_begin = i;
+        let mut j = i + 1;
+        while j <= cnt {
+            if slines[j].flag & mark == 0 {
+                // Look beyond the end for an interesting line within the context span, which would continue the hunk.
+                let tail = adjust_hunk_tail(slines, all_mask, hunk_begin, j);
+                let mut lookahead = (tail + HUNK_CONTEXT).min(cnt + 1);
+                let mut continues = false;
+                while lookahead > j {
+                    lookahead -= 1;
+                    if slines[lookahead].flag & mark != 0 {
+                        continues = true;
+                        break;
+                    }
+                }
+                if !continues {
+                    break;
+                }
+                j = lookahead;
+            }
+            j += 1;
+        }
+        let hunk_end = j;
+
+        // The hunk is only really interesting if there are more than two versions of it, which shows up as lines that differ
+        // from different sets of parents. With just two versions, it's still interesting if the result matches neither.
+        let mut same_diff = 0;
+        let mut has_interesting = false;
+        'lines: for sline in &slines[i..hunk_end] {
+            let this_diff = sline.flag & all_mask;
+            let lost_diffs = sline.lost.iter().map(|lost| lost.parents);
+            for diff in std::iter::once(this_diff)
+                .filter(|d| *d != 0)
+                .chain(lost_diffs)
+            {
+                if same_diff == 0 {
+                    same_diff = diff;
+                } else if same_diff != diff {
+                    has_interesting = true;
+                    break 'lines;
+                }
+            }
+        }
+        if !has_interesting && same_diff != all_mask {
+            for sline in &mut slines[hunk_begin..hunk_end] {
+                sline.flag &= !mark;
+            }
+        }
+        i = hunk_end;
+    }
+
+    give_context(slines, num_parents)
+}
+
+// Lines starting like a function or other definition are shown after the hunk header, like git's default funcname pattern.
+fn is_hunk_comment_line(text: &[u8]) -> bool {
+    text.first()
+        .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_' || *c == b'$')
+}
+
+fn dump_slines(out: &mut impl Write, slines: &[Sline], num_parents: usize) -> io::Result<()> {
+    let cnt = slines.len() - 2;
+    let mark = 1 << num_parents;
+    let no_pre_delete = 2 << num_parents;
+    let markers = "@".repeat(num_parents + 1);
+
+    let mut lno = 0;
+    loop {
+        let mut hunk_comment = None;
+        while lno <= cnt && slines[lno].flag & mark == 0 {
+            if lno < cnt && is_hunk_comment_line(&slines[lno].text) {
+                hunk_comment = Some(&slines[lno].text);
+            }
+            lno += 1;
+        }
+        if cnt < lno {
+            return Ok(());
+        }
+        let mut hunk_end = lno + 1;
+        while hunk_end <= cnt && slines[hunk_end].flag & mark != 0 {
+            hunk_end += 1;
+        }
+        let mut rlines = hunk_end - lno;
+        if cnt < hunk_end {
+            // The hunk ends with lines lost after the last line.
+            rlines -= 1;
+        }
+
+        write!(out, "{markers}")?;
+        for n in 0..num_parents {
+            let start = slines[lno].parent_lines[n];
+            let end = slines[hunk_end].parent_lines[n];
+            write!(out, " -{start},{}", end - start)?;
+        }
+        write!(out, " +{},{rlines} {markers}", lno + 1)?;
+        if let Some(comment) = hunk_comment {
+            // Like git, only the first 40 bytes are used, and the last non-space character is left out.
+            let comment_end = comment
+                .iter()
+                .take(40)
+                .rposition(|c| !c.is_ascii_whitespace())
+                .unwrap_or(0);
+            if comment_end > 0 {
+                write!(out, " ")?;
+                out.write_all(&comment[..comment_end])?;
+            }
+        }
+        writeln!(out)?;
+
+        while lno < hunk_end {
+            let sline = &slines[lno];
+            lno += 1;
+            if sline.flag & no_pre_delete == 0 {
+                for lost in &sline.lost {
+                    for n in 0..num_parents {
+                        let marker = if lost.parents & (1 << n) != 0 {
+                            '-'
+                        } else {
+                            ' '
+                        };
+                        write!(out, "{marker}")?;
+                    }
+                    out.write_all(&lost.text)?;
+                    writeln!(out)?;
+                }
+            }
+            if cnt < lno {
+                break;
+            }
+            for n in 0..num_parents {
+                let marker = if sline.flag & (1 << n) != 0 { '+' } else { ' ' };
+                write!(out, "{marker}")?;
+            }
+            out.write_all(&sline.text)?;
+            writeln!(out)?;
+        }
+    }
+}
+
+// Writes the combined diff for one path of a merge: a single patch comparing the result with all parents at once, with one
+// column of +/- markers per parent. With `dense` (--cc) only the hunks that weren't taken from one side as they are are shown.
+pub fn write_combined_diff(
+    out: &mut impl Write,
+    database: &Database,
+    attributes: &Attributes,
+    path: &CombinedPath,
+    dense: bool,
+) -> io::Result<()> {
+    let num_parents = path.parents.len();
+    let load = |entry: &Option<DatabaseEntry>| {
+        entry
+            .map(|entry| database.load_blob(&entry.oid))
+            .unwrap_or_default()
+    };
+    let result = load(&path.result);
+    let parents: Vec<Vec<u8>> = path.parents.iter().map(load).collect();
+    let mode = |entry: &Option<DatabaseEntry>| entry.map_or(0, |entry| entry.mode);
+    let mode_differs = path
+        .parents
+        .iter()
+        .any(|parent| mode(parent) != mode(&path.result));
+
+    let is_binary = match attributes.get(&path.path, "diff") {
+        Some(AttrValue::Unset) => true,
+        Some(_) => false,
+        None => diff::is_binary(&result) || parents.iter().any(|parent| diff::is_binary(parent)),
+    };
+    if is_binary {
+        write_header(out, database, path, dense, mode_differs, false)?;
+        return writeln!(out, "Binary files differ");
+    }
+
+    let cnt = diff::lines(&result).len();
+    let mut slines: Vec<Sline> = diff::lines(&result)
+        .into_iter()
+        .map(|line| Sline {
+            text: line.strip_suffix(b"\n").unwrap_or(line).to_vec(),
+            ..Default::default()
+        })
+        .collect();
+    slines.resize_with(cnt + 2, Sline::default);
+    for sline in slines.iter_mut() {
+        sline.parent_lines = vec![0; num_parents];
+    }
+
+    for (i, parent) in parents.iter().enumerate() {
+        let same_as =
+            (0..i).find(|j| path.parents[*j].map(|e| e.oid) == path.parents[i].map(|e| e.oid));
+        match same_as {
+            Some(j) => reuse_comparison(&mut slines, i, j),
+            None => compare_with_parent(&mut slines, &result, parent, i),
+        }
+    }
+
+    let show_hunks = make_hunks(&mut slines, num_parents, dense);
+    if show_hunks || mode_differs {
+        write_header(out, database, path, dense, mode_differs, true)?;
+        dump_slines(out, &slines, num_parents)?;
+    }
+    Ok(())
+}
+
+fn write_header(
+    out: &mut impl Write,
+    database: &Database,
+    path: &CombinedPath,
+    dense: bool,
+    mode_differs: bool,
+    show_file_header: bool,
+) -> io::Result<()> {
+    let name = path.path.display();
+    let kind = if dense { "cc" } else { "combined" };
+    writeln!(out, "diff --{kind} {name}")?;
+    let abbreviate = |entry: &Option<DatabaseEntry>| {
+        entry.map_or("0".repeat(7), |entry| database.abbreviate(&entry.oid))
+    };
+    let parents: Vec<String> = path.parents.iter().map(abbreviate).collect();
+    writeln!(
+        out,
+        "index {}..{}",
+        parents.join(","),
+        abbreviate(&path.result)
+    )?;
+
+    let deleted = path.result.is_none();
+    // It was added if none of the parents had it.
+    let added = !deleted && path.parents.iter().all(Option::is_none);
+    if mode_differs {
+        if let Some(result) = path.result.filter(|_| added) {
+            writeln!(out, "new file mode {:06o}", result.mode)?;
+        } else {
+            if deleted {
+                write!(out, "deleted file ")?;
+            }
+            let modes: Vec<String> = path
+                .parents
+                .iter()
+                .map(|parent| format!("{:06o}", parent.map_or(0, |entry| entry.mode)))
+                .collect();
+            write!(out, "mode {}", modes.join(","))?;
+            if let Some(result) = path.result {
+                write!(out, "..{:06o}", result.mode)?;
+            }
+            writeln!(out)?;
+        }
+    }
+
+    if !show_file_header {
+        return Ok(());
+    }
+    if added {
+        writeln!(out, "--- /dev/null")?;
+    } else {
+        writeln!(out, "--- a/{name}")?;
+    }
+    if deleted {
+        writeln!(out, "+++ /dev/null")
+    } else {
+        writeln!(out, "+++ b/{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lost(text: &str, parents: u64) -> LostLine {
+        LostLine {
+            text: text.as_bytes().to_vec(),
+            parents,
+        }
+    }
+
+    #[test]
+    fn test_coalesce_lines() {
+        let base = vec![lost("a", 1), lost("b", 1), lost("c", 1)];
+        let new = vec![lost("b", 2), lost("x", 2), lost("c", 2)];
+        let coalesced: Vec<(String, u64)> = coalesce_lines(base, new, 1)
+            .into_iter()
+            .map(|line| (String::from_utf8(line.text).unwrap(), line.parents))
+            .collect();
+        assert_eq!(
+            coalesced,
+            [
+                ("a".to_string(), 1),
+                ("b".to_string(), 3),
+                ("x".to_string(), 2),
+                ("c".to_string(), 3)
+            ]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use crate::blob::Blob;
+use crate::fatal::fatal;
+use crate::index::IndexMetadata;
+use crate::object::Object;
+use crate::repository::Repository;
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    // With no paths, everything is added.
+    let pathspec = repo.pathspec(args);
+    let ws = &repo.workspace;
+
+    // The pathspec is matched against the files in the workspace (leaving out ignored ones) and the files in the
+    // index. Tracked files that are gone from the workspace are removed from the index.
+    let workspace_files: BTreeSet<PathBuf> = ws
+        .list_files(ws.root())
+        .expect("Could not list workspace files")
+        .into_iter()
+        .collect();
+    let tracked_files: BTreeSet<PathBuf> = repo
+        .index
+        .entries()
+        .map(|entry| entry.get_path().to_path_buf())
+        .collect();
+    let mut added = BTreeSet::new();
+    let mut removed = BTreeSet::new();
+    for path in workspace_files.iter().chain(&tracked_files) {
+        if !pathspec.matches(path) {
+            continue;
+        }
+        if ws.stat_file(path).is_ok_and(|stat| !stat.is_dir()) {
+            added.insert(path.clone());
+        } else {
+            removed.insert(path.clone());
+        }
+    }
+
+    // Like git, a pattern that matches nothing is fatal, unless it names a file that's there (but ignored).
+    let seen = pathspec.seen(
+        workspace_files
+            .iter()
+            .chain(&tracked_files)
+            .map(PathBuf::as_path),
+    );
+    for (item, seen) in &seen {
+        if seen.is_none()
+            && !item.pattern.is_empty()
+            && (item.glob || item.icase || !ws.root().join(&item.pattern).exists())
+        {
+            fatal(&format!(
+                "pathspec '{}' did not match any files",
+                item.original
+            ));
+        }
+    }
+
+    // Ignored paths that are asked for by name aren't added. Like git, they're reported (by the ignored directory, if
+    // it's one of the path's parents) once everything else has been added.
+    let mut ignored_paths = BTreeSet::new();
+    for (item, _) in &seen {
+        let mut ancestors: Vec<&Path> = Path::new(item.literal_prefix()).ancestors().collect();
+        ancestors.pop();
+        for path in ancestors.into_iter().rev() {
+            if !ws.root().join(path).exists() || repo.index.is_tracked_file(path) {
+                break;
+            }
+            if ws.is_ignored(path) {
+                ignored_paths.insert(path.to_path_buf());
+                break;
+            }
+        }
+    }
+
+    // TODO don't try to add/write files that already exist in the index unless they have changes.
+    for path in added {
+        let data = ws.read_file(&path).expect("Could not read file in add");
+        let fs_metadata = ws.stat_file(&path).expect("Could not get file metadata");
+
+        let blob = Blob::new(data, path.clone());
+        repo.database.store(&blob);
+        repo.index
+            .add(path, *blob.get_oid(), IndexMetadata::from(fs_metadata));
+    }
+    for path in removed {
+        repo.index.remove(&path);
+    }
+
+    repo.index.write_updates();
+
+    if !ignored_paths.is_empty() {
+        eprintln!("The following paths are ignored by one of your .gitignore files:");
+        for path in ignored_paths {
+            eprintln!("{}", path.display());
+        }
+        eprintln!("hint: Use -f if you really want to add them.");
+        eprintln!("hint: Turn this message off by running");
+        eprintln!("hint: \"git config advice.addIgnoredFile false\"");
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::fatal::fatal;
+use crate::repository::Repository;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+pub fn run(repo: &Repository, args: &[String]) -> io::Result<()> {
+    let mut quiet = false;
+    let mut verbose = false;
+    let mut stdin = false;
+    let mut nul_terminated = false;
+    let mut non_matching = false;
+    let mut no_index = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            "-v" | "--verbose" => verbose = true,
+            "--stdin" => stdin = true,
+            "-z" => nul_terminated = true,
+            "-n" | "--non-matching" => non_matching = true,
+            "--no-index" => no_index = true,
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    if stdin {
+        if !paths.is_empty() {
+            fatal("cannot specify pathnames with --stdin");
+        }
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        let terminator = if nul_terminated { '\0' } else { '\n' };
+        paths = input
+            .split_terminator(terminator)
+            .map(str::to_string)
+            .collect();
+    } else if paths.is_empty() {
+        fatal("no path specified");
+    }
+    if quiet && paths.len() > 1 {
+        fatal("--quiet is only valid with a single pathname");
+    }
+    if quiet && verbose {
+        fatal("cannot have both --quiet and --verbose");
+    }
+    if non_matching && !verbose {
+        fatal("--non-matching is only valid with --verbose");
+    }
+
+    let mut out = io::stdout().lock();
+    let mut ignored = 0;
+    for (path, lookup) in paths.iter().zip(repo.workspace_paths(&paths)) {
+        let lookup = Path::new(&lookup);
+        // Tracked files aren't subject to the ignore rules, unless we're told to pretend that the index is empty.
+        let rule = if !no_index && repo.index.is_tracked_file(lookup) {
+            None
+        } else {
+            repo.workspace.ignore_rule(lookup)
+        };
+        // Negated rules only count as a match when we're showing which rule matched.
+        let rule = rule.filter(|rule| verbose || !rule.negated);
+        if rule.is_some() {
+            ignored += 1;
+        }
+        if quiet || (rule.is_none() && !non_matching) {
+            continue;
+        }
+        match (verbose, &rule, nul_terminated) {
+            (false, _, false) => writeln!(out, "{path}")?,
+            (false, _, true) => write!(out, "{path}\0")?,
+            (true, Some(rule), false) => writeln!(
+                out,
+                "{}:{}:{}\t{path}",
+                rule.source, rule.line_number, rule.text
+            )?,
+            (true, Some(rule), true) => write!(
+                out,
+                "{}\0{}\0{}\0{path}\0",
+                rule.source, rule.line_number, rule.text
+            )?,
+            (true, None, false) => writeln!(out, "::\t{path}")?,
+            (true, None, true) => write!(out, "\0\0\0{path}\0")?,
+        }
+    }
+    out.flush()?;
+
+    // Like git, the exit code says whether anything was ignored.
+    if ignored == 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,392 @@
+use crate::commands::restore::{self, RestoreOptions};
+use crate::database::DatabaseEntry;
+use crate::fatal::fatal;
+use crate::index::IndexMetadata;
+use crate::migration::Migration;
+use crate::object::Object;
+use crate::pretty;
+use crate::refs;
+use crate::repository::Repository;
+use crate::rev_list::{RevList, RevListOptions};
+use crate::revision;
+use sha1_smol::Digest;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+// How many commits that are about to become unreachable get listed before summarizing the rest.
+const ORPHAN_CUTOFF: usize = 4;
+
+// Where HEAD should end up after switching.
+pub struct Target {
+    // The revision as it was given, used to describe a detached HEAD.
+    pub name: String,
+    // None only when creating a branch in a repository with no commits yet.
+    pub oid: Option<Digest>,
+    // The branch to put HEAD on, or None to detach it.
+    pub branch: Option<String>,
+    // Whether the branch is being created (true) or reset (false) by -b/-B or -c/-C.
+    pub new_branch: Option<bool>,
+    // Detaching was asked for with --detach, so there's no need to explain what a detached HEAD is.
+    pub forced_detach: bool,
+}
+
+impl Target {
+    pub fn branch(name: &str, oid: Digest) -> Self {
+        Target {
+            name: name.to_string(),
+            oid: Some(oid),
+            branch: Some(name.to_string()),
+            new_branch: None,
+            forced_detach: false,
+        }
+    }
+
+    pub fn detached(name: &str, oid: Option<Digest>, forced_detach: bool) -> Self {
+        Target {
+            name: name.to_string(),
+            oid,
+            branch: None,
+            new_branch: None,
+            forced_detach,
+        }
+    }
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut new_branch = None;
+    let mut detach = false;
+    let mut quiet = false;
+    let mut revisions = Vec::new();
+    let mut paths = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                paths = Some(args.by_ref().cloned().collect::<Vec<_>>());
+                break;
+            }
+            "-b" | "-B" => {
+                let Some(name) = args.next() else {
+                    eprintln!("error: switch `{}' requires a value", &arg[1..]);
+                    std::process::exit(129);
+                };
+                new_branch = Some((name.to_string(), arg == "-B"));
+            }
+            "--detach" => detach = true,
+            "-q" | "--quiet" => quiet = true,
+            // "-" is the branch (or commit) we were on before.
+            "-" => revisions.push("@{-1}"),
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => revisions.push(arg.as_str()),
+        }
+    }
+    // Paths after "--" are checked out from the revision before it (if any). Without "--", anything after the first
+    // argument is a path, and so is the first argument itself if it isn't a revision.
+    if let Some(paths) = paths {
+        if revisions.len() > 1 {
+            fatal(&format!(
+                "only one reference expected, {} given.",
+                revisions.len()
+            ));
+        }
+        checkout_paths(repo, revisions.first().copied(), &paths)?;
+        return Ok(());
+    }
+    let previous = revisions
+        .first()
+        .and_then(|name| revision::previous_checkout(repo, name));
+    if let Some(previous) = &previous {
+        revisions[0] = previous;
+    }
+    if new_branch.is_none() && !revisions.is_empty() {
+        let is_revision = revision::resolve_object(repo, revisions[0]).is_some();
+        if revisions.len() > 1 || !is_revision {
+            let (source, paths) = if is_revision {
+                (Some(revisions[0]), &revisions[1..])
+            } else {
+                (None, &revisions[..])
+            };
+            let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+            let written = checkout_paths(repo, source, &paths)?;
+            let from = match source {
+                Some(source) => {
+                    let tree = restore::resolve_tree(repo, source).expect("Revision is not a tree");
+                    repo.database.abbreviate(&tree)
+                }
+                None => "the index".to_string(),
+            };
+            let plural = if written == 1 { "" } else { "s" };
+            eprintln!("Updated {written} path{plural} from {from}");
+            return Ok(());
+        }
+    }
+    if revisions.len() > 1 {
+        eprintln!(
+            "error: pathspec '{}' did not match any file(s) known to git",
+            revisions[1]
+        );
+        std::process::exit(1);
+    }
+
+    let target = match (new_branch, revisions.first()) {
+        (Some((name, reset)), start) => new_branch_target(repo, &name, reset, start.copied()),
+        (None, Some(name)) => match repo.refs.read_branch(name).filter(|_| !detach) {
+            Some(oid) => Target::branch(name, oid),
+            None => match revision::resolve(repo, name) {
+                Some(oid) => Target::detached(name, Some(oid), detach),
+                None => fatal(&format!("Cannot switch branch to a non-commit '{name}'")),
+            },
+        },
+        // Without a revision, everything stays where it is and only the local changes are listed.
+        (None, None) => {
+            show_local_changes(repo, &repo.head_tree_entries());
+            return Ok(());
+        }
+    };
+    switch_logged(repo, &target, quiet)
+}
+
+// Checks out files from the index, or from a revision into both the index and the workspace. Unlike restore, files the
+// revision doesn't have are left alone. Returns how many workspace files were written.
+fn checkout_paths(
+    repo: &mut Repository,
+    source: Option<&str>,
+    paths: &[String],
+) -> io::Result<usize> {
+    let source = source.map(|name| {
+        restore::resolve_tree(repo, name)
+            .unwrap_or_else(|| fatal(&format!("invalid reference: {name}")))
+    });
+    let options = RestoreOptions {
+        source,
+        staged: source.is_some(),
+        worktree: true,
+        overlay: true,
+    };
+    restore::restore(repo, &repo.pathspec(paths), &options)
+}
+
+// The target for creating (or with `reset`, resetting) a branch at a start point, which defaults to HEAD.
+pub fn new_branch_target(
+    repo: &Repository,
+    name: &str,
+    reset: bool,
+    start: Option<&str>,
+) -> Target {
+    if !refs::is_valid_branch_name(name) {
+        fatal(&format!("'{name}' is not a valid branch name"));
+    }
+    if !reset && repo.refs.read_branch(name).is_some() {
+        fatal(&format!("a branch named '{name}' already exists"));
+    }
+    let oid = match start {
+        Some(start) => Some(revision::resolve(repo, start).unwrap_or_else(|| {
+            fatal(&format!(
+                "'{start}' is not a commit and a branch '{name}' cannot be created from it"
+            ))
+        })),
+        None => repo.refs.read_head(),
+    };
+    Target {
+        name: name.to_string(),
+        oid,
+        branch: Some(name.to_string()),
+        new_branch: Some(!reset || repo.refs.read_branch(name).is_none()),
+        forced_detach: false,
+    }
+}
+
+// Switches to the target like switch_to, and records the move in HEAD's reflog as "checkout: moving from <old> to <new>" like
+// git does for both checkout and switch, which is what "@{-N}" looks for.
+pub fn switch_logged(repo: &mut Repository, target: &Target, quiet: bool) -> io::Result<()> {
+    let old_oid = repo.refs.read_head();
+    let from = repo
+        .refs
+        .current_branch()
+        .or_else(|| old_oid.map(|oid| oid.to_string()));
+    switch_to(repo, target, quiet)?;
+    if let (Some(from), Some(oid)) = (from, target.oid) {
+        let message = format!("checkout: moving from {from} to {}", target.name);
+        repo.refs.log_head(old_oid, &oid, &message);
+    }
+    Ok(())
+}
+
+// Moves the workspace, index and HEAD to the target, refusing (and exiting) if that would lose uncommitted changes.
+pub fn switch_to(repo: &mut Repository, target: &Target, quiet: bool) -> io::Result<()> {
+    let old_branch = repo.refs.current_branch();
+    let old_oid = repo.refs.read_head();
+
+    if old_oid != target.oid {
+        let tree =
+            |oid: &Option<Digest>| oid.map(|oid| *repo.database.load_commit(&oid).get_tree());
+        let diff = repo
+            .database
+            .tree_diff(tree(&old_oid).as_ref(), tree(&target.oid).as_ref());
+        let migration = Migration::new(repo, diff);
+        let errors = migration.errors("checkout");
+        if !errors.is_empty() {
+            for error in errors {
+                eprint!("error: {error}");
+            }
+            eprintln!("Aborting");
+            std::process::exit(1);
+        }
+        migration.apply(repo)?;
+    }
+    if !quiet {
+        let entries = target
+            .oid
+            .map(|oid| {
+                let commit = repo.database.load_commit(&oid);
+                repo.database.load_tree_entries(commit.get_tree())
+            })
+            .unwrap_or_default();
+        show_local_changes(repo, &entries);
+    }
+
+    // Leaving a detached HEAD might leave commits behind that nothing else points to.
+    if let Some(old_oid) = old_oid.filter(|_| old_branch.is_none() && !quiet) {
+        if Some(old_oid) != target.oid {
+            orphaned_commit_warning(repo, old_oid, target.oid);
+        }
+    }
+
+    let Some(branch) = &target.branch else {
+        let Some(oid) = target.oid else {
+            fatal("You are on a branch yet to be born");
+        };
+        repo.refs.detach_head(&oid);
+        if !quiet {
+            if old_branch.is_some() && !target.forced_detach {
+                detach_advice(&target.name);
+            }
+            eprintln!("HEAD is now at {}", describe(repo, &oid));
+        }
+        return Ok(());
+    };
+
+    if let (Some(_), Some(oid)) = (target.new_branch, target.oid) {
+        repo.refs.set_branch(branch, &oid);
+    }
+    repo.refs.set_head_branch(branch);
+    if quiet {
+        return Ok(());
+    }
+    let message = match target.new_branch {
+        Some(_) if old_branch.as_ref() == Some(branch) => "Reset branch",
+        None if old_branch.as_ref() == Some(branch) => "Already on",
+        Some(true) => "Switched to a new branch",
+        Some(false) => "Switched to and reset branch",
+        None => "Switched to branch",
+    };
+    eprintln!("{message} '{branch}'");
+    Ok(())
+}
+
+// A commit's abbreviated oid and subject, e.g. "1a2b3c4 Fix the build".
+pub fn describe(repo: &Repository, oid: &Digest) -> String {
+    let commit = repo.database.load_commit(oid);
+    format!(
+        "{} {}",
+        repo.database.abbreviate(oid),
+        pretty::subject(commit.get_message())
+    )
+}
+
+fn detach_advice(name: &str) {
+    eprintln!(
+        "Note: switching to '{name}'.
+
+You are in 'detached HEAD' state. You can look around, make experimental
+changes and commit them, and you can discard any commits you make in this
+state without impacting any branches by switching back to a branch.
+
+If you want to create a new branch to retain commits you create, you may
+do so (now or later) by using -c with the switch command. Example:
+
+  git switch -c <new-branch-name>
+
+Or undo this operation with:
+
+  git switch -
+
+Turn off this advice by setting config variable advice.detachedHead to false
+"
+    );
+}
+
+// Warns about the commits a detached HEAD was on that can't be reached from any ref once HEAD moves away.
+fn orphaned_commit_warning(repo: &Repository, old_oid: Digest, new_oid: Option<Digest>) {
+    let mut rev_list = RevList::new(repo, RevListOptions::default());
+    rev_list.include(old_oid);
+    let refs = repo.refs.list_refs().into_iter().map(|(_, oid)| oid);
+    for oid in refs.chain(new_oid) {
+        let oid = repo.database.peel(&oid);
+        if repo.database.read_object(&oid).0 == "commit" {
+            rev_list.exclude(oid);
+        }
+    }
+    let lost = rev_list.walk();
+    if lost.is_empty() {
+        eprintln!("Previous HEAD position was {}", describe(repo, &old_oid));
+        return;
+    }
+
+    let (commits, them) = if lost.len() == 1 {
+        ("commit", "it")
+    } else {
+        ("commits", "them")
+    };
+    eprintln!(
+        "Warning: you are leaving {} {commits} behind, not connected to\nany of your branches:\n",
+        lost.len()
+    );
+    // Listing just one more is shorter than saying there's one more.
+    let shown = if lost.len() == ORPHAN_CUTOFF + 1 {
+        lost.len()
+    } else {
+        lost.len().min(ORPHAN_CUTOFF)
+    };
+    for commit in &lost[..shown] {
+        eprintln!("  {}", describe(repo, commit.get_oid()));
+    }
+    if shown < lost.len() {
+        eprintln!(" ... and {} more.", lost.len() - shown);
+    }
+    eprintln!(
+        "\nIf you want to keep {them} by creating a new branch, this may be a good time\nto do so with:\n\n git branch <new-branch-name> {}\n",
+        repo.database.abbreviate(&old_oid)
+    );
+}
+
+// Lists the files whose workspace or staged versions differ from the commit being checked out (given by its tree entries),
+// which a checkout carries along.
+fn show_local_changes(repo: &Repository, head: &BTreeMap<PathBuf, DatabaseEntry>) {
+    let mut paths: Vec<PathBuf> = head.keys().cloned().collect();
+    paths.extend(repo.index.entries().map(|e| e.get_path().to_path_buf()));
+    paths.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
+    paths.dedup();
+    for path in paths {
+        let in_head = head.get(&path).map(|entry| (entry.oid, entry.mode));
+        let status = match repo.index.get_entry(&path) {
+            None => 'D',
+            Some(_) if in_head.is_none() => 'A',
+            Some(entry) => match repo.workspace.stat_file(&path) {
+                Err(_) => 'D',
+                Ok(stat) => {
+                    let staged = (*entry.get_oid(), entry.get_mode());
+                    if in_head != Some(staged)
+                        || !repo.is_unchanged(entry, &IndexMetadata::from(stat))
+                    {
+                        'M'
+                    } else {
+                        continue;
+                    }
+                }
+            },
+        };
+        println!("{status}\t{}", path.display());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::fatal::fatal;
+use crate::object::Object;
+use crate::repository::Repository;
+use crate::rev_list::{RevList, RevListOptions};
+use crate::revision;
+use crate::sequencer::{Action, Options, Sequencer};
+use sha1_smol::Digest;
+use std::io::{self, IsTerminal};
+
+// What to do with a cherry-pick or revert that's already under way, instead of starting one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    Continue,
+    Skip,
+    Abort,
+    Quit,
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    run_action(repo, args, Action::Pick)
+}
+
+// Parses the arguments of cherry-pick or revert, which are the same apart from -x, and hands the commits to the sequencer.
+pub fn run_action(repo: &mut Repository, args: &[String], action: Action) -> io::Result<()> {
+    let command = action.command();
+    let mut options = Options::default();
+    let mut edit = None;
+    let mut resume = None;
+    let mut revisions = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--continue" => resume = Some(Resume::Continue),
+            "--skip" => resume = Some(Resume::Skip),
+            "--abort" => resume = Some(Resume::Abort),
+            "--quit" => resume = Some(Resume::Quit),
+            "-e" | "--edit" => edit = Some(true),
+            "--no-edit" => edit = Some(false),
+            "-x" if action == Action::Pick => options.record_origin = true,
+            _ if arg.starts_with('-') && !arg.contains("..") => {
+                fatal(&format!("unrecognized argument: {arg}"))
+            }
+            _ => revisions.push(arg.clone()),
+        }
+    }
+    // A revert's message is written for the user, so they get to edit it when there's someone at the terminal.
+    options.edit = edit.unwrap_or(action == Action::Revert && io::stdin().is_terminal());
+
+    if let Some(resume) = resume {
+        if !revisions.is_empty() {
+            let flag = match resume {
+                Resume::Continue => "--continue",
+                Resume::Skip => "--skip",
+                Resume::Abort => "--abort",
+                Resume::Quit => "--quit",
+            };
+            eprintln!("error: option '{flag}' cannot be used with commits");
+            std::process::exit(129);
+        }
+        let mut sequencer = Sequencer::new(repo, action, options);
+        return match resume {
+            Resume::Continue => sequencer.resume(),
+            Resume::Skip => sequencer.skip(),
+            Resume::Abort => sequencer.abort(),
+            Resume::Quit => sequencer.quit(),
+        };
+    }
+    if revisions.is_empty() {
+        eprintln!("usage: git {command} [--edit] [-x] <commit>...");
+        eprintln!("   or: git {command} (--continue | --skip | --abort | --quit)");
+        std::process::exit(129);
+    }
+
+    // A single commit is applied on its own. Ranges (and exclusions) are walked, oldest commit first when picking, so that
+    // each change applies on top of the ones before it, and newest first when reverting, to undo them in reverse.
+    let single = revisions.len() == 1 && !is_range(&revisions[0]);
+    let commits = if revisions.iter().any(|arg| is_range(arg)) {
+        let options = RevListOptions {
+            reverse: action == Action::Pick,
+            ..Default::default()
+        };
+        let mut rev_list = RevList::new(repo, options);
+        for arg in &revisions {
+            if !rev_list.add_arg(arg) {
+                bad_revision(arg);
+            }
+        }
+        let commits: Vec<Digest> = rev_list
+            .walk()
+            .iter()
+            .map(|commit| *commit.get_oid())
+            .collect();
+        if commits.is_empty() {
+            eprintln!("error: empty commit set passed");
+            fatal(&format!("{command} failed"));
+        }
+        commits
+    } else {
+        revisions
+            .iter()
+            .map(|arg| revision::resolve(repo, arg).unwrap_or_else(|| bad_revision(arg)))
+            .collect()
+    };
+    Sequencer::new(repo, action, options).start(commits, single)
+}
+
+fn is_range(arg: &str) -> bool {
+    arg.contains("..") || arg.starts_with('^')
+}
+
+fn bad_revision(arg: &str) -> ! {
+    fatal(&format!("bad revision '{arg}'"));
+}
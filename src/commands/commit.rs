@@ -0,0 +1,884 @@
+use crate::blob::Blob;
+use crate::commands::diff::DiffFormat;
+use crate::commands::status;
+use crate::commit::{Author, Commit};
+use crate::database::DatabaseEntry;
+use crate::date;
+use crate::diff;
+use crate::fatal::fatal;
+use crate::index::{Index, IndexMetadata};
+use crate::object::Object;
+use crate::pretty;
+use crate::rebase;
+use crate::rename::{self, RenameOptions};
+use crate::repository::Repository;
+use crate::tree::Tree;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// The line that, with --cleanup=scissors, ends the message: everything from it on is dropped.
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+// Added to the message of a merge commit while it's being edited.
+const MERGE_ADVICE: &str = "#
+# It looks like you may be committing a merge.
+# If this is not correct, please run
+#\tgit update-ref -d MERGE_HEAD
+# and try again.
+
+";
+
+// Added to the message of a cherry-picked commit while it's being edited, after its conflicts were resolved.
+const CHERRY_PICK_ADVICE: &str = "#
+# It looks like you may be committing a cherry-pick.
+# If this is not correct, please run
+#\tgit update-ref -d CHERRY_PICK_HEAD
+# and try again.
+
+";
+
+// How a message is tidied up before it's committed (--cleanup).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cleanup {
+    // Like Whitespace, and also drops "#" comment lines. This is the default when the message was edited.
+    Strip,
+    // Drops trailing whitespace, leading and trailing blank lines, and repeated blank lines.
+    Whitespace,
+    Verbatim,
+    // Like Whitespace, but an edited message is also cut off at the scissors line.
+    Scissors,
+}
+
+impl Cleanup {
+    fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "strip" => Some(Cleanup::Strip),
+            "whitespace" => Some(Cleanup::Whitespace),
+            "verbatim" => Some(Cleanup::Verbatim),
+            "scissors" => Some(Cleanup::Scissors),
+            _ => None,
+        }
+    }
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut amend = false;
+    let mut edit = None;
+    let mut messages = Vec::new();
+    let mut file = None;
+    let mut cleanup = None;
+    let mut all = false;
+    let mut include = false;
+    let mut only = false;
+    let mut allow_empty = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |short: &str, long: &str| match args.next() {
+            Some(value) => value.clone(),
+            None => {
+                match arg.strip_prefix("--") {
+                    Some(_) => eprintln!("error: option `{long}' requires a value"),
+                    None => eprintln!("error: switch `{short}' requires a value"),
+                }
+                std::process::exit(129);
+            }
+        };
+        match arg.as_str() {
+            "--amend" => amend = true,
+            "-e" | "--edit" => edit = Some(true),
+            "--no-edit" => edit = Some(false),
+            "-a" | "--all" => all = true,
+            "-i" | "--include" => include = true,
+            "-o" | "--only" => only = true,
+            "--allow-empty" => allow_empty = true,
+            "--" => {
+                paths.extend(args.by_ref().cloned());
+                break;
+            }
+            "-m" | "--message" => messages.push(value("m", "message")),
+            "-F" | "--file" => file = Some(value("F", "file")),
+            _ if arg.starts_with("--message=") => {
+                messages.push(arg["--message=".len()..].to_string())
+            }
+            _ if arg.starts_with("--file=") => file = Some(arg["--file=".len()..].to_string()),
+            _ if arg.starts_with("-m") => messages.push(arg[2..].to_string()),
+            _ if arg.starts_with("-F") => file = Some(arg[2..].to_string()),
+            _ if arg.starts_with("--cleanup=") => {
+                let mode = &arg["--cleanup=".len()..];
+                cleanup = Some(
+                    Cleanup::parse(mode)
+                        .unwrap_or_else(|| fatal(&format!("Invalid cleanup mode {mode}"))),
+                );
+            }
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if !messages.is_empty() && file.is_some() {
+        fatal("options '-m' and '-F' cannot be used together");
+    }
+    if include && all {
+        fatal("options '-i/--include' and '-a/--all' cannot be used together");
+    }
+    if include && only {
+        fatal("options '-i/--include' and '-o/--only' cannot be used together");
+    }
+    if only && all {
+        fatal("options '-o/--only' and '-a/--all' cannot be used together");
+    }
+    if all && !paths.is_empty() {
+        fatal(&format!(
+            "paths '{} ...' with -a does not make sense",
+            paths[0]
+        ));
+    }
+    if paths.is_empty() && (include || (only && !amend)) {
+        fatal("No paths with --include/--only does not make sense.");
+    }
+    // A merge whose conflicts have been resolved is committed with the merged commit as a second parent.
+    let merge_head = fs::read_to_string(repo.git_path.join("MERGE_HEAD"))
+        .ok()
+        .map(|oid| oid.trim().parse().expect("MERGE_HEAD is not an oid"));
+    // So is a cherry-pick that stopped on conflicts, but with the picked commit's author.
+    let picked = fs::read_to_string(repo.git_path.join("CHERRY_PICK_HEAD"))
+        .ok()
+        .map(|oid| {
+            repo.database
+                .load_commit(&oid.trim().parse().expect("CHERRY_PICK_HEAD is not an oid"))
+        });
+    let in_progress = if merge_head.is_some() {
+        Some("merge")
+    } else if picked.is_some() {
+        Some("cherry-pick")
+    } else {
+        None
+    };
+    if let Some(operation) = in_progress {
+        if amend {
+            fatal(&format!(
+                "You are in the middle of a {operation} -- cannot amend."
+            ));
+        }
+        if !paths.is_empty() && !include {
+            fatal(&format!("cannot do a partial commit during a {operation}."));
+        }
+    }
+
+    // With -a, every tracked file is staged as it is in the workspace first. Given paths are staged along with what's in
+    // the index with -i, and otherwise committed on their own: the commit is made from a temporary index holding HEAD's tree
+    // and just those paths, and only then are they staged in the real index as well.
+    let mut real_index = None;
+    let mut partial = Vec::new();
+    if all {
+        // A conflicted path has several entries, but it's staged just once (which resolves the conflict).
+        let tracked: Vec<PathBuf> = repo
+            .index
+            .entries()
+            .map(|entry| entry.get_path().to_path_buf())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        stage_paths(repo, &tracked)?;
+    } else if include || only || !paths.is_empty() {
+        let mut candidates: BTreeSet<PathBuf> = repo
+            .index
+            .entries()
+            .map(|entry| entry.get_path().to_path_buf())
+            .collect();
+        let head_entries = repo.head_tree_entries();
+        if !include {
+            candidates.extend(head_entries.keys().cloned());
+        }
+        let pathspec = repo.pathspec(&paths);
+        // Like git, only a partial commit insists that every pattern matches something.
+        let unmatched = pathspec.unmatched(candidates.iter().map(|path| path.as_path()));
+        if !include && !unmatched.is_empty() {
+            for pattern in unmatched {
+                eprintln!("error: pathspec '{pattern}' did not match any file(s) known to git");
+            }
+            std::process::exit(1);
+        }
+        partial = candidates
+            .into_iter()
+            .filter(|path| pathspec.matches(path))
+            .collect();
+        if !include {
+            let mut index = Index::default();
+            for (path, entry) in head_entries {
+                index.add(path, entry.oid, IndexMetadata::with_mode(entry.mode));
+            }
+            real_index = Some(std::mem::replace(&mut repo.index, index));
+        }
+        stage_paths(repo, &partial)?;
+    }
+
+    if repo.index.has_conflicts() {
+        for path in repo.index.conflicted_paths() {
+            println!("U\t{}", path.display());
+        }
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'git add/rm <file>'");
+        eprintln!("hint: as appropriate to mark resolution and make a commit.");
+        fatal("Exiting because of an unresolved conflict.");
+    }
+
+    let head = repo.refs.read_head();
+    // Amending replaces the HEAD commit with one that has the same parents, author and (unless edited) message.
+    let amended = if amend {
+        let Some(head) = head else {
+            fatal("You have nothing to amend.");
+        };
+        Some(repo.database.load_commit(&head))
+    } else {
+        None
+    };
+
+    // The message starts out as the -m paragraphs, the -F file's contents, the amended commit's message or the one merge (or
+    // cherry-pick or revert) prepared. It's only edited if none of the first two were given, unless asked otherwise.
+    let given = !messages.is_empty() || file.is_some();
+    let merge_message = fs::read_to_string(repo.git_path.join("MERGE_MSG")).ok();
+    let message = if !messages.is_empty() {
+        format!("{}\n", messages.join("\n\n"))
+    } else if let Some(file) = file {
+        read_message_file(&file, "could not read log file")
+    } else if let Some(merge_message) = &merge_message {
+        merge_message.clone()
+    } else {
+        amended
+            .as_ref()
+            .map(|old| old.get_message().to_string())
+            .unwrap_or_default()
+    };
+
+    // Like git, there has to be something to commit, unless it's a merge (or amends one) or an empty commit was asked for.
+    // This is checked after reading the message (so a missing -F file is reported first), but before editing it.
+    let root_tree = write_tree(repo);
+    let parent = match &amended {
+        Some(old) => old.get_parents().first().copied(),
+        None => head,
+    };
+    let unchanged = match parent {
+        Some(parent) => repo.database.load_commit(&parent).get_tree() == root_tree.get_oid(),
+        None => repo.index.entries().next().is_none(),
+    };
+    let merging = merge_head.is_some()
+        || amended
+            .as_ref()
+            .is_some_and(|old| old.get_parents().len() > 1);
+    if unchanged && !allow_empty && !merging {
+        if amended.is_some() {
+            eprintln!("You asked to amend the most recent commit, but doing so would make");
+            eprintln!("it empty. You can repeat your command with --allow-empty, or you can");
+            eprintln!("remove the commit entirely with \"git reset HEAD^\".");
+        } else if picked.is_some() {
+            eprintln!(
+                "The previous cherry-pick is now empty, possibly due to conflict resolution."
+            );
+            eprintln!("If you wish to commit it anyway, use:");
+            eprintln!();
+            eprintln!("    git commit --allow-empty");
+            eprintln!();
+            if repo.git_path.join("sequencer").exists() {
+                eprintln!("and then use:");
+                eprintln!();
+                eprintln!("    git cherry-pick --continue");
+                eprintln!();
+                eprintln!("to resume cherry-picking the remaining commits.");
+                eprintln!("If you wish to skip this commit, use:");
+                eprintln!();
+                eprintln!("    git cherry-pick --skip");
+                eprintln!();
+            } else {
+                eprintln!("Otherwise, please use 'git cherry-pick --skip'");
+            }
+        }
+        // The status is of the real index, even if a partial commit was going to be made from another one.
+        if let Some(index) = real_index {
+            repo.index = index;
+        }
+        status::print_nothing_to_commit(repo, amended.is_some())?;
+        std::process::exit(1);
+    }
+
+    let edit = edit.unwrap_or(!given);
+    let cleanup = cleanup.unwrap_or(if edit {
+        Cleanup::Strip
+    } else {
+        Cleanup::Whitespace
+    });
+    let message = if edit {
+        let message = match in_progress {
+            Some("merge") => format!("{message}{MERGE_ADVICE}"),
+            Some(_) => format!("{message}{CHERRY_PICK_ADVICE}"),
+            None => message,
+        };
+        let borrowed = amended.as_ref().or(picked.as_ref());
+        let template = commit_template(
+            repo,
+            &message,
+            cleanup,
+            borrowed.map(Commit::get_author),
+            amended.as_ref(),
+        );
+        edit_message(repo, &template)?
+    } else {
+        fs::write(repo.git_path.join("COMMIT_EDITMSG"), &message)?;
+        message
+    };
+    let message = cleanup_message(&message, cleanup, edit);
+    if message.trim().is_empty() {
+        eprintln!("Aborting commit due to empty commit message.");
+        std::process::exit(1);
+    }
+
+    let (parents, author) = match &amended {
+        Some(old) => (old.get_parents().to_vec(), old.get_author().clone()),
+        None => (
+            head.into_iter().chain(merge_head).collect(),
+            picked
+                .as_ref()
+                .map_or_else(Author::now, |picked| picked.get_author().clone()),
+        ),
+    };
+
+    let commit = Commit::new(
+        *root_tree.get_oid(),
+        parents,
+        author,
+        Author::now(),
+        message,
+    );
+    repo.database.store(&commit);
+
+    // A partial commit leaves the rest of the index as it was, with just the committed paths staged.
+    if let Some(index) = real_index {
+        repo.index = index;
+        stage_paths(repo, &partial)?;
+    }
+    repo.index.write_updates();
+
+    let subject = pretty::subject(commit.get_message());
+    let kind = match (&amended, commit.get_parents().len()) {
+        (Some(_), _) => " (amend)",
+        (None, 0) => " (initial)",
+        (None, 1) if picked.is_some() => " (cherry-pick)",
+        (None, 1) => "",
+        (None, _) => " (merge)",
+    };
+    repo.refs
+        .update_head_logged(commit.get_oid(), &format!("commit{kind}: {subject}"));
+    repo.remove_merge_state();
+    print_summary(repo, &commit, amended.is_some() || picked.is_some());
+    Ok(())
+}
+
+// Updates the index entries of the given paths to match the workspace, removing the ones whose files are gone. The blobs
+// of changed files are stored as they're staged.
+pub fn stage_paths(repo: &mut Repository, paths: &[PathBuf]) -> io::Result<()> {
+    for path in paths {
+        let stat = match repo.workspace.stat_file(path) {
+            Ok(stat) if !stat.is_dir() => IndexMetadata::from(stat),
+            _ => {
+                repo.index.remove(path);
+                continue;
+            }
+        };
+        let unchanged = repo
+            .index
+            .get_entry(path)
+            .is_some_and(|entry| repo.is_unchanged(entry, &stat));
+        if unchanged {
+            continue;
+        }
+        let blob = Blob::new(repo.workspace.read_file(path)?, path.clone());
+        repo.database.store(&blob);
+        repo.index.add(path.clone(), *blob.get_oid(), stat);
+    }
+    Ok(())
+}
+
+// Stores the tree of everything in the index, and every subtree in it. The blobs were stored when they were added.
+pub fn write_tree(repo: &mut Repository) -> Tree {
+    let entries: BTreeMap<_, _> = repo
+        .index
+        .entries()
+        .map(|entry| {
+            let blob = DatabaseEntry {
+                oid: *entry.get_oid(),
+                mode: entry.get_mode(),
+            };
+            (entry.get_path().to_path_buf(), blob)
+        })
+        .collect();
+    let root_tree = Tree::new(entries);
+    root_tree.traverse(&mut |subtree| {
+        repo.database.store(subtree);
+    });
+    root_tree
+}
+
+// Reads the message given with -F, where "-" means standard input. If it can't be read, that's a fatal error, whose message
+// starts with the given text like it does in git (which words it differently for different commands).
+pub fn read_message_file(file: &str, error: &str) -> String {
+    let contents = if file == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(file)
+    };
+    contents.unwrap_or_else(|e| {
+        let reason = match e.kind() {
+            io::ErrorKind::NotFound => "No such file or directory".to_string(),
+            _ => e.to_string(),
+        };
+        fatal(&format!("{error} '{file}': {reason}"));
+    })
+}
+
+// Lets the user edit the message of a commit that's about to be made from the index, the way commit does when it isn't
+// given one: with the usual template, stripped of comments afterwards. The author is shown if it's someone else's commit,
+// and the changes are shown against the amended commit's parent if it replaces HEAD.
+pub fn edit(
+    repo: &Repository,
+    message: &str,
+    author: Option<&Author>,
+    amended: Option<&Commit>,
+) -> io::Result<String> {
+    let template = commit_template(repo, message, Cleanup::Strip, author, amended);
+    let message = cleanup_message(&edit_message(repo, &template)?, Cleanup::Strip, true);
+    if message.is_empty() {
+        eprintln!("Aborting commit due to empty commit message.");
+        std::process::exit(1);
+    }
+    Ok(message)
+}
+
+// What the editor starts out with: the message so far, then (as comments) how it will be cleaned up and what's about to be
+// committed, as status would show it. The author and date are shown when they come from another commit.
+fn commit_template(
+    repo: &Repository,
+    message: &str,
+    cleanup: Cleanup,
+    author: Option<&Author>,
+    amended: Option<&Commit>,
+) -> String {
+    let mut template = format!("{message}\n");
+    template.push_str(match cleanup {
+        Cleanup::Strip => {
+            "# Please enter the commit message for your changes. Lines starting
+# with '#' will be ignored, and an empty message aborts the commit.
+"
+        }
+        Cleanup::Scissors => {
+            "# ------------------------ >8 ------------------------
+# Do not modify or remove the line above.
+# Everything below it will be ignored.
+"
+        }
+        Cleanup::Whitespace | Cleanup::Verbatim => {
+            "# Please enter the commit message for your changes. Lines starting
+# with '#' will be kept; you may remove them yourself if you want to.
+# An empty message aborts the commit.
+"
+        }
+    });
+    template.push_str("#\n");
+    if let Some(author) = author {
+        let committer = Author::now();
+        if (&author.name, &author.email) != (&committer.name, &committer.email) {
+            template.push_str(&format!(
+                "# Author:    {} <{}>\n",
+                author.name, author.email
+            ));
+        }
+        let date = date::format_default(author.timestamp, &author.timezone);
+        template.push_str(&format!("# Date:      {date}\n#\n"));
+    }
+
+    match (repo.refs.current_branch(), rebase::status_comment(repo)) {
+        (Some(branch), _) => template.push_str(&format!("# On branch {branch}\n")),
+        (None, Some(rebasing)) => template.push_str(&rebasing),
+        (None, None) => {
+            let head = repo.refs.read_head().expect("HEAD is detached but unset");
+            template.push_str(&format!(
+                "# HEAD detached at {}\n",
+                repo.database.abbreviate(&head)
+            ));
+        }
+    }
+    if repo.git_path.join("MERGE_HEAD").exists() {
+        template.push_str("# All conflicts fixed but you are still merging.\n#\n");
+    }
+    for (file, doing) in [
+        ("CHERRY_PICK_HEAD", "cherry-picking"),
+        ("REVERT_HEAD", "reverting"),
+    ] {
+        if let Ok(oid) = fs::read_to_string(repo.git_path.join(file)) {
+            let oid = oid.trim().parse().expect("Not an oid");
+            template.push_str(&format!(
+                "# You are currently {doing} commit {}.\n#\n",
+                repo.database.abbreviate(&oid)
+            ));
+        }
+    }
+    // An amended commit replaces HEAD, so what's staged is compared with its parent instead.
+    let base = match amended {
+        Some(old) => old.get_parents().first().copied(),
+        None => repo.refs.read_head(),
+    };
+    if base.is_none() {
+        template.push_str("#\n# Initial commit\n#\n");
+    }
+    let base = base
+        .map(|oid| {
+            let commit = repo.database.load_commit(&oid);
+            repo.database.load_tree_entries(commit.get_tree())
+        })
+        .unwrap_or_default();
+    let staged = rename::detect(repo.tree_to_index(&base), &RenameOptions::default());
+    let mut sections = Vec::new();
+    status::write_sections(
+        &mut sections,
+        &repo.prefix,
+        &staged,
+        &status::unmerged_paths(repo),
+        &repo.index_to_workspace(),
+        &repo.untracked_files(),
+    )
+    .expect("Could not write to a buffer");
+    for line in String::from_utf8_lossy(&sections).lines() {
+        let separator = if line.is_empty() || line.starts_with('\t') {
+            ""
+        } else {
+            " "
+        };
+        template.push_str(&format!("#{separator}{line}\n"));
+    }
+    template
+}
+
+// Lets the user edit a message in their editor, starting from the given text, and returns what they saved.
+fn edit_message(repo: &Repository, initial: &str) -> io::Result<String> {
+    let path = repo.git_path.join("COMMIT_EDITMSG");
+    fs::write(&path, initial)?;
+    let editor = editor(repo);
+    if !launch_editor(&editor, &path)? {
+        eprintln!("error: There was a problem with the editor '{editor}'.");
+        eprintln!("Please supply the message using either -m or -F option.");
+        std::process::exit(1);
+    }
+    fs::read_to_string(&path)
+}
+
+// Runs an editor on a file and waits for it, returning whether it exited successfully. Like git, the editor is run through
+// the shell so that it can have arguments of its own, e.g. "code --wait".
+pub fn launch_editor(editor: &str, path: &Path) -> io::Result<bool> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(editor)
+        .arg(path)
+        .status()?;
+    Ok(status.success())
+}
+
+// The editor to run, in the order git looks for one: $GIT_EDITOR, core.editor, $VISUAL (unless the terminal is dumb),
+// $EDITOR, and finally vi.
+pub fn editor(repo: &Repository) -> String {
+    let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+    let dumb = env::var("TERM").map_or(true, |term| term == "dumb");
+    let editor = var("GIT_EDITOR")
+        .or_else(|| repo.config.get("core.editor").map(str::to_string))
+        .or_else(|| var("VISUAL").filter(|_| !dumb))
+        .or_else(|| var("EDITOR"));
+    match editor {
+        Some(editor) => editor,
+        None if dumb => {
+            eprintln!("error: Terminal is dumb, but EDITOR unset");
+            eprintln!("Please supply the message using either -m or -F option.");
+            std::process::exit(1);
+        }
+        None => "vi".to_string(),
+    }
+}
+
+// Cleans up a message the way an edited one is by default: without comments, trailing whitespace or extra blank lines.
+pub fn strip_message(message: &str) -> String {
+    cleanup_message(message, Cleanup::Strip, true)
+}
+
+// Tidies up a message according to the cleanup mode, which for scissors depends on whether the message was edited (and so
+// has the scissors line in it).
+fn cleanup_message(message: &str, cleanup: Cleanup, edited: bool) -> String {
+    let message = match cleanup {
+        Cleanup::Verbatim => return message.to_string(),
+        Cleanup::Scissors if edited => {
+            let cut = message
+                .lines()
+                .scan(0, |offset, line| {
+                    let start = *offset;
+                    *offset += line.len() + 1;
+                    Some((start, line))
+                })
+                .find(|(_, line)| *line == SCISSORS)
+                .map(|(start, _)| start);
+            &message[..cut.unwrap_or(message.len())]
+        }
+        _ => message,
+    };
+
+    let mut lines: Vec<&str> = Vec::new();
+    for line in message.lines().map(str::trim_end) {
+        if cleanup == Cleanup::Strip && line.starts_with('#') {
+            continue;
+        }
+        // Runs of blank lines become one, and there are none at the start.
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    if lines.last() == Some(&"") {
+        lines.pop();
+    }
+    if lines.is_empty() {
+        return String::new();
+    }
+    format!("{}\n", lines.join("\n"))
+}
+
+// Prints what git does after a commit, e.g. "[main 1a2b3c4] Fix the build" followed by a summary of the changes made since
+// its first parent. The author is shown if it isn't the committer, and the date if asked for (when it was taken from
+// another commit).
+pub fn print_summary(repo: &Repository, commit: &Commit, show_date: bool) {
+    let branch = repo
+        .refs
+        .current_branch()
+        .unwrap_or_else(|| "detached HEAD".to_string());
+    let root = if commit.get_parents().is_empty() {
+        " (root-commit)"
+    } else {
+        ""
+    };
+    println!(
+        "[{branch}{root} {}] {}",
+        repo.database.abbreviate(commit.get_oid()),
+        pretty::subject(commit.get_message())
+    );
+    let author = commit.get_author();
+    let committer = commit.get_committer();
+    if (&author.name, &author.email) != (&committer.name, &committer.email) {
+        println!(" Author: {} <{}>", author.name, author.email);
+    }
+    if show_date {
+        println!(
+            " Date: {}",
+            date::format_default(author.timestamp, &author.timezone)
+        );
+    }
+    // Like git, a merge commit's changes aren't summarized.
+    if commit.get_parents().len() > 1 {
+        return;
+    }
+
+    let parent_tree = commit
+        .get_parents()
+        .first()
+        .map(|oid| *repo.database.load_commit(oid).get_tree());
+    let changes = repo
+        .database
+        .tree_diff(parent_tree.as_ref(), Some(commit.get_tree()));
+    let pairs = diff::pairs_from_tree_diff(&repo.database, &changes);
+    let summary = DiffFormat {
+        shortstat: true,
+        summary: true,
+        ..Default::default()
+    };
+    summary
+        .write(&mut io::stdout().lock(), pairs, &repo.attributes)
+        .expect("Could not write the commit summary");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::Location;
+
+    // A repository in a fresh directory with one commit holding the given files.
+    fn repo_with_files(name: &str, files: &[(&str, &str)]) -> Repository {
+        let root = env::temp_dir().join(format!("commit-{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        let mut repo = Repository::new(Location {
+            git_path: root.join(".git"),
+            root_path: Some(root),
+            prefix: PathBuf::new(),
+        });
+        repo.refs.set_head_branch("main");
+        let paths = write_files(&repo, files);
+        stage_paths(&mut repo, &paths).unwrap();
+        commit(&mut repo, &["-m", "initial"]);
+        repo
+    }
+
+    fn write_files(repo: &Repository, files: &[(&str, &str)]) -> Vec<PathBuf> {
+        for (path, contents) in files {
+            fs::write(repo.workspace.root().join(path), contents).unwrap();
+        }
+        files.iter().map(|(path, _)| PathBuf::from(path)).collect()
+    }
+
+    fn commit(repo: &mut Repository, args: &[&str]) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        run(repo, &args).unwrap();
+    }
+
+    fn contents(repo: &Repository, oid: &sha1_smol::Digest) -> String {
+        String::from_utf8(repo.database.read_object(oid).1).unwrap()
+    }
+
+    // The contents of every file in HEAD's tree.
+    fn committed(repo: &Repository) -> Vec<(String, String)> {
+        repo.head_tree_entries()
+            .iter()
+            .map(|(path, entry)| (path.display().to_string(), contents(repo, &entry.oid)))
+            .collect()
+    }
+
+    fn staged(repo: &Repository, path: &str) -> String {
+        contents(
+            repo,
+            repo.index.get_entry(Path::new(path)).unwrap().get_oid(),
+        )
+    }
+
+    fn pairs(files: &[(&str, &str)]) -> Vec<(String, String)> {
+        files
+            .iter()
+            .map(|(path, contents)| (path.to_string(), contents.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_commit_all() {
+        let mut repo = repo_with_files("all", &[("a", "1\n"), ("b", "1\n")]);
+        write_files(&repo, &[("a", "2\n"), ("c", "untracked\n")]);
+        fs::remove_file(repo.workspace.root().join("b")).unwrap();
+        commit(&mut repo, &["-a", "-m", "second"]);
+        // Changed and deleted files are committed, but untracked ones are left alone.
+        assert_eq!(committed(&repo), pairs(&[("a", "2\n")]));
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_commit_include() {
+        let mut repo = repo_with_files("include", &[("a", "1\n"), ("b", "1\n"), ("c", "1\n")]);
+        let paths = write_files(&repo, &[("a", "2\n"), ("b", "2\n"), ("c", "2\n")]);
+        stage_paths(&mut repo, &paths[..1]).unwrap();
+        commit(&mut repo, &["-i", "-m", "second", "b"]);
+        // What was already staged goes in along with the given paths.
+        assert_eq!(
+            committed(&repo),
+            pairs(&[("a", "2\n"), ("b", "2\n"), ("c", "1\n")])
+        );
+        assert_eq!(staged(&repo, "c"), "1\n");
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_partial_commit() {
+        let mut repo = repo_with_files("partial", &[("a", "1\n"), ("b", "1\n")]);
+        let paths = write_files(&repo, &[("a", "2\n"), ("b", "2\n")]);
+        stage_paths(&mut repo, &paths[..1]).unwrap();
+        write_files(&repo, &[("a", "3\n")]);
+        commit(&mut repo, &["-m", "second", "b"]);
+        // Only the given paths are committed, while what was staged for the others stays staged.
+        assert_eq!(committed(&repo), pairs(&[("a", "1\n"), ("b", "2\n")]));
+        assert_eq!(staged(&repo, "a"), "2\n");
+        assert_eq!(staged(&repo, "b"), "2\n");
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_amend() {
+        let mut repo = repo_with_files("amend", &[("a", "1\n")]);
+        let initial = repo.refs.read_head().unwrap();
+        // A merge by someone else, so that what the amend keeps isn't what a new commit would get anyway.
+        let someone = Author {
+            name: "Someone Else".to_string(),
+            email: "someone@example.com".to_string(),
+            timestamp: 1_000_000_000,
+            timezone: "+0200".to_string(),
+        };
+        let tree = *repo.database.load_commit(&initial).get_tree();
+        let side = Commit::new(
+            tree,
+            vec![],
+            someone.clone(),
+            someone.clone(),
+            "side\n".to_string(),
+        );
+        repo.database.store(&side);
+        let merge = Commit::new(
+            tree,
+            vec![initial, *side.get_oid()],
+            someone.clone(),
+            someone.clone(),
+            "Merge side\n\nDetails\n".to_string(),
+        );
+        repo.database.store(&merge);
+        repo.refs.update_head_logged(merge.get_oid(), "merge side");
+
+        let paths = write_files(&repo, &[("a", "2\n")]);
+        stage_paths(&mut repo, &paths).unwrap();
+        commit(&mut repo, &["--amend", "--no-edit"]);
+        let head = repo.refs.read_head().unwrap();
+        let amended = repo.database.load_commit(&head);
+        assert_ne!(head, *merge.get_oid());
+        assert_eq!(amended.get_parents(), merge.get_parents());
+        assert_eq!(*amended.get_author(), someone);
+        assert_ne!(*amended.get_committer(), someone);
+        assert_eq!(amended.get_message(), "Merge side\n\nDetails\n");
+        assert_eq!(committed(&repo), pairs(&[("a", "2\n")]));
+        let reflog = repo.refs.read_reflog("HEAD");
+        let last = reflog.last().unwrap();
+        assert_eq!(
+            (last.old, last.new, last.message.as_str()),
+            (Some(*merge.get_oid()), head, "commit (amend): Merge side")
+        );
+
+        // A new message replaces the old one, but the parents and author still stay.
+        commit(&mut repo, &["--amend", "-m", "Reworded"]);
+        let amended = repo.database.load_commit(&repo.refs.read_head().unwrap());
+        assert_eq!(amended.get_parents(), merge.get_parents());
+        assert_eq!(*amended.get_author(), someone);
+        assert_eq!(amended.get_message(), "Reworded\n");
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_cleanup_message() {
+        let message = "\n\nSubject  \n\n\n\nBody\t\n# comment\n\n";
+        assert_eq!(
+            cleanup_message(message, Cleanup::Strip, true),
+            "Subject\n\nBody\n"
+        );
+        assert_eq!(
+            cleanup_message(message, Cleanup::Whitespace, true),
+            "Subject\n\nBody\n# comment\n"
+        );
+        assert_eq!(cleanup_message(message, Cleanup::Verbatim, true), message);
+        assert_eq!(cleanup_message("# only\n", Cleanup::Strip, true), "");
+
+        let edited = format!("Subject\n# kept\n{SCISSORS}\n# Do not modify\nignored\n");
+        assert_eq!(
+            cleanup_message(&edited, Cleanup::Scissors, true),
+            "Subject\n# kept\n"
+        );
+        assert!(cleanup_message(&edited, Cleanup::Scissors, false).contains("ignored"));
+    }
+}
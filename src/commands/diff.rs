@@ -0,0 +1,172 @@
+use crate::attributes::Attributes;
+use crate::diff::{FilePair, OriginKind};
+use crate::diffstat::{self, FileStat, StatOptions};
+use crate::fatal::fatal;
+use crate::pathspec;
+use crate::rename::{self, RenameOptions};
+use crate::repository::Repository;
+use std::io::{self, Write};
+use std::path::Path;
+
+// The output modes that were asked for. If none are given, we default to showing the patch.
+#[derive(Debug, Default, Clone)]
+pub struct DiffFormat {
+    pub patch: bool,
+    pub stat: Option<StatOptions>,
+    pub numstat: bool,
+    pub shortstat: bool,
+    pub summary: bool,
+    pub name_only: bool,
+    pub name_status: bool,
+    // Treat all files as text (--text).
+    pub text: bool,
+    // Write applicable patches for binary files (--binary).
+    pub binary: bool,
+    pub renames: RenameOptions,
+}
+
+impl DiffFormat {
+    // Consumes the argument if it's one of the output format options and returns whether it was.
+    pub fn parse_arg(&mut self, arg: &str) -> bool {
+        match arg {
+            "-p" | "-u" | "--patch" => self.patch = true,
+            "--stat" => self.stat = Some(StatOptions::default()),
+            "--numstat" => self.numstat = true,
+            "--shortstat" => self.shortstat = true,
+            "--summary" => self.summary = true,
+            "--name-only" => self.name_only = true,
+            "--name-status" => self.name_status = true,
+            "-a" | "--text" => self.text = true,
+            "--binary" => self.binary = true,
+            _ if self.renames.parse_arg(arg) => {}
+            _ => match arg.strip_prefix("--stat=") {
+                Some(value) => self.stat = Some(StatOptions::parse(value)),
+                None => return false,
+            },
+        }
+        true
+    }
+
+    pub fn patch_only(&self) -> bool {
+        self.stat.is_none()
+            && !self.numstat
+            && !self.shortstat
+            && !self.summary
+            && !self.name_only
+            && !self.name_status
+    }
+
+    pub fn shows_patch(&self) -> bool {
+        self.patch || self.patch_only()
+    }
+
+    // Runs rename detection and applies attributes to the given changes, then writes them out in every requested format.
+    pub fn write(
+        &self,
+        out: &mut impl Write,
+        pairs: Vec<FilePair>,
+        attributes: &Attributes,
+    ) -> io::Result<()> {
+        let mut pairs = rename::detect(pairs, &self.renames);
+        for pair in pairs.iter_mut() {
+            pair.apply_attributes(attributes);
+            if self.text {
+                pair.forced_binary = Some(false);
+            }
+        }
+
+        if self.name_only {
+            for pair in &pairs {
+                writeln!(out, "{}", pair.path.display())?;
+            }
+        }
+        if self.name_status {
+            write_name_status(out, &pairs)?;
+        }
+        let stats: Vec<FileStat> = pairs.iter().map(FileStat::from).collect();
+        if self.numstat {
+            diffstat::write_numstat(out, &stats)?;
+        }
+        if let Some(options) = &self.stat {
+            diffstat::write_stat(out, &stats, options)?;
+        } else if self.shortstat {
+            diffstat::write_shortstat(out, &stats)?;
+        }
+        if self.summary {
+            diffstat::write_summary(out, &pairs)?;
+        }
+        if self.shows_patch() {
+            // Separate the stats from the patch with a blank line like git does.
+            if !self.patch_only() && !pairs.is_empty() {
+                writeln!(out)?;
+            }
+            for pair in &pairs {
+                pair.write_patch(out, self.binary)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Prints one line per file with its status letter, e.g. "M\tfile" or "R097\told\tnew".
+fn write_name_status(out: &mut impl Write, pairs: &[FilePair]) -> io::Result<()> {
+    for pair in pairs {
+        let path = pair.path.display();
+        match (&pair.origin, pair.similarity_percent()) {
+            (Some(origin), Some(percent)) => {
+                let letter = match origin.kind {
+                    OriginKind::Rename => 'R',
+                    OriginKind::Copy => 'C',
+                };
+                writeln!(
+                    out,
+                    "{letter}{percent:03}\t{}\t{path}",
+                    origin.path.display()
+                )?;
+            }
+            _ => {
+                let letter = match (&pair.a, &pair.b) {
+                    (None, _) => 'A',
+                    (_, None) => 'D',
+                    _ => 'M',
+                };
+                writeln!(out, "{letter}\t{path}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run(repo: &Repository, args: &[String]) -> io::Result<()> {
+    let mut format = DiffFormat::default();
+    let mut cached = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cached" | "--staged" => cached = true,
+            "--" => {
+                paths.extend(args.by_ref().cloned());
+                break;
+            }
+            _ if format.parse_arg(arg) => {}
+            // Paths can be given without "--", as long as they exist (or have wildcards or magic).
+            _ if !arg.starts_with('-')
+                && (Path::new(arg).exists() || pathspec::looks_like_pathspec(arg)) =>
+            {
+                paths.push(arg.clone())
+            }
+            _ => fatal(&format!("unrecognized argument: {arg}")),
+        }
+    }
+
+    let mut pairs = if cached {
+        repo.head_to_index()
+    } else {
+        repo.require_workspace();
+        repo.index_to_workspace()
+    };
+    let pathspec = repo.pathspec(&paths);
+    pairs.retain(|pair| pathspec.matches(&pair.path));
+    format.write(&mut io::stdout().lock(), pairs, &repo.attributes)
+}
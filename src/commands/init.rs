@@ -0,0 +1,266 @@
+use crate::config::Config;
+use crate::fatal::fatal;
+use crate::refs::{self, Refs};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use walkdir::WalkDir;
+
+const USAGE: &str = "usage: git init [-q | --quiet] [--bare] [--template=<template-directory>]
+         [--object-format=<format>]
+         [-b <branch-name> | --initial-branch=<branch-name>] [<directory>]
+";
+
+// What a new repository gets when there's no template directory: the same info/exclude that git's own template has.
+const DEFAULT_EXCLUDE: &str = "# git ls-files --others --exclude-from=.git/info/exclude
+# Lines that start with '#' are comments.
+# For a project mostly in C, the following would be a good set of
+# exclude patterns (uncomment them if you want to use them):
+# *.[oa]
+# *~
+";
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut quiet = false;
+    let mut bare = false;
+    let mut template = None;
+    let mut object_format = None;
+    let mut initial_branch = None;
+    let mut directories = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            "--bare" => bare = true,
+            "--template" => template = Some(required_value(args.next(), "template")),
+            "--object-format" => object_format = Some(required_value(args.next(), "object-format")),
+            "-b" | "--initial-branch" => {
+                initial_branch = Some(required_value(args.next(), "initial-branch"))
+            }
+            _ if arg.starts_with("--template=") => {
+                template = Some(arg["--template=".len()..].to_string())
+            }
+            _ if arg.starts_with("--object-format=") => {
+                object_format = Some(arg["--object-format=".len()..].to_string())
+            }
+            _ if arg.starts_with("--initial-branch=") => {
+                initial_branch = Some(arg["--initial-branch=".len()..].to_string())
+            }
+            _ if arg.starts_with("-b") => initial_branch = Some(arg[2..].to_string()),
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => directories.push(arg),
+        }
+    }
+    if directories.len() > 1 {
+        eprint!("{USAGE}");
+        std::process::exit(129);
+    }
+    let cwd = env::current_dir().expect("failed to get cwd");
+    let dir = match directories.first() {
+        Some(dir) => {
+            let dir = cwd.join(dir);
+            fs::create_dir_all(&dir)
+                .unwrap_or_else(|e| fatal(&format!("cannot mkdir {}: {e}", dir.display())));
+            dir
+        }
+        None => cwd.clone(),
+    };
+    // Objects are named by their SHA-1 hashes, so that's the only format we can make repositories for.
+    match object_format.as_deref() {
+        None | Some("sha1") => {}
+        Some("sha256") => fatal("the sha256 object format is not supported"),
+        Some(format) => fatal(&format!("unknown hash algorithm '{format}'")),
+    }
+
+    // A bare repository is the directory itself. Otherwise it's the .git directory inside it, unless $GIT_DIR says where.
+    let git_path = match env::var_os("GIT_DIR").filter(|dir| !dir.is_empty()) {
+        _ if bare => dir,
+        Some(git_dir) => cwd.join(git_dir),
+        None => dir.join(".git"),
+    };
+    let reinit = git_path.join("HEAD").exists();
+    fs::create_dir_all(&git_path)?;
+    let git_path = git_path.canonicalize()?;
+    let config = Config::load(&git_path);
+
+    // Like git, the template comes from --template, $GIT_TEMPLATE_DIR or init.templateDir (where an empty one means no
+    // template), and it never overwrites files that are already there.
+    let template = template
+        .or_else(|| env::var("GIT_TEMPLATE_DIR").ok())
+        .or_else(|| config.get("init.templateDir").map(str::to_string));
+    match template {
+        Some(template) if !template.is_empty() => copy_template(Path::new(&template), &git_path)?,
+        Some(_) => {}
+        None => write_new(&git_path.join("info/exclude"), DEFAULT_EXCLUDE)?,
+    }
+    for dir in ["refs/heads", "refs/tags"] {
+        fs::create_dir_all(git_path.join(dir))?;
+    }
+
+    // A new repository starts out on the branch given by --initial-branch or init.defaultBranch, or else master.
+    if reinit {
+        if let Some(branch) = initial_branch {
+            eprintln!("warning: re-init: ignored --initial-branch={branch}");
+        }
+    } else {
+        let branch =
+            match initial_branch.or_else(|| config.get("init.defaultBranch").map(str::to_string)) {
+                Some(branch) => branch,
+                None => {
+                    if !quiet {
+                        print_default_branch_hint();
+                    }
+                    "master".to_string()
+                }
+            };
+        if !refs::is_valid_branch_name(&branch) {
+            fatal(&format!("invalid initial branch name: '{branch}'"));
+        }
+        Refs::new(git_path.clone()).set_head_branch(&branch);
+    }
+    for dir in ["objects/info", "objects/pack"] {
+        fs::create_dir_all(git_path.join(dir))?;
+    }
+
+    let mut core = "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n".to_string();
+    core.push_str(&format!("\tbare = {bare}\n"));
+    if !bare {
+        core.push_str("\tlogallrefupdates = true\n");
+    }
+    write_new(&git_path.join("config"), &core)?;
+
+    if !quiet {
+        let state = if reinit {
+            "Reinitialized existing"
+        } else {
+            "Initialized empty"
+        };
+        println!("{state} Git repository in {}/", git_path.display());
+    }
+    Ok(())
+}
+
+// Copies everything in a template directory into the new repository, leaving alone any files it already has.
+fn copy_template(template: &Path, git_path: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(template).min_depth(1).into_iter().flatten() {
+        let path = entry
+            .path()
+            .strip_prefix(template)
+            .expect("failed to strip prefix from template entry");
+        let target = git_path.join(path);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if !target.exists() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+// Writes a file (creating its directory) unless it already exists.
+fn write_new(path: &Path, contents: &str) -> io::Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)
+}
+
+fn print_default_branch_hint() {
+    for line in [
+        "Using 'master' as the name for the initial branch. This default branch name",
+        "is subject to change. To configure the initial branch name to use in all",
+        "of your new repositories, which will suppress this warning, call:",
+        "",
+        "\tgit config --global init.defaultBranch <name>",
+        "",
+        "Names commonly chosen instead of 'master' are 'main', 'trunk' and",
+        "'development'. The just-created branch can be renamed via this command:",
+        "",
+        "\tgit branch -m <name>",
+    ] {
+        eprintln!("hint: {line}");
+    }
+}
+
+fn required_value(value: Option<&String>, option: &str) -> String {
+    match value {
+        Some(value) => value.clone(),
+        None => {
+            eprintln!("error: option `{option}' requires a value");
+            std::process::exit(129);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Runs init in a fresh directory, returning it.
+    fn init(name: &str, args: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("init-{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        init_in(&dir, args);
+        dir
+    }
+
+    fn init_in(dir: &Path, args: &[&str]) {
+        let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.extend(["-q".to_string(), dir.display().to_string()]);
+        run(&args).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_init_layout() {
+        let dir = init("layout", &["-b", "main"]);
+        let git_path = dir.join(".git");
+        assert_eq!(read(&git_path.join("HEAD")), "ref: refs/heads/main\n");
+        for subdir in ["objects/info", "objects/pack", "refs/heads", "refs/tags"] {
+            assert!(git_path.join(subdir).is_dir(), "{subdir} is missing");
+        }
+        assert_eq!(read(&git_path.join("info/exclude")), DEFAULT_EXCLUDE);
+        let config = read(&git_path.join("config"));
+        assert!(config.contains("\tbare = false\n"));
+        assert!(config.contains("\tlogallrefupdates = true\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_init_bare() {
+        let dir = init("bare", &["--bare", "--initial-branch=trunk"]);
+        // The repository is the directory itself, with no workspace.
+        assert!(!dir.join(".git").exists());
+        assert_eq!(read(&dir.join("HEAD")), "ref: refs/heads/trunk\n");
+        assert!(dir.join("objects/pack").is_dir());
+        let config = read(&dir.join("config"));
+        assert!(config.contains("\tbare = true\n"));
+        assert!(!config.contains("logallrefupdates"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reinit() {
+        let dir = init("reinit", &["-b", "main"]);
+        let git_path = dir.join(".git");
+        fs::write(git_path.join("config"), "[user]\n\tname = Someone\n").unwrap();
+        fs::write(git_path.join("info/exclude"), "*.o\n").unwrap();
+        fs::write(git_path.join("refs/heads/main"), "not touched\n").unwrap();
+
+        // Running init again keeps what's there, including the branch HEAD is on.
+        init_in(&dir, &["-b", "other"]);
+        assert_eq!(read(&git_path.join("HEAD")), "ref: refs/heads/main\n");
+        assert_eq!(read(&git_path.join("config")), "[user]\n\tname = Someone\n");
+        assert_eq!(read(&git_path.join("info/exclude")), "*.o\n");
+        assert_eq!(read(&git_path.join("refs/heads/main")), "not touched\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
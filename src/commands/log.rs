@@ -0,0 +1,166 @@
+use crate::commands::diff::DiffFormat;
+use crate::commit::Commit;
+use crate::diff::{self, FilePair};
+use crate::fatal::fatal;
+use crate::graph::Graph;
+use crate::object::Object;
+use crate::pathspec;
+use crate::pretty::{Pretty, PrettyOptions};
+use crate::rename::{self, RenameOptions};
+use crate::repository::Repository;
+use crate::rev_list::{RevList, RevListOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+pub fn run(repo: &Repository, args: &[String]) -> io::Result<()> {
+    let mut options = RevListOptions::default();
+    let mut pretty_options = PrettyOptions::default();
+    // Commits are shown without their changes unless one of the diff output formats is asked for.
+    let mut format = DiffFormat::default();
+    let mut show_diff = false;
+    let mut revisions = Vec::new();
+    let mut graph = false;
+    let mut all = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                paths.extend(args.by_ref().cloned());
+                break;
+            }
+            "--graph" => graph = true,
+            "--all" => all = true,
+            _ if options.parse_arg(arg, &mut args) => {}
+            _ if pretty_options.parse_arg(arg) => {}
+            _ if format.renames.parse_arg(arg) => {}
+            _ if format.parse_arg(arg) => show_diff = true,
+            _ if arg.starts_with('-') && !arg.contains("..") => {
+                fatal(&format!("unrecognized argument: {arg}"))
+            }
+            _ => revisions.push(arg),
+        }
+    }
+    if graph {
+        if options.reverse {
+            fatal("options '--reverse' and '--graph' cannot be used together");
+        }
+        // The graph needs every child drawn before its parents.
+        options.topo_order = true;
+    }
+
+    let follow = options.follow;
+    let first_parent = options.first_parent;
+    let mut rev_list = RevList::new(repo, options);
+    let mut has_revisions = false;
+    if all {
+        // Every ref (with tags peeled to what they tag) and then HEAD, like git.
+        let refs = repo.refs.list_refs().into_iter().map(|(_, oid)| oid);
+        for oid in refs.chain(repo.refs.read_head()) {
+            let oid = repo.database.peel(&oid);
+            if repo.database.read_object(&oid).0 == "commit" {
+                rev_list.include(oid);
+            }
+        }
+        has_revisions = true;
+    }
+    for arg in revisions {
+        // Anything that isn't a revision can be a path, as long as it exists (or has wildcards or magic) and comes after all
+        // the revisions.
+        if paths.is_empty() && rev_list.add_arg(arg) {
+            has_revisions = true;
+        } else if Path::new(arg).exists() || pathspec::looks_like_pathspec(arg) {
+            paths.push(arg.clone());
+        } else {
+            eprintln!("fatal: ambiguous argument '{arg}': unknown revision or path not in the working tree.");
+            eprintln!("Use '--' to separate paths from revisions, like this:");
+            eprintln!("'git <command> [<revision>...] -- [<file>...]'");
+            std::process::exit(128);
+        }
+    }
+    if follow && paths.len() != 1 {
+        fatal("--follow requires exactly one pathspec");
+    }
+    rev_list.set_pathspec(repo.pathspec(&paths));
+    if !has_revisions {
+        match repo.refs.read_head() {
+            Some(head) => rev_list.include(head),
+            None => {
+                let branch = repo.refs.current_branch().unwrap_or("HEAD".to_string());
+                fatal(&format!(
+                    "your current branch '{branch}' does not have any commits yet"
+                ));
+            }
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    let mut graph = graph.then(|| Graph::new(pretty_options.color));
+    let mut pretty = Pretty::new(repo, pretty_options);
+    for commit in rev_list.walk() {
+        if let Some(graph) = graph.as_mut() {
+            graph.update(*commit.get_oid(), rev_list.graph_parents(&commit));
+        }
+        pretty.write(&mut out, &commit, graph.as_mut())?;
+        if show_diff && (first_parent || commit.get_parents().len() <= 1) {
+            let pairs = changed_pairs(repo, &rev_list, &commit);
+            write_diff(&mut out, repo, &pretty, &format, pairs, graph.as_mut())?;
+        }
+    }
+    Ok(())
+}
+
+// The changes a commit made to the paths being logged, compared to its first parent. When following a file, that's just the
+// change to the file, which is shown as a rename where it was renamed.
+fn changed_pairs(repo: &Repository, rev_list: &RevList, commit: &Commit) -> Vec<FilePair> {
+    let database = &repo.database;
+    let parent_tree = commit
+        .get_parents()
+        .first()
+        .map(|parent| *database.load_commit(parent).get_tree());
+    let changes = database.tree_diff(parent_tree.as_ref(), Some(commit.get_tree()));
+    let pairs = diff::pairs_from_tree_diff(database, &changes);
+    match rev_list.followed_path(commit.get_oid()) {
+        Some(path) => rename::detect(pairs, &RenameOptions::default())
+            .into_iter()
+            .filter(|pair| pair.path == path)
+            .collect(),
+        None => pairs
+            .into_iter()
+            .filter(|pair| rev_list.pathspec().matches(&pair.path))
+            .collect(),
+    }
+}
+
+// Writes a commit's changes after its message, continuing the graph (if any) in front of every line.
+fn write_diff(
+    out: &mut impl Write,
+    repo: &Repository,
+    pretty: &Pretty,
+    format: &DiffFormat,
+    pairs: Vec<FilePair>,
+    graph: Option<&mut Graph>,
+) -> io::Result<()> {
+    if pairs.is_empty() {
+        return Ok(());
+    }
+    let mut diff = Vec::new();
+    if !pretty.is_oneline() && !pretty.is_empty() {
+        // Like in `show`, a "---" line separates the message from the stat when a patch follows.
+        if format.stat.is_some() && format.shows_patch() {
+            write!(diff, "---")?;
+        }
+        writeln!(diff)?;
+    }
+    format.write(&mut diff, pairs, &repo.attributes)?;
+    match graph {
+        Some(graph) => {
+            for line in diff.split_inclusive(|byte| *byte == b'\n') {
+                graph.show_padding(out)?;
+                out.write_all(line)?;
+            }
+            Ok(())
+        }
+        None => out.write_all(&diff),
+    }
+}
@@ -0,0 +1,164 @@
+use crate::fatal::fatal;
+use crate::index::{IndexEntry, IndexMetadata};
+use crate::pathspec::relative_path;
+use crate::repository::Repository;
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default)]
+struct Options {
+    cached: bool,
+    deleted: bool,
+    modified: bool,
+    others: bool,
+    ignored: bool,
+    stage: bool,
+    exclude_standard: bool,
+    full_name: bool,
+    nul_terminated: bool,
+}
+
+pub fn run(repo: &Repository, args: &[String]) -> io::Result<()> {
+    let mut options = Options::default();
+    let mut error_unmatch = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "--cached" => options.cached = true,
+            "-d" | "--deleted" => options.deleted = true,
+            "-m" | "--modified" => options.modified = true,
+            "-o" | "--others" => options.others = true,
+            "-i" | "--ignored" => options.ignored = true,
+            "-s" | "--stage" => options.stage = true,
+            "--exclude-standard" => options.exclude_standard = true,
+            "--full-name" => options.full_name = true,
+            "-z" => options.nul_terminated = true,
+            "--error-unmatch" => error_unmatch = true,
+            "--" => {
+                paths.extend(args.by_ref().cloned());
+                break;
+            }
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if options.ignored && !options.others && !options.cached {
+        fatal("ls-files -i must be used with either -o or -c");
+    }
+    // Like git, the files in the index are listed unless we're asked for something else.
+    if !(options.stage || options.deleted || options.others || options.modified) {
+        options.cached = true;
+    }
+    if options.ignored && !options.exclude_standard {
+        fatal("ls-files --ignored needs some exclude pattern");
+    }
+    if options.others || options.deleted || options.modified {
+        repo.require_workspace();
+    }
+
+    // Without a pathspec (or with one that only excludes things), only the files under the current directory are listed.
+    let mut pathspec = repo.pathspec(&paths);
+    if !pathspec.has_patterns() {
+        paths.push(".".to_string());
+        pathspec = repo.pathspec(&paths);
+    }
+    let mut out = io::stdout().lock();
+    let mut listed = BTreeSet::new();
+
+    if options.others {
+        let files = if options.exclude_standard && !options.ignored {
+            repo.workspace.list_files(repo.workspace.root())
+        } else {
+            repo.workspace.list_all_files(repo.workspace.root())
+        };
+        // Like the index entries below, untracked files are listed in the byte order of their paths, as git sorts them.
+        let mut files = files.expect("Could not list workspace files");
+        files.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
+        for path in files {
+            if repo.index.is_tracked_file(&path) || !pathspec.matches(&path) {
+                continue;
+            }
+            if options.ignored && !repo.workspace.is_ignored(&path) {
+                continue;
+            }
+            write_path(&mut out, repo, &options, &path)?;
+            listed.insert(path);
+        }
+    }
+
+    for entry in repo.index.entries() {
+        let path = entry.get_path();
+        if !pathspec.matches(path) {
+            continue;
+        }
+        if options.ignored && !repo.workspace.is_ignored(path) {
+            continue;
+        }
+        listed.insert(path.to_path_buf());
+        if options.cached || options.stage {
+            write_entry(&mut out, repo, &options, entry)?;
+        }
+        if !(options.deleted || options.modified) {
+            continue;
+        }
+        let stat = repo.workspace.stat_file(path).ok();
+        if options.deleted && stat.is_none() {
+            write_entry(&mut out, repo, &options, entry)?;
+        }
+        let modified = match stat {
+            Some(stat) => !repo.is_unchanged(entry, &IndexMetadata::from(stat)),
+            None => true,
+        };
+        if options.modified && modified {
+            write_entry(&mut out, repo, &options, entry)?;
+        }
+    }
+    out.flush()?;
+
+    if error_unmatch {
+        let unmatched = pathspec.unmatched(listed.iter().map(PathBuf::as_path));
+        if !unmatched.is_empty() {
+            for pattern in unmatched {
+                eprintln!("error: pathspec '{pattern}' did not match any file(s) known to git");
+            }
+            eprintln!("Did you forget to 'git add'?");
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn write_entry(
+    out: &mut impl Write,
+    repo: &Repository,
+    options: &Options,
+    entry: &IndexEntry,
+) -> io::Result<()> {
+    if options.stage {
+        write!(
+            out,
+            "{:o} {} {}\t",
+            entry.get_mode(),
+            entry.get_oid(),
+            entry.get_stage()
+        )?;
+    }
+    write_path(out, repo, options, entry.get_path())
+}
+
+fn write_path(
+    out: &mut impl Write,
+    repo: &Repository,
+    options: &Options,
+    path: &Path,
+) -> io::Result<()> {
+    let path = if options.full_name {
+        path.to_path_buf()
+    } else {
+        relative_path(path, &repo.prefix)
+    };
+    let terminator = if options.nul_terminated { '\0' } else { '\n' };
+    write!(out, "{}{terminator}", path.display())
+}
@@ -0,0 +1,253 @@
+use crate::commands::commit;
+use crate::commands::diff::DiffFormat;
+use crate::commit::{Author, Commit};
+use crate::diff;
+use crate::diff3::ConflictStyle;
+use crate::diffstat::StatOptions;
+use crate::fatal::fatal;
+use crate::merge::{self, Merge};
+use crate::merge_base::MergeBase;
+use crate::migration::Migration;
+use crate::object::Object;
+use crate::repository::Repository;
+use crate::revision;
+use sha1_smol::Digest;
+use std::fs;
+use std::io;
+
+// Which merges may (or must) be done by just moving the branch forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FastForward {
+    Allow,
+    Never,
+    Only,
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut fast_forward = FastForward::Allow;
+    let mut message = None;
+    let mut allow_unrelated = false;
+    let mut resume = false;
+    let mut revisions = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ff" => fast_forward = FastForward::Allow,
+            "--no-ff" => fast_forward = FastForward::Never,
+            "--ff-only" => fast_forward = FastForward::Only,
+            "--allow-unrelated-histories" => allow_unrelated = true,
+            "--continue" => resume = true,
+            "-m" | "--message" => match args.next() {
+                Some(value) => message = Some(value.clone()),
+                None => {
+                    match arg.as_str() {
+                        "-m" => eprintln!("error: switch `m' requires a value"),
+                        _ => eprintln!("error: option `message' requires a value"),
+                    }
+                    std::process::exit(129);
+                }
+            },
+            _ if arg.starts_with("--message=") => {
+                message = Some(arg["--message=".len()..].to_string())
+            }
+            _ if arg.starts_with("-m") => message = Some(arg[2..].to_string()),
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => revisions.push(arg.clone()),
+        }
+    }
+
+    let merge_head = repo.git_path.join("MERGE_HEAD");
+    // Continuing a merge whose conflicts have been resolved just commits it.
+    if resume {
+        if !merge_head.exists() {
+            fatal("There is no merge in progress (MERGE_HEAD missing).");
+        }
+        return commit::run(repo, &[]);
+    }
+    if repo.index.has_conflicts() {
+        eprintln!("error: Merging is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'git add/rm <file>'");
+        eprintln!("hint: as appropriate to mark resolution and make a commit.");
+        fatal("Exiting because of an unresolved conflict.");
+    }
+    if merge_head.exists() {
+        fatal("You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge.");
+    }
+    if repo.git_path.join("CHERRY_PICK_HEAD").exists() {
+        fatal("You have not concluded your cherry-pick (CHERRY_PICK_HEAD exists).\nPlease, commit your changes before you merge.");
+    }
+    let Some(name) = revisions.first() else {
+        fatal("No remote for the current branch.");
+    };
+    let Some(theirs) = revision::resolve(repo, name) else {
+        eprintln!("merge: {name} - not something we can merge");
+        std::process::exit(1);
+    };
+
+    // With no commits yet, the branch simply starts out at the merged commit.
+    let Some(head) = repo.refs.read_head() else {
+        let tree = *repo.database.load_commit(&theirs).get_tree();
+        let diff = repo.database.tree_diff(None, Some(&tree));
+        apply(repo, Migration::new(repo, diff), false)?;
+        repo.refs.update_head_logged(&theirs, "initial pull");
+        return Ok(());
+    };
+
+    repo.refs.set_orig_head(&head);
+    let bases = MergeBase::new(repo).find(head, &[theirs]);
+    if bases.contains(&theirs) {
+        println!("Already up to date.");
+        return Ok(());
+    }
+    if bases == [head] && fast_forward != FastForward::Never {
+        return fast_forward_to(repo, head, theirs, name);
+    }
+    if fast_forward == FastForward::Only {
+        fatal("Not possible to fast-forward, aborting.");
+    }
+    if bases.is_empty() && !allow_unrelated {
+        fatal("refusing to merge unrelated histories");
+    }
+
+    // The merge works from the committed trees, so anything staged would be lost. Like git, HEAD's reflog still records
+    // the attempt.
+    let staged = repo.head_to_index();
+    if !staged.is_empty() {
+        repo.refs
+            .update_head_logged(&head, &format!("merge {name}: updating HEAD"));
+        eprintln!(
+            "error: Your local changes to the following files would be overwritten by merge:"
+        );
+        for pair in staged {
+            eprintln!("  {}", pair.path.display());
+        }
+        eprintln!("Merge with strategy ort failed.");
+        std::process::exit(2);
+    }
+
+    let style = repo
+        .config
+        .get("merge.conflictStyle")
+        .and_then(ConflictStyle::parse)
+        .unwrap_or_default();
+    let outcome = Merge::new(repo, style).merge_commits(&bases, head, theirs, "HEAD", name);
+    let head_tree = *repo.database.load_commit(&head).get_tree();
+    let merged_tree = merge::store_tree(repo, outcome.tree.clone());
+    let diff = repo
+        .database
+        .tree_diff(Some(&head_tree), Some(&merged_tree));
+    apply(repo, Migration::new(repo, diff), false)?;
+    for message in outcome.messages() {
+        println!("{message}");
+    }
+
+    let message = message.map_or_else(
+        || merge_message(repo, name),
+        |message| format!("{message}\n"),
+    );
+    if !outcome.conflicts.is_empty() {
+        merge::stage_conflicts(repo, &outcome);
+        fs::write(&merge_head, format!("{theirs}\n"))?;
+        let mode = if fast_forward == FastForward::Never {
+            "no-ff"
+        } else {
+            ""
+        };
+        fs::write(repo.git_path.join("MERGE_MODE"), mode)?;
+        fs::write(
+            repo.git_path.join("MERGE_MSG"),
+            format!("{message}\n{}", outcome.conflicts_comment()),
+        )?;
+        println!("Automatic merge failed; fix conflicts and then commit the result.");
+        std::process::exit(1);
+    }
+
+    let commit = Commit::new(
+        merged_tree,
+        vec![head, theirs],
+        Author::now(),
+        Author::now(),
+        message,
+    );
+    repo.database.store(&commit);
+    repo.refs.update_head_logged(
+        commit.get_oid(),
+        &format!("merge {name}: Merge made by the 'ort' strategy."),
+    );
+    println!("Merge made by the 'ort' strategy.");
+    print_diffstat(repo, head_tree, merged_tree);
+    Ok(())
+}
+
+// Moves the branch forward to a descendant of HEAD, bringing the workspace and index along.
+fn fast_forward_to(
+    repo: &mut Repository,
+    head: Digest,
+    theirs: Digest,
+    name: &str,
+) -> io::Result<()> {
+    println!(
+        "Updating {}..{}",
+        repo.database.abbreviate(&head),
+        repo.database.abbreviate(&theirs)
+    );
+    let head_tree = *repo.database.load_commit(&head).get_tree();
+    let theirs_tree = *repo.database.load_commit(&theirs).get_tree();
+    let diff = repo
+        .database
+        .tree_diff(Some(&head_tree), Some(&theirs_tree));
+    apply(repo, Migration::new(repo, diff), true)?;
+    println!("Fast-forward");
+    repo.refs
+        .update_head_logged(&theirs, &format!("merge {name}: Fast-forward"));
+    print_diffstat(repo, head_tree, theirs_tree);
+    Ok(())
+}
+
+// Updates the workspace and index, or exits without touching anything if that would lose local changes.
+fn apply(repo: &mut Repository, migration: Migration, fast_forward: bool) -> io::Result<()> {
+    let errors = migration.errors("merge");
+    if !errors.is_empty() {
+        for error in errors {
+            eprint!("error: {error}");
+        }
+        eprintln!("Aborting");
+        if !fast_forward {
+            eprintln!("Merge with strategy ort failed.");
+            std::process::exit(2);
+        }
+        std::process::exit(1);
+    }
+    migration.apply(repo)
+}
+
+// The default message of a merge commit, e.g. "Merge branch 'topic' into next". Merges into master or main leave out which
+// branch they were made on.
+fn merge_message(repo: &Repository, name: &str) -> String {
+    let what = if repo.refs.read_ref(&format!("refs/heads/{name}")).is_some() {
+        "branch"
+    } else if repo.refs.read_ref(&format!("refs/tags/{name}")).is_some() {
+        "tag"
+    } else {
+        "commit"
+    };
+    let into = match repo.refs.current_branch() {
+        Some(branch) if branch == "master" || branch == "main" => String::new(),
+        Some(branch) => format!(" into {branch}"),
+        None => " into HEAD".to_string(),
+    };
+    format!("Merge {what} '{name}'{into}\n")
+}
+
+fn print_diffstat(repo: &Repository, old: Digest, new: Digest) {
+    let changes = repo.database.tree_diff(Some(&old), Some(&new));
+    let pairs = diff::pairs_from_tree_diff(&repo.database, &changes);
+    let format = DiffFormat {
+        stat: Some(StatOptions::default()),
+        summary: true,
+        ..Default::default()
+    };
+    format
+        .write(&mut io::stdout().lock(), pairs, &repo.attributes)
+        .expect("Could not write the diffstat");
+}
@@ -0,0 +1,88 @@
+use crate::fatal::fatal;
+use crate::merge_base::MergeBase;
+use crate::repository::Repository;
+use crate::revision;
+use sha1_smol::Digest;
+use std::io;
+
+const USAGE: &str = "usage: git merge-base [-a | --all] <commit> <commit>...
+   or: git merge-base [-a | --all] --octopus <commit>...
+   or: git merge-base --is-ancestor <commit> <commit>
+
+    -a, --all             output all common ancestors
+    --octopus             find ancestors for a single n-way merge
+    --is-ancestor         is the first one ancestor of the other?
+";
+
+pub fn run(repo: &Repository, args: &[String]) -> io::Result<()> {
+    let mut all = false;
+    let mut octopus = false;
+    let mut is_ancestor = false;
+    let mut names = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-a" | "--all" => all = true,
+            "--octopus" => octopus = true,
+            "--is-ancestor" => is_ancestor = true,
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => names.push(arg.as_str()),
+        }
+    }
+    if is_ancestor && octopus {
+        eprintln!("error: option `octopus' is incompatible with --is-ancestor");
+        std::process::exit(129);
+    }
+    if is_ancestor && all {
+        fatal("options '--is-ancestor' and '--all' cannot be used together");
+    }
+
+    let mut merge_base = MergeBase::new(repo);
+    if is_ancestor {
+        let [ancestor, descendant] = names[..] else {
+            usage();
+        };
+        // Only the exit status tells whether it is one.
+        if !merge_base.is_ancestor(resolve(repo, ancestor), resolve(repo, descendant)) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let bases = if octopus {
+        let commits: Vec<Digest> = names.iter().map(|name| resolve(repo, name)).collect();
+        merge_base.octopus(&commits)
+    } else {
+        if names.len() < 2 {
+            usage();
+        }
+        // With more than two commits, the bases are those of the first and a merge of all the others.
+        let commits: Vec<Digest> = names.iter().map(|name| resolve(repo, name)).collect();
+        merge_base.find(commits[0], &commits[1..])
+    };
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+    let shown = if all { bases.len() } else { 1 };
+    for base in &bases[..shown] {
+        println!("{base}");
+    }
+    Ok(())
+}
+
+fn resolve(repo: &Repository, name: &str) -> Digest {
+    let Some(oid) = revision::resolve_object(repo, name) else {
+        fatal(&format!("Not a valid object name {name}"));
+    };
+    let oid = repo.database.peel(&oid);
+    let (object_type, _) = repo.database.read_object(&oid);
+    if object_type != "commit" {
+        eprintln!("error: object {oid} is a {object_type}, not a commit");
+        fatal(&format!("Not a valid commit name {name}"));
+    }
+    oid
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(129);
+}
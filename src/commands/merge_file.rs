@@ -0,0 +1,118 @@
+use crate::diff;
+use crate::diff3::{self, ConflictStyle, Favor, Labels, MergeOptions};
+use crate::fatal::fatal;
+use std::fs;
+use std::io::{self, Write};
+
+const USAGE: &str = "usage: git merge-file [<options>] [-L <name1> [-L <orig> [-L <name2>]]] <file1> <orig-file> <file2>
+
+    -p, --stdout          send results to standard output
+    --diff3               use a diff3 based merge
+    --zdiff3              use a zealous diff3 based merge
+    --ours                for conflicts, use our version
+    --theirs              for conflicts, use their version
+    --union               for conflicts, use a union version
+    --marker-size <n>     for conflicts, use this marker size
+    -q, --quiet           do not warn about conflicts
+    -L <name>             set labels for file1/orig-file/file2
+";
+
+// Merges the changes from <orig-file> to <file2> into <file1>, which doesn't need a repository. The exit status is the
+// number of conflicts left.
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut stdout = false;
+    let mut labels = Vec::new();
+    let mut files = Vec::new();
+    let mut options = MergeOptions {
+        join_non_alnum: true,
+        ..Default::default()
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |short: &str, long: &str| match args.next() {
+            Some(value) => value.clone(),
+            None => {
+                match arg.strip_prefix("--") {
+                    Some(_) => eprintln!("error: option `{long}' requires a value"),
+                    None => eprintln!("error: switch `{short}' requires a value"),
+                }
+                std::process::exit(129);
+            }
+        };
+        match arg.as_str() {
+            "-p" | "--stdout" => stdout = true,
+            "--diff3" => options.style = ConflictStyle::Diff3,
+            "--zdiff3" => options.style = ConflictStyle::Zdiff3,
+            "--ours" => options.favor = Some(Favor::Ours),
+            "--theirs" => options.favor = Some(Favor::Theirs),
+            "--union" => options.favor = Some(Favor::Union),
+            // There are no warnings to silence.
+            "-q" | "--quiet" => {}
+            "-L" => labels.push(value("L", "L")),
+            "--marker-size" => options.marker_size = marker_size(&value("", "marker-size")),
+            _ if arg.starts_with("--marker-size=") => {
+                options.marker_size = marker_size(&arg["--marker-size=".len()..])
+            }
+            _ if arg.starts_with("-L") => labels.push(arg[2..].to_string()),
+            _ if arg.starts_with('-') && arg != "-" => {
+                fatal(&format!("unrecognized argument: {arg}"))
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+    if labels.len() > 3 {
+        eprintln!("error: too many labels on the command line");
+        std::process::exit(129);
+    }
+    let [ours_file, base_file, theirs_file] = &files[..] else {
+        eprintln!("{USAGE}");
+        std::process::exit(129);
+    };
+
+    let mut contents = Vec::new();
+    for file in [ours_file, base_file, theirs_file] {
+        let data = fs::read(file).unwrap_or_else(|e| {
+            let reason = match e.kind() {
+                io::ErrorKind::NotFound => "No such file or directory".to_string(),
+                _ => e.to_string(),
+            };
+            eprintln!("error: Could not stat {file}: {reason}");
+            std::process::exit(255);
+        });
+        if diff::is_binary(&data) {
+            eprintln!("error: Cannot merge binary files: {file}");
+            std::process::exit(255);
+        }
+        contents.push(data);
+    }
+    // Each side is labelled with its file name unless a label was given for it.
+    let label = |i: usize, file: &str| labels.get(i).cloned().unwrap_or(file.to_string());
+    let (ours, base, theirs) = (
+        label(0, ours_file),
+        label(1, base_file),
+        label(2, theirs_file),
+    );
+    let labels = Labels {
+        ours: &ours,
+        base: &base,
+        theirs: &theirs,
+    };
+    let result = diff3::merge(&contents[1], &contents[0], &contents[2], &labels, &options);
+
+    if stdout {
+        io::stdout().lock().write_all(&result.data)?;
+    } else {
+        fs::write(ours_file, &result.data)?;
+    }
+    if result.conflicts > 0 {
+        std::process::exit(result.conflicts.min(127) as i32);
+    }
+    Ok(())
+}
+
+fn marker_size(value: &str) -> usize {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("error: option `marker-size' expects a numerical value");
+        std::process::exit(129);
+    })
+}
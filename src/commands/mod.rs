@@ -0,0 +1,22 @@
+pub mod add;
+pub mod check_ignore;
+pub mod checkout;
+pub mod cherry_pick;
+pub mod commit;
+pub mod diff;
+pub mod init;
+pub mod log;
+pub mod ls_files;
+pub mod merge;
+pub mod merge_base;
+pub mod merge_file;
+pub mod rebase;
+pub mod reset;
+pub mod restore;
+pub mod revert;
+pub mod rm;
+pub mod show;
+pub mod stash;
+pub mod status;
+pub mod switch;
+pub mod tag;
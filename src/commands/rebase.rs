@@ -0,0 +1,211 @@
+use crate::commands::checkout::{self, Target};
+use crate::fatal::fatal;
+use crate::merge_base::MergeBase;
+use crate::pretty;
+use crate::rebase::{self, Plan, Rebase, Step};
+use crate::repository::Repository;
+use crate::rev_list::{RevList, RevListOptions};
+use crate::revision;
+use sha1_smol::Digest;
+use std::io;
+
+// What to do with a rebase that's already under way, instead of starting one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    Continue,
+    Skip,
+    Abort,
+    Quit,
+    EditTodo,
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut interactive = false;
+    let mut autosquash = None;
+    let mut onto_name = None;
+    let mut resume = None;
+    let mut revisions = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-i" | "--interactive" => interactive = true,
+            "--autosquash" => autosquash = Some(true),
+            "--no-autosquash" => autosquash = Some(false),
+            "--continue" => resume = Some(Resume::Continue),
+            "--skip" => resume = Some(Resume::Skip),
+            "--abort" => resume = Some(Resume::Abort),
+            "--quit" => resume = Some(Resume::Quit),
+            "--edit-todo" => resume = Some(Resume::EditTodo),
+            "--onto" => match args.next() {
+                Some(value) => onto_name = Some(value.clone()),
+                None => {
+                    eprintln!("error: option `onto' requires a value");
+                    std::process::exit(129);
+                }
+            },
+            _ if arg.starts_with("--onto=") => onto_name = Some(arg["--onto=".len()..].to_string()),
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => revisions.push(arg.clone()),
+        }
+    }
+
+    if let Some(resume) = resume {
+        if !revisions.is_empty() || interactive || onto_name.is_some() {
+            eprintln!("usage: git rebase (--continue | --skip | --abort | --quit | --edit-todo)");
+            std::process::exit(129);
+        }
+        let mut rebase = Rebase::new(repo);
+        return match resume {
+            Resume::Continue => rebase.resume(),
+            Resume::Skip => rebase.skip(),
+            Resume::Abort => rebase.abort(),
+            Resume::Quit => rebase.quit(),
+            Resume::EditTodo => rebase.edit_todo(),
+        };
+    }
+    if Rebase::in_progress(repo) {
+        fatal(
+            "It seems that there is already a rebase-merge directory, and
+I wonder if you are in the middle of another rebase.  If that is the
+case, please try
+\tgit rebase (--continue | --abort | --skip)
+If that is not the case, please
+\trm -fr \".git/rebase-merge\"
+and run me again.  I am stopping in case you still have something
+valuable there.
+",
+        );
+    }
+    if revisions.len() > 2 {
+        eprintln!("usage: git rebase [-i] [--onto <newbase>] [<upstream> [<branch>]]");
+        std::process::exit(129);
+    }
+
+    let Some(upstream_name) = revisions.first() else {
+        no_upstream(repo);
+    };
+    let Some(upstream) = revision::resolve(repo, upstream_name) else {
+        fatal(&format!("invalid upstream '{upstream_name}'"));
+    };
+    let onto = match &onto_name {
+        Some(name) => revision::resolve(repo, name)
+            .unwrap_or_else(|| fatal(&format!("Does not point to a valid commit '{name}'"))),
+        None => upstream,
+    };
+    check_clean(repo);
+
+    // Rebasing another branch starts by switching to it.
+    if let Some(name) = revisions.get(1) {
+        let target = match repo.refs.read_branch(name) {
+            Some(oid) => Target::branch(name, oid),
+            None => match revision::resolve(repo, name) {
+                Some(oid) => Target::detached(name, Some(oid), true),
+                None => fatal(&format!("no such branch/commit '{name}'")),
+            },
+        };
+        checkout::switch_to(repo, &target, true)?;
+    }
+    let Some(head) = repo.refs.read_head() else {
+        fatal(&format!("invalid upstream '{upstream_name}'"));
+    };
+    let head_name = repo.refs.current_branch();
+
+    if !interactive && is_up_to_date(repo, upstream, onto, head) {
+        match &head_name {
+            Some(branch) => println!("Current branch {branch} is up to date."),
+            None => println!("HEAD is up to date."),
+        }
+        return Ok(());
+    }
+
+    let commits = rebase::commits_to_rebase(repo, upstream, head);
+    let autosquash = autosquash.unwrap_or_else(|| {
+        matches!(
+            repo.config.get("rebase.autoSquash"),
+            Some("true" | "yes" | "on" | "1")
+        )
+    });
+    let todo = if interactive && autosquash {
+        let steps = commits
+            .iter()
+            .map(|oid| {
+                let commit = repo.database.load_commit(oid);
+                (Step::pick(*oid), pretty::subject(commit.get_message()))
+            })
+            .collect();
+        rebase::autosquash(steps)
+    } else {
+        commits.into_iter().map(Step::pick).collect()
+    };
+    let plan = Plan {
+        head_name: head_name.map(|branch| format!("refs/heads/{branch}")),
+        orig_head: head,
+        upstream,
+        onto,
+        onto_name: onto_name.unwrap_or_else(|| upstream_name.clone()),
+        todo,
+        interactive,
+    };
+    Rebase::new(repo).start(plan)
+}
+
+// Whether rebasing would give back the same commits, because HEAD is already a line of commits on top of the new base (which
+// is where upstream branched off).
+fn is_up_to_date(repo: &Repository, upstream: Digest, onto: Digest, head: Digest) -> bool {
+    let mut merge_base = MergeBase::new(repo);
+    if merge_base.find(onto, &[head]) != [onto] || merge_base.find(upstream, &[head]) != [onto] {
+        return false;
+    }
+    let mut rev_list = RevList::new(repo, RevListOptions::default());
+    rev_list.include(head);
+    rev_list.exclude(onto);
+    rev_list
+        .walk()
+        .iter()
+        .all(|commit| commit.get_parents().len() <= 1)
+}
+
+// Refuses to start with changes that aren't committed, which the rebase would get mixed up with (or lose).
+fn check_clean(repo: &Repository) {
+    let unstaged = repo.index.has_conflicts() || !repo.index_to_workspace().is_empty();
+    let staged = !repo.head_to_index().is_empty();
+    if unstaged {
+        eprintln!("error: cannot rebase: You have unstaged changes.");
+    }
+    if staged {
+        match unstaged {
+            true => eprintln!("error: additionally, your index contains uncommitted changes."),
+            false => eprintln!("error: cannot rebase: Your index contains uncommitted changes."),
+        }
+    }
+    if unstaged || staged {
+        eprintln!("error: Please commit or stash them.");
+        std::process::exit(1);
+    }
+}
+
+fn no_upstream(repo: &Repository) -> ! {
+    match repo.refs.current_branch() {
+        Some(branch) => println!(
+            "There is no tracking information for the current branch.
+Please specify which branch you want to rebase against.
+See git-rebase(1) for details.
+
+    git rebase '<branch>'
+
+If you wish to set tracking information for this branch you can do so with:
+
+    git branch --set-upstream-to=<remote>/<branch> {branch}
+"
+        ),
+        None => println!(
+            "You are not currently on a branch.
+Please specify which branch you want to rebase against.
+See git-rebase(1) for details.
+
+    git rebase '<branch>'
+"
+        ),
+    }
+    std::process::exit(1);
+}
@@ -0,0 +1,323 @@
+use crate::commands::checkout;
+use crate::commands::restore;
+use crate::database::DatabaseEntry;
+use crate::fatal::fatal;
+use crate::pathspec::Pathspec;
+use crate::repository::Repository;
+use crate::revision;
+use sha1_smol::Digest;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::PathBuf;
+
+// How much of the repository a reset moves to the target commit: just the branch (soft), the index as well (mixed), or the
+// workspace too (hard).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Soft,
+    Mixed,
+    Hard,
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut mode = None;
+    let mut quiet = false;
+    let mut positional = Vec::new();
+    let mut paths = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                paths = Some(args.by_ref().cloned().collect::<Vec<_>>());
+                break;
+            }
+            "--soft" => mode = Some(Mode::Soft),
+            "--mixed" => mode = Some(Mode::Mixed),
+            "--hard" => mode = Some(Mode::Hard),
+            "-q" | "--quiet" => quiet = true,
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    // Without "--", the first argument is the revision if it is one, and everything else is a path.
+    let (revision, paths) = match paths {
+        Some(paths) => (
+            positional.first().cloned(),
+            [positional.get(1..).unwrap_or_default(), &paths].concat(),
+        ),
+        None if positional.is_empty() => (None, Vec::new()),
+        None if revision::resolve_object(repo, &positional[0]).is_some() => {
+            (Some(positional[0].clone()), positional[1..].to_vec())
+        }
+        None => {
+            if repo.workspace.stat_file(&positional[0]).is_err() {
+                ambiguous_argument(&positional[0]);
+            }
+            (None, positional)
+        }
+    };
+
+    match mode {
+        Some(Mode::Soft) => {}
+        Some(Mode::Hard) => repo.require_workspace(),
+        Some(Mode::Mixed) | None if repo.bare => {
+            fatal("mixed reset is not allowed in a bare repository")
+        }
+        _ => {}
+    }
+
+    if !paths.is_empty() {
+        match mode {
+            Some(Mode::Soft) => fatal("Cannot do soft reset with paths."),
+            Some(Mode::Hard) => fatal("Cannot do hard reset with paths."),
+            Some(Mode::Mixed) => eprintln!(
+                "warning: --mixed with paths is deprecated; use 'git reset -- <paths>' instead."
+            ),
+            None => {}
+        }
+        let tree = match &revision {
+            Some(name) => {
+                Some(restore::resolve_tree(repo, name).unwrap_or_else(|| ambiguous_argument(name)))
+            }
+            None => repo
+                .refs
+                .read_head()
+                .map(|oid| *repo.database.load_commit(&oid).get_tree()),
+        };
+        let pathspec = repo.pathspec(&paths);
+        reset_paths(repo, tree, &pathspec)?;
+        if !quiet {
+            show_unstaged_changes(repo);
+        }
+        return Ok(());
+    }
+
+    let name = revision.unwrap_or_else(|| "HEAD".to_string());
+    let target = match revision::resolve_object(repo, &name) {
+        None if name == "HEAD" => None,
+        None => ambiguous_argument(&name),
+        Some(oid) => {
+            let oid = repo.database.peel(&oid);
+            let (object_type, _) = repo.database.read_object(&oid);
+            if object_type != "commit" {
+                eprintln!("error: object {oid} is a {object_type}, not a commit");
+                fatal(&format!("Could not parse object '{name}'."));
+            }
+            Some(oid)
+        }
+    };
+    let mode = mode.unwrap_or(Mode::Mixed);
+    reset(repo, target, mode, &name)?;
+    if quiet {
+        return Ok(());
+    }
+    match (mode, target) {
+        (Mode::Hard, Some(oid)) => println!("HEAD is now at {}", checkout::describe(repo, &oid)),
+        (Mode::Mixed, _) => show_unstaged_changes(repo),
+        _ => {}
+    }
+    Ok(())
+}
+
+// Moves HEAD (or the branch it's on) to a commit, keeping the old position in ORIG_HEAD, and then brings the index and
+// workspace along as far as the mode asks for. A None target (when there are no commits yet) only empties the index.
+fn reset(repo: &mut Repository, target: Option<Digest>, mode: Mode, name: &str) -> io::Result<()> {
+    if let Some(oid) = target {
+        if let Some(old) = repo.refs.read_head() {
+            repo.refs.set_orig_head(&old);
+        }
+        repo.refs
+            .update_head_logged(&oid, &format!("reset: moving to {name}"));
+    }
+    // Like git, resetting also gives up on a merge, cherry-pick or revert in progress.
+    repo.remove_merge_state();
+    if mode == Mode::Soft {
+        return Ok(());
+    }
+
+    let tree = target.map(|oid| *repo.database.load_commit(&oid).get_tree());
+    let entries = tree_entries(repo, tree);
+    let paths: Vec<PathBuf> = affected_paths(repo, &entries).into_iter().collect();
+    restore::update(repo, &entries, &paths, true, mode == Mode::Hard)?;
+    Ok(())
+}
+
+// Throws away everything since the given commit, in the branch, index and workspace alike.
+pub fn reset_hard(repo: &mut Repository, oid: Digest) -> io::Result<()> {
+    reset(repo, Some(oid), Mode::Hard, &oid.to_string())
+}
+
+// Throws away all changes to the index and workspace, the way "reset --hard" without a commit does.
+pub fn reset_hard_to_head(repo: &mut Repository) -> io::Result<()> {
+    let head = repo.refs.read_head();
+    reset(repo, head, Mode::Hard, "HEAD")
+}
+
+// Throws away all changes to the index and workspace, making them match a commit again without moving HEAD.
+pub fn discard_changes(repo: &mut Repository, oid: Digest) -> io::Result<()> {
+    repo.remove_merge_state();
+    let tree = *repo.database.load_commit(&oid).get_tree();
+    let entries = tree_entries(repo, Some(tree));
+    let paths: Vec<PathBuf> = affected_paths(repo, &entries).into_iter().collect();
+    restore::update(repo, &entries, &paths, true, true)?;
+    Ok(())
+}
+
+// Unstages the paths matching a pathspec, by putting back the versions the tree has (or removing them if it has none).
+fn reset_paths(repo: &mut Repository, tree: Option<Digest>, pathspec: &Pathspec) -> io::Result<()> {
+    let entries = tree_entries(repo, tree);
+    let paths: Vec<PathBuf> = affected_paths(repo, &entries)
+        .into_iter()
+        .filter(|path| pathspec.matches(path))
+        .collect();
+    restore::update(repo, &entries, &paths, true, false)?;
+    Ok(())
+}
+
+fn tree_entries(repo: &Repository, tree: Option<Digest>) -> BTreeMap<PathBuf, DatabaseEntry> {
+    tree.map(|tree| repo.database.load_tree_entries(&tree))
+        .unwrap_or_default()
+}
+
+// Everything in the target tree, and everything tracked now (which has to go if the tree doesn't have it).
+fn affected_paths(
+    repo: &Repository,
+    entries: &BTreeMap<PathBuf, DatabaseEntry>,
+) -> BTreeSet<PathBuf> {
+    let mut paths: BTreeSet<PathBuf> = entries.keys().cloned().collect();
+    paths.extend(
+        repo.index
+            .entries()
+            .map(|entry| entry.get_path().to_path_buf()),
+    );
+    paths
+}
+
+// Lists the tracked files that still differ from the (newly reset) index, e.g. "M\tsrc/main.rs".
+fn show_unstaged_changes(repo: &Repository) {
+    let changes = repo.index_to_workspace();
+    if changes.is_empty() {
+        return;
+    }
+    println!("Unstaged changes after reset:");
+    for change in changes {
+        let status = if change.b.is_some() { 'M' } else { 'D' };
+        println!("{status}\t{}", change.path.display());
+    }
+}
+
+fn ambiguous_argument(name: &str) -> ! {
+    eprintln!(
+        "fatal: ambiguous argument '{name}': unknown revision or path not in the working tree."
+    );
+    eprintln!("Use '--' to separate paths from revisions, like this:");
+    eprintln!("'git <command> [<revision>...] -- [<file>...]'");
+    std::process::exit(128);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::commit;
+    use crate::discovery::Location;
+    use std::path::Path;
+    use std::{env, fs};
+
+    // A repository with two commits: "first" with a = 1, and "second" with a = 2 and a new file b.
+    fn repo_with_history(name: &str) -> (Repository, Digest, Digest) {
+        let root = env::temp_dir().join(format!("reset-{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        let mut repo = Repository::new(Location {
+            git_path: root.join(".git"),
+            root_path: Some(root),
+            prefix: PathBuf::new(),
+        });
+        repo.refs.set_head_branch("main");
+        commit_files(&mut repo, &[("a", "1\n")], "first");
+        let first = repo.refs.read_head().unwrap();
+        commit_files(&mut repo, &[("a", "2\n"), ("b", "new\n")], "second");
+        let second = repo.refs.read_head().unwrap();
+        (repo, first, second)
+    }
+
+    // Writes, stages and commits files.
+    fn commit_files(repo: &mut Repository, files: &[(&str, &str)], message: &str) {
+        for (path, contents) in files {
+            fs::write(repo.workspace.root().join(path), contents).unwrap();
+        }
+        let paths: Vec<PathBuf> = files.iter().map(|(path, _)| PathBuf::from(path)).collect();
+        commit::stage_paths(repo, &paths).unwrap();
+        run_command(commit::run, repo, &["-m", message]);
+    }
+
+    fn run_command(
+        command: fn(&mut Repository, &[String]) -> io::Result<()>,
+        repo: &mut Repository,
+        args: &[&str],
+    ) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        command(repo, &args).unwrap();
+    }
+
+    fn staged(repo: &Repository, path: &str) -> Option<String> {
+        let entry = repo.index.get_entry(Path::new(path))?;
+        Some(String::from_utf8(repo.database.load_blob(entry.get_oid())).unwrap())
+    }
+
+    fn workspace(repo: &Repository, path: &str) -> Option<String> {
+        fs::read_to_string(repo.workspace.root().join(path)).ok()
+    }
+
+    // The state after a reset: HEAD, ORIG_HEAD and the reflog message, then a's and b's staged and workspace contents.
+    fn reset_to_first(name: &str, mode: &str) -> (Repository, Vec<Option<String>>) {
+        let (mut repo, first, second) = repo_with_history(name);
+        fs::write(repo.workspace.root().join("c"), "untracked\n").unwrap();
+        run_command(run, &mut repo, &[mode, "-q", &first.to_string()]);
+        assert_eq!(repo.refs.read_head(), Some(first));
+        assert_eq!(repo.refs.read_ref("ORIG_HEAD"), Some(second));
+        let reflog = repo.refs.read_reflog("refs/heads/main");
+        assert_eq!(
+            reflog.last().unwrap().message,
+            format!("reset: moving to {first}")
+        );
+        // Untracked files are never touched.
+        assert_eq!(workspace(&repo, "c").as_deref(), Some("untracked\n"));
+        let files = ["a", "b"]
+            .iter()
+            .flat_map(|path| [staged(&repo, path), workspace(&repo, path)])
+            .collect();
+        (repo, files)
+    }
+
+    fn some(contents: &[&str]) -> Vec<Option<String>> {
+        contents
+            .iter()
+            .map(|contents| (!contents.is_empty()).then(|| contents.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_reset_soft() {
+        let (repo, files) = reset_to_first("soft", "--soft");
+        // The index and workspace still have the second commit's files, which are now staged changes.
+        assert_eq!(files, some(&["2\n", "2\n", "new\n", "new\n"]));
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_reset_mixed() {
+        let (repo, files) = reset_to_first("mixed", "--mixed");
+        // The index goes back to the first commit, leaving the second's changes in the workspace only.
+        assert_eq!(files, some(&["1\n", "2\n", "", "new\n"]));
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_reset_hard() {
+        let (repo, files) = reset_to_first("hard", "--hard");
+        assert_eq!(files, some(&["1\n", "1\n", "", ""]));
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+use crate::database::DatabaseEntry;
+use crate::fatal::fatal;
+use crate::index::{Index, IndexMetadata};
+use crate::pathspec::Pathspec;
+use crate::repository::Repository;
+use crate::revision;
+use sha1_smol::Digest;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Where restored files come from and where they go.
+pub struct RestoreOptions {
+    // The tree to restore from, or None for the index.
+    pub source: Option<Digest>,
+    pub staged: bool,
+    pub worktree: bool,
+    // In overlay mode (checkout's), files missing from the source are left alone instead of being removed.
+    pub overlay: bool,
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut source = None;
+    let mut staged = false;
+    let mut worktree = false;
+    let mut overlay = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                paths.extend(args.by_ref().cloned());
+                break;
+            }
+            "-s" | "--source" => {
+                let Some(value) = args.next() else {
+                    eprintln!("error: option `source' requires a value");
+                    std::process::exit(129);
+                };
+                source = Some(value.clone());
+            }
+            "-S" | "--staged" => staged = true,
+            "-W" | "--worktree" => worktree = true,
+            "--overlay" => overlay = true,
+            "--no-overlay" => overlay = false,
+            _ if arg.starts_with("--source=") => {
+                source = Some(arg["--source=".len()..].to_string());
+            }
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.is_empty() {
+        fatal("you must specify path(s) to restore");
+    }
+    // Only the workspace is restored unless asked otherwise, and the index is restored from HEAD unless a source is given.
+    if !staged {
+        worktree = true;
+    }
+    if staged && source.is_none() {
+        source = Some("HEAD".to_string());
+    }
+    let source = source.map(|name| {
+        resolve_tree(repo, &name).unwrap_or_else(|| fatal(&format!("could not resolve {name}")))
+    });
+
+    let options = RestoreOptions {
+        source,
+        staged,
+        worktree,
+        overlay,
+    };
+    restore(repo, &repo.pathspec(&paths), &options)?;
+    Ok(())
+}
+
+// Finds the tree a commit (or tag of one) has, or the tree itself if it names one.
+pub fn resolve_tree(repo: &Repository, name: &str) -> Option<Digest> {
+    let oid = repo.database.peel(&revision::resolve_object(repo, name)?);
+    match repo.database.read_object(&oid).0.as_str() {
+        "commit" => Some(*repo.database.load_commit(&oid).get_tree()),
+        "tree" => Some(oid),
+        _ => None,
+    }
+}
+
+// Restores the files matching a pathspec into the index and/or the workspace, exiting with an error if any part of the
+// pathspec matches nothing. Returns how many workspace files had to be written.
+pub fn restore(
+    repo: &mut Repository,
+    pathspec: &Pathspec,
+    options: &RestoreOptions,
+) -> io::Result<usize> {
+    let staged_entries: BTreeMap<PathBuf, DatabaseEntry> = repo
+        .index
+        .entries()
+        .map(|entry| {
+            let staged = DatabaseEntry {
+                oid: *entry.get_oid(),
+                mode: entry.get_mode(),
+            };
+            (entry.get_path().to_path_buf(), staged)
+        })
+        .collect();
+    let source = match options.source {
+        Some(tree) => repo.database.load_tree_entries(&tree),
+        None => staged_entries.clone(),
+    };
+
+    // Besides what's in the source, tracked files might have to be removed if it doesn't have them.
+    let mut candidates: BTreeSet<&PathBuf> = source.keys().collect();
+    if !options.overlay {
+        candidates.extend(staged_entries.keys());
+    }
+    let unmatched = pathspec.unmatched(candidates.iter().map(|path| path.as_path()));
+    if !unmatched.is_empty() {
+        for pattern in unmatched {
+            eprintln!("error: pathspec '{pattern}' did not match any file(s) known to git");
+        }
+        std::process::exit(1);
+    }
+
+    let paths: Vec<PathBuf> = candidates
+        .into_iter()
+        .filter(|path| pathspec.matches(path))
+        .cloned()
+        .collect();
+    update(repo, &source, &paths, options.staged, options.worktree)
+}
+
+// Makes the given paths in the index (if `staged`) and/or the workspace (if `worktree`) match the source entries, removing
+// the ones the source doesn't have. Returns how many workspace files had to be written.
+pub fn update(
+    repo: &mut Repository,
+    source: &BTreeMap<PathBuf, DatabaseEntry>,
+    paths: &[PathBuf],
+    staged: bool,
+    worktree: bool,
+) -> io::Result<usize> {
+    let is_staged = |repo: &Repository, path: &Path, entry: &DatabaseEntry| {
+        repo.index
+            .get_entry(path)
+            .is_some_and(|staged| *staged.get_oid() == entry.oid && staged.get_mode() == entry.mode)
+    };
+    if staged {
+        for path in paths {
+            match source.get(path) {
+                // An entry that's already staged keeps its stat data.
+                Some(entry) if is_staged(repo, path, entry) => {}
+                Some(entry) => {
+                    // A file that's about to be written out gets its stat data then.
+                    let stat = if worktree {
+                        IndexMetadata::with_mode(entry.mode)
+                    } else {
+                        repo.index_metadata_for(path, entry)
+                    };
+                    repo.index.add(path.clone(), entry.oid, stat);
+                }
+                None => repo.index.remove(path),
+            }
+        }
+    }
+
+    let mut written = 0;
+    if worktree {
+        // Deletions come first, so that a file replacing a directory has room to be written.
+        for path in paths.iter().filter(|path| !source.contains_key(*path)) {
+            repo.workspace.remove_file(path)?;
+            for dir in Index::parent_directories(path).iter().rev() {
+                repo.workspace.remove_directory(dir);
+            }
+        }
+        for (path, entry) in paths
+            .iter()
+            .filter_map(|path| Some((path, source.get(path)?)))
+        {
+            // Like git, only a file the index already has up to date (by its stat data) is left as it is.
+            let staged = is_staged(repo, path, entry);
+            let up_to_date = staged
+                && repo.workspace.stat_file(path).is_ok_and(|stat| {
+                    let stat = IndexMetadata::from(stat);
+                    let entry = repo
+                        .index
+                        .get_entry(path)
+                        .expect("Path is not in the index");
+                    entry.stat_match(&stat) && entry.times_match(&stat)
+                });
+            if up_to_date {
+                continue;
+            }
+            let stat = repo.write_to_workspace(path, entry)?;
+            written += 1;
+            if staged {
+                repo.index.add(path.clone(), entry.oid, stat);
+            }
+        }
+    }
+    repo.index.write_updates();
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob::Blob;
+    use crate::discovery::Location;
+    use crate::index::SYMLINK_MODE;
+    use crate::merge;
+    use crate::object::Object;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::{env, fs};
+
+    fn empty_repo(name: &str) -> Repository {
+        let root = env::temp_dir().join(format!("restore-{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        Repository::new(Location {
+            git_path: root.join(".git"),
+            root_path: Some(root),
+            prefix: PathBuf::new(),
+        })
+    }
+
+    fn store(repo: &mut Repository, data: &str, mode: u32) -> DatabaseEntry {
+        let blob = Blob::new(data.as_bytes().to_vec(), PathBuf::new());
+        repo.database.store(&blob);
+        DatabaseEntry {
+            oid: *blob.get_oid(),
+            mode,
+        }
+    }
+
+    fn options(source: Option<Digest>, staged: bool) -> RestoreOptions {
+        RestoreOptions {
+            source,
+            staged,
+            worktree: true,
+            overlay: false,
+        }
+    }
+
+    // Whether a path's workspace file is a symlink, is executable, and what it holds (or points to).
+    fn workspace_file(repo: &Repository, path: &str) -> (bool, bool, String) {
+        let stat = repo.workspace.stat_file(path).unwrap();
+        let data = repo.workspace.read_file(path).unwrap();
+        (
+            stat.is_symlink(),
+            !stat.is_symlink() && stat.permissions().mode() & 0o111 != 0,
+            String::from_utf8(data).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_restore_modes() {
+        let mut repo = empty_repo("modes");
+        let root = repo.workspace.root().to_path_buf();
+        let link = store(&mut repo, "target", SYMLINK_MODE);
+        let script = store(&mut repo, "run\n", 0o100755);
+        let tree = merge::store_tree(
+            &mut repo,
+            BTreeMap::from([
+                (PathBuf::from("link"), link),
+                (PathBuf::from("script"), script),
+            ]),
+        );
+
+        // From a tree, into both the index and the workspace.
+        let pathspec = repo.pathspec(&[".".to_string()]);
+        restore(&mut repo, &pathspec, &options(Some(tree), true)).unwrap();
+        assert_eq!(
+            workspace_file(&repo, "link"),
+            (true, false, "target".to_string())
+        );
+        assert_eq!(
+            workspace_file(&repo, "script"),
+            (false, true, "run\n".to_string())
+        );
+        for (path, entry) in [("link", link), ("script", script)] {
+            let staged = repo.index.get_entry(Path::new(path)).unwrap();
+            assert_eq!(
+                (*staged.get_oid(), staged.get_mode()),
+                (entry.oid, entry.mode)
+            );
+            let stat = IndexMetadata::from(repo.workspace.stat_file(path).unwrap());
+            assert!(repo.is_unchanged(staged, &stat));
+        }
+
+        // From the index, over a file that replaced the symlink and a symlink that replaced the file.
+        fs::remove_file(root.join("link")).unwrap();
+        fs::write(root.join("link"), "target").unwrap();
+        fs::remove_file(root.join("script")).unwrap();
+        symlink("link", root.join("script")).unwrap();
+        restore(&mut repo, &pathspec, &options(None, false)).unwrap();
+        assert_eq!(
+            workspace_file(&repo, "link"),
+            (true, false, "target".to_string())
+        );
+        assert_eq!(
+            workspace_file(&repo, "script"),
+            (false, true, "run\n".to_string())
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
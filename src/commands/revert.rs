@@ -0,0 +1,9 @@
+use crate::commands::cherry_pick;
+use crate::repository::Repository;
+use crate::sequencer::Action;
+use std::io;
+
+// Undoes the changes made by some commits, each with a new commit of its own.
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    cherry_pick::run_action(repo, args, Action::Revert)
+}
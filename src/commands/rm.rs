@@ -0,0 +1,163 @@
+use crate::fatal::fatal;
+use crate::index::{Index, IndexMetadata};
+use crate::pathspec::Match;
+use crate::repository::Repository;
+use std::collections::BTreeSet;
+use std::io;
+use std::path::PathBuf;
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut cached = false;
+    let mut force = false;
+    let mut recursive = false;
+    let mut quiet = false;
+    let mut dry_run = false;
+    let mut ignore_unmatch = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cached" => cached = true,
+            "-f" | "--force" => force = true,
+            "-r" => recursive = true,
+            "-q" | "--quiet" => quiet = true,
+            "-n" | "--dry-run" => dry_run = true,
+            "--ignore-unmatch" => ignore_unmatch = true,
+            "--" => {
+                paths.extend(args.by_ref().cloned());
+                break;
+            }
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.is_empty() {
+        fatal("No pathspec was given. Which files should I remove?");
+    }
+
+    // Only files in the index are removed. Like git, a pattern that only matched by naming a directory needs -r.
+    let mut pathspec = repo.pathspec(&paths);
+    // Patterns that only exclude things exclude them from the current directory.
+    if !pathspec.has_patterns() {
+        paths.push(".".to_string());
+        pathspec = repo.pathspec(&paths);
+    }
+    let tracked: BTreeSet<PathBuf> = repo
+        .index
+        .entries()
+        .map(|entry| entry.get_path().to_path_buf())
+        .collect();
+    for (item, seen) in pathspec.seen(tracked.iter().map(PathBuf::as_path)) {
+        match seen {
+            Some(Match::Recursively) if !recursive => fatal(&format!(
+                "not removing '{}' recursively without -r",
+                item.original
+            )),
+            None if !ignore_unmatch => fatal(&format!(
+                "pathspec '{}' did not match any files",
+                item.original
+            )),
+            _ => {}
+        }
+    }
+    let targets: Vec<PathBuf> = tracked
+        .into_iter()
+        .filter(|path| pathspec.matches(path))
+        .collect();
+    if targets.is_empty() {
+        return Ok(());
+    }
+    if !force && has_local_changes(repo, &targets, cached) {
+        std::process::exit(1);
+    }
+
+    for path in &targets {
+        if !quiet {
+            println!("rm '{}'", path.display());
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+    for path in &targets {
+        repo.index.remove(path);
+    }
+    if !cached {
+        for path in &targets {
+            if let Err(e) = repo.workspace.remove_file(path) {
+                fatal(&format!("git rm: '{}': {e}", path.display()));
+            }
+            for dir in Index::parent_directories(path).iter().rev() {
+                repo.workspace.remove_directory(dir);
+            }
+        }
+    }
+    repo.index.write_updates();
+    Ok(())
+}
+
+// Checks that removing the files won't lose anything that isn't in HEAD or in the workspace, reporting the ones that would
+// like git does. Files that are already gone from the workspace are fine. With --cached, only files whose staged content
+// matches neither HEAD nor the workspace are a problem.
+fn has_local_changes(repo: &Repository, paths: &[PathBuf], cached: bool) -> bool {
+    let head = repo.head_tree_entries();
+    let mut staged_and_local = Vec::new();
+    let mut staged = Vec::new();
+    let mut local = Vec::new();
+    for path in paths {
+        // Conflicted files have nothing staged to lose.
+        let Some(entry) = repo.index.get_entry(path) else {
+            continue;
+        };
+        let Ok(stat) = repo.workspace.stat_file(path) else {
+            continue;
+        };
+        let staged_changes = head
+            .get(path)
+            .is_none_or(|head| head.oid != *entry.get_oid() || head.mode != entry.get_mode());
+        let local_changes = !repo.is_unchanged(entry, &IndexMetadata::from(stat));
+        if staged_changes && local_changes {
+            staged_and_local.push(path);
+        } else if !cached {
+            if staged_changes {
+                staged.push(path);
+            }
+            if local_changes {
+                local.push(path);
+            }
+        }
+    }
+
+    let keep_hint = "(use --cached to keep the file, or -f to force removal)";
+    report(
+        &staged_and_local,
+        "the following file has staged content different from both the\nfile and the HEAD:",
+        "the following files have staged content different from both the\nfile and the HEAD:",
+        "(use -f to force removal)",
+    );
+    report(
+        &staged,
+        "the following file has changes staged in the index:",
+        "the following files have changes staged in the index:",
+        keep_hint,
+    );
+    report(
+        &local,
+        "the following file has local modifications:",
+        "the following files have local modifications:",
+        keep_hint,
+    );
+    !(staged_and_local.is_empty() && staged.is_empty() && local.is_empty())
+}
+
+fn report(paths: &[&PathBuf], singular: &str, plural: &str, hint: &str) {
+    if paths.is_empty() {
+        return;
+    }
+    let message = if paths.len() == 1 { singular } else { plural };
+    eprintln!("error: {message}");
+    for path in paths {
+        eprintln!("    {}", path.display());
+    }
+    eprintln!("{hint}");
+}
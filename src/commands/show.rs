@@ -0,0 +1,176 @@
+use crate::combined_diff;
+use crate::commands::diff::DiffFormat;
+use crate::commit::Commit;
+use crate::diff;
+use crate::fatal::fatal;
+use crate::pretty::{Pretty, PrettyOptions};
+use crate::repository::Repository;
+use crate::revision;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+// What to show for each commit besides its header.
+struct ShowOptions {
+    format: DiffFormat,
+    // -s / --no-patch shows the header alone.
+    no_patch: bool,
+    // --cc leaves out the hunks of a merge that were taken from one parent as they are, while -c shows them all.
+    dense: bool,
+}
+
+pub fn run(repo: &Repository, args: &[String]) -> io::Result<()> {
+    let mut options = ShowOptions {
+        format: DiffFormat::default(),
+        no_patch: false,
+        dense: true,
+    };
+    let mut pretty_options = PrettyOptions::default();
+    let mut objects = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-s" | "--no-patch" => options.no_patch = true,
+            "--cc" => options.dense = true,
+            "-c" => options.dense = false,
+            _ if options.format.parse_arg(arg) => {}
+            _ if pretty_options.parse_arg(arg) => {}
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => objects.push(arg.as_str()),
+        }
+    }
+    if objects.is_empty() {
+        objects.push("HEAD");
+    }
+
+    let mut out = io::stdout().lock();
+    let mut pretty = Pretty::new(repo, pretty_options);
+    // Like git, a commit named more than once is only shown the first time.
+    let mut shown_commits = HashSet::new();
+    for name in objects {
+        let Some(oid) = revision::resolve_object(repo, name) else {
+            if let Some((revision, path)) = name.split_once(':') {
+                if !revision.is_empty() && revision::resolve_object(repo, revision).is_some() {
+                    fatal(&format!("path '{path}' does not exist in '{revision}'"));
+                }
+            }
+            eprintln!("fatal: ambiguous argument '{name}': unknown revision or path not in the working tree.");
+            eprintln!("Use '--' to separate paths from revisions, like this:");
+            eprintln!("'git <command> [<revision>...] -- [<file>...]'");
+            std::process::exit(128);
+        };
+        // Tags are followed to what they tag, showing each of them along the way.
+        let mut oid = oid;
+        loop {
+            let (object_type, data) = repo.database.read_object(&oid);
+            match object_type.as_str() {
+                "tag" => {
+                    let tag = repo.database.load_tag(&oid);
+                    pretty.write_tag(&mut out, &tag)?;
+                    oid = *tag.get_object();
+                    continue;
+                }
+                "commit" if !shown_commits.insert(oid) => {}
+                "commit" => {
+                    let commit = repo.database.load_commit(&oid);
+                    show_commit(&mut out, repo, &mut pretty, &commit, &options)?;
+                }
+                "tree" => {
+                    if pretty.shown_any() {
+                        writeln!(out)?;
+                    }
+                    pretty.mark_shown();
+                    writeln!(out, "tree {name}\n")?;
+                    // Like git, directories get a trailing slash and sort as if it were part of their name.
+                    let mut entries: Vec<String> = repo
+                        .database
+                        .load_tree(&oid)
+                        .into_iter()
+                        .map(|(path, entry)| {
+                            let slash = if entry.is_tree() { "/" } else { "" };
+                            format!("{}{slash}", path.display())
+                        })
+                        .collect();
+                    entries.sort();
+                    for entry in entries {
+                        writeln!(out, "{entry}")?;
+                    }
+                }
+                // Blobs are written out as they are.
+                _ => out.write_all(&data)?,
+            }
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Writes a commit's header followed by its changes: a patch against its parent, or a combined diff against all parents for a
+// merge.
+fn show_commit(
+    out: &mut impl Write,
+    repo: &Repository,
+    pretty: &mut Pretty,
+    commit: &Commit,
+    options: &ShowOptions,
+) -> io::Result<()> {
+    pretty.write(out, commit, None)?;
+    if options.no_patch {
+        return Ok(());
+    }
+    let format = &options.format;
+    let database = &repo.database;
+    let parents = commit.get_parents();
+    if parents.len() <= 1 {
+        let parent_tree = parents
+            .first()
+            .map(|parent| *database.load_commit(parent).get_tree());
+        let changes = database.tree_diff(parent_tree.as_ref(), Some(commit.get_tree()));
+        let pairs = diff::pairs_from_tree_diff(database, &changes);
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        if !pretty.is_oneline() && !pretty.is_empty() {
+            // A "---" line separates the message from the stat when a patch follows, so the result can be applied as a patch.
+            if format.stat.is_some() && format.shows_patch() {
+                write!(out, "---")?;
+            }
+            writeln!(out)?;
+        }
+        return format.write(out, pairs, &repo.attributes);
+    }
+
+    if !pretty.is_empty() {
+        writeln!(out)?;
+    }
+    // Stats for a merge are against its first parent, since there's no combined form of them.
+    let wants_stats = format.stat.is_some() || format.numstat || format.shortstat || format.summary;
+    if wants_stats {
+        let first_parent = database.load_commit(&parents[0]);
+        let changes = database.tree_diff(Some(first_parent.get_tree()), Some(commit.get_tree()));
+        let stat_format = DiffFormat {
+            patch: false,
+            name_only: false,
+            name_status: false,
+            ..format.clone()
+        };
+        stat_format.write(
+            out,
+            diff::pairs_from_tree_diff(database, &changes),
+            &repo.attributes,
+        )?;
+    }
+    if !format.shows_patch() {
+        return Ok(());
+    }
+    let parent_trees: Vec<_> = parents
+        .iter()
+        .map(|parent| *database.load_commit(parent).get_tree())
+        .collect();
+    let paths = combined_diff::changed_paths(database, commit.get_tree(), &parent_trees);
+    if !paths.is_empty() && (format.stat.is_some() || format.numstat) {
+        writeln!(out)?;
+    }
+    for path in &paths {
+        combined_diff::write_combined_diff(out, database, &repo.attributes, path, options.dense)?;
+    }
+    Ok(())
+}
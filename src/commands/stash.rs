@@ -0,0 +1,701 @@
+use crate::blob::Blob;
+use crate::commands::{commit, reset, restore, status};
+use crate::commit::{Author, Commit};
+use crate::database::DatabaseEntry;
+use crate::diff3::Labels;
+use crate::fatal::fatal;
+use crate::index::IndexMetadata;
+use crate::merge::{self, Merge};
+use crate::object::Object;
+use crate::pretty;
+use crate::repository::Repository;
+use crate::revision;
+use crate::sequencer;
+use sha1_smol::Digest;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Stashes are kept as the entries of this ref's reflog, the newest being stash@{0}.
+const STASH_REF: &str = "refs/stash";
+
+// A stash to apply or drop: how it was named (for messages) and its commit. The commit's tree is the workspace, and its
+// parents are the commit it was made on, a commit of the index and, if untracked files were stashed too, a commit of those.
+struct Entry {
+    name: String,
+    oid: Digest,
+    // Which entry of the stash list it is, if it was named as one (like "stash@{1}") rather than by its commit.
+    position: Option<usize>,
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    // Without a subcommand (or with only options), it's push.
+    let (subcommand, args) = match args.split_first() {
+        Some((first, rest)) if !first.starts_with('-') => (first.as_str(), rest),
+        _ => ("push", args),
+    };
+    match subcommand {
+        "push" => push(repo, args),
+        "save" => save(repo, args),
+        "list" => list(repo, args),
+        "apply" => apply(repo, args, false),
+        "pop" => apply(repo, args, true),
+        "drop" => drop(repo, args),
+        _ => fatal(&format!(
+            "subcommand wasn't specified; 'push' can't be assumed due to unexpected token '{subcommand}'"
+        )),
+    }
+}
+
+fn push(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut include_untracked = false;
+    let mut quiet = false;
+    let mut message = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-u" | "--include-untracked" => include_untracked = true,
+            "--no-include-untracked" => include_untracked = false,
+            "-q" | "--quiet" => quiet = true,
+            "-m" | "--message" => match args.next() {
+                Some(value) => message = Some(value.clone()),
+                None => {
+                    eprintln!("error: switch `m' requires a value");
+                    process::exit(129);
+                }
+            },
+            _ if arg.starts_with("--message=") => {
+                message = Some(arg["--message=".len()..].to_string())
+            }
+            _ if arg.starts_with("-m") => message = Some(arg[2..].to_string()),
+            _ => fatal(&format!("unrecognized argument: {arg}")),
+        }
+    }
+    save_state(repo, message, include_untracked, quiet)
+}
+
+// The older way to stash, which takes the message as the rest of the arguments.
+fn save(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut include_untracked = false;
+    let mut quiet = false;
+    let mut words = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-u" | "--include-untracked" => include_untracked = true,
+            "--no-include-untracked" => include_untracked = false,
+            "-q" | "--quiet" => quiet = true,
+            _ if arg.starts_with('-') && words.is_empty() => {
+                fatal(&format!("unrecognized argument: {arg}"))
+            }
+            _ => words.push(arg.as_str()),
+        }
+    }
+    let message = (!words.is_empty()).then(|| words.join(" "));
+    save_state(repo, message, include_untracked, quiet)
+}
+
+// Records the index and the tracked files in the workspace (and the untracked files too, if asked to) as a new stash, and
+// then throws those changes away, leaving the workspace as HEAD has it.
+fn save_state(
+    repo: &mut Repository,
+    message: Option<String>,
+    include_untracked: bool,
+    quiet: bool,
+) -> io::Result<()> {
+    let Some(head) = repo.refs.read_head() else {
+        eprintln!("You do not have the initial commit yet");
+        process::exit(1);
+    };
+    check_merged(repo);
+    let untracked = match include_untracked {
+        true => repo.untracked_files(),
+        false => BTreeSet::new(),
+    };
+    if repo.head_to_index().is_empty()
+        && repo.index_to_workspace().is_empty()
+        && untracked.is_empty()
+    {
+        println!("No local changes to save");
+        return Ok(());
+    }
+
+    let branch = repo
+        .refs
+        .current_branch()
+        .unwrap_or_else(|| "(no branch)".to_string());
+    let head_commit = repo.database.load_commit(&head);
+    let on = format!(
+        "{branch}: {} {}",
+        repo.database.abbreviate(&head),
+        pretty::subject(head_commit.get_message())
+    );
+    let index_tree = *commit::write_tree(repo).get_oid();
+    let mut parents = vec![
+        head,
+        store_commit(repo, index_tree, vec![head], format!("index on {on}")),
+    ];
+    if !untracked.is_empty() {
+        let tree = untracked_tree(repo, &untracked)?;
+        parents.push(store_commit(
+            repo,
+            tree,
+            Vec::new(),
+            format!("untracked files on {on}"),
+        ));
+    }
+    let message = match message {
+        Some(message) => format!("On {branch}: {message}"),
+        None => format!("WIP on {on}"),
+    };
+    let workspace_tree = workspace_tree(repo)?;
+    let stash = store_commit(repo, workspace_tree, parents, message.clone());
+    repo.refs.update_ref_logged(STASH_REF, &stash, &message);
+    if !quiet {
+        println!("Saved working directory and index state {message}");
+    }
+
+    reset::reset_hard_to_head(repo)?;
+    for path in &untracked {
+        match path.strip_suffix('/') {
+            Some(dir) => fs::remove_dir_all(repo.workspace.root().join(dir))?,
+            None => repo.workspace.remove_file(Path::new(path))?,
+        }
+    }
+    Ok(())
+}
+
+// Like git, the commits of a stash have no newline at the end of their messages.
+fn store_commit(
+    repo: &mut Repository,
+    tree: Digest,
+    parents: Vec<Digest>,
+    message: String,
+) -> Digest {
+    let commit = Commit::new(tree, parents, Author::now(), Author::now(), message);
+    repo.database.store(&commit);
+    *commit.get_oid()
+}
+
+// The tree of the tracked files as they are in the workspace: what's staged, updated with the files that changed since and
+// without the ones that were deleted. A file HEAD has that was only removed from the index is still tracked here.
+fn workspace_tree(repo: &mut Repository) -> io::Result<Digest> {
+    let mut entries: BTreeMap<PathBuf, DatabaseEntry> = repo
+        .index
+        .entries()
+        .map(|entry| {
+            let blob = DatabaseEntry {
+                oid: *entry.get_oid(),
+                mode: entry.get_mode(),
+            };
+            (entry.get_path().to_path_buf(), blob)
+        })
+        .collect();
+    let mut changed: Vec<PathBuf> = repo
+        .index_to_workspace()
+        .into_iter()
+        .map(|pair| pair.path)
+        .collect();
+    changed.extend(
+        repo.head_tree_entries()
+            .into_keys()
+            .filter(|path| !repo.index.is_tracked_file(path)),
+    );
+    for path in changed {
+        match store_file(repo, &path)? {
+            Some(entry) => entries.insert(path, entry),
+            None => entries.remove(&path),
+        };
+    }
+    Ok(merge::store_tree(repo, entries))
+}
+
+// The tree of the untracked files, where a directory that's untracked as a whole has everything in it included.
+fn untracked_tree(repo: &mut Repository, untracked: &BTreeSet<String>) -> io::Result<Digest> {
+    let mut entries = BTreeMap::new();
+    for path in untracked {
+        let files = match path.strip_suffix('/') {
+            Some(dir) => repo
+                .workspace
+                .list_files(&repo.workspace.root().join(dir))
+                .map_err(io::Error::from)?,
+            None => vec![PathBuf::from(path)],
+        };
+        for file in files {
+            if let Some(entry) = store_file(repo, &file)? {
+                entries.insert(file, entry);
+            }
+        }
+    }
+    Ok(merge::store_tree(repo, entries))
+}
+
+// Stores the blob of a workspace file, returning its tree entry, or None if there's no file at the path.
+fn store_file(repo: &mut Repository, path: &Path) -> io::Result<Option<DatabaseEntry>> {
+    let Ok(stat) = repo.workspace.stat_file(path) else {
+        return Ok(None);
+    };
+    if stat.is_dir() {
+        return Ok(None);
+    }
+    let blob = Blob::new(repo.workspace.read_file(path)?, path.to_path_buf());
+    repo.database.store(&blob);
+    Ok(Some(DatabaseEntry {
+        oid: *blob.get_oid(),
+        mode: IndexMetadata::from(stat).get_mode(),
+    }))
+}
+
+fn list(repo: &Repository, args: &[String]) -> io::Result<()> {
+    if let Some(arg) = args.first() {
+        fatal(&format!("unrecognized argument: {arg}"));
+    }
+    for (i, entry) in repo.refs.read_reflog(STASH_REF).iter().rev().enumerate() {
+        println!("stash@{{{i}}}: {}", entry.message);
+    }
+    Ok(())
+}
+
+// Applies a stash, and with pop, drops it too if it applied cleanly.
+fn apply(repo: &mut Repository, args: &[String], pop: bool) -> io::Result<()> {
+    let mut restore_index = false;
+    let mut quiet = false;
+    let mut revisions = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--index" => restore_index = true,
+            "-q" | "--quiet" => quiet = true,
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => revisions.push(arg.clone()),
+        }
+    }
+    let entry = find_entry(repo, &revisions);
+    let position = pop.then(|| stash_position(&entry));
+    if !apply_entry(repo, &entry, restore_index, quiet)? {
+        if pop {
+            println!("The stash entry is kept in case you need it again.");
+        }
+        process::exit(1);
+    }
+    if let Some(position) = position {
+        drop_entry(repo, &entry, position, quiet);
+    }
+    Ok(())
+}
+
+fn drop(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut quiet = false;
+    let mut revisions = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => revisions.push(arg.clone()),
+        }
+    }
+    let entry = find_entry(repo, &revisions);
+    let position = stash_position(&entry);
+    drop_entry(repo, &entry, position, quiet);
+    Ok(())
+}
+
+fn drop_entry(repo: &mut Repository, entry: &Entry, position: usize, quiet: bool) {
+    repo.refs.drop_reflog_entry(STASH_REF, position);
+    if !quiet {
+        println!("Dropped {} ({})", entry.name, entry.oid);
+    }
+}
+
+// Finds the stash named on the command line: the latest one by default, and a number n stands for stash@{n}.
+fn find_entry(repo: &Repository, revisions: &[String]) -> Entry {
+    if revisions.len() > 1 {
+        let quoted: Vec<String> = revisions.iter().map(|name| format!("'{name}'")).collect();
+        eprintln!("Too many revisions specified: {}", quoted.join(" "));
+        process::exit(1);
+    }
+    let name = match revisions.first() {
+        None if repo.refs.read_ref(STASH_REF).is_none() => {
+            eprintln!("No stash entries found.");
+            process::exit(1);
+        }
+        None => format!("{STASH_REF}@{{0}}"),
+        Some(name) if name.chars().all(|c| c.is_ascii_digit()) => format!("{STASH_REF}@{{{name}}}"),
+        Some(name) => name.clone(),
+    };
+
+    // An entry past the end of a reflog is reported as such, rather than as a name that doesn't resolve.
+    let reflog_entry = name.split_once("@{").and_then(|(ref_name, rest)| {
+        let nth: usize = rest.strip_suffix('}')?.parse().ok()?;
+        Some((repo.refs.full_name(ref_name)?, ref_name, nth))
+    });
+    if let Some((full_name, ref_name, nth)) = &reflog_entry {
+        let count = repo.refs.read_reflog(full_name).len();
+        if count > 0 && *nth >= count {
+            fatal(&format!("log for '{ref_name}' only has {count} entries"));
+        }
+    }
+    let Some(oid) = revision::resolve(repo, &name) else {
+        eprintln!("error: {name} is not a valid reference");
+        process::exit(1);
+    };
+    if repo.database.load_commit(&oid).get_parents().len() < 2 {
+        fatal(&format!("'{name}' is not a stash-like commit"));
+    }
+    let position = reflog_entry
+        .filter(|(full_name, _, _)| full_name == STASH_REF)
+        .map(|(_, _, nth)| nth);
+    Entry {
+        name,
+        oid,
+        position,
+    }
+}
+
+// Only an entry of the stash list can be dropped from it.
+fn stash_position(entry: &Entry) -> usize {
+    entry.position.unwrap_or_else(|| {
+        eprintln!("error: '{}' is not a stash reference", entry.name);
+        process::exit(1);
+    })
+}
+
+// Merges the stashed changes into the workspace and index, and restores the untracked files if there are any. Returns
+// whether all of that went cleanly; if it didn't, what could be done has been, and the problems shown.
+fn apply_entry(
+    repo: &mut Repository,
+    entry: &Entry,
+    restore_index: bool,
+    quiet: bool,
+) -> io::Result<bool> {
+    check_merged(repo);
+    let stash = repo.database.load_commit(&entry.oid);
+    let tree = |oid: &Digest| *repo.database.load_commit(oid).get_tree();
+    let base_tree = tree(&stash.get_parents()[0]);
+    let index_tree = tree(&stash.get_parents()[1]);
+    let untracked_tree = stash.get_parents().get(2).map(tree);
+    let current_tree = *commit::write_tree(repo).get_oid();
+
+    let mut staged_tree = None;
+    if restore_index && index_tree != base_tree && index_tree != current_tree {
+        let Some(tree) = merge_staged_changes(repo, base_tree, current_tree, index_tree) else {
+            eprintln!("error: conflicts in index. Try without --index.");
+            return Ok(false);
+        };
+        staged_tree = Some(tree);
+        // Git resets the index to HEAD once it has worked this out, which HEAD's reflog shows.
+        let head = repo
+            .refs
+            .read_head()
+            .expect("Applying a stash without a HEAD");
+        repo.refs.update_head_logged(&head, "reset: moving to HEAD");
+    }
+
+    let labels = Labels {
+        ours: match base_tree == current_tree {
+            true => "Version stash was based on",
+            false => "Updated upstream",
+        },
+        base: "Stash base",
+        theirs: "Stashed changes",
+    };
+    let mut clean = true;
+    if base_tree == *stash.get_tree() {
+        if !quiet {
+            println!("Already up to date.");
+        }
+    } else {
+        let merged = sequencer::merge_into_index(
+            repo,
+            Some(base_tree),
+            Some(current_tree),
+            Some(*stash.get_tree()),
+            &labels,
+        )?;
+        match merged {
+            Some((outcome, _)) => {
+                if !quiet {
+                    for message in outcome.messages() {
+                        println!("{message}");
+                    }
+                }
+                clean = outcome.conflicts.is_empty();
+            }
+            None => clean = false,
+        }
+    }
+
+    if !clean {
+        if restore_index {
+            eprintln!("Index was not unstashed.");
+        }
+    } else if let Some(staged_tree) = staged_tree {
+        let entries = repo.database.load_tree_entries(&staged_tree);
+        let mut paths: BTreeSet<PathBuf> = entries.keys().cloned().collect();
+        paths.extend(
+            repo.index
+                .entries()
+                .map(|entry| entry.get_path().to_path_buf()),
+        );
+        let paths: Vec<PathBuf> = paths.into_iter().collect();
+        restore::update(repo, &entries, &paths, true, false)?;
+    } else {
+        unstage_changes_unless_new(repo, current_tree)?;
+    }
+
+    if let Some(untracked_tree) = untracked_tree {
+        if !restore_untracked(repo, untracked_tree)? {
+            eprintln!("error: could not restore untracked files from stash");
+            clean = false;
+        }
+    }
+    if !quiet {
+        status::print_long(repo)?;
+    }
+    Ok(clean)
+}
+
+// With --index, the changes that were staged when stashing are staged again, on top of what's staged now. Git applies them
+// as a patch, so a file can't be added if it's already there, or changed if it isn't, and changes to a file that don't
+// merge cleanly don't apply. Returns the tree to stage, or None (having said why) if the changes don't apply.
+fn merge_staged_changes(
+    repo: &mut Repository,
+    base_tree: Digest,
+    current_tree: Digest,
+    index_tree: Digest,
+) -> Option<Digest> {
+    let current = repo.database.load_tree_entries(&current_tree);
+    let mut applies = true;
+    for (path, (old, _)) in repo.database.tree_diff(Some(&base_tree), Some(&index_tree)) {
+        match (old, current.contains_key(&path)) {
+            (None, true) => eprintln!("error: {}: already exists in index", path.display()),
+            (Some(_), false) => eprintln!("error: {}: does not exist in index", path.display()),
+            _ => continue,
+        }
+        applies = false;
+    }
+    if !applies {
+        return None;
+    }
+    let labels = Labels {
+        ours: "HEAD",
+        base: "Stash base",
+        theirs: "Stashed changes",
+    };
+    let outcome = Merge::new(repo, Default::default()).merge_trees(
+        Some(base_tree),
+        Some(current_tree),
+        Some(index_tree),
+        &labels,
+    );
+    if !outcome.conflicts.is_empty() {
+        for path in outcome.conflicts.keys() {
+            eprintln!("error: {}: patch does not apply", path.display());
+        }
+        return None;
+    }
+    Some(merge::store_tree(repo, outcome.tree))
+}
+
+// After a merge everything it changed is staged, but the stashed changes only come back staged with --index. Like git,
+// files the stash adds stay staged anyway, so that they aren't left untracked.
+fn unstage_changes_unless_new(repo: &mut Repository, tree: Digest) -> io::Result<()> {
+    let entries = repo.database.load_tree_entries(&tree);
+    let paths: Vec<PathBuf> = repo
+        .tree_to_index(&entries)
+        .into_iter()
+        .filter(|pair| pair.a.is_some())
+        .map(|pair| pair.path)
+        .collect();
+    restore::update(repo, &entries, &paths, true, false)?;
+    Ok(())
+}
+
+// Writes the stashed untracked files back, unless a file is in the way of any of them. Returns whether all of them were.
+fn restore_untracked(repo: &mut Repository, tree: Digest) -> io::Result<bool> {
+    let mut restored = true;
+    for (path, entry) in repo.database.load_tree_entries(&tree) {
+        if repo.workspace.stat_file(&path).is_ok() {
+            eprintln!("{} already exists, no checkout", path.display());
+            restored = false;
+            continue;
+        }
+        repo.write_to_workspace(&path, &entry)?;
+    }
+    Ok(restored)
+}
+
+// Neither saving nor applying a stash can work with conflicts still to resolve.
+fn check_merged(repo: &Repository) {
+    let conflicted = repo.index.conflicted_paths();
+    if conflicted.is_empty() {
+        return;
+    }
+    for path in conflicted {
+        println!("{}: needs merge", path.display());
+    }
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::Location;
+    use std::env;
+
+    // A repository with one commit, in which a = 1 and b = 1.
+    fn repo_with_commit(name: &str) -> Repository {
+        let root = env::temp_dir().join(format!("stash-{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        let mut repo = Repository::new(Location {
+            git_path: root.join(".git"),
+            root_path: Some(root),
+            prefix: PathBuf::new(),
+        });
+        repo.refs.set_head_branch("main");
+        write_files(&repo, &[("a", "1\n"), ("b", "1\n")]);
+        stage(&mut repo, &["a", "b"]);
+        run_command(commit::run, &mut repo, &["-m", "initial"]);
+        repo
+    }
+
+    fn write_files(repo: &Repository, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            fs::write(repo.workspace.root().join(path), contents).unwrap();
+        }
+    }
+
+    fn stage(repo: &mut Repository, paths: &[&str]) {
+        let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        commit::stage_paths(repo, &paths).unwrap();
+    }
+
+    fn run_command(
+        command: fn(&mut Repository, &[String]) -> io::Result<()>,
+        repo: &mut Repository,
+        args: &[&str],
+    ) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        command(repo, &args).unwrap();
+    }
+
+    // Each path's staged and workspace contents (None where there's no file).
+    fn files(repo: &Repository, paths: &[&str]) -> Vec<(Option<String>, Option<String>)> {
+        paths
+            .iter()
+            .map(|path| {
+                let staged = repo.index.get_entry(Path::new(path)).map(|entry| {
+                    String::from_utf8(repo.database.load_blob(entry.get_oid())).unwrap()
+                });
+                let workspace = fs::read_to_string(repo.workspace.root().join(path)).ok();
+                (staged, workspace)
+            })
+            .collect()
+    }
+
+    fn both(staged: &str, workspace: &str) -> (Option<String>, Option<String>) {
+        (Some(staged.to_string()), Some(workspace.to_string()))
+    }
+
+    fn stash_messages(repo: &Repository) -> Vec<String> {
+        let reflog = repo.refs.read_reflog(STASH_REF);
+        reflog
+            .into_iter()
+            .rev()
+            .map(|entry| entry.message)
+            .collect()
+    }
+
+    // Stages a = 2, then changes it to 3 and b to 2 in the workspace, and stashes all that.
+    fn push_changes(repo: &mut Repository) {
+        write_files(repo, &[("a", "2\n")]);
+        stage(repo, &["a"]);
+        write_files(repo, &[("a", "3\n"), ("b", "2\n"), ("c", "untracked\n")]);
+        run_command(run, repo, &["push", "-q"]);
+    }
+
+    #[test]
+    fn test_push() {
+        let mut repo = repo_with_commit("push");
+        let head = repo.refs.read_head().unwrap();
+        push_changes(&mut repo);
+        // The index and workspace go back to HEAD, while untracked files are left alone.
+        assert_eq!(
+            files(&repo, &["a", "b", "c"]),
+            [
+                both("1\n", "1\n"),
+                both("1\n", "1\n"),
+                (None, Some("untracked\n".to_string()))
+            ]
+        );
+        let abbreviated = repo.database.abbreviate(&head);
+        assert_eq!(
+            stash_messages(&repo),
+            [format!("WIP on main: {abbreviated} initial")]
+        );
+        // The stash commit holds the workspace, with HEAD and the index's commit as its parents.
+        let stash = repo
+            .database
+            .load_commit(&repo.refs.read_ref(STASH_REF).unwrap());
+        assert_eq!(stash.get_parents()[0], head);
+        assert_eq!(stash.get_parents().len(), 2);
+
+        write_files(&repo, &[("b", "3\n")]);
+        run_command(run, &mut repo, &["push", "-q", "-u", "-m", "second"]);
+        assert_eq!(
+            files(&repo, &["b", "c"]),
+            [both("1\n", "1\n"), (None, None)]
+        );
+        assert_eq!(
+            stash_messages(&repo),
+            [
+                "On main: second".to_string(),
+                format!("WIP on main: {abbreviated} initial")
+            ]
+        );
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut repo = repo_with_commit("apply");
+        push_changes(&mut repo);
+        // The changes come back unstaged, and the stash stays.
+        run_command(run, &mut repo, &["apply", "-q"]);
+        assert_eq!(
+            files(&repo, &["a", "b"]),
+            [both("1\n", "3\n"), both("1\n", "2\n")]
+        );
+        assert_eq!(stash_messages(&repo).len(), 1);
+
+        // With --index, what was staged is staged again.
+        reset::reset_hard_to_head(&mut repo).unwrap();
+        run_command(run, &mut repo, &["apply", "-q", "--index"]);
+        assert_eq!(
+            files(&repo, &["a", "b"]),
+            [both("2\n", "3\n"), both("1\n", "2\n")]
+        );
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut repo = repo_with_commit("pop");
+        push_changes(&mut repo);
+        write_files(&repo, &[("b", "3\n")]);
+        run_command(run, &mut repo, &["push", "-q"]);
+        // The latest stash is applied and dropped, leaving the one before.
+        run_command(run, &mut repo, &["pop", "-q"]);
+        assert_eq!(files(&repo, &["b"]), [both("1\n", "3\n")]);
+        assert_eq!(stash_messages(&repo).len(), 1);
+        reset::reset_hard_to_head(&mut repo).unwrap();
+        run_command(run, &mut repo, &["pop", "-q"]);
+        assert_eq!(
+            files(&repo, &["a", "b"]),
+            [both("1\n", "3\n"), both("1\n", "2\n")]
+        );
+        assert!(repo.refs.read_ref(STASH_REF).is_none());
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+}
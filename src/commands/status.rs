@@ -0,0 +1,305 @@
+use crate::diff::{FilePair, OriginKind};
+use crate::fatal::fatal;
+use crate::pathspec::relative_path;
+use crate::rename::{self, RenameOptions};
+use crate::repository::Repository;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(PartialEq)]
+enum StatusFormat {
+    Long,
+    Short,
+    Porcelain,
+}
+
+// The single letter git uses for each kind of change in the short formats.
+fn status_letter(pair: &FilePair) -> char {
+    match (&pair.origin, &pair.a, &pair.b) {
+        (Some(origin), _, _) if origin.kind == OriginKind::Rename => 'R',
+        (Some(_), _, _) => 'C',
+        (None, None, _) => 'A',
+        (None, _, None) => 'D',
+        // A file that became a symlink or the other way around.
+        (None, Some(a), Some(b)) if a.mode & 0o170000 != b.mode & 0o170000 => 'T',
+        _ => 'M',
+    }
+}
+
+// The label used in the long format, padded so that the paths line up.
+fn status_label(pair: &FilePair) -> String {
+    let label = match status_letter(pair) {
+        'R' => "renamed:",
+        'C' => "copied:",
+        'A' => "new file:",
+        'D' => "deleted:",
+        'T' => "typechange:",
+        _ => "modified:",
+    };
+    format!("{label:<12}")
+}
+
+// Which of the base, ours and theirs versions a conflicted path has in the index.
+type Stages = [bool; 3];
+
+// The two letters for a conflicted path in the short formats, e.g. "UU" when both sides changed it and "DU" when we deleted
+// it.
+fn unmerged_code(stages: &Stages) -> &'static str {
+    match stages {
+        [true, false, false] => "DD",
+        [false, true, false] => "AU",
+        [true, true, false] => "UD",
+        [false, false, true] => "UA",
+        [true, false, true] => "DU",
+        [false, true, true] => "AA",
+        _ => "UU",
+    }
+}
+
+fn unmerged_label(stages: &Stages) -> String {
+    let label = match unmerged_code(stages) {
+        "DD" => "both deleted:",
+        "AU" => "added by us:",
+        "UD" => "deleted by them:",
+        "UA" => "added by them:",
+        "DU" => "deleted by us:",
+        "AA" => "both added:",
+        _ => "both modified:",
+    };
+    format!("{label:<17}")
+}
+
+// The paths with merge conflicts (in index order), and which versions of each the index has.
+pub fn unmerged_paths(repo: &Repository) -> Vec<(PathBuf, Stages)> {
+    repo.index
+        .conflicted_paths()
+        .into_iter()
+        .filter_map(|path| {
+            let stages = repo.index.get_conflict(path)?.map(|entry| entry.is_some());
+            Some((path.to_path_buf(), stages))
+        })
+        .collect()
+}
+
+// A path as seen from the directory status runs in (the prefix). Untracked directories keep their trailing slash, which
+// makes the directory itself "./".
+fn relative(path: &str, prefix: &Path) -> String {
+    let relative = relative_path(Path::new(path), prefix);
+    let relative = relative.to_string_lossy();
+    match (relative.is_empty(), path.ends_with('/')) {
+        (true, _) => "./".to_string(),
+        (false, true) => format!("{relative}/"),
+        (false, false) => relative.into_owned(),
+    }
+}
+
+fn display_path(pair: &FilePair, prefix: &Path) -> String {
+    let path = relative(&pair.path.to_string_lossy(), prefix);
+    match &pair.origin {
+        Some(origin) => format!(
+            "{} -> {path}",
+            relative(&origin.path.to_string_lossy(), prefix)
+        ),
+        None => path,
+    }
+}
+
+pub fn run(repo: &Repository, args: &[String]) -> io::Result<()> {
+    let mut format = StatusFormat::Long;
+    let mut renames = RenameOptions::default();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                paths.extend(args.by_ref().cloned());
+                break;
+            }
+            "-s" | "--short" => format = StatusFormat::Short,
+            "--porcelain" | "--porcelain=v1" => format = StatusFormat::Porcelain,
+            "--long" => format = StatusFormat::Long,
+            // Status only ever looks for renames, not copies.
+            _ if renames.parse_arg(arg) && !renames.copies => {}
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    // Only paths matching the pathspec are shown (renames are looked for among them).
+    let pathspec = repo.pathspec(&paths);
+    let mut staged = repo.head_to_index();
+    staged.retain(|pair| pathspec.matches(&pair.path));
+    let staged = rename::detect(staged, &renames);
+    let mut unmerged = unmerged_paths(repo);
+    unmerged.retain(|(path, _)| pathspec.matches(path));
+    let mut unstaged = repo.index_to_workspace();
+    unstaged.retain(|pair| pathspec.matches(&pair.path));
+    let untracked = repo.untracked_files_in(&pathspec);
+
+    let mut out = io::stdout().lock();
+    if format == StatusFormat::Long {
+        return write_long(
+            &mut out,
+            &repo.prefix,
+            &staged,
+            &unmerged,
+            &unstaged,
+            &untracked,
+        );
+    }
+    // Like git, the porcelain format always shows paths from the top of the workspace.
+    let prefix = match format {
+        StatusFormat::Porcelain => Path::new(""),
+        _ => &repo.prefix,
+    };
+
+    // Each tracked path gets one line with its staged (X) and unstaged (Y) status, ordered by the bytes of the paths like git.
+    let mut lines: BTreeMap<&OsStr, (Option<&FilePair>, Option<&FilePair>)> = BTreeMap::new();
+    for pair in &staged {
+        lines.entry(pair.path.as_os_str()).or_default().0 = Some(pair);
+    }
+    for pair in &unstaged {
+        lines.entry(pair.path.as_os_str()).or_default().1 = Some(pair);
+    }
+    let mut output: BTreeMap<&OsStr, String> = lines
+        .iter()
+        .map(|(path, (staged, unstaged))| {
+            let x = staged.map_or(' ', status_letter);
+            let y = unstaged.map_or(' ', status_letter);
+            let name = staged
+                .or(*unstaged)
+                .map(|pair| display_path(pair, prefix))
+                .unwrap_or_default();
+            (*path, format!("{x}{y} {name}"))
+        })
+        .collect();
+    for (path, stages) in &unmerged {
+        output.insert(
+            path.as_os_str(),
+            format!(
+                "{} {}",
+                unmerged_code(stages),
+                relative(&path.to_string_lossy(), prefix)
+            ),
+        );
+    }
+    for line in output.values() {
+        writeln!(out, "{line}")?;
+    }
+    for path in untracked {
+        writeln!(out, "?? {}", relative(&path, prefix))?;
+    }
+    Ok(())
+}
+
+// Shows the status in the long format, which is also how commands like stash apply finish.
+pub fn print_long(repo: &Repository) -> io::Result<()> {
+    let staged = rename::detect(repo.head_to_index(), &RenameOptions::default());
+    write_long(
+        &mut io::stdout().lock(),
+        &repo.prefix,
+        &staged,
+        &unmerged_paths(repo),
+        &repo.index_to_workspace(),
+        &repo.untracked_files(),
+    )
+}
+
+// What commit shows when there's nothing to commit. When amending, that means nothing is staged compared to HEAD's parent, so
+// instead of the usual advice it just says there are no changes.
+pub fn print_nothing_to_commit(repo: &Repository, amend: bool) -> io::Result<()> {
+    if !amend {
+        return print_long(repo);
+    }
+    let mut out = io::stdout().lock();
+    write_sections(
+        &mut out,
+        &repo.prefix,
+        &[],
+        &unmerged_paths(repo),
+        &repo.index_to_workspace(),
+        &repo.untracked_files(),
+    )?;
+    writeln!(out, "No changes")
+}
+
+fn write_long(
+    out: &mut impl Write,
+    prefix: &Path,
+    staged: &[FilePair],
+    unmerged: &[(PathBuf, Stages)],
+    unstaged: &[FilePair],
+    untracked: &BTreeSet<String>,
+) -> io::Result<()> {
+    write_sections(out, prefix, staged, unmerged, unstaged, untracked)?;
+    if !staged.is_empty() {
+        return Ok(());
+    }
+    if !unmerged.is_empty() || !unstaged.is_empty() {
+        writeln!(
+            out,
+            "no changes added to commit (use \"git add\" and/or \"git commit -a\")"
+        )
+    } else if !untracked.is_empty() {
+        writeln!(
+            out,
+            "nothing added to commit but untracked files present (use \"git add\" to track)"
+        )
+    } else {
+        writeln!(out, "nothing to commit, working tree clean")
+    }
+}
+
+// The lists of staged changes, conflicted paths, unstaged changes and untracked files in the long format, each followed by
+// a blank line, with the paths relative to the prefix. Commit shows these (commented out) in the message template too.
+pub fn write_sections(
+    out: &mut impl Write,
+    prefix: &Path,
+    staged: &[FilePair],
+    unmerged: &[(PathBuf, Stages)],
+    unstaged: &[FilePair],
+    untracked: &BTreeSet<String>,
+) -> io::Result<()> {
+    if !staged.is_empty() {
+        writeln!(out, "Changes to be committed:")?;
+        for pair in staged {
+            writeln!(
+                out,
+                "\t{}{}",
+                status_label(pair),
+                display_path(pair, prefix)
+            )?;
+        }
+        writeln!(out)?;
+    }
+    if !unmerged.is_empty() {
+        writeln!(out, "Unmerged paths:")?;
+        for (path, stages) in unmerged {
+            let path = relative(&path.to_string_lossy(), prefix);
+            writeln!(out, "\t{}{path}", unmerged_label(stages))?;
+        }
+        writeln!(out)?;
+    }
+    if !unstaged.is_empty() {
+        writeln!(out, "Changes not staged for commit:")?;
+        for pair in unstaged {
+            writeln!(
+                out,
+                "\t{}{}",
+                status_label(pair),
+                display_path(pair, prefix)
+            )?;
+        }
+        writeln!(out)?;
+    }
+    if !untracked.is_empty() {
+        writeln!(out, "Untracked files:")?;
+        for path in untracked {
+            writeln!(out, "\t{}", relative(path, prefix))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use crate::commands::checkout::{self, Target};
+use crate::fatal::fatal;
+use crate::repository::Repository;
+use crate::revision;
+use std::io;
+
+// Like checkout, but only for branches: anything else has to be asked for with --detach.
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut new_branch = None;
+    let mut detach = false;
+    let mut quiet = false;
+    let mut revisions = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "-C" | "--create" | "--force-create" => {
+                let Some(name) = args.next() else {
+                    eprintln!(
+                        "error: option `{}' requires a value",
+                        arg.trim_start_matches('-')
+                    );
+                    std::process::exit(129);
+                };
+                new_branch = Some((name.to_string(), arg == "-C" || arg == "--force-create"));
+            }
+            "-d" | "--detach" => detach = true,
+            "-q" | "--quiet" => quiet = true,
+            // "-" is the branch we were on before.
+            "-" => revisions.push("@{-1}"),
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => revisions.push(arg.as_str()),
+        }
+    }
+    if revisions.len() > 1 {
+        fatal(&format!(
+            "only one reference expected, {} given.",
+            revisions.len()
+        ));
+    }
+    let previous = revisions
+        .first()
+        .and_then(|name| revision::previous_checkout(repo, name));
+    if let Some(previous) = &previous {
+        revisions[0] = previous;
+    }
+
+    let target = match (new_branch, revisions.first()) {
+        (Some((name, reset)), start) => {
+            checkout::new_branch_target(repo, &name, reset, start.copied())
+        }
+        (None, Some(name)) if detach => match revision::resolve(repo, name) {
+            Some(oid) => Target::detached(name, Some(oid), true),
+            None => fatal(&format!("invalid reference: {name}")),
+        },
+        (None, Some(name)) => match repo.refs.read_branch(name) {
+            Some(oid) => Target::branch(name, oid),
+            None if revision::resolve(repo, name).is_some() => {
+                let kind = if repo.refs.read_ref(&format!("refs/tags/{name}")).is_some() {
+                    "tag"
+                } else {
+                    "commit"
+                };
+                eprintln!("fatal: a branch is expected, got {kind} '{name}'");
+                eprintln!("hint: If you want to detach HEAD at the commit, try again with the --detach option.");
+                std::process::exit(128);
+            }
+            None => fatal(&format!("invalid reference: {name}")),
+        },
+        // "--detach" alone detaches HEAD where it is.
+        (None, None) if detach => Target::detached("HEAD", repo.refs.read_head(), true),
+        (None, None) => fatal("missing branch or commit argument"),
+    };
+    checkout::switch_logged(repo, &target, quiet)
+}
@@ -0,0 +1,306 @@
+use crate::commands::commit;
+use crate::commit::Author;
+use crate::fatal::fatal;
+use crate::object::Object;
+use crate::refs;
+use crate::repository::Repository;
+use crate::revision;
+use crate::tag::Tag;
+use crate::wildmatch::{self, MatchFlags};
+use std::cmp::Ordering;
+use std::fs;
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Create,
+    List,
+    Delete,
+}
+
+// The order tags are listed in: by name, or by the version numbers in their names (so that "v1.10" comes after "v1.9").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sort {
+    version: bool,
+    reverse: bool,
+}
+
+impl Sort {
+    fn parse(key: &str) -> Option<Self> {
+        let (reverse, key) = match key.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, key),
+        };
+        let version = match key {
+            "refname" => false,
+            "version:refname" | "v:refname" => true,
+            _ => return None,
+        };
+        Some(Sort { version, reverse })
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let ordering = match self.version {
+            true => compare_versions(a, b),
+            false => a.cmp(b),
+        };
+        match self.reverse {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+}
+
+pub fn run(repo: &mut Repository, args: &[String]) -> io::Result<()> {
+    let mut mode = None;
+    let mut annotate = false;
+    let mut force = false;
+    let mut messages = Vec::new();
+    let mut message_file = None;
+    let mut sort = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    let mut set_mode = |new_mode: Mode, flag: char| match mode {
+        Some(old) if old != new_mode => {
+            let old_name = if old == Mode::List {
+                "--list"
+            } else {
+                "--delete"
+            };
+            eprintln!("error: switch `{flag}' is incompatible with {old_name}");
+            std::process::exit(129);
+        }
+        _ => mode = Some(new_mode),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-l" | "--list" => set_mode(Mode::List, 'l'),
+            "-d" | "--delete" => set_mode(Mode::Delete, 'd'),
+            "-a" | "--annotate" => annotate = true,
+            "-f" | "--force" => force = true,
+            "-m" | "--message" => messages.push(required_value(args.next(), 'm')),
+            "-F" | "--file" => message_file = Some(required_value(args.next(), 'F')),
+            "--sort" => sort = Some(required_value(args.next(), 's')),
+            _ if arg.starts_with("--message=") => {
+                messages.push(arg["--message=".len()..].to_string())
+            }
+            _ if arg.starts_with("--file=") => {
+                message_file = Some(arg["--file=".len()..].to_string())
+            }
+            _ if arg.starts_with("--sort=") => sort = Some(arg["--sort=".len()..].to_string()),
+            _ if arg.starts_with("-m") => messages.push(arg[2..].to_string()),
+            _ if arg.starts_with('-') => fatal(&format!("unrecognized argument: {arg}")),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let mode = mode.unwrap_or(if positional.is_empty() {
+        Mode::List
+    } else {
+        Mode::Create
+    });
+    match mode {
+        Mode::List => {
+            let key = sort.or_else(|| repo.config.get("tag.sort").map(str::to_string));
+            let sort = match key {
+                Some(key) => Sort::parse(&key)
+                    .unwrap_or_else(|| fatal(&format!("unknown field name: {key}"))),
+                None => Sort {
+                    version: false,
+                    reverse: false,
+                },
+            };
+            list(repo, &positional, sort);
+            Ok(())
+        }
+        Mode::Delete => delete(repo, &positional),
+        Mode::Create => {
+            let message = match message_file {
+                Some(file) => Some(commit::read_message_file(&file, "could not open or read")),
+                None if messages.is_empty() => None,
+                // Each -m is a paragraph of its own.
+                None => Some(messages.join("\n\n")),
+            };
+            create(repo, &positional, annotate, force, message)
+        }
+    }
+}
+
+// Lists the tags whose names match any of the patterns (or all of them, if there are none).
+fn list(repo: &Repository, patterns: &[String], sort: Sort) {
+    let mut names: Vec<String> = repo
+        .refs
+        .list_refs()
+        .into_iter()
+        .filter_map(|(name, _)| name.strip_prefix("refs/tags/").map(str::to_string))
+        .filter(|name| {
+            patterns.is_empty()
+                || patterns
+                    .iter()
+                    .any(|pattern| wildmatch::wildmatch(pattern, name, MatchFlags::default()))
+        })
+        .collect();
+    names.sort_by(|a, b| sort.compare(a, b));
+    for name in names {
+        println!("{name}");
+    }
+}
+
+fn delete(repo: &mut Repository, names: &[String]) -> io::Result<()> {
+    let mut failed = false;
+    for name in names {
+        let Some(oid) = repo.refs.read_tag(name) else {
+            eprintln!("error: tag '{name}' not found.");
+            failed = true;
+            continue;
+        };
+        repo.refs.delete_ref(&format!("refs/tags/{name}"));
+        println!(
+            "Deleted tag '{name}' (was {})",
+            repo.database.abbreviate(&oid)
+        );
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// Makes a tag pointing at an object (HEAD by default). It's an annotated tag, a tag object with a message, if there's a
+// message or -a asks for one, and otherwise a lightweight tag, which is just the ref.
+fn create(
+    repo: &mut Repository,
+    args: &[String],
+    annotate: bool,
+    force: bool,
+    message: Option<String>,
+) -> io::Result<()> {
+    let (name, target) = match args {
+        [name] => (name, "HEAD"),
+        [name, target] => (name, target.as_str()),
+        _ => fatal("too many arguments"),
+    };
+    if !refs::is_valid_tag_name(name) {
+        fatal(&format!("'{name}' is not a valid tag name."));
+    }
+    let old = repo.refs.read_tag(name);
+    if old.is_some() && !force {
+        fatal(&format!("tag '{name}' already exists"));
+    }
+    let Some(object) = revision::resolve_object(repo, target) else {
+        fatal(&format!("Failed to resolve '{target}' as a valid ref."));
+    };
+
+    let mut oid = object;
+    if annotate || message.is_some() {
+        let message = match message {
+            Some(message) => commit::strip_message(&message),
+            None => edit_message(repo, name)?,
+        };
+        let (object_type, _) = repo.database.read_object(&object);
+        if object_type == "tag" && repo.config.get("advice.nestedTag") != Some("false") {
+            print_nested_tag_hint(name, target);
+        }
+        let tag = Tag::new(object, &object_type, name, Author::now(), message);
+        repo.database.store(&tag);
+        oid = *tag.get_oid();
+    }
+    repo.refs.set_tag(name, &oid);
+    if let Some(old) = old.filter(|old| *old != oid) {
+        println!(
+            "Updated tag '{name}' (was {})",
+            repo.database.abbreviate(&old)
+        );
+    }
+    Ok(())
+}
+
+// Asks for the message of an annotated tag in the editor. Like git, an empty message isn't allowed here.
+fn edit_message(repo: &Repository, name: &str) -> io::Result<String> {
+    let path = repo.git_path.join("TAG_EDITMSG");
+    fs::write(
+        &path,
+        format!(
+            "\n#\n# Write a message for tag:\n#   {name}\n# Lines starting with '#' will be ignored.\n"
+        ),
+    )?;
+    let editor = commit::editor(repo);
+    if !commit::launch_editor(&editor, &path)? {
+        eprintln!("error: There was a problem with the editor '{editor}'.");
+        eprintln!("Please supply the message using either -m or -F option.");
+        std::process::exit(1);
+    }
+    let message = commit::strip_message(&fs::read_to_string(&path)?);
+    if message.is_empty() {
+        fatal("no tag message?");
+    }
+    Ok(message)
+}
+
+fn print_nested_tag_hint(name: &str, target: &str) {
+    eprintln!("hint: You have created a nested tag. The object referred to by your new tag is");
+    eprintln!("hint: already a tag. If you meant to tag the object that it points to, use:");
+    eprintln!("hint: ");
+    eprintln!("hint: \tgit tag -f {name} {target}^{{}}");
+    eprintln!("hint: Disable this message with \"git config advice.nestedTag false\"");
+}
+
+// Compares names the way git's version sort does, where runs of digits compare as numbers.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+                let (a_len, b_len) = (digits(a), digits(b));
+                // Without leading zeros, the longer number is the larger one.
+                let number = |s: &[u8]| {
+                    let zeros = s.iter().take_while(|c| **c == b'0').count();
+                    s[zeros..].to_vec()
+                };
+                let (a_number, b_number) = (number(&a[..a_len]), number(&b[..b_len]));
+                let ordering = a_number
+                    .len()
+                    .cmp(&b_number.len())
+                    .then_with(|| a_number.cmp(&b_number));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                (a, b) = (&a[a_len..], &b[b_len..]);
+            }
+            (Some(x), Some(y)) if x != y => return x.cmp(y),
+            _ => (a, b) = (&a[1..], &b[1..]),
+        }
+    }
+}
+
+fn required_value(value: Option<&String>, switch: char) -> String {
+    match value {
+        Some(value) => value.clone(),
+        None => {
+            eprintln!("error: switch `{switch}' requires a value");
+            std::process::exit(129);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_versions() {
+        let mut names = vec![
+            "v1.10", "v1.2", "v10.0", "v1.9", "v2.0-rc1", "v2.0", "a1", "v1.2.3",
+        ];
+        names.sort_by(|a, b| compare_versions(a, b));
+        assert_eq!(
+            names,
+            ["a1", "v1.2", "v1.2.3", "v1.9", "v1.10", "v2.0", "v2.0-rc1", "v10.0"]
+        );
+        assert_eq!(compare_versions("v01", "v1"), Ordering::Equal);
+    }
+}
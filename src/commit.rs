@@ -1,20 +1,80 @@
-use std::time::SystemTime;
+use std::fmt;
 
+use crate::date;
 use crate::object::Object;
 use sha1_smol::{Digest, Sha1};
 
+// TODO eventually take these in from a config or args.
+const AUTHOR_NAME: &str = "bsubei";
+const AUTHOR_EMAIL: &str = "6508762+bsubei@users.noreply.github.com";
+
+// The name, email and time recorded for the author or committer of a commit, e.g. "A U Thor <a@u.thor> 1700000000 -0400".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+    // Seconds since the unix epoch.
+    pub timestamp: i64,
+    // The offset from UTC as written in the commit, e.g. "-0400".
+    pub timezone: String,
+}
+
+impl Author {
+    // Who is doing something right now (e.g. moving a ref), as recorded in a commit or a reflog.
+    pub fn now() -> Self {
+        Author {
+            name: AUTHOR_NAME.to_string(),
+            email: AUTHOR_EMAIL.to_string(),
+            timestamp: date::now(),
+            // TODO deal with timezones eventually, hardcoded mine (NYC) for now.
+            timezone: "-0400".to_string(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let (name, rest) = text.split_once(" <").expect("Author is missing an email");
+        let (email, time) = rest.split_once('>').expect("Author email is not closed");
+        let mut time = time.split_whitespace();
+        let timestamp = time
+            .next()
+            .and_then(|seconds| seconds.parse().ok())
+            .unwrap_or_default();
+        let timezone = time.next().unwrap_or("+0000").to_string();
+        Author {
+            name: name.to_string(),
+            email: email.to_string(),
+            timestamp,
+            timezone,
+        }
+    }
+}
+
+impl fmt::Display for Author {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name, self.email, self.timestamp, self.timezone
+        )
+    }
+}
+
 pub struct Commit {
     oid: Digest,
     content: Vec<u8>,
+    tree: Digest,
+    parents: Vec<Digest>,
+    author: Author,
+    committer: Author,
+    message: String,
 }
 
 impl Commit {
     pub fn new(
         tree_oid: Digest,
-        parent: Option<Digest>,
-        author_name: String,
-        author_email: String,
-        timestamp: SystemTime,
+        parents: Vec<Digest>,
+        author: Author,
+        committer: Author,
         commit_message: String,
     ) -> Self {
         // The data that the commit stores/points-to is the tree, author, committer, and the commit message.
@@ -22,24 +82,12 @@ impl Commit {
 
         data.push_str(&format!("tree {}\n", tree_oid));
 
-        if let Some(parent) = parent {
+        for parent in &parents {
             data.push_str(&format!("parent {}\n", parent));
         }
 
-        let seconds = timestamp
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("Could not get system time to record in commit")
-            .as_secs();
-        // TODO deal with timezones eventually, hardcoded mine (NYC) for now.
-        let timezone = "-0400";
-        let formatted_timestamp = format!("{seconds} {timezone}");
-        // TODO for now, commiter name/email are the same as author name/email.
-        data.push_str(&format!(
-            "author {author_name} <{author_email}> {formatted_timestamp}\n"
-        ));
-        data.push_str(&format!(
-            "committer {author_name} <{author_email}> {formatted_timestamp}\n\n"
-        ));
+        data.push_str(&format!("author {author}\n"));
+        data.push_str(&format!("committer {committer}\n\n"));
 
         data.push_str(&commit_message);
 
@@ -48,7 +96,85 @@ impl Commit {
 
         // The oid for this commit is the Sha of its content.
         let oid = Sha1::from(&content).digest();
-        Commit { oid, content }
+        Commit {
+            oid,
+            content,
+            tree: tree_oid,
+            parents,
+            author,
+            committer,
+            message: commit_message,
+        }
+    }
+
+    // Builds a Commit from the data of a commit object that was read back from the database.
+    pub fn parse(oid: Digest, data: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(data);
+        // The headers end at the first blank line, and everything after it is the message.
+        let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        for line in headers.lines() {
+            // Other headers (like signatures) can span several lines, but we don't need any of those.
+            match line.split_once(' ') {
+                Some(("tree", value)) => {
+                    tree = Some(value.parse().expect("Commit has an invalid tree oid"))
+                }
+                Some(("parent", value)) => {
+                    parents.push(value.parse().expect("Commit has an invalid parent oid"))
+                }
+                Some(("author", value)) => author = Some(Author::parse(value)),
+                Some(("committer", value)) => committer = Some(Author::parse(value)),
+                _ => {}
+            }
+        }
+
+        let content = [format!("commit {}\0", data.len()).as_bytes(), data].concat();
+        let author = author.expect("Commit is missing its author");
+        Commit {
+            oid,
+            content,
+            tree: tree.expect("Commit is missing its tree"),
+            parents,
+            committer: committer.unwrap_or_else(|| author.clone()),
+            author,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn get_tree(&self) -> &Digest {
+        &self.tree
+    }
+
+    pub fn get_parents(&self) -> &[Digest] {
+        &self.parents
+    }
+
+    pub fn get_author(&self) -> &Author {
+        &self.author
+    }
+
+    pub fn get_committer(&self) -> &Author {
+        &self.committer
+    }
+
+    // The header lines (tree, parents, author, committer and any others) exactly as they were stored, each ending in a newline.
+    pub fn get_headers(&self) -> String {
+        let header_end = self
+            .content
+            .iter()
+            .position(|b| *b == 0)
+            .expect("Commit is missing its object header");
+        let text = String::from_utf8_lossy(&self.content[header_end + 1..]);
+        let (headers, _) = text.split_once("\n\n").unwrap_or((&text, ""));
+        format!("{headers}\n")
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
     }
 }
 
@@ -60,3 +186,26 @@ impl Object for Commit {
         &self.content
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commit() {
+        let data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+                    parent 0000000000000000000000000000000000000001\n\
+                    parent 0000000000000000000000000000000000000002\n\
+                    author A U Thor <author@example.com> 1700000000 +0100\n\
+                    committer C O Mitter <committer@example.com> 1700000100 -0400\n\
+                    \n\
+                    Subject\n\nBody\n";
+        let commit = Commit::parse(Sha1::new().digest(), data.as_bytes());
+        assert_eq!(commit.get_parents().len(), 2);
+        assert_eq!(commit.get_author().name, "A U Thor");
+        assert_eq!(commit.get_author().timezone, "+0100");
+        assert_eq!(commit.get_committer().email, "committer@example.com");
+        assert_eq!(commit.get_committer().timestamp, 1700000100);
+        assert_eq!(commit.get_message(), "Subject\n\nBody\n");
+    }
+}
@@ -0,0 +1,159 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Settings from git's config files, e.g. "core.editor". The user's global files are read first and the repository's own
+// config last, so that its settings win.
+#[derive(Debug, Default)]
+pub struct Config {
+    // Each setting in the order it was read, with its section and name lowercased, e.g. ("core.editor", "vim").
+    values: Vec<(String, String)>,
+}
+
+impl Config {
+    pub fn load(git_path: &Path) -> Self {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let xdg = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|home| home.join(".config")));
+        let files = [
+            xdg.map(|xdg| xdg.join("git/config")),
+            home.map(|home| home.join(".gitconfig")),
+            Some(git_path.join("config")),
+        ];
+        let mut config = Config::default();
+        for text in files
+            .into_iter()
+            .flatten()
+            .filter_map(|file| fs::read_to_string(file).ok())
+        {
+            config.values.extend(parse(&text));
+        }
+        config
+    }
+
+    // The last value set for a key like "core.editor" (section and name are case-insensitive, subsections aren't).
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key);
+        self.values
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Lowercases the section and name of "section[.subsection].name", leaving the subsection as it is.
+fn normalize_key(key: &str) -> String {
+    let (section, rest) = key.split_once('.').unwrap_or((key, ""));
+    let (subsection, name) = match rest.rsplit_once('.') {
+        Some((subsection, name)) => (Some(subsection), name),
+        None => (None, rest),
+    };
+    let mut key = section.to_lowercase();
+    if let Some(subsection) = subsection {
+        key.push('.');
+        key.push_str(subsection);
+    }
+    key.push('.');
+    key.push_str(&name.to_lowercase());
+    key
+}
+
+// Reads the settings in a config file: "[section]" or "[section "subsection"]" headers, each followed by "name = value"
+// lines. Values can be quoted and use backslash escapes, "#" and ";" start comments, and a name on its own means "true".
+fn parse(text: &str) -> Vec<(String, String)> {
+    let mut values = Vec::new();
+    let mut section = String::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.split(']').next().unwrap_or_default();
+            section = match header.split_once(char::is_whitespace) {
+                Some((name, subsection)) => {
+                    let subsection = subsection.trim().trim_matches('"').replace("\\\"", "\"");
+                    format!("{}.{subsection}", name.to_lowercase())
+                }
+                // The old "[section.subsection]" syntax.
+                None => match header.split_once('.') {
+                    Some((name, subsection)) => format!("{}.{subsection}", name.to_lowercase()),
+                    None => header.to_lowercase(),
+                },
+            };
+            continue;
+        }
+
+        let (name, raw) = match line.split_once('=') {
+            Some((name, raw)) => (name.trim(), raw.trim().to_string()),
+            None => (line, "true".to_string()),
+        };
+        // A backslash at the end of a line continues the value on the next one.
+        let mut raw = raw;
+        while raw.ends_with('\\') && !raw.ends_with("\\\\") {
+            raw.pop();
+            raw.push_str(lines.next().unwrap_or_default().trim());
+        }
+        values.push((
+            format!("{section}.{}", name.to_lowercase()),
+            parse_value(&raw),
+        ));
+    }
+    values
+}
+
+fn parse_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('b') => {
+                    value.pop();
+                }
+                Some(other) => value.push(other),
+                None => {}
+            },
+            '#' | ';' if !quoted => break,
+            _ => value.push(c),
+        }
+    }
+    // Whitespace around the value is dropped, unless it was quoted.
+    if raw.ends_with('"') {
+        value
+    } else {
+        value.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "# a comment\n\
+                    [core]\n\
+                    \tEditor = vim -f ; trailing comment\n\
+                    \tbare\n\
+                    [remote \"Origin\"]\n\
+                    \turl = \"a \\\"b\\\" #c\"\n\
+                    [Core]\n\
+                    \teditor = nano\n";
+        let config = Config {
+            values: parse(text),
+        };
+        assert_eq!(config.get("core.editor"), Some("nano"));
+        assert_eq!(config.get("CORE.BARE"), Some("true"));
+        assert_eq!(config.get("remote.Origin.url"), Some("a \"b\" #c"));
+        assert_eq!(config.get("remote.origin.url"), None);
+        assert_eq!(config.get("core.missing"), None);
+    }
+}
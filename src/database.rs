@@ -1,9 +1,31 @@
-use crate::object::Object;
-use flate2::{write::ZlibEncoder, Compression};
+use crate::commit::Commit;
+use crate::object::{digest_from_bytes, Object};
+use crate::tag::Tag;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use sha1_smol::Digest;
+use std::collections::BTreeMap;
 use std::fs::File;
+use std::io::Read;
 
-use std::{fs, io::ErrorKind, io::Write, path::PathBuf};
+use std::{fs, io::ErrorKind, io::Write, path::Path, path::PathBuf};
+
+pub const TREE_MODE: u32 = 0o40000;
+
+// A single entry of a tree that was read back from the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseEntry {
+    pub oid: Digest,
+    pub mode: u32,
+}
+
+impl DatabaseEntry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == TREE_MODE
+    }
+}
+
+// Maps each changed path to its old and new entries (None means the path is absent on that side).
+pub type TreeDiff = BTreeMap<PathBuf, (Option<DatabaseEntry>, Option<DatabaseEntry>)>;
 
 pub struct Database {
     path: PathBuf,
@@ -21,9 +43,39 @@ impl Database {
         self.write_object(object.get_oid(), object.get_content());
     }
 
-    fn write_object(&self, oid: &Digest, content: &[u8]) {
+    fn object_path(&self, oid: &Digest) -> PathBuf {
         let oid = oid.to_string();
-        let object_path = self.path.join(&oid[0..2]).join(&oid[2..]);
+        self.path.join(&oid[0..2]).join(&oid[2..])
+    }
+
+    // Finds every stored object whose hex oid starts with the given prefix (which must be at least 2 characters long).
+    pub fn find_by_prefix(&self, prefix: &str) -> Vec<Digest> {
+        let prefix = prefix.to_ascii_lowercase();
+        let Ok(dir) = fs::read_dir(self.path.join(&prefix[..2])) else {
+            return Vec::new();
+        };
+        let mut oids: Vec<Digest> = dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| format!("{}{}", &prefix[..2], entry.file_name().to_string_lossy()))
+            .filter(|oid| oid.starts_with(&prefix))
+            .filter_map(|oid| oid.parse().ok())
+            .collect();
+        oids.sort();
+        oids
+    }
+
+    // Shortens an oid to the fewest hex characters (at least 7) that don't match any other stored object.
+    pub fn abbreviate(&self, oid: &Digest) -> String {
+        let hex = oid.to_string();
+        (7..hex.len())
+            .map(|len| &hex[..len])
+            .find(|prefix| self.find_by_prefix(prefix).len() <= 1)
+            .unwrap_or(&hex)
+            .to_string()
+    }
+
+    fn write_object(&self, oid: &Digest, content: &[u8]) {
+        let object_path = self.object_path(oid);
         let dirname = object_path
             .parent()
             .expect("Cannot get parent dir for object");
@@ -49,4 +101,152 @@ impl Database {
         f.write_all(&encoder.finish().expect("Could not flush deflate encode"))
             .expect("Could not write encoded data to blob file");
     }
+
+    // Reads the object with the given oid and returns its type (e.g. "blob") and its data (without the header).
+    pub fn read_object(&self, oid: &Digest) -> (String, Vec<u8>) {
+        let compressed = fs::read(self.object_path(oid))
+            .unwrap_or_else(|_| panic!("Could not read object {oid}"));
+        let mut content = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut content)
+            .expect("Could not inflate object");
+
+        // The header looks like "<type> <size>\0".
+        let header_end = content
+            .iter()
+            .position(|b| *b == 0)
+            .expect("Object is missing its header");
+        let header = String::from_utf8_lossy(&content[..header_end]).to_string();
+        let object_type = header
+            .split(' ')
+            .next()
+            .expect("Object header has no type")
+            .to_string();
+        (object_type, content[header_end + 1..].to_vec())
+    }
+
+    pub fn load_blob(&self, oid: &Digest) -> Vec<u8> {
+        let (object_type, data) = self.read_object(oid);
+        assert!(object_type == "blob", "Object {oid} is not a blob");
+        data
+    }
+
+    // Follows annotated tags to the object they tag, returning any other oid as it is.
+    pub fn peel(&self, oid: &Digest) -> Digest {
+        let (object_type, data) = self.read_object(oid);
+        if object_type != "tag" {
+            return *oid;
+        }
+        self.peel(Tag::parse(*oid, &data).get_object())
+    }
+
+    pub fn load_tag(&self, oid: &Digest) -> Tag {
+        let (object_type, data) = self.read_object(oid);
+        assert!(object_type == "tag", "Object {oid} is not a tag");
+        Tag::parse(*oid, &data)
+    }
+
+    pub fn load_commit(&self, oid: &Digest) -> Commit {
+        let (object_type, data) = self.read_object(oid);
+        assert!(object_type == "commit", "Object {oid} is not a commit");
+        Commit::parse(*oid, &data)
+    }
+
+    pub fn load_tree(&self, oid: &Digest) -> BTreeMap<PathBuf, DatabaseEntry> {
+        let (object_type, data) = self.read_object(oid);
+        assert!(object_type == "tree", "Object {oid} is not a tree");
+
+        // Each entry is "<mode> <name>\0" followed by 20 bytes of oid.
+        let mut entries = BTreeMap::new();
+        let mut rest = data.as_slice();
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|b| *b == b' ')
+                .expect("Tree entry is missing its mode");
+            let null = rest
+                .iter()
+                .position(|b| *b == 0)
+                .expect("Tree entry is missing its name");
+            let mode = u32::from_str_radix(&String::from_utf8_lossy(&rest[..space]), 8)
+                .expect("Tree entry mode is not octal");
+            let name = String::from_utf8_lossy(&rest[space + 1..null]).to_string();
+            entries.insert(
+                PathBuf::from(name),
+                DatabaseEntry {
+                    oid: digest_from_bytes(&rest[null + 1..null + 21]),
+                    mode,
+                },
+            );
+            rest = &rest[null + 21..];
+        }
+        entries
+    }
+
+    // Loads a tree and all of its subtrees, returning every blob in it keyed by its full path.
+    pub fn load_tree_entries(&self, oid: &Digest) -> BTreeMap<PathBuf, DatabaseEntry> {
+        let mut entries = BTreeMap::new();
+        for (name, entry) in self.load_tree(oid) {
+            if entry.is_tree() {
+                for (path, nested) in self.load_tree_entries(&entry.oid) {
+                    entries.insert(name.join(path), nested);
+                }
+            } else {
+                entries.insert(name, entry);
+            }
+        }
+        entries
+    }
+
+    // Compares two trees (either of which may be missing) and returns every blob path that differs between them.
+    pub fn tree_diff(&self, a: Option<&Digest>, b: Option<&Digest>) -> TreeDiff {
+        let mut changes = TreeDiff::new();
+        self.compare_trees(a, b, Path::new(""), &mut changes);
+        changes
+    }
+
+    fn compare_trees(
+        &self,
+        a: Option<&Digest>,
+        b: Option<&Digest>,
+        prefix: &Path,
+        changes: &mut TreeDiff,
+    ) {
+        if a == b {
+            return;
+        }
+        let a_entries = a.map(|oid| self.load_tree(oid)).unwrap_or_default();
+        let b_entries = b.map(|oid| self.load_tree(oid)).unwrap_or_default();
+
+        for (name, a_entry) in &a_entries {
+            let b_entry = b_entries.get(name);
+            if b_entry == Some(a_entry) {
+                continue;
+            }
+            let path = prefix.join(name);
+            let a_tree = a_entry.is_tree().then_some(&a_entry.oid);
+            let b_tree = b_entry.filter(|e| e.is_tree()).map(|e| &e.oid);
+            // Recurse into any tree, then record blobs on either side as changes.
+            if a_tree.is_some() || b_tree.is_some() {
+                self.compare_trees(a_tree, b_tree, &path, changes);
+            }
+            let a_blob = (!a_entry.is_tree()).then_some(*a_entry);
+            let b_blob = b_entry.filter(|e| !e.is_tree()).copied();
+            if a_blob.is_some() || b_blob.is_some() {
+                changes.insert(path, (a_blob, b_blob));
+            }
+        }
+
+        for (name, b_entry) in &b_entries {
+            if a_entries.contains_key(name) {
+                continue;
+            }
+            let path = prefix.join(name);
+            if b_entry.is_tree() {
+                self.compare_trees(None, Some(&b_entry.oid), &path, changes);
+            } else {
+                changes.insert(path, (None, Some(*b_entry)));
+            }
+        }
+    }
 }
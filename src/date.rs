@@ -0,0 +1,458 @@
+// Calendar math for the dates recorded in commits. Times are always handled as seconds since the unix epoch plus a "+hhmm" offset,
+// since that's all a commit records.
+use std::time::SystemTime;
+
+const DAY: i64 = 24 * 60 * 60;
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const LONG_WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const LONG_MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+// The ways a date can be shown, as chosen with --date.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DateMode {
+    #[default]
+    Default,
+    // "2023-10-05 12:34:56 -0400"
+    Iso,
+    // "2023-10-05T12:34:56-04:00"
+    IsoStrict,
+    // "Thu, 5 Oct 2023 12:34:56 -0400"
+    Rfc,
+    // "3 days ago"
+    Relative,
+    // "2023-10-05"
+    Short,
+    // "1696523696"
+    Unix,
+    // "1696523696 -0400"
+    Raw,
+    // A strftime format, e.g. "%Y/%m/%d".
+    Format(String),
+}
+
+impl DateMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        if let Some(format) = mode.strip_prefix("format:") {
+            return Some(DateMode::Format(format.to_string()));
+        }
+        let mode = match mode {
+            "default" => DateMode::Default,
+            "iso" | "iso8601" => DateMode::Iso,
+            "iso-strict" | "iso8601-strict" => DateMode::IsoStrict,
+            "rfc" | "rfc2822" => DateMode::Rfc,
+            "relative" => DateMode::Relative,
+            "short" => DateMode::Short,
+            "unix" => DateMode::Unix,
+            "raw" => DateMode::Raw,
+            _ => return None,
+        };
+        Some(mode)
+    }
+}
+
+// Broken-down wall clock time, like C's struct tm (but with 1-based months and days).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    // 0 is Sunday.
+    pub weekday: u32,
+}
+
+// Days since 1970-01-01 for a date in the proleptic Gregorian calendar (Howard Hinnant's days_from_civil).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// The inverse of days_from_civil.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Converts an offset like "-0430" into seconds east of UTC.
+pub fn tz_offset_seconds(timezone: &str) -> i64 {
+    let (sign, digits) = match timezone.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, timezone.trim_start_matches('+')),
+    };
+    let value: i64 = digits.parse().unwrap_or_default();
+    sign * ((value / 100) * 3600 + (value % 100) * 60)
+}
+
+// Breaks a timestamp down into the wall clock time at the given offset.
+pub fn to_datetime(timestamp: i64, timezone: &str) -> DateTime {
+    let local = timestamp + tz_offset_seconds(timezone);
+    let days = local.div_euclid(DAY);
+    let seconds = local.rem_euclid(DAY);
+    let (year, month, day) = civil_from_days(days);
+    DateTime {
+        year,
+        month,
+        day,
+        hour: (seconds / 3600) as u32,
+        minute: (seconds / 60 % 60) as u32,
+        second: (seconds % 60) as u32,
+        weekday: (days + 4).rem_euclid(7) as u32,
+    }
+}
+
+fn from_datetime(datetime: &DateTime, offset: i64) -> i64 {
+    days_from_civil(datetime.year, datetime.month, datetime.day) * DAY
+        + (datetime.hour * 3600 + datetime.minute * 60 + datetime.second) as i64
+        - offset
+}
+
+// The default format git uses for dates, e.g. "Thu Oct 5 12:34:56 2023 -0400".
+pub fn format_default(timestamp: i64, timezone: &str) -> String {
+    let dt = to_datetime(timestamp, timezone);
+    format!(
+        "{} {} {} {:02}:{:02}:{:02} {} {timezone}",
+        WEEKDAYS[dt.weekday as usize],
+        MONTHS[dt.month as usize - 1],
+        dt.day,
+        dt.hour,
+        dt.minute,
+        dt.second,
+        dt.year
+    )
+}
+
+// Shows a date the way the given mode asks for. `now` is only needed for relative dates.
+pub fn format(timestamp: i64, timezone: &str, mode: &DateMode, now: i64) -> String {
+    match mode {
+        DateMode::Default => format_default(timestamp, timezone),
+        DateMode::Iso => strftime("%Y-%m-%d %H:%M:%S %z", timestamp, timezone),
+        DateMode::IsoStrict => {
+            let offset = if timezone == "+0000" {
+                "Z".to_string()
+            } else {
+                format!("{}:{}", &timezone[..3], &timezone[3..])
+            };
+            strftime("%Y-%m-%dT%H:%M:%S", timestamp, timezone) + &offset
+        }
+        DateMode::Rfc => strftime("%a, %-d %b %Y %H:%M:%S %z", timestamp, timezone),
+        DateMode::Relative => format_relative(timestamp, now),
+        DateMode::Short => strftime("%Y-%m-%d", timestamp, timezone),
+        DateMode::Unix => timestamp.to_string(),
+        DateMode::Raw => format!("{timestamp} {timezone}"),
+        DateMode::Format(format) => strftime(format, timestamp, timezone),
+    }
+}
+
+// Describes how long ago a date was, rounding like git does, e.g. "5 minutes ago" or "2 years, 3 months ago".
+pub fn format_relative(timestamp: i64, now: i64) -> String {
+    let plural = |count: i64, unit: &str| {
+        if count == 1 {
+            format!("{count} {unit}")
+        } else {
+            format!("{count} {unit}s")
+        }
+    };
+    if timestamp > now {
+        return "in the future".to_string();
+    }
+    let seconds = now - timestamp;
+    if seconds < 90 {
+        return format!("{} ago", plural(seconds, "second"));
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return format!("{} ago", plural(minutes, "minute"));
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return format!("{} ago", plural(hours, "hour"));
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return format!("{} ago", plural(days, "day"));
+    }
+    if days < 70 {
+        return format!("{} ago", plural((days + 3) / 7, "week"));
+    }
+    if days < 365 {
+        return format!("{} ago", plural((days + 15) / 30, "month"));
+    }
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months > 0 {
+            return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+        }
+        return format!("{} ago", plural(years, "year"));
+    }
+    format!("{} ago", plural((days + 183) / 365, "year"))
+}
+
+// Formats a date at the given offset with the usual strftime conversions. A '-' flag (e.g. "%-d") drops the padding.
+pub fn strftime(format: &str, timestamp: i64, timezone: &str) -> String {
+    let dt = to_datetime(timestamp, timezone);
+    let day_of_year =
+        days_from_civil(dt.year, dt.month, dt.day) - days_from_civil(dt.year, 1, 1) + 1;
+    let hour12 = if dt.hour.is_multiple_of(12) {
+        12
+    } else {
+        dt.hour % 12
+    };
+
+    let mut result = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        let mut conversion = chars.next();
+        let unpadded = conversion == Some('-');
+        if unpadded {
+            conversion = chars.next();
+        }
+        let number = |value: i64, width: usize| {
+            if unpadded {
+                value.to_string()
+            } else {
+                format!("{value:0width$}")
+            }
+        };
+        let text = match conversion {
+            Some('a') => WEEKDAYS[dt.weekday as usize].to_string(),
+            Some('A') => LONG_WEEKDAYS[dt.weekday as usize].to_string(),
+            Some('b') | Some('h') => MONTHS[dt.month as usize - 1].to_string(),
+            Some('B') => LONG_MONTHS[dt.month as usize - 1].to_string(),
+            Some('d') => number(dt.day as i64, 2),
+            Some('e') => format!("{:2}", dt.day),
+            Some('H') => number(dt.hour as i64, 2),
+            Some('I') => number(hour12 as i64, 2),
+            Some('j') => number(day_of_year, 3),
+            Some('m') => number(dt.month as i64, 2),
+            Some('M') => number(dt.minute as i64, 2),
+            Some('p') => if dt.hour < 12 { "AM" } else { "PM" }.to_string(),
+            Some('S') => number(dt.second as i64, 2),
+            Some('s') => timestamp.to_string(),
+            Some('u') => (if dt.weekday == 0 { 7 } else { dt.weekday }).to_string(),
+            Some('w') => dt.weekday.to_string(),
+            Some('y') => number(dt.year % 100, 2),
+            Some('Y') => dt.year.to_string(),
+            Some('z') | Some('Z') => timezone.to_string(),
+            Some('F') => strftime("%Y-%m-%d", timestamp, timezone),
+            Some('T') => strftime("%H:%M:%S", timestamp, timezone),
+            Some('R') => strftime("%H:%M", timestamp, timezone),
+            Some('D') => strftime("%m/%d/%y", timestamp, timezone),
+            Some('n') => "\n".to_string(),
+            Some('t') => "\t".to_string(),
+            Some('%') => "%".to_string(),
+            Some(other) => format!("%{other}"),
+            None => "%".to_string(),
+        };
+        result.push_str(&text);
+    }
+    result
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Could not get the current time")
+        .as_secs() as i64
+}
+
+// Parses the kinds of dates people pass to --since and --until: "@1700000000", "2023-10-05", "2023-10-05 12:34[:56] [+hhmm]",
+// "yesterday", "now", or "<n> <unit>s ago" (units from seconds up to years). Dates without an offset are taken as UTC.
+pub fn parse_approximate(text: &str, now: i64) -> Option<i64> {
+    let text = text.trim();
+    if let Some(seconds) = text.strip_prefix('@') {
+        return seconds.parse().ok();
+    }
+    match text {
+        "now" => return Some(now),
+        "yesterday" => return Some(now - DAY),
+        _ => {}
+    }
+    if let Some(date) = parse_iso(text, now) {
+        return Some(date);
+    }
+    parse_relative(text, now)
+}
+
+// "YYYY-MM-DD", optionally followed by a time and offset. Like git, a date on its own keeps the current time of day.
+fn parse_iso(text: &str, now: i64) -> Option<i64> {
+    let date = text.get(..10)?;
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if date.len() != 10 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut rest = text[10..].trim_start_matches(['T', ' ']).split_whitespace();
+    let (hour, minute, second) = match rest.next() {
+        Some(time) => {
+            let mut fields = time.trim_end_matches('Z').split(':');
+            let hour = fields.next()?.parse().ok()?;
+            let minute = fields.next()?.parse().ok()?;
+            let second = fields.next().map_or(Some(0), |s| s.parse().ok())?;
+            (hour, minute, second)
+        }
+        None => {
+            let current = to_datetime(now, "+0000");
+            (current.hour, current.minute, current.second)
+        }
+    };
+    let offset = rest.next().map_or(0, tz_offset_seconds);
+    let datetime = DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        weekday: 0,
+    };
+    Some(from_datetime(&datetime, offset))
+}
+
+// "<n> <unit> ago", where the words can also be separated by dots (e.g. "2.weeks.ago").
+fn parse_relative(text: &str, now: i64) -> Option<i64> {
+    let words: Vec<&str> = text.split([' ', '.']).filter(|w| !w.is_empty()).collect();
+    let [count, unit, "ago"] = words.as_slice() else {
+        return None;
+    };
+    let count: i64 = count.parse().ok()?;
+    let seconds = match unit.trim_end_matches('s') {
+        "second" | "sec" => 1,
+        "minute" | "min" => 60,
+        "hour" => 3600,
+        "day" => DAY,
+        "week" => 7 * DAY,
+        // Months and years move the calendar date back, keeping the time of day.
+        "month" | "year" => {
+            let months = if unit.starts_with("month") {
+                count
+            } else {
+                count * 12
+            };
+            let mut datetime = to_datetime(now, "+0000");
+            let total = datetime.year * 12 + datetime.month as i64 - 1 - months;
+            datetime.year = total.div_euclid(12);
+            datetime.month = total.rem_euclid(12) as u32 + 1;
+            return Some(from_datetime(&datetime, 0));
+        }
+        _ => return None,
+    };
+    Some(now - count * seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_default() {
+        assert_eq!(
+            format_default(1700000000, "+0000"),
+            "Tue Nov 14 22:13:20 2023 +0000"
+        );
+        assert_eq!(
+            format_default(1700000000, "-0530"),
+            "Tue Nov 14 16:43:20 2023 -0530"
+        );
+        assert_eq!(format_default(0, "+0100"), "Thu Jan 1 01:00:00 1970 +0100");
+    }
+
+    #[test]
+    fn test_format_modes() {
+        let (timestamp, timezone) = (1704700800, "+0200");
+        let show = |mode: &str| format(timestamp, timezone, &DateMode::parse(mode).unwrap(), 0);
+        assert_eq!(show("iso"), "2024-01-08 10:00:00 +0200");
+        assert_eq!(show("iso-strict"), "2024-01-08T10:00:00+02:00");
+        assert_eq!(show("rfc"), "Mon, 8 Jan 2024 10:00:00 +0200");
+        assert_eq!(show("short"), "2024-01-08");
+        assert_eq!(show("unix"), "1704700800");
+        assert_eq!(
+            show("format:%A %e %B %j %I%p %%"),
+            "Monday  8 January 008 10AM %"
+        );
+        assert_eq!(DateMode::parse("bogus"), None);
+    }
+
+    #[test]
+    fn test_format_relative() {
+        let now = 1_000_000_000;
+        assert_eq!(format_relative(now - 1, now), "1 second ago");
+        assert_eq!(format_relative(now - 3 * 3600, now), "3 hours ago");
+        assert_eq!(format_relative(now - 20 * DAY, now), "3 weeks ago");
+        assert_eq!(
+            format_relative(now - 1000 * DAY, now),
+            "2 years, 9 months ago"
+        );
+        assert_eq!(format_relative(now - 4000 * DAY, now), "11 years ago");
+        assert_eq!(format_relative(now + 1, now), "in the future");
+    }
+
+    #[test]
+    fn test_parse_approximate() {
+        let now = 1700000000;
+        assert_eq!(parse_approximate("@123", now), Some(123));
+        assert_eq!(
+            parse_approximate("2023-11-14 22:13:20", now),
+            Some(1700000000)
+        );
+        assert_eq!(
+            parse_approximate("2023-11-14T16:43:20 -0530", now),
+            Some(1700000000)
+        );
+        assert_eq!(parse_approximate("2023-11-13", now), Some(now - DAY));
+        assert_eq!(parse_approximate("2.weeks.ago", now), Some(now - 14 * DAY));
+        assert_eq!(
+            parse_approximate("1 year ago", now),
+            parse_approximate("2022-11-14 22:13:20", now)
+        );
+        assert_eq!(parse_approximate("whenever", now), None);
+    }
+}
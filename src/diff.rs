@@ -0,0 +1,822 @@
+use crate::attributes::{AttrValue, Attributes};
+use crate::binary_patch;
+use crate::database::{Database, TreeDiff};
+use crate::rename;
+use sha1_smol::Digest;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// How many unchanged lines to show around each change in a hunk.
+pub const HUNK_CONTEXT: usize = 3;
+// How much of the start of a file to look at when deciding if it's binary (same as git).
+const BINARY_CHECK_SIZE: usize = 8000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditKind {
+    Eql,
+    Ins,
+    Del,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Line<'a> {
+    // Line numbers are 1-based, just like in the hunk headers.
+    pub number: usize,
+    pub text: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edit<'a> {
+    pub kind: EditKind,
+    pub a_line: Option<Line<'a>>,
+    pub b_line: Option<Line<'a>>,
+}
+
+impl Edit<'_> {
+    pub fn text(&self) -> &[u8] {
+        self.a_line.or(self.b_line).expect("Edit has no lines").text
+    }
+}
+
+// Splits data into lines, keeping the newline at the end of each line (so we can tell if the last line is missing one).
+pub fn lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|b| *b == b'\n').collect()
+}
+
+// Guesses whether data is binary by looking at its start: any NUL byte makes it binary, and so does having more than one
+// non-printable character per 128 printable ones (the same heuristic git uses when converting line endings).
+pub fn is_binary(data: &[u8]) -> bool {
+    let sample = &data[..data.len().min(BINARY_CHECK_SIZE)];
+    let mut printable = 0;
+    let mut nonprintable = 0;
+    for byte in sample {
+        match byte {
+            0 => return true,
+            b'\n' | b'\r' => {}
+            // Backspace, tab, escape and form feed are common in text files.
+            0x08 | b'\t' | 0x1b | 0x0c => printable += 1,
+            0x7f => nonprintable += 1,
+            byte if *byte < 0x20 => nonprintable += 1,
+            _ => printable += 1,
+        }
+    }
+    // A trailing EOF character (^Z) doesn't count.
+    if sample.last() == Some(&0x1a) {
+        nonprintable -= 1;
+    }
+    (printable >> 7) < nonprintable
+}
+
+// Finds the shortest edit script that turns a into b using the Myers algorithm (see the book's "The Myers diff algorithm" chapter),
+// in its linear space form: rather than keeping the furthest reaching x of every diagonal for every edit distance so that the
+// path can be traced back, we search forwards from the start and backwards from the end at the same time until the searches
+// meet in a "middle snake", and then diff the parts on either side of it the same way. The changes found are then shifted
+// to where git would put them, and like in git, the lines each change removes come before the lines it adds.
+pub fn diff<'a>(a: &[&'a [u8]], b: &[&'a [u8]]) -> Vec<Edit<'a>> {
+    let max_d = (a.len() + b.len()).div_ceil(2) + 1;
+    let mut myers = Myers {
+        a,
+        b,
+        forward: Diagonals::new(max_d),
+        backward: Diagonals::new(max_d),
+        removed: vec![false; a.len()],
+        added: vec![false; b.len()],
+    };
+    myers.conquer(0..a.len(), 0..b.len());
+    let Myers {
+        mut removed,
+        mut added,
+        ..
+    } = myers;
+    compact(a, &mut removed, &added);
+    compact(b, &mut added, &removed);
+
+    let line = |lines: &[&'a [u8]], i: usize| Line {
+        number: i + 1,
+        text: lines[i],
+    };
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    while x < a.len() || y < b.len() {
+        if x < a.len() && removed[x] {
+            edits.push(Edit {
+                kind: EditKind::Del,
+                a_line: Some(line(a, x)),
+                b_line: None,
+            });
+            x += 1;
+        } else if y < b.len() && added[y] {
+            edits.push(Edit {
+                kind: EditKind::Ins,
+                a_line: None,
+                b_line: Some(line(b, y)),
+            });
+            y += 1;
+        } else {
+            edits.push(Edit {
+                kind: EditKind::Eql,
+                a_line: Some(line(a, x)),
+                b_line: Some(line(b, y)),
+            });
+            x += 1;
+            y += 1;
+        }
+    }
+    edits
+}
+
+// A run of changed lines in one side of a diff, from start up to (but not including) end. It's empty between two unchanged
+// lines.
+#[derive(Clone, Copy)]
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn first(changed: &[bool]) -> Self {
+        let mut group = Group { start: 0, end: 0 };
+        group.extend_down(changed);
+        group
+    }
+
+    fn extend_down(&mut self, changed: &[bool]) {
+        while self.end < changed.len() && changed[self.end] {
+            self.end += 1;
+        }
+    }
+
+    fn extend_up(&mut self, changed: &[bool]) {
+        while self.start > 0 && changed[self.start - 1] {
+            self.start -= 1;
+        }
+    }
+
+    fn next(&mut self, changed: &[bool]) -> bool {
+        if self.end == changed.len() {
+            return false;
+        }
+        self.start = self.end + 1;
+        self.end = self.start;
+        self.extend_down(changed);
+        true
+    }
+
+    fn previous(&mut self, changed: &[bool]) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+        self.end = self.start - 1;
+        self.start = self.end;
+        self.extend_up(changed);
+        true
+    }
+
+    // Moves a group down a line, which works when the line after it is the same as its first line. It then joins any group
+    // that follows it.
+    fn slide_down(&mut self, lines: &[&[u8]], changed: &mut [bool]) -> bool {
+        if self.end == lines.len() || lines[self.start] != lines[self.end] {
+            return false;
+        }
+        changed[self.start] = false;
+        changed[self.end] = true;
+        self.start += 1;
+        self.end += 1;
+        self.extend_down(changed);
+        true
+    }
+
+    fn slide_up(&mut self, lines: &[&[u8]], changed: &mut [bool]) -> bool {
+        if self.start == 0 || lines[self.start - 1] != lines[self.end - 1] {
+            return false;
+        }
+        self.start -= 1;
+        self.end -= 1;
+        changed[self.start] = true;
+        changed[self.end] = false;
+        self.extend_up(changed);
+        true
+    }
+}
+
+// Shifts each group of changed lines in one side of a diff as far down as it goes, unless it can line up with a change on the
+// other side, merging groups that meet along the way. This is git's xdl_change_compact, which makes diffs of the same change
+// come out the same way however they were found.
+fn compact(lines: &[&[u8]], changed: &mut [bool], other_changed: &[bool]) {
+    let mut group = Group::first(changed);
+    let mut other = Group::first(other_changed);
+    loop {
+        if group.end != group.start {
+            let mut end_matching_other = None;
+            let mut earliest_end;
+            loop {
+                let size = group.end - group.start;
+                while group.slide_up(lines, changed) {
+                    assert!(other.previous(other_changed), "Groups are out of step");
+                }
+                earliest_end = group.end;
+                if other.end > other.start {
+                    end_matching_other = Some(group.end);
+                }
+                while group.slide_down(lines, changed) {
+                    assert!(other.next(other_changed), "Groups are out of step");
+                    if other.end > other.start {
+                        end_matching_other = Some(group.end);
+                    }
+                }
+                if size == group.end - group.start {
+                    break;
+                }
+            }
+            // Move the group back up to line up with the last change on the other side it went past.
+            if group.end != earliest_end && end_matching_other.is_some() {
+                while other.end == other.start {
+                    assert!(group.slide_up(lines, changed), "Group can't slide back up");
+                    assert!(other.previous(other_changed), "Groups are out of step");
+                }
+            }
+        }
+        if !group.next(changed) {
+            break;
+        }
+        assert!(other.next(other_changed), "Groups are out of step");
+    }
+}
+
+// The furthest reaching x on each diagonal k (from -max_d to max_d) for the edit distance being searched.
+struct Diagonals {
+    offset: isize,
+    x: Vec<usize>,
+}
+
+impl Diagonals {
+    fn new(max_d: usize) -> Self {
+        Diagonals {
+            offset: max_d as isize,
+            x: vec![0; 2 * max_d + 1],
+        }
+    }
+}
+
+impl std::ops::Index<isize> for Diagonals {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.x[(k + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for Diagonals {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.x[(k + self.offset) as usize]
+    }
+}
+
+struct Myers<'a, 'b> {
+    a: &'b [&'a [u8]],
+    b: &'b [&'a [u8]],
+    forward: Diagonals,
+    backward: Diagonals,
+    // Which lines of a are removed, and which lines of b are added.
+    removed: Vec<bool>,
+    added: Vec<bool>,
+}
+
+impl Myers<'_, '_> {
+    // Finds the lines that are removed and added to turn one range of a's lines into one range of b's.
+    fn conquer(&mut self, mut a_range: Range<usize>, mut b_range: Range<usize>) {
+        let prefix = self.common_prefix(a_range.clone(), b_range.clone());
+        a_range.start += prefix;
+        b_range.start += prefix;
+        let suffix = self.common_suffix(a_range.clone(), b_range.clone());
+        a_range.end -= suffix;
+        b_range.end -= suffix;
+
+        if a_range.is_empty() {
+            self.added[b_range].fill(true);
+        } else if b_range.is_empty() {
+            self.removed[a_range].fill(true);
+        } else {
+            let (x, y) = self.middle_snake(a_range.clone(), b_range.clone());
+            self.conquer(a_range.start..x, b_range.start..y);
+            self.conquer(x..a_range.end, y..b_range.end);
+        }
+    }
+
+    // Finds where the forward and backward searches over two ranges meet, returning the start of the snake the forward
+    // search was on, which splits the ranges into two smaller problems. The ranges must both be non-empty and can't start or
+    // end with the same line.
+    fn middle_snake(&mut self, a_range: Range<usize>, b_range: Range<usize>) -> (usize, usize) {
+        let (n, m) = (a_range.len(), b_range.len());
+        let delta = n as isize - m as isize;
+        let odd = delta & 1 == 1;
+        self.forward[1] = 0;
+        self.backward[1] = 0;
+
+        for d in 0..=((n + m).div_ceil(2) as isize) {
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && self.forward[k - 1] < self.forward[k + 1]) {
+                    self.forward[k + 1]
+                } else {
+                    self.forward[k - 1] + 1
+                };
+                let y = (x as isize - k) as usize;
+                let (start_x, start_y) = (x, y);
+                if x < n && y < m {
+                    x += self.common_prefix(
+                        a_range.start + x..a_range.end,
+                        b_range.start + y..b_range.end,
+                    );
+                }
+                self.forward[k] = x;
+                if odd && (k - delta).abs() < d && x + self.backward[delta - k] >= n {
+                    return (a_range.start + start_x, b_range.start + start_y);
+                }
+            }
+
+            // Backwards, x and y count lines from the end of the ranges.
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && self.backward[k - 1] < self.backward[k + 1]) {
+                    self.backward[k + 1]
+                } else {
+                    self.backward[k - 1] + 1
+                };
+                let mut y = (x as isize - k) as usize;
+                if x < n && y < m {
+                    let len = self.common_suffix(
+                        a_range.start..a_range.end - x,
+                        b_range.start..b_range.end - y,
+                    );
+                    x += len;
+                    y += len;
+                }
+                self.backward[k] = x;
+                if !odd && (k - delta).abs() <= d && x + self.forward[delta - k] >= n {
+                    return (a_range.end - x, b_range.end - y);
+                }
+            }
+        }
+        unreachable!("The searches always meet by half the edit distance")
+    }
+
+    fn common_prefix(&self, a_range: Range<usize>, b_range: Range<usize>) -> usize {
+        self.a[a_range]
+            .iter()
+            .zip(&self.b[b_range])
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+
+    fn common_suffix(&self, a_range: Range<usize>, b_range: Range<usize>) -> usize {
+        self.a[a_range]
+            .iter()
+            .rev()
+            .zip(self.b[b_range].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+}
+
+// A group of nearby edits along with their surrounding context lines.
+#[derive(Debug)]
+pub struct Hunk<'a> {
+    a_start: usize,
+    b_start: usize,
+    pub edits: Vec<Edit<'a>>,
+}
+
+impl Hunk<'_> {
+    // Formats the "@@ -a,b +c,d @@" line. Like git, the count is omitted when it's 1, and an empty range points at the line before it.
+    pub fn header(&self) -> String {
+        let a_len = self.edits.iter().filter(|e| e.a_line.is_some()).count();
+        let b_len = self.edits.iter().filter(|e| e.b_line.is_some()).count();
+        format!(
+            "@@ -{} +{} @@",
+            Self::format_range(self.a_start, a_len),
+            Self::format_range(self.b_start, b_len)
+        )
+    }
+
+    fn format_range(start: usize, len: usize) -> String {
+        match len {
+            0 => format!("{},0", start.saturating_sub(1)),
+            1 => format!("{start}"),
+            _ => format!("{start},{len}"),
+        }
+    }
+}
+
+// Groups edits into hunks, merging changes that are close enough for their context lines to overlap.
+pub fn hunks<'a>(edits: &[Edit<'a>]) -> Vec<Hunk<'a>> {
+    let mut hunks = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        // Skip to the next change.
+        while offset < edits.len() && edits[offset].kind == EditKind::Eql {
+            offset += 1;
+        }
+        if offset >= edits.len() {
+            return hunks;
+        }
+
+        let start = offset.saturating_sub(HUNK_CONTEXT);
+        let a_start = edits[start..]
+            .iter()
+            .find_map(|e| e.a_line)
+            .map(|line| line.number)
+            .unwrap_or_else(|| line_before(edits, start, |e| e.a_line));
+        let b_start = edits[start..]
+            .iter()
+            .find_map(|e| e.b_line)
+            .map(|line| line.number)
+            .unwrap_or_else(|| line_before(edits, start, |e| e.b_line));
+
+        // Keep extending the hunk while the next change is at most 2 * context lines away (so their context would touch).
+        let mut end = offset;
+        let mut last_change = offset;
+        while end < edits.len() && end <= last_change + 2 * HUNK_CONTEXT + 1 {
+            if edits[end].kind != EditKind::Eql {
+                last_change = end;
+            }
+            end += 1;
+        }
+        let end = (last_change + HUNK_CONTEXT + 1).min(edits.len());
+
+        hunks.push(Hunk {
+            a_start,
+            b_start,
+            edits: edits[start..end].to_vec(),
+        });
+        offset = end;
+    }
+}
+
+// When one side of a hunk has no lines at all, its start is one past the last line that came before the hunk on that side.
+fn line_before<'a>(
+    edits: &[Edit<'a>],
+    start: usize,
+    side: impl Fn(&Edit<'a>) -> Option<Line<'a>>,
+) -> usize {
+    edits[..start]
+        .iter()
+        .rev()
+        .find_map(side)
+        .map(|line| line.number + 1)
+        .unwrap_or(1)
+}
+
+// One side of a file comparison: the blob's oid, its mode, and its contents.
+#[derive(Debug, Clone)]
+pub struct Side {
+    pub oid: Digest,
+    pub mode: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OriginKind {
+    Rename,
+    Copy,
+}
+
+// Where a renamed or copied file came from, and how similar (out of rename::MAX_SCORE) it is to its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub path: PathBuf,
+    pub similarity: usize,
+    pub kind: OriginKind,
+}
+
+// A changed path along with its old (a) and new (b) versions. A missing side means the file was added or deleted.
+#[derive(Debug, Clone)]
+pub struct FilePair {
+    pub path: PathBuf,
+    pub a: Option<Side>,
+    pub b: Option<Side>,
+    // Set when the "diff" attribute (or --text) decides whether this is binary instead of looking at the contents.
+    pub forced_binary: Option<bool>,
+    // Set when rename/copy detection found that this file came from another path (which the a side then belongs to).
+    pub origin: Option<Origin>,
+}
+
+impl FilePair {
+    pub fn new(path: PathBuf, a: Option<Side>, b: Option<Side>) -> Self {
+        FilePair {
+            path,
+            a,
+            b,
+            forced_binary: None,
+            origin: None,
+        }
+    }
+
+    // The path of the old side, which differs from the new one for renames and copies.
+    pub fn old_path(&self) -> &Path {
+        self.origin
+            .as_ref()
+            .map_or(self.path.as_path(), |origin| origin.path.as_path())
+    }
+
+    // The name shown in stats and summaries: either the path, or something like "dir/{old => new}.rs" for renames and copies.
+    pub fn display_name(&self) -> String {
+        match &self.origin {
+            Some(origin) => {
+                pprint_rename(&origin.path.to_string_lossy(), &self.path.to_string_lossy())
+            }
+            None => self.path.display().to_string(),
+        }
+    }
+
+    // The percentage shown in "similarity index" lines and summaries.
+    pub fn similarity_percent(&self) -> Option<usize> {
+        self.origin
+            .as_ref()
+            .map(|origin| origin.similarity * 100 / rename::MAX_SCORE)
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.forced_binary.unwrap_or_else(|| {
+            [&self.a, &self.b]
+                .into_iter()
+                .flatten()
+                .any(|side| is_binary(&side.data))
+        })
+    }
+
+    // Applies the "diff" gitattribute: "-diff" (or "binary") always treats the file as binary, while "diff" or a named diff
+    // driver always treats it as text.
+    pub fn apply_attributes(&mut self, attributes: &Attributes) {
+        self.forced_binary = match attributes.get(&self.path, "diff") {
+            Some(AttrValue::Unset) => Some(true),
+            Some(AttrValue::Set) | Some(AttrValue::Value(_)) => Some(false),
+            None => None,
+        };
+    }
+
+    // Returns the line-level edits between the two sides (an empty list for binary files).
+    pub fn edits(&self) -> Vec<Edit<'_>> {
+        if self.is_binary() {
+            return Vec::new();
+        }
+        let a = self.a.as_ref().map(|s| lines(&s.data)).unwrap_or_default();
+        let b = self.b.as_ref().map(|s| lines(&s.data)).unwrap_or_default();
+        diff(&a, &b)
+    }
+
+    // Writes the patch for this file. With binary_patch set (i.e. --binary), binary files get a "GIT binary patch" that can be
+    // applied, and all oids are written in full.
+    pub fn write_patch(&self, out: &mut impl Write, binary_patch: bool) -> io::Result<()> {
+        let old_path = self.old_path().display();
+        let path = self.path.display();
+        writeln!(out, "diff --git a/{old_path} b/{path}")?;
+
+        let oid_len = if binary_patch { 40 } else { 7 };
+        let short = |side: &Option<Side>| {
+            side.as_ref()
+                .map(|s| s.oid.to_string()[..oid_len].to_string())
+                .unwrap_or_else(|| "0".repeat(oid_len))
+        };
+        match (&self.a, &self.b) {
+            (None, Some(b)) => writeln!(out, "new file mode {:o}", b.mode)?,
+            (Some(a), None) => writeln!(out, "deleted file mode {:o}", a.mode)?,
+            (Some(a), Some(b)) if a.mode != b.mode => {
+                writeln!(out, "old mode {:o}", a.mode)?;
+                writeln!(out, "new mode {:o}", b.mode)?;
+            }
+            _ => {}
+        }
+        if let (Some(origin), Some(percent)) = (&self.origin, self.similarity_percent()) {
+            let verb = match origin.kind {
+                OriginKind::Rename => "rename",
+                OriginKind::Copy => "copy",
+            };
+            writeln!(out, "similarity index {percent}%")?;
+            writeln!(out, "{verb} from {old_path}")?;
+            writeln!(out, "{verb} to {path}")?;
+        }
+
+        // A pure mode change (or an exact rename) has no content to show.
+        if self.a.as_ref().map(|s| s.oid) == self.b.as_ref().map(|s| s.oid) {
+            return Ok(());
+        }
+        write!(out, "index {}..{}", short(&self.a), short(&self.b))?;
+        match (&self.a, &self.b) {
+            (Some(a), Some(b)) if a.mode == b.mode => writeln!(out, " {:o}", a.mode)?,
+            _ => writeln!(out)?,
+        }
+
+        let a_name = match self.a {
+            Some(_) => format!("a/{old_path}"),
+            None => "/dev/null".to_string(),
+        };
+        let b_name = match self.b {
+            Some(_) => format!("b/{path}"),
+            None => "/dev/null".to_string(),
+        };
+        if self.is_binary() {
+            if binary_patch {
+                let data =
+                    |side: &Option<Side>| side.as_ref().map(|s| s.data.clone()).unwrap_or_default();
+                return binary_patch::write_binary_patch(out, &data(&self.a), &data(&self.b));
+            }
+            return writeln!(out, "Binary files {a_name} and {b_name} differ");
+        }
+        writeln!(out, "--- {a_name}")?;
+        writeln!(out, "+++ {b_name}")?;
+
+        for hunk in hunks(&self.edits()) {
+            writeln!(out, "{}", hunk.header())?;
+            for edit in &hunk.edits {
+                let marker = match edit.kind {
+                    EditKind::Eql => b' ',
+                    EditKind::Ins => b'+',
+                    EditKind::Del => b'-',
+                };
+                out.write_all(&[marker])?;
+                out.write_all(edit.text())?;
+                if !edit.text().ends_with(b"\n") {
+                    write!(out, "\n\\ No newline at end of file\n")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Formats a rename like git does, pulling the common leading and trailing directories out of the braces,
+// e.g. "src/{old.rs => new.rs}" or "{a => b}/file.txt".
+pub fn pprint_rename(a: &str, b: &str) -> String {
+    let (a_bytes, b_bytes) = (a.as_bytes(), b.as_bytes());
+    // Treat the end of each string like a NUL terminator, so the suffix scan can start at the very end.
+    let byte = |s: &[u8], i: usize| s.get(i).copied().unwrap_or(0);
+
+    // The common prefix has to end in a slash.
+    let mut prefix = 0;
+    let mut i = 0;
+    while i < a_bytes.len() && i < b_bytes.len() && a_bytes[i] == b_bytes[i] {
+        if a_bytes[i] == b'/' {
+            prefix = i + 1;
+        }
+        i += 1;
+    }
+
+    // The common suffix has to start with a slash. If there's a prefix, let the scan reach its slash so "a/x/c" and "a/y/c" work.
+    let mut suffix = 0;
+    let (mut old_end, mut new_end) = (a_bytes.len() as isize, b_bytes.len() as isize);
+    let adjust = isize::from(prefix > 0);
+    let limit = prefix as isize - adjust;
+    while limit <= old_end
+        && limit <= new_end
+        && old_end >= 0
+        && new_end >= 0
+        && byte(a_bytes, old_end as usize) == byte(b_bytes, new_end as usize)
+    {
+        if byte(a_bytes, old_end as usize) == b'/' {
+            suffix = a_bytes.len() - old_end as usize;
+        }
+        old_end -= 1;
+        new_end -= 1;
+    }
+
+    let a_mid = a.len().saturating_sub(prefix + suffix);
+    let b_mid = b.len().saturating_sub(prefix + suffix);
+    let a_middle = &a[prefix..prefix + a_mid];
+    let b_middle = &b[prefix..prefix + b_mid];
+    if prefix + suffix > 0 {
+        format!(
+            "{}{{{a_middle} => {b_middle}}}{}",
+            &a[..prefix],
+            &a[a.len() - suffix..]
+        )
+    } else {
+        format!("{a_middle} => {b_middle}")
+    }
+}
+
+// Turns the result of a tree diff into file pairs, loading each blob's contents from the database. Like git, they're sorted
+// by the bytes of their paths ("a-b" before "a/b"), which isn't the order of the tree diff's path components.
+pub fn pairs_from_tree_diff(database: &Database, tree_diff: &TreeDiff) -> Vec<FilePair> {
+    let side = |entry: &Option<crate::database::DatabaseEntry>| {
+        entry.map(|e| Side {
+            oid: e.oid,
+            mode: e.mode,
+            data: database.load_blob(&e.oid),
+        })
+    };
+    let mut pairs: Vec<FilePair> = tree_diff
+        .iter()
+        .map(|(path, (a, b))| FilePair::new(path.clone(), side(a), side(b)))
+        .collect();
+    pairs.sort_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(a: &str, b: &str) -> String {
+        let (a, b) = (lines(a.as_bytes()), lines(b.as_bytes()));
+        diff(&a, &b)
+            .iter()
+            .map(|edit| {
+                let marker = match edit.kind {
+                    EditKind::Eql => ' ',
+                    EditKind::Ins => '+',
+                    EditKind::Del => '-',
+                };
+                format!("{marker}{}", String::from_utf8_lossy(edit.text()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_book_example() {
+        let a = "A\nB\nC\nA\nB\nB\nA\n";
+        let b = "C\nB\nA\nB\nA\nC\n";
+        // The same length of script as the book's, but the one git finds.
+        assert_eq!(render(a, b), "-A\n-B\n C\n-A\n B\n+A\n B\n A\n+C\n");
+    }
+
+    #[test]
+    fn test_diff_large_rewrite() {
+        // Every third line changes, so there are thousands of separate changes to find.
+        let a: String = (0..3000).map(|i| format!("{i}\n")).collect();
+        let b: String = (0..3000)
+            .map(|i| match i % 3 {
+                0 => format!("changed {i}\n"),
+                _ => format!("{i}\n"),
+            })
+            .collect();
+        let (a, b) = (lines(a.as_bytes()), lines(b.as_bytes()));
+        let edits = diff(&a, &b);
+        let count = |kind| edits.iter().filter(|edit| edit.kind == kind).count();
+        assert_eq!(count(EditKind::Eql), 2000);
+        assert_eq!(count(EditKind::Del), 1000);
+        assert_eq!(count(EditKind::Ins), 1000);
+        let old: Vec<_> = edits
+            .iter()
+            .filter_map(|edit| edit.a_line)
+            .map(|line| line.text)
+            .collect();
+        let new: Vec<_> = edits
+            .iter()
+            .filter_map(|edit| edit.b_line)
+            .map(|line| line.text)
+            .collect();
+        assert_eq!((old, new), (a.clone(), b.clone()));
+
+        // A file rewritten from scratch is all deletions followed by all insertions.
+        let c: String = (0..3000).map(|i| format!("new {i}\n")).collect();
+        let c = lines(c.as_bytes());
+        let edits = diff(&a, &c);
+        assert_eq!(edits.len(), 6000);
+        assert!(edits[..3000].iter().all(|edit| edit.kind == EditKind::Del));
+    }
+
+    #[test]
+    fn test_diff_empty_sides() {
+        assert_eq!(render("", "x\ny\n"), "+x\n+y\n");
+        assert_eq!(render("x\ny\n", ""), "-x\n-y\n");
+        assert_eq!(render("", ""), "");
+    }
+
+    #[test]
+    fn test_hunks_split_and_header() {
+        let a: String = (1..=20).map(|i| format!("{i}\n")).collect();
+        let b: String = (1..=20)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                19 => "nineteen\n".to_string(),
+                _ => format!("{i}\n"),
+            })
+            .collect();
+        let (a, b) = (lines(a.as_bytes()), lines(b.as_bytes()));
+        let edits = diff(&a, &b);
+        let hunks = hunks(&edits);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header(), "@@ -1,5 +1,5 @@");
+        assert_eq!(hunks[1].header(), "@@ -16,5 +16,5 @@");
+    }
+
+    #[test]
+    fn test_pprint_rename() {
+        assert_eq!(pprint_rename("z.txt", "a.txt"), "z.txt => a.txt");
+        assert_eq!(
+            pprint_rename("src/dir/old.rs", "src/dir/new.rs"),
+            "src/dir/{old.rs => new.rs}"
+        );
+        assert_eq!(pprint_rename("a/x/c.txt", "a/y/c.txt"), "a/{x => y}/c.txt");
+        assert_eq!(pprint_rename("old/file", "new/file"), "{old => new}/file");
+        assert_eq!(pprint_rename("a/file", "a/sub/file"), "a/{ => sub}/file");
+    }
+
+    #[test]
+    fn test_hunk_header_for_new_file() {
+        let b = lines(b"one\n");
+        let edits = diff(&[], &b);
+        assert_eq!(hunks(&edits)[0].header(), "@@ -0,0 +1 @@");
+    }
+}
@@ -0,0 +1,522 @@
+use crate::diff::{self, EditKind};
+use std::ops::Range;
+
+// How many characters each conflict marker has by default, e.g. "<<<<<<<".
+pub const MARKER_SIZE: usize = 7;
+
+// How conflicts are written out (merge.conflictStyle). Diff3 also shows what the base had, and zdiff3 does the same but first
+// moves lines that both sides start or end with out of the conflict.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStyle {
+    #[default]
+    Merge,
+    Diff3,
+    Zdiff3,
+}
+
+impl ConflictStyle {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "merge" => Some(ConflictStyle::Merge),
+            "diff3" => Some(ConflictStyle::Diff3),
+            "zdiff3" => Some(ConflictStyle::Zdiff3),
+            _ => None,
+        }
+    }
+}
+
+// Which side wins a conflict, instead of leaving it marked up: ours, theirs, or both (ours first) for a union.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Favor {
+    Ours,
+    Theirs,
+    Union,
+}
+
+pub struct MergeOptions {
+    pub style: ConflictStyle,
+    pub favor: Option<Favor>,
+    pub marker_size: usize,
+    // Whether conflicts separated only by lines without any letters or digits (like blank lines and closing braces) are
+    // joined, however far apart they are. Merge-file does this, while tree merges only join conflicts that are close.
+    pub join_non_alnum: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            style: ConflictStyle::default(),
+            favor: None,
+            marker_size: MARKER_SIZE,
+            join_non_alnum: false,
+        }
+    }
+}
+
+// The names written after the conflict markers, e.g. "<<<<<<< HEAD" and ">>>>>>> topic".
+pub struct Labels<'a> {
+    pub ours: &'a str,
+    pub base: &'a str,
+    pub theirs: &'a str,
+}
+
+pub struct MergeResult {
+    pub data: Vec<u8>,
+    pub conflicts: usize,
+}
+
+// A run of lines that one side changed: `base_len` lines from `base_start` in the base were replaced by `len` lines from
+// `start` on that side.
+#[derive(Debug, Clone, Copy)]
+struct Change {
+    base_start: usize,
+    base_len: usize,
+    start: usize,
+    len: usize,
+}
+
+impl Change {
+    fn base_end(&self) -> usize {
+        self.base_start + self.base_len
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+// A part of the merged file that isn't just the lines both sides kept from the base: the lines of the base, ours and theirs
+// it covers, and which of them it takes.
+#[derive(Debug, Clone)]
+struct Region {
+    resolution: Resolution,
+    base: Range<usize>,
+    ours: Range<usize>,
+    theirs: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    Conflict,
+    Ours,
+    Theirs,
+    // Both sides turned out to make the same change, so ours already has it.
+    Same,
+}
+
+// Merges the changes that ours and theirs each made to base, line by line, the way git's xdiff does: changes that don't
+// overlap (or touch) are both taken, and the rest become conflicts. A conflict is then narrowed down to the lines the two
+// sides actually disagree on, and conflicts only a few lines apart are joined up again. With a favored side, conflicts are
+// resolved in its favor instead.
+pub fn merge(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: &Labels,
+    options: &MergeOptions,
+) -> MergeResult {
+    let style = options.style;
+    let base = diff::lines(base);
+    let ours = diff::lines(ours);
+    let theirs = diff::lines(theirs);
+    let mut regions = find_regions(&base, &ours, &theirs);
+    match style {
+        // Narrowing a conflict down would leave the base lines shown with it out of step.
+        ConflictStyle::Diff3 => {}
+        ConflictStyle::Zdiff3 => trim_conflicts(&mut regions, &ours, &theirs),
+        ConflictStyle::Merge => {
+            regions = refine_conflicts(regions, &ours, &theirs);
+            regions = join_conflicts(regions, &ours, options.join_non_alnum);
+        }
+    }
+
+    let mut data = Vec::new();
+    let mut conflicts = 0;
+    // Everything between the regions is what ours has.
+    let mut next = 0;
+    for region in &regions {
+        if region.resolution == Resolution::Same {
+            continue;
+        }
+        copy_lines(&mut data, &ours[next..region.ours.start], false);
+        let ours_lines = &ours[region.ours.clone()];
+        let theirs_lines = &theirs[region.theirs.clone()];
+        match region.resolution {
+            Resolution::Ours => copy_lines(&mut data, ours_lines, false),
+            Resolution::Theirs => copy_lines(&mut data, theirs_lines, false),
+            Resolution::Same => unreachable!("Skipped above"),
+            Resolution::Conflict => match options.favor {
+                Some(Favor::Ours) => copy_lines(&mut data, ours_lines, false),
+                Some(Favor::Theirs) => copy_lines(&mut data, theirs_lines, false),
+                Some(Favor::Union) => {
+                    copy_lines(&mut data, ours_lines, false);
+                    copy_lines(&mut data, theirs_lines, false);
+                }
+                None => {
+                    conflicts += 1;
+                    let size = options.marker_size;
+                    write_marker(&mut data, '<', size, labels.ours);
+                    copy_lines(&mut data, ours_lines, true);
+                    if style != ConflictStyle::Merge {
+                        write_marker(&mut data, '|', size, labels.base);
+                        copy_lines(&mut data, &base[region.base.clone()], true);
+                    }
+                    write_marker(&mut data, '=', size, "");
+                    copy_lines(&mut data, theirs_lines, true);
+                    write_marker(&mut data, '>', size, labels.theirs);
+                }
+            },
+        }
+        next = region.ours.end;
+    }
+    copy_lines(&mut data, &ours[next..], false);
+    MergeResult { data, conflicts }
+}
+
+// The runs of changed lines in a diff from a to b.
+fn changes(a: &[&[u8]], b: &[&[u8]]) -> Vec<Change> {
+    let mut changes: Vec<Change> = Vec::new();
+    let (mut a_index, mut b_index) = (0, 0);
+    let mut in_change = false;
+    for edit in diff::diff(a, b) {
+        if edit.kind == EditKind::Eql {
+            a_index += 1;
+            b_index += 1;
+            in_change = false;
+            continue;
+        }
+        if !in_change {
+            changes.push(Change {
+                base_start: a_index,
+                base_len: 0,
+                start: b_index,
+                len: 0,
+            });
+            in_change = true;
+        }
+        let change = changes.last_mut().expect("A change was just started");
+        if edit.kind == EditKind::Del {
+            change.base_len += 1;
+            a_index += 1;
+        } else {
+            change.len += 1;
+            b_index += 1;
+        }
+    }
+    changes
+}
+
+// Walks both sides' changes in base order. A change that ends before the other side's next one starts is taken as it is, and
+// overlapping or adjacent changes become a conflict spanning both (unless they're exactly the same change).
+fn find_regions(base: &[&[u8]], ours: &[&[u8]], theirs: &[&[u8]]) -> Vec<Region> {
+    let ours_changes = changes(base, ours);
+    let theirs_changes = changes(base, theirs);
+    // Maps a range of the base onto a side, given how far that side's lines are shifted from the base's there.
+    let shift = |range: Range<usize>, offset: isize| {
+        (range.start as isize + offset) as usize..(range.end as isize + offset) as usize
+    };
+    let offset = |change: &Change| change.start as isize - change.base_start as isize;
+
+    let mut regions = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while let (Some(a), Some(b)) = (ours_changes.get(i), theirs_changes.get(j)) {
+        if a.base_end() < b.base_start {
+            append_region(
+                &mut regions,
+                Region {
+                    resolution: Resolution::Ours,
+                    base: a.base_start..a.base_end(),
+                    ours: a.start..a.end(),
+                    theirs: shift(a.base_start..a.base_end(), offset(b)),
+                },
+            );
+            i += 1;
+            continue;
+        }
+        if b.base_end() < a.base_start {
+            append_region(
+                &mut regions,
+                Region {
+                    resolution: Resolution::Theirs,
+                    base: b.base_start..b.base_end(),
+                    ours: shift(b.base_start..b.base_end(), offset(a)),
+                    theirs: b.start..b.end(),
+                },
+            );
+            j += 1;
+            continue;
+        }
+
+        let same = (a.base_start, a.base_len) == (b.base_start, b.base_len)
+            && ours[a.start..a.end()] == theirs[b.start..b.end()];
+        if !same {
+            // The conflict covers both changes, and on each side the unchanged lines that the other change covers too.
+            let start = a.base_start.min(b.base_start);
+            let end = a.base_end().max(b.base_end());
+            append_region(
+                &mut regions,
+                Region {
+                    resolution: Resolution::Conflict,
+                    base: start..end,
+                    ours: a.start.saturating_sub(a.base_start - start)
+                        ..a.end() + end - a.base_end(),
+                    theirs: b.start.saturating_sub(b.base_start - start)
+                        ..b.end() + end - b.base_end(),
+                },
+            );
+        }
+        if a.base_end() >= b.base_end() {
+            j += 1;
+        }
+        if b.base_end() >= a.base_end() {
+            i += 1;
+        }
+    }
+
+    let theirs_offset = theirs.len() as isize - base.len() as isize;
+    for a in &ours_changes[i..] {
+        append_region(
+            &mut regions,
+            Region {
+                resolution: Resolution::Ours,
+                base: a.base_start..a.base_end(),
+                ours: a.start..a.end(),
+                theirs: shift(a.base_start..a.base_end(), theirs_offset),
+            },
+        );
+    }
+    let ours_offset = ours.len() as isize - base.len() as isize;
+    for b in &theirs_changes[j..] {
+        append_region(
+            &mut regions,
+            Region {
+                resolution: Resolution::Theirs,
+                base: b.base_start..b.base_end(),
+                ours: shift(b.base_start..b.base_end(), ours_offset),
+                theirs: b.start..b.end(),
+            },
+        );
+    }
+    regions
+}
+
+// Adds a region, joining it into the previous one (as a conflict, if they resolve differently) when they overlap or touch.
+fn append_region(regions: &mut Vec<Region>, region: Region) {
+    if let Some(last) = regions.last_mut() {
+        if region.ours.start <= last.ours.end || region.theirs.start <= last.theirs.end {
+            if region.resolution != last.resolution {
+                last.resolution = Resolution::Conflict;
+            }
+            last.base.end = region.base.end;
+            last.ours.end = region.ours.end;
+            last.theirs.end = region.theirs.end;
+            return;
+        }
+    }
+    regions.push(region);
+}
+
+// Splits each conflict into the parts where ours and theirs really differ, by diffing the two sides' lines. A conflict where
+// they turn out the same isn't one.
+fn refine_conflicts(regions: Vec<Region>, ours: &[&[u8]], theirs: &[&[u8]]) -> Vec<Region> {
+    let mut refined = Vec::new();
+    for region in regions {
+        if region.resolution != Resolution::Conflict
+            || region.ours.is_empty()
+            || region.theirs.is_empty()
+        {
+            refined.push(region);
+            continue;
+        }
+        let changes = changes(&ours[region.ours.clone()], &theirs[region.theirs.clone()]);
+        if changes.is_empty() {
+            refined.push(Region {
+                resolution: Resolution::Same,
+                ..region
+            });
+            continue;
+        }
+        for change in changes {
+            refined.push(Region {
+                resolution: Resolution::Conflict,
+                base: region.base.clone(),
+                ours: region.ours.start + change.base_start..region.ours.start + change.base_end(),
+                theirs: region.theirs.start + change.start..region.theirs.start + change.end(),
+            });
+        }
+    }
+    refined
+}
+
+// Joins conflicts that are no more than three lines apart (or, if asked, apart only by lines without letters or digits),
+// which are easier to resolve as one.
+fn join_conflicts(regions: Vec<Region>, ours: &[&[u8]], join_non_alnum: bool) -> Vec<Region> {
+    let mut joined: Vec<Region> = Vec::new();
+    for region in regions {
+        if let Some(last) = joined.last_mut() {
+            let gap = &ours[last.ours.end..region.ours.start];
+            let close = gap.len() <= 3
+                || (join_non_alnum
+                    && !gap
+                        .iter()
+                        .any(|line| line.iter().any(u8::is_ascii_alphanumeric)));
+            if last.resolution == Resolution::Conflict
+                && region.resolution == Resolution::Conflict
+                && close
+            {
+                last.base.end = region.base.end;
+                last.ours.end = region.ours.end;
+                last.theirs.end = region.theirs.end;
+                continue;
+            }
+        }
+        joined.push(region);
+    }
+    joined
+}
+
+// For zdiff3, moves the lines that both sides of a conflict start or end with out of it.
+fn trim_conflicts(regions: &mut [Region], ours: &[&[u8]], theirs: &[&[u8]]) {
+    for region in regions
+        .iter_mut()
+        .filter(|region| region.resolution == Resolution::Conflict)
+    {
+        while !region.ours.is_empty()
+            && !region.theirs.is_empty()
+            && ours[region.ours.start] == theirs[region.theirs.start]
+        {
+            region.ours.start += 1;
+            region.theirs.start += 1;
+        }
+        while !region.ours.is_empty()
+            && !region.theirs.is_empty()
+            && ours[region.ours.end - 1] == theirs[region.theirs.end - 1]
+        {
+            region.ours.end -= 1;
+            region.theirs.end -= 1;
+        }
+    }
+}
+
+// Copies lines to the output. The lines of a conflict always end in a newline, so that the next marker starts a line.
+fn copy_lines(data: &mut Vec<u8>, lines: &[&[u8]], complete: bool) {
+    for line in lines {
+        data.extend_from_slice(line);
+    }
+    if complete && lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        data.push(b'\n');
+    }
+}
+
+fn write_marker(data: &mut Vec<u8>, marker: char, size: usize, label: &str) {
+    data.extend(std::iter::repeat_n(marker as u8, size));
+    if !label.is_empty() {
+        data.push(b' ');
+        data.extend_from_slice(label.as_bytes());
+    }
+    data.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABELS: Labels = Labels {
+        ours: "ours",
+        base: "base",
+        theirs: "theirs",
+    };
+
+    fn merge_str(base: &str, ours: &str, theirs: &str, style: ConflictStyle) -> (String, usize) {
+        let options = MergeOptions {
+            style,
+            ..Default::default()
+        };
+        let result = merge(
+            base.as_bytes(),
+            ours.as_bytes(),
+            theirs.as_bytes(),
+            &LABELS,
+            &options,
+        );
+        (String::from_utf8(result.data).unwrap(), result.conflicts)
+    }
+
+    #[test]
+    fn test_clean_merge() {
+        let base = "1\n2\n3\n4\n5\n";
+        let (merged, conflicts) = merge_str(
+            base,
+            "one\n2\n3\n4\n5\n",
+            "1\n2\n3\n4\nfive\n",
+            ConflictStyle::Merge,
+        );
+        assert_eq!(merged, "one\n2\n3\n4\nfive\n");
+        assert_eq!(conflicts, 0);
+    }
+
+    #[test]
+    fn test_conflict_styles() {
+        let base = "a\nb\nc\n";
+        let ours = "a\nX\nsame\nc\n";
+        let theirs = "a\nY\nsame\nc\n";
+        let (merged, conflicts) = merge_str(base, ours, theirs, ConflictStyle::Merge);
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< ours\nX\n=======\nY\n>>>>>>> theirs\nsame\nc\n"
+        );
+        assert_eq!(conflicts, 1);
+        let (merged, _) = merge_str(base, ours, theirs, ConflictStyle::Diff3);
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< ours\nX\nsame\n||||||| base\nb\n=======\nY\nsame\n>>>>>>> theirs\nc\n"
+        );
+        let (merged, _) = merge_str(base, ours, theirs, ConflictStyle::Zdiff3);
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< ours\nX\n||||||| base\nb\n=======\nY\n>>>>>>> theirs\nsame\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_favor_and_joining() {
+        let base = "a\n}\n}\n}\n}\nb\n";
+        let ours = "A\n}\n}\n}\n}\nB\n";
+        let theirs = "C\n}\n}\n}\n}\nD\n";
+        let merge_with = |options: MergeOptions| {
+            let result = merge(
+                base.as_bytes(),
+                ours.as_bytes(),
+                theirs.as_bytes(),
+                &LABELS,
+                &options,
+            );
+            (String::from_utf8(result.data).unwrap(), result.conflicts)
+        };
+
+        // Conflicts four lines apart stay apart, unless those lines have no letters or digits in them.
+        let (_, conflicts) = merge_with(MergeOptions::default());
+        assert_eq!(conflicts, 2);
+        let (merged, conflicts) = merge_with(MergeOptions {
+            join_non_alnum: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            merged,
+            "<<<<<<< ours\nA\n}\n}\n}\n}\nB\n=======\nC\n}\n}\n}\n}\nD\n>>>>>>> theirs\n"
+        );
+        assert_eq!(conflicts, 1);
+
+        let (merged, conflicts) = merge_with(MergeOptions {
+            favor: Some(Favor::Union),
+            ..Default::default()
+        });
+        assert_eq!(merged, "A\nC\n}\n}\n}\n}\nB\nD\n");
+        assert_eq!(conflicts, 0);
+        let (merged, _) = merge_with(MergeOptions {
+            favor: Some(Favor::Theirs),
+            ..Default::default()
+        });
+        assert_eq!(merged, theirs);
+    }
+}
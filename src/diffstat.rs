@@ -0,0 +1,345 @@
+use crate::diff::{EditKind, FilePair, OriginKind};
+use std::io::{self, Write};
+
+// The width git falls back to when it can't ask the terminal.
+const DEFAULT_WIDTH: usize = 80;
+
+// How many lines were added and deleted in a single file. For binary files these are the old and new sizes in bytes instead.
+#[derive(Debug, PartialEq)]
+pub struct FileStat {
+    pub path: String,
+    pub added: usize,
+    pub deleted: usize,
+    pub binary: bool,
+}
+
+impl From<&FilePair> for FileStat {
+    fn from(pair: &FilePair) -> Self {
+        let path = pair.display_name();
+        if pair.is_binary() {
+            let size = |side: &Option<crate::diff::Side>| side.as_ref().map_or(0, |s| s.data.len());
+            return FileStat {
+                path,
+                added: size(&pair.b),
+                deleted: size(&pair.a),
+                binary: true,
+            };
+        }
+        let edits = pair.edits();
+        FileStat {
+            path,
+            added: edits.iter().filter(|e| e.kind == EditKind::Ins).count(),
+            deleted: edits.iter().filter(|e| e.kind == EditKind::Del).count(),
+            binary: false,
+        }
+    }
+}
+
+// Settings for "--stat[=<width>[,<name-width>[,<count>]]]". Anything left unset falls back to git's defaults.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StatOptions {
+    pub width: Option<usize>,
+    pub name_width: Option<usize>,
+    pub count: Option<usize>,
+}
+
+impl StatOptions {
+    pub fn parse(value: &str) -> Self {
+        let mut parts = value
+            .split(',')
+            .map(|part| part.parse::<usize>().ok().filter(|n| *n > 0));
+        StatOptions {
+            width: parts.next().flatten(),
+            name_width: parts.next().flatten(),
+            count: parts.next().flatten(),
+        }
+    }
+
+    // Uses the terminal width from $COLUMNS like git does when it's not talking to a terminal directly.
+    fn total_width(&self) -> usize {
+        self.width.unwrap_or_else(|| {
+            std::env::var("COLUMNS")
+                .ok()
+                .and_then(|columns| columns.parse().ok())
+                .filter(|columns| *columns > 0)
+                .unwrap_or(DEFAULT_WIDTH)
+        })
+    }
+}
+
+fn decimal_width(n: usize) -> usize {
+    n.to_string().len()
+}
+
+// Scales a change count down to fit in the graph, making sure that any non-zero count gets at least one column.
+fn scale_linear(it: usize, width: usize, max_change: usize) -> usize {
+    if it == 0 {
+        0
+    } else {
+        1 + (it * (width - 1) / max_change)
+    }
+}
+
+// Prints the "--stat" view followed by the "--shortstat" line, using the same width calculations as git's diff.c.
+pub fn write_stat(
+    out: &mut impl Write,
+    stats: &[FileStat],
+    options: &StatOptions,
+) -> io::Result<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+    let mut max_len = 0;
+    let mut max_change = 0;
+    let mut number_width = 0;
+    let mut bin_width = 0;
+    for stat in stats {
+        max_len = max_len.max(stat.path.chars().count());
+        if stat.binary {
+            // "Bin XXX -> YYY bytes"
+            bin_width = bin_width.max(14 + decimal_width(stat.added) + decimal_width(stat.deleted));
+            // Display change counts aligned with "Bin".
+            number_width = 3;
+        } else {
+            max_change = max_change.max(stat.added + stat.deleted);
+        }
+    }
+    number_width = number_width.max(decimal_width(max_change));
+
+    // Guarantee 6 columns for the graph and 10 for the filename.
+    let width = options.total_width().max(16 + 6 + number_width);
+
+    // First ask for as much room as we'd like, then shrink things down to fit in the total width.
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    };
+    let mut name_width = match options.name_width {
+        Some(name_width) if name_width < max_len => name_width,
+        _ => max_len,
+    };
+    if name_width + number_width + 6 + graph_width > width {
+        let graph_limit = (width * 3 / 8).saturating_sub(number_width + 6);
+        if graph_width > graph_limit {
+            graph_width = graph_limit.max(6);
+        }
+        if name_width > width.saturating_sub(number_width + 6 + graph_width) {
+            name_width = width.saturating_sub(number_width + 6 + graph_width);
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let shown = options.count.unwrap_or(stats.len()).min(stats.len());
+    for stat in &stats[..shown] {
+        let (prefix, name) = truncate_name(&stat.path, name_width);
+        let padding = name_width.saturating_sub(prefix.len() + name.chars().count());
+        write!(out, " {prefix}{name}{} |", " ".repeat(padding))?;
+
+        if stat.binary {
+            write!(out, " {:>number_width$}", "Bin")?;
+            if stat.added == 0 && stat.deleted == 0 {
+                writeln!(out)?;
+            } else {
+                writeln!(out, " {} -> {} bytes", stat.deleted, stat.added)?;
+            }
+            continue;
+        }
+
+        let total = stat.added + stat.deleted;
+        write!(
+            out,
+            " {total:>number_width$}{}",
+            if total > 0 { " " } else { "" }
+        )?;
+        let (mut added, mut deleted) = (stat.added, stat.deleted);
+        if graph_width <= max_change {
+            let mut scaled = scale_linear(total, graph_width, max_change);
+            if scaled < 2 && added > 0 && deleted > 0 {
+                scaled = 2;
+            }
+            if added < deleted {
+                added = scale_linear(added, graph_width, max_change);
+                deleted = scaled - added;
+            } else {
+                deleted = scale_linear(deleted, graph_width, max_change);
+                added = scaled - deleted;
+            }
+        }
+        writeln!(out, "{}{}", "+".repeat(added), "-".repeat(deleted))?;
+    }
+    if shown < stats.len() {
+        writeln!(out, " ...")?;
+    }
+
+    write_shortstat(out, stats)
+}
+
+// When a name is too long, keep its end and replace the start with "...", cutting at a directory boundary if there is one.
+fn truncate_name(name: &str, name_width: usize) -> (&'static str, &str) {
+    let name_len = name.chars().count();
+    if name_len <= name_width {
+        return ("", name);
+    }
+    let keep = name_width.saturating_sub(3);
+    let start = name
+        .char_indices()
+        .nth(name_len - keep)
+        .map_or(name.len(), |(index, _)| index);
+    let rest = &name[start..];
+    match rest.find('/') {
+        Some(slash) => ("...", &rest[slash..]),
+        None => ("...", rest),
+    }
+}
+
+pub fn write_shortstat(out: &mut impl Write, stats: &[FileStat]) -> io::Result<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+    let insertions: usize = stats.iter().filter(|s| !s.binary).map(|s| s.added).sum();
+    let deletions: usize = stats.iter().filter(|s| !s.binary).map(|s| s.deleted).sum();
+    writeln!(
+        out,
+        "{}",
+        shortstat_line(stats.len(), insertions, deletions)
+    )
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+fn shortstat_line(files: usize, insertions: usize, deletions: usize) -> String {
+    let mut line = format!(" {files} file{} changed", plural(files));
+    // Like git, only mention the zero counts when nothing at all changed.
+    if insertions > 0 || deletions == 0 {
+        line.push_str(&format!(
+            ", {insertions} insertion{}(+)",
+            plural(insertions)
+        ));
+    }
+    if deletions > 0 || insertions == 0 {
+        line.push_str(&format!(", {deletions} deletion{}(-)", plural(deletions)));
+    }
+    line
+}
+
+pub fn write_numstat(out: &mut impl Write, stats: &[FileStat]) -> io::Result<()> {
+    for stat in stats {
+        if stat.binary {
+            writeln!(out, "-\t-\t{}", stat.path)?;
+        } else {
+            writeln!(out, "{}\t{}\t{}", stat.added, stat.deleted, stat.path)?;
+        }
+    }
+    Ok(())
+}
+
+// Prints the "--summary" lines describing created, deleted, renamed and copied files and mode changes.
+pub fn write_summary(out: &mut impl Write, pairs: &[FilePair]) -> io::Result<()> {
+    for pair in pairs {
+        let path = pair.path.display();
+        if let (Some(origin), Some(percent)) = (&pair.origin, pair.similarity_percent()) {
+            let verb = match origin.kind {
+                OriginKind::Rename => "rename",
+                OriginKind::Copy => "copy",
+            };
+            writeln!(out, " {verb} {} ({percent}%)", pair.display_name())?;
+        }
+        match (&pair.a, &pair.b) {
+            (None, Some(b)) => writeln!(out, " create mode {:o} {path}", b.mode)?,
+            (Some(a), None) => writeln!(out, " delete mode {:o} {path}", a.mode)?,
+            (Some(a), Some(b)) if a.mode != b.mode => {
+                writeln!(out, " mode change {:o} => {:o} {path}", a.mode, b.mode)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(path: &str, added: usize, deleted: usize) -> FileStat {
+        FileStat {
+            path: path.to_string(),
+            added,
+            deleted,
+            binary: false,
+        }
+    }
+
+    fn render(stats: &[FileStat], options: &StatOptions) -> String {
+        let mut out = Vec::new();
+        write_stat(&mut out, stats, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_shortstat_line() {
+        assert_eq!(
+            shortstat_line(3, 10, 0),
+            " 3 files changed, 10 insertions(+)"
+        );
+        assert_eq!(shortstat_line(1, 0, 1), " 1 file changed, 1 deletion(-)");
+        assert_eq!(
+            shortstat_line(1, 0, 0),
+            " 1 file changed, 0 insertions(+), 0 deletions(-)"
+        );
+    }
+
+    #[test]
+    fn test_stat_basic() {
+        let stats = [stat("a.txt", 2, 1), stat("dir/b.txt", 0, 3)];
+        let options = StatOptions {
+            width: Some(80),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&stats, &options),
+            " a.txt     | 3 ++-\n dir/b.txt | 3 ---\n 2 files changed, 2 insertions(+), 4 deletions(-)\n"
+        );
+    }
+
+    #[test]
+    fn test_stat_scales_graph() {
+        let stats = [stat("a", 1000, 0), stat("b", 1, 1)];
+        let output = render(
+            &stats,
+            &StatOptions {
+                width: Some(80),
+                ..Default::default()
+            },
+        );
+        let lines: Vec<_> = output.lines().collect();
+        // 80 columns leaves 80 - 4 (number) - 6 - 1 (name) = 69 for the graph.
+        assert_eq!(lines[0], format!(" a | 1000 {}", "+".repeat(69)));
+        assert_eq!(lines[1], " b |    2 +-");
+    }
+
+    #[test]
+    fn test_stat_binary_and_truncation() {
+        let stats = [
+            FileStat {
+                path: "image.png".to_string(),
+                added: 2048,
+                deleted: 1024,
+                binary: true,
+            },
+            stat("some/very/long/directory/name/file.txt", 1, 0),
+        ];
+        let options = StatOptions::parse("40,20");
+        assert_eq!(
+            render(&stats, &options),
+            " image.png            | Bin 1024 -> 2048 bytes\n .../name/file.txt    |   1 +\n 2 files changed, 1 insertion(+)\n"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use crate::config::Config;
+use crate::fatal::fatal;
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// Where a command found its repository: the .git directory, the top of the workspace (if it isn't a bare repository), and the
+// directory the command was run from relative to the top (which paths on the command line are relative to).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub git_path: PathBuf,
+    pub root_path: Option<PathBuf>,
+    pub prefix: PathBuf,
+}
+
+// Finds the repository for the current directory the way git does. $GIT_DIR names the .git directory outright (and then the
+// current directory is the top of the workspace, unless $GIT_WORK_TREE says otherwise or the repository is bare). Otherwise
+// we look for a .git directory (or a .git file pointing at one) here and in each parent directory, or a bare repository,
+// stopping at $GIT_CEILING_DIRECTORIES and, unless $GIT_DISCOVERY_ACROSS_FILESYSTEM is set, at the edge of the current
+// filesystem.
+pub fn discover() -> Location {
+    let cwd = env::current_dir().expect("failed to get cwd");
+    let (git_path, root_path) = match env::var_os("GIT_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => {
+            let git_path = cwd.join(&dir);
+            if !is_git_directory(&git_path) {
+                fatal(&format!(
+                    "not a git repository: '{}'",
+                    Path::new(&dir).display()
+                ));
+            }
+            let bare = Config::load(&git_path).get("core.bare") == Some("true");
+            (git_path, (!bare).then(|| cwd.clone()))
+        }
+        None => find_repository(&cwd),
+    };
+    let root_path = match env::var_os("GIT_WORK_TREE").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(cwd.join(dir)),
+        None => root_path,
+    };
+    let root_path = root_path.map(|root_path| {
+        root_path.canonicalize().unwrap_or_else(|e| {
+            fatal(&format!("Invalid path '{}': {e}", root_path.display()));
+        })
+    });
+    let git_path = git_path.canonicalize().unwrap_or(git_path);
+    // Running from outside the workspace (which only happens with $GIT_WORK_TREE) is like running from its top.
+    let prefix = root_path
+        .as_ref()
+        .and_then(|root_path| cwd.strip_prefix(root_path).ok())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    Location {
+        git_path,
+        root_path,
+        prefix,
+    }
+}
+
+// Walks up from a directory looking for a repository, returning its .git directory and the top of its workspace (or None for
+// a bare repository).
+fn find_repository(cwd: &Path) -> (PathBuf, Option<PathBuf>) {
+    let ceiling = ceiling_directory(cwd);
+    let across_filesystems = env::var("GIT_DISCOVERY_ACROSS_FILESYSTEM")
+        .is_ok_and(|value| matches!(value.to_lowercase().as_str(), "true" | "yes" | "on" | "1"));
+    let device = fs::metadata(cwd).map(|stat| stat.dev()).ok();
+
+    let mut dir = cwd;
+    loop {
+        let dot_git = dir.join(".git");
+        if dot_git.is_file() {
+            return (read_gitfile(&dot_git), Some(dir.to_path_buf()));
+        }
+        if is_git_directory(&dot_git) {
+            return (dot_git, Some(dir.to_path_buf()));
+        }
+        if is_git_directory(dir) {
+            return (dir.to_path_buf(), None);
+        }
+
+        let parent = match dir.parent() {
+            Some(parent)
+                if !ceiling
+                    .as_ref()
+                    .is_some_and(|ceiling| ceiling.starts_with(parent)) =>
+            {
+                parent
+            }
+            _ => fatal("not a git repository (or any of the parent directories): .git"),
+        };
+        if !across_filesystems && fs::metadata(parent).map(|stat| stat.dev()).ok() != device {
+            eprintln!(
+                "fatal: not a git repository (or any parent up to mount point {})",
+                dir.display()
+            );
+            eprintln!("Stopping at filesystem boundary (GIT_DISCOVERY_ACROSS_FILESYSTEM not set).");
+            std::process::exit(128);
+        }
+        dir = parent;
+    }
+}
+
+// The deepest of the $GIT_CEILING_DIRECTORIES above the current directory, which the search never goes up into. Only
+// absolute paths count.
+fn ceiling_directory(cwd: &Path) -> Option<PathBuf> {
+    let dirs = env::var_os("GIT_CEILING_DIRECTORIES")?;
+    env::split_paths(&dirs)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.canonicalize().unwrap_or(dir))
+        .filter(|dir| cwd.starts_with(dir) && cwd != dir)
+        .max_by_key(|dir| dir.as_os_str().len())
+}
+
+// Reads a .git file, which holds "gitdir: <path>" to point at a .git directory elsewhere (relative to the file's directory).
+fn read_gitfile(path: &Path) -> PathBuf {
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    let Some(target) = contents.strip_prefix("gitdir: ") else {
+        fatal(&format!("invalid gitfile format: {}", path.display()));
+    };
+    let git_path = path
+        .parent()
+        .expect("a .git file has a parent directory")
+        .join(target.trim_end());
+    if !is_git_directory(&git_path) {
+        fatal(&format!("not a git repository: {}", git_path.display()));
+    }
+    git_path
+}
+
+// Whether a directory looks like a .git directory: it needs a HEAD, and objects and refs directories.
+pub fn is_git_directory(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_repository() {
+        let dir = env::temp_dir().join(format!("discovery-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let git_path = dir.join("repo/.git");
+        for sub in ["objects", "refs"] {
+            fs::create_dir_all(git_path.join(sub)).unwrap();
+        }
+        fs::write(git_path.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        let deep = dir.join("repo/a/b");
+        fs::create_dir_all(&deep).unwrap();
+        assert_eq!(
+            find_repository(&deep),
+            (git_path.clone(), Some(dir.join("repo")))
+        );
+        // Inside the .git directory, it's found as a bare repository.
+        assert_eq!(find_repository(&git_path), (git_path.clone(), None));
+
+        // A .git file points at a .git directory somewhere else.
+        let linked = dir.join("linked");
+        fs::create_dir_all(&linked).unwrap();
+        fs::write(linked.join(".git"), "gitdir: ../repo/.git\n").unwrap();
+        assert_eq!(
+            find_repository(&linked),
+            (linked.join("../repo/.git"), Some(linked.clone()))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
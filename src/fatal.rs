@@ -0,0 +1,5 @@
+// Reports an error the user can do something about and exits, like git's die().
+pub fn fatal(message: &str) -> ! {
+    eprintln!("fatal: {message}");
+    std::process::exit(128);
+}
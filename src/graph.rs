@@ -0,0 +1,735 @@
+use sha1_smol::Digest;
+use std::io::{self, Write};
+
+// The colors lines of history cycle through, like git's default color.graph list.
+const COLUMN_COLORS: [&str; 12] = [
+    "\x1b[31m",
+    "\x1b[32m",
+    "\x1b[33m",
+    "\x1b[34m",
+    "\x1b[35m",
+    "\x1b[36m",
+    "\x1b[1;31m",
+    "\x1b[1;32m",
+    "\x1b[1;33m",
+    "\x1b[1;34m",
+    "\x1b[1;35m",
+    "\x1b[1;36m",
+];
+const RESET: &str = "\x1b[m";
+
+// Which kind of line the graph draws next for the current commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    // Every line is straight down, e.g. "| | |". This is where a commit's lines end up once it's fully drawn.
+    Padding,
+    // The previous commit wasn't fully drawn, so "..." marks the skipped lines.
+    Skip,
+    // Extra lines before an octopus merge to make room for its parents.
+    PreCommit,
+    // The line with the commit's "*".
+    Commit,
+    // The line right after a merge that branches out to its parents, e.g. "|\".
+    PostMerge,
+    // Lines that move branches to the left until each one is in its own column, e.g. "|/".
+    Collapsing,
+}
+
+// A line of history, waiting for the commit it leads to.
+#[derive(Debug, Clone, Copy)]
+struct Column {
+    commit: Digest,
+    color: usize,
+}
+
+// A line of output being built, along with how many columns it takes up on screen (which color codes don't).
+#[derive(Default)]
+struct Line {
+    text: String,
+    width: usize,
+}
+
+impl Line {
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.width += 1;
+    }
+
+    fn push_n(&mut self, c: char, count: usize) {
+        for _ in 0..count {
+            self.push(c);
+        }
+    }
+}
+
+// Draws the lines of history next to commits as they're shown, one text line at a time. This is a port of git's graph.c, so that
+// the pictures come out exactly the same.
+//
+// Each commit is given to `update` along with the parents to draw lines to, and then the lines for it are taken with the `show_*`
+// methods, which know how to continue the graph alongside the commit's header and message.
+pub struct Graph {
+    color: bool,
+    commit: Option<Digest>,
+    parents: Vec<Digest>,
+    // How many screen columns the lines for the current commit take up.
+    width: usize,
+    // How many PreCommit lines have been drawn for the current commit so far.
+    expansion_row: usize,
+    state: State,
+    prev_state: State,
+    // The column the current (and the previous) commit is in.
+    commit_index: usize,
+    prev_commit_index: usize,
+    // For merges, whether the first parent's line leaves towards the left (0) or straight down (1). -1 until it's known.
+    merge_layout: i32,
+    // How many columns the current commit adds to the right of itself, and how many the previous one did.
+    edges_added: i32,
+    prev_edges_added: i32,
+    // The lines of history above the current commit, and the ones below it once its parents have taken its place.
+    columns: Vec<Column>,
+    new_columns: Vec<Column>,
+    // For each screen column (two per column of history), which of `new_columns` the line drawn there ends up in, or -1.
+    mapping: Vec<i32>,
+    old_mapping: Vec<i32>,
+    mapping_size: usize,
+    // The color the next new line of history gets.
+    default_color: usize,
+}
+
+impl Graph {
+    pub fn new(color: bool) -> Self {
+        Graph {
+            color,
+            commit: None,
+            parents: Vec::new(),
+            width: 0,
+            expansion_row: 0,
+            state: State::Padding,
+            prev_state: State::Padding,
+            commit_index: 0,
+            prev_commit_index: 0,
+            merge_layout: 0,
+            edges_added: 0,
+            prev_edges_added: 0,
+            columns: Vec::new(),
+            new_columns: Vec::new(),
+            mapping: Vec::new(),
+            old_mapping: Vec::new(),
+            mapping_size: 0,
+            // The first line of history takes the first color.
+            default_color: COLUMN_COLORS.len() - 1,
+        }
+    }
+
+    // Moves on to the next commit to draw, with the parents (among the commits being shown) its lines lead to.
+    pub fn update(&mut self, commit: Digest, parents: Vec<Digest>) {
+        self.commit = Some(commit);
+        self.parents = parents;
+        self.prev_commit_index = self.commit_index;
+        self.update_columns();
+        self.expansion_row = 0;
+
+        // If the previous commit didn't get to finish drawing, this one starts with "...".
+        self.state = if self.state != State::Padding {
+            State::Skip
+        } else if self.needs_pre_commit_line() {
+            State::PreCommit
+        } else {
+            State::Commit
+        };
+    }
+
+    fn update_state(&mut self, state: State) {
+        self.prev_state = self.state;
+        self.state = state;
+    }
+
+    fn is_commit_finished(&self) -> bool {
+        self.state == State::Padding
+    }
+
+    fn current_commit(&self) -> Digest {
+        self.commit.expect("Graph has no commit to draw")
+    }
+
+    fn find_new_column(&self, commit: &Digest) -> Option<usize> {
+        self.new_columns
+            .iter()
+            .position(|column| column.commit == *commit)
+    }
+
+    fn find_commit_color(&self, commit: &Digest) -> usize {
+        self.columns
+            .iter()
+            .find(|column| column.commit == *commit)
+            .map_or(self.default_color, |column| column.color)
+    }
+
+    fn increment_color(&mut self) {
+        self.default_color = (self.default_color + 1) % COLUMN_COLORS.len();
+    }
+
+    // Works out the columns below the current commit: the ones above it, with the commit's own column replaced by its parents.
+    fn update_columns(&mut self) {
+        std::mem::swap(&mut self.columns, &mut self.new_columns);
+        self.new_columns.clear();
+
+        // At most, every parent gets a new column.
+        let max_new_columns = self.columns.len() + self.parents.len();
+        if self.mapping.len() < 2 * max_new_columns {
+            self.mapping.resize(2 * max_new_columns, -1);
+            self.old_mapping.resize(2 * max_new_columns, -1);
+        }
+        self.mapping_size = 2 * max_new_columns;
+        self.mapping[..self.mapping_size].fill(-1);
+
+        self.width = 0;
+        self.prev_edges_added = self.edges_added;
+        self.edges_added = 0;
+
+        // The commit isn't in any column yet if none of its children were shown, in which case it goes at the end.
+        let commit = self.current_commit();
+        let mut seen_this = false;
+        let mut is_commit_in_columns = true;
+        for i in 0..=self.columns.len() {
+            let column_commit = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                is_commit_in_columns = false;
+                commit
+            } else {
+                self.columns[i].commit
+            };
+
+            if column_commit == commit {
+                seen_this = true;
+                self.commit_index = i;
+                self.merge_layout = -1;
+                for parent in self.parents.clone() {
+                    // Merges and new lines of history each get a new color.
+                    if self.parents.len() > 1 || !is_commit_in_columns {
+                        self.increment_color();
+                    }
+                    self.insert_into_new_columns(parent, Some(i));
+                }
+                // The commit takes up its own column even if it has no parents to continue it.
+                if self.parents.is_empty() {
+                    self.width += 2;
+                }
+            } else {
+                self.insert_into_new_columns(column_commit, None);
+            }
+        }
+
+        while self.mapping_size > 1 && self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+    }
+
+    // Adds a commit to the columns below the current one (unless a line already leads there) and maps the next screen column to
+    // it. `index` is the current commit's column when adding one of its parents.
+    fn insert_into_new_columns(&mut self, commit: Digest, index: Option<usize>) {
+        let i = match self.find_new_column(&commit) {
+            Some(i) => i,
+            None => {
+                let color = self.find_commit_color(&commit);
+                self.new_columns.push(Column { commit, color });
+                self.new_columns.len() - 1
+            }
+        };
+
+        let mapping_index;
+        if let Some(index) = index.filter(|_| self.parents.len() > 1 && self.merge_layout == -1) {
+            // The first parent of a merge decides how the merge is laid out, depending on whether the parent is already in a
+            // column to the left of it.
+            let distance = index as i32 - i as i32;
+            let shift = if distance > 1 { 2 * distance - 3 } else { 1 };
+            self.merge_layout = if distance > 0 { 0 } else { 1 };
+            self.edges_added = self.parents.len() as i32 + self.merge_layout - 2;
+            mapping_index = (self.width as i32 + (self.merge_layout - 1) * shift) as usize;
+            self.width += 2 * self.merge_layout as usize;
+        } else if self.edges_added > 0 && i as i32 == self.mapping[self.width - 2] {
+            // A merge added columns but this commit was found in the last existing one, so the two edges join right away:
+            //
+            //     * |        * |
+            //     |\ \   =>  |\|
+            //     | |/       | *
+            //     | *
+            mapping_index = self.width - 2;
+            self.edges_added = -1;
+        } else {
+            mapping_index = self.width;
+            self.width += 2;
+        }
+        self.mapping[mapping_index] = i as i32;
+    }
+
+    fn num_dashed_parents(&self) -> i32 {
+        self.parents.len() as i32 + self.merge_layout - 3
+    }
+
+    // An octopus merge needs two extra lines for each parent that's drawn with dashes, to route the lines to its right around it.
+    fn num_expansion_rows(&self) -> i32 {
+        self.num_dashed_parents() * 2
+    }
+
+    fn needs_pre_commit_line(&self) -> bool {
+        self.parents.len() >= 3
+            && self.commit_index + 1 < self.columns.len()
+            && (self.expansion_row as i32) < self.num_expansion_rows()
+    }
+
+    fn is_mapping_correct(&self) -> bool {
+        self.mapping[..self.mapping_size]
+            .iter()
+            .enumerate()
+            .all(|(i, target)| *target < 0 || *target as usize == i / 2)
+    }
+
+    fn write_column(&self, line: &mut Line, column: &Column, c: char) {
+        if self.color {
+            line.text.push_str(COLUMN_COLORS[column.color]);
+        }
+        line.push(c);
+        if self.color {
+            line.text.push_str(RESET);
+        }
+    }
+
+    // Fills the line with spaces so that everything printed next to the graph lines up for the whole commit.
+    fn pad_horizontally(&self, line: &mut Line) {
+        if line.width < self.width {
+            line.push_n(' ', self.width - line.width);
+        }
+    }
+
+    // Draws the next line for the current commit, returning it and whether it was the one with the commit on it.
+    fn next_line(&mut self) -> (String, bool) {
+        let mut line = Line::default();
+        if self.commit.is_none() {
+            return (line.text, false);
+        }
+        let state = self.state;
+        match state {
+            State::Padding => self.output_padding_line(&mut line),
+            State::Skip => self.output_skip_line(&mut line),
+            State::PreCommit => self.output_pre_commit_line(&mut line),
+            State::Commit => self.output_commit_line(&mut line),
+            State::PostMerge => self.output_post_merge_line(&mut line),
+            State::Collapsing => self.output_collapsing_line(&mut line),
+        }
+        self.pad_horizontally(&mut line);
+        (line.text, state == State::Commit)
+    }
+
+    fn output_padding_line(&self, line: &mut Line) {
+        for column in &self.new_columns {
+            self.write_column(line, column, '|');
+            line.push(' ');
+        }
+    }
+
+    fn output_skip_line(&mut self, line: &mut Line) {
+        line.text.push_str("...");
+        line.width += 3;
+        if self.needs_pre_commit_line() {
+            self.update_state(State::PreCommit);
+        } else {
+            self.update_state(State::Commit);
+        }
+    }
+
+    // Widens the space to the right of an octopus merge, one column per line:
+    //
+    //     | *
+    //     | |\
+    //     | | \
+    //     | |  \
+    //     | *-. \
+    //     | |\ \ \
+    fn output_pre_commit_line(&mut self, line: &mut Line) {
+        let commit = self.current_commit();
+        let mut seen_this = false;
+        for (i, column) in self.columns.iter().enumerate() {
+            if column.commit == commit {
+                seen_this = true;
+                self.write_column(line, column, '|');
+                line.push_n(' ', self.expansion_row);
+            } else if seen_this && self.expansion_row == 0 {
+                // If the previous commit was a merge, the lines to its right were drawn as "\", so keep drawing them that way.
+                let c = if self.prev_state == State::PostMerge && self.prev_commit_index < i {
+                    '\\'
+                } else {
+                    '|'
+                };
+                self.write_column(line, column, c);
+            } else if seen_this {
+                self.write_column(line, column, '\\');
+            } else {
+                self.write_column(line, column, '|');
+            }
+            line.push(' ');
+        }
+
+        self.expansion_row += 1;
+        if !self.needs_pre_commit_line() {
+            self.update_state(State::Commit);
+        }
+    }
+
+    // The dashes after an octopus merge's "*", each in the color of the parent whose line it leads to.
+    fn draw_octopus_merge(&self, line: &mut Line) {
+        let dashed_parents = self.num_dashed_parents();
+        for i in 0..dashed_parents {
+            let j = self.mapping[(self.commit_index + i as usize + 2) * 2];
+            let column = &self.new_columns[j as usize];
+            self.write_column(line, column, '-');
+            let c = if i == dashed_parents - 1 { '.' } else { '-' };
+            self.write_column(line, column, c);
+        }
+    }
+
+    fn output_commit_line(&mut self, line: &mut Line) {
+        let commit = self.current_commit();
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column_commit = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                commit
+            } else {
+                self.columns[i].commit
+            };
+
+            if column_commit == commit {
+                seen_this = true;
+                line.push('*');
+                if self.parents.len() > 2 {
+                    self.draw_octopus_merge(line);
+                }
+            } else if seen_this && self.edges_added > 1 {
+                self.write_column(line, &self.columns[i], '\\');
+            } else if seen_this && self.edges_added == 1 {
+                // A merge without PreCommit lines, so this is its first line. If the line above was drawn as "\" after another
+                // merge, keep it that way.
+                let c = if self.prev_state == State::PostMerge
+                    && self.prev_edges_added > 0
+                    && self.prev_commit_index < i
+                {
+                    '\\'
+                } else {
+                    '|'
+                };
+                self.write_column(line, &self.columns[i], c);
+            } else if self.prev_state == State::Collapsing
+                && self.old_mapping[2 * i + 1] == i as i32
+                && self.mapping[2 * i] < i as i32
+            {
+                self.write_column(line, &self.columns[i], '/');
+            } else {
+                self.write_column(line, &self.columns[i], '|');
+            }
+            line.push(' ');
+        }
+
+        if self.parents.len() > 1 {
+            self.update_state(State::PostMerge);
+        } else if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        } else {
+            self.update_state(State::Collapsing);
+        }
+    }
+
+    fn output_post_merge_line(&mut self, line: &mut Line) {
+        const MERGE_CHARS: [char; 3] = ['/', '|', '\\'];
+        let commit = self.current_commit();
+        let first_parent = self.parents[0];
+        let mut parent_column: Option<usize> = None;
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column_commit = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                commit
+            } else {
+                self.columns[i].commit
+            };
+
+            if column_commit == commit {
+                // Draw an edge to each parent, in the color of the column it goes to.
+                seen_this = true;
+                let mut index = self.merge_layout as usize;
+                for (j, parent) in self.parents.iter().enumerate() {
+                    let parent_index = self
+                        .find_new_column(parent)
+                        .expect("Merge parent has no column");
+                    self.write_column(line, &self.new_columns[parent_index], MERGE_CHARS[index]);
+                    if index == 2 {
+                        if self.edges_added > 0 || j + 1 < self.parents.len() {
+                            line.push(' ');
+                        }
+                    } else {
+                        index += 1;
+                    }
+                }
+                if self.edges_added == 0 {
+                    line.push(' ');
+                }
+            } else if seen_this {
+                let c = if self.edges_added > 0 { '\\' } else { '|' };
+                self.write_column(line, &self.columns[i], c);
+                line.push(' ');
+            } else {
+                self.write_column(line, &self.columns[i], '|');
+                if self.merge_layout != 0 || i + 1 != self.commit_index {
+                    match parent_column {
+                        Some(parent_column) => {
+                            self.write_column(line, &self.columns[parent_column], '_')
+                        }
+                        None => line.push(' '),
+                    }
+                }
+            }
+
+            if column_commit == first_parent {
+                parent_column = Some(i);
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        } else {
+            self.update_state(State::Collapsing);
+        }
+    }
+
+    // Moves every branch line that isn't in its final column one step to the left. Only one line at a time may cross others
+    // horizontally (drawn with "_"), which keeps crossings readable.
+    fn output_collapsing_line(&mut self, line: &mut Line) {
+        let mut used_horizontal = false;
+        let mut horizontal_edge: i32 = -1;
+        let mut horizontal_edge_target: i32 = -1;
+
+        std::mem::swap(&mut self.mapping, &mut self.old_mapping);
+        self.mapping[..self.mapping_size].fill(-1);
+
+        for i in 0..self.mapping_size {
+            let target = self.old_mapping[i];
+            if target < 0 {
+                continue;
+            }
+            // Columns are always filled from the left, so a branch never has to move to the right.
+            let target_index = 2 * target as usize;
+            assert!(target_index <= i, "Graph branch would move to the right");
+
+            if target_index == i {
+                // The branch is already where it belongs.
+                self.mapping[i] = target;
+            } else if self.mapping[i - 1] < 0 {
+                // Nothing is to the left, so move one step left. If no other branch is moving horizontally, this one does.
+                self.mapping[i - 1] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge = i as i32;
+                    horizontal_edge_target = target;
+                    for j in (target_index + 3..i.saturating_sub(2)).step_by(2) {
+                        self.mapping[j] = target;
+                    }
+                }
+            } else if self.mapping[i - 1] == target {
+                // The branch to the left goes to the same commit, so the two lines merge into one.
+            } else {
+                // The branch to the left goes somewhere else, so cross over it.
+                assert!(
+                    self.mapping[i - 1] > target,
+                    "Graph branches are out of order"
+                );
+                assert!(
+                    self.mapping[i - 2] < 0,
+                    "Graph branch has nowhere to cross to"
+                );
+                self.mapping[i - 2] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge_target = target;
+                    horizontal_edge = i as i32 - 1;
+                    for j in (target_index + 3..i.saturating_sub(2)).step_by(2) {
+                        self.mapping[j] = target;
+                    }
+                }
+            }
+        }
+
+        self.old_mapping[..self.mapping_size].copy_from_slice(&self.mapping[..self.mapping_size]);
+        // The new mapping may be one column narrower than the old one.
+        if self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+
+        for i in 0..self.mapping_size {
+            let target = self.mapping[i];
+            if target < 0 {
+                line.push(' ');
+                continue;
+            }
+            let column = self.new_columns[target as usize];
+            if 2 * target as usize == i {
+                self.write_column(line, &column, '|');
+            } else if target == horizontal_edge_target && i as i32 != horizontal_edge - 1 {
+                // All but the first segment of the horizontal line stop here rather than continuing to the next line.
+                if i != 2 * target as usize + 3 {
+                    self.mapping[i] = -1;
+                }
+                used_horizontal = true;
+                self.write_column(line, &column, '_');
+            } else {
+                if used_horizontal && (i as i32) < horizontal_edge {
+                    self.mapping[i] = -1;
+                }
+                self.write_column(line, &column, '/');
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        }
+    }
+
+    // A line that continues every branch straight down, for lines of a commit's message. Before the commit's "*" has been
+    // drawn, this is just the next line of the graph.
+    fn padding_line(&mut self) -> String {
+        if self.state != State::Commit {
+            return self.next_line().0;
+        }
+        let commit = self.current_commit();
+        let mut line = Line::default();
+        for column in &self.columns {
+            self.write_column(&mut line, column, '|');
+            if column.commit == commit && self.parents.len() > 2 {
+                line.push_n(' ', (self.parents.len() - 2) * 2);
+            } else {
+                line.push(' ');
+            }
+        }
+        self.pad_horizontally(&mut line);
+        self.prev_state = State::Padding;
+        line.text
+    }
+
+    // Writes the graph lines up to and including the one with the current commit on it, leaving the cursor after that line's
+    // graph so the commit's header can follow it.
+    pub fn show_commit(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.is_commit_finished() {
+            return write!(out, "{}", self.padding_line());
+        }
+        while !self.is_commit_finished() {
+            let (text, shown_commit_line) = self.next_line();
+            write!(out, "{text}")?;
+            if shown_commit_line {
+                break;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    // Writes the graph for the start of the next line of output.
+    pub fn show_oneline(&mut self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "{}", self.next_line().0)
+    }
+
+    // Writes a graph line that just continues each branch, e.g. for the blank line between commits.
+    pub fn show_padding(&mut self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "{}", self.padding_line())
+    }
+
+    // Writes whatever lines the current commit still needs (e.g. collapsing branches after a merge), separated by newlines
+    // but without one at the end.
+    fn show_remainder(&mut self, out: &mut impl Write) -> io::Result<()> {
+        while !self.is_commit_finished() {
+            write!(out, "{}", self.next_line().0)?;
+            if !self.is_commit_finished() {
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes a commit's message (whose first line follows graph output already written), with the graph in front of every
+    // later line, and then finishes drawing the commit.
+    pub fn show_commit_message(&mut self, out: &mut impl Write, message: &str) -> io::Result<()> {
+        let mut lines = message.split_inclusive('\n').peekable();
+        while let Some(line) = lines.next() {
+            write!(out, "{line}")?;
+            if line.ends_with('\n') && lines.peek().is_some() {
+                self.show_oneline(out)?;
+            }
+        }
+
+        if !self.is_commit_finished() {
+            let newline_terminated = message.ends_with('\n');
+            if !newline_terminated {
+                writeln!(out)?;
+            }
+            self.show_remainder(out)?;
+            if newline_terminated {
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oid(name: &str) -> Digest {
+        sha1_smol::Sha1::from(name).digest()
+    }
+
+    // Draws just the graph for a list of commits (given with their parents), one "*" line and whatever follows it per commit.
+    fn draw(commits: &[(&str, &[&str])]) -> Vec<String> {
+        let mut graph = Graph::new(false);
+        let mut out = Vec::new();
+        for (commit, parents) in commits {
+            graph.update(oid(commit), parents.iter().map(|p| oid(p)).collect());
+            graph.show_commit(&mut out).unwrap();
+            graph.show_commit_message(&mut out, commit).unwrap();
+            writeln!(out).unwrap();
+        }
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_merge() {
+        let lines = draw(&[
+            ("m", &["a", "b"]),
+            ("b", &["base"]),
+            ("a", &["base"]),
+            ("base", &[]),
+        ]);
+        assert_eq!(lines, ["*   m", "|\\", "| * b", "* | a", "|/", "* base"]);
+    }
+
+    #[test]
+    fn test_octopus_merge() {
+        let lines = draw(&[
+            ("m", &["a", "b", "c"]),
+            ("c", &["base"]),
+            ("b", &["base"]),
+            ("a", &["base"]),
+            ("base", &[]),
+        ]);
+        assert_eq!(
+            lines,
+            ["*-.   m", "|\\ \\", "| | * c", "| * | b", "| |/", "* / a", "|/", "* base"]
+        );
+    }
+}
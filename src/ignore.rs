@@ -0,0 +1,211 @@
+use crate::config::Config;
+use crate::wildmatch::{wildmatch, MatchFlags};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// One line of an ignore file, e.g. "*.log", "!keep.log" or "/build/".
+#[derive(Clone, Debug)]
+pub struct IgnoreRule {
+    // The file the rule comes from, as it's shown by check-ignore, e.g. "sub/.gitignore" or ".git/info/exclude".
+    pub source: String,
+    pub line_number: usize,
+    // The line as it was written (minus trailing spaces), for showing the rule.
+    pub text: String,
+    // The directory holding the .gitignore file, relative to the workspace root ("" for the root and the other files).
+    base: String,
+    pattern: String,
+    // A rule starting with "!" re-includes paths that an earlier rule ignored.
+    pub negated: bool,
+    // Patterns ending in a slash only match directories.
+    directory_only: bool,
+    // Patterns without a slash (other than a trailing one) are matched against the name alone, in any directory below base.
+    basename_only: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str, base: &str, source: &str, line_number: usize) -> Option<Self> {
+        if line.starts_with('#') {
+            return None;
+        }
+        let text = trim_trailing_spaces(line);
+        if text.is_empty() {
+            return None;
+        }
+        let (negated, pattern) = match text.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, text),
+        };
+        let (directory_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let basename_only = !pattern.contains('/');
+        Some(IgnoreRule {
+            source: source.to_string(),
+            line_number,
+            text: text.to_string(),
+            base: base.to_string(),
+            pattern: pattern.strip_prefix('/').unwrap_or(pattern).to_string(),
+            negated,
+            directory_only,
+            basename_only,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let Some(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let flags = MatchFlags {
+            pathname: true,
+            casefold: false,
+        };
+        if self.basename_only {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(&self.pattern, name, flags)
+        } else {
+            wildmatch(&self.pattern, relative, flags)
+        }
+    }
+}
+
+// Removes the spaces at the end of a line, except for one that's escaped with a backslash.
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = 0;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ' ' => continue,
+            '\\' => end = chars.next().map_or(line.len(), |(i, c)| i + c.len_utf8()),
+            _ => end = i + c.len_utf8(),
+        }
+    }
+    &line[..end]
+}
+
+// Decides which workspace paths are ignored, from the .gitignore files in the path's directories, .git/info/exclude and the
+// file named by core.excludesFile.
+pub struct Ignore {
+    root_path: PathBuf,
+    info_path: PathBuf,
+    excludes_file: Option<PathBuf>,
+}
+
+impl Ignore {
+    pub fn new(root_path: PathBuf, git_path: &Path, config: &Config) -> Self {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        // Without core.excludesFile, git uses $XDG_CONFIG_HOME/git/ignore (or ~/.config/git/ignore).
+        let excludes_file = match config.get("core.excludesFile") {
+            Some(file) => match (file.strip_prefix("~/"), &home) {
+                (Some(rest), Some(home)) => Some(home.join(rest)),
+                _ => Some(PathBuf::from(file)),
+            },
+            None => env::var_os("XDG_CONFIG_HOME")
+                .filter(|xdg| !xdg.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| home.map(|home| home.join(".config")))
+                .map(|xdg| xdg.join("git/ignore")),
+        };
+        Ignore {
+            info_path: git_path.join("info").join("exclude"),
+            excludes_file,
+            root_path,
+        }
+    }
+
+    fn read_rules(file: &Path, base: &str, source: &str) -> Vec<IgnoreRule> {
+        fs::read_to_string(file)
+            .map(|contents| {
+                contents
+                    .lines()
+                    .enumerate()
+                    .filter_map(|(i, line)| IgnoreRule::parse(line, base, source, i + 1))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matching_rule(path, is_dir)
+            .is_some_and(|rule| !rule.negated)
+    }
+
+    // The rule that decides whether a path is ignored, which may be a negated one that re-includes it. Like git, nothing inside
+    // an ignored directory can be re-included, so that the directory's rule is the one returned for everything in it.
+    pub fn matching_rule(&self, path: &Path, is_dir: bool) -> Option<IgnoreRule> {
+        let path = path.to_string_lossy();
+        let path = path.trim_end_matches('/');
+
+        // Collect the files from lowest to highest precedence: core.excludesFile, info/exclude, then the .gitignore files from
+        // the root down. The files in a directory only apply to the paths inside it, so they're added as we go deeper.
+        let mut rules = match &self.excludes_file {
+            Some(file) => Self::read_rules(file, "", &file.to_string_lossy()),
+            None => Vec::new(),
+        };
+        rules.extend(Self::read_rules(&self.info_path, "", ".git/info/exclude"));
+        rules.extend(Self::read_rules(
+            &self.root_path.join(".gitignore"),
+            "",
+            ".gitignore",
+        ));
+
+        let mut base = String::new();
+        let components: Vec<&str> = path.split('/').collect();
+        for (i, component) in components.iter().enumerate() {
+            let last = i == components.len() - 1;
+            let prefix = format!("{base}{component}");
+            // The last matching rule wins.
+            let rule = rules
+                .iter()
+                .rev()
+                .find(|rule| rule.matches(&prefix, is_dir || !last));
+            if last || rule.is_some_and(|rule| !rule.negated) {
+                return rule.cloned();
+            }
+            base = format!("{prefix}/");
+            let source = format!("{base}.gitignore");
+            rules.extend(Self::read_rules(
+                &self.root_path.join(&source),
+                &base,
+                &source,
+            ));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let rule = IgnoreRule::parse("!build/  ", "", ".gitignore", 1).unwrap();
+        assert!(rule.negated);
+        assert_eq!(rule.text, "!build/");
+        assert!(rule.matches("src/build", true));
+        assert!(!rule.matches("src/build", false));
+
+        let escaped = IgnoreRule::parse("space\\ ", "", ".gitignore", 2).unwrap();
+        assert!(escaped.matches("space ", false));
+        assert!(IgnoreRule::parse("# comment", "", ".gitignore", 3).is_none());
+        assert!(IgnoreRule::parse("   ", "", ".gitignore", 4).is_none());
+    }
+
+    #[test]
+    fn test_anchored_and_nested_patterns() {
+        let rule = IgnoreRule::parse("/doc/*.txt", "", ".gitignore", 1).unwrap();
+        assert!(rule.matches("doc/a.txt", false));
+        assert!(!rule.matches("src/doc/a.txt", false));
+        assert!(!rule.matches("doc/sub/a.txt", false));
+
+        let nested = IgnoreRule::parse("a/**/z", "sub/", "sub/.gitignore", 1).unwrap();
+        assert!(nested.matches("sub/a/b/c/z", false));
+        assert!(nested.matches("sub/a/z", false));
+        assert!(!nested.matches("a/z", false));
+    }
+}
@@ -1,17 +1,23 @@
 use sha1_smol::{Digest, Sha1};
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
     fs::{self, File},
-    io::{BufRead, Cursor, Read, Write},
+    io::{Cursor, Read, Write},
     os::{linux::fs::MetadataExt, unix::fs::PermissionsExt},
     path::{Path, PathBuf},
 };
 
-use crate::tree::Tree;
+use crate::database::DatabaseEntry;
+use crate::object::digest_from_bytes;
 
 const REGULAR_MODE: u32 = 0o100644;
 const EXECUTABLE_MODE: u32 = 0o100755;
+pub const SYMLINK_MODE: u32 = 0o120000;
 const MAX_PATH_SIZE: u32 = 0xfff;
+// The two bits of an entry's flags (above the path length) that hold its stage.
+const STAGE_SHIFT: u16 = 12;
+const STAGE_MASK: u16 = 0x3000;
 
 const SIGNATURE: &[u8] = b"DIRC";
 const VERSION: u32 = 2;
@@ -30,10 +36,31 @@ pub struct IndexMetadata {
     size: u32,
 }
 
+impl IndexMetadata {
+    // Stat data for a file that isn't in the workspace yet, so that only its mode is known.
+    pub fn with_mode(mode: u32) -> Self {
+        IndexMetadata {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_mode(&self) -> u32 {
+        self.mode
+    }
+
+    // The same stat data, recorded with the mode of the blob that was written out rather than the one read from the file.
+    pub fn set_mode(&mut self, mode: u32) {
+        self.mode = mode;
+    }
+}
+
 impl From<fs::Metadata> for IndexMetadata {
     fn from(m: fs::Metadata) -> Self {
         // NOTE: I extracted this directly from the is_executable crate.
-        let mode = if m.permissions().mode() & 0o111 != 0 {
+        let mode = if m.file_type().is_symlink() {
+            SYMLINK_MODE
+        } else if m.permissions().mode() & 0o111 != 0 {
             EXECUTABLE_MODE
         } else {
             REGULAR_MODE
@@ -84,14 +111,46 @@ impl From<[u8; 40]> for IndexMetadata {
 }
 
 #[derive(Debug, PartialEq)]
-struct IndexEntry {
+pub struct IndexEntry {
     path: PathBuf,
     oid: Digest,
     metadata: IndexMetadata,
+    // 0 for a normal entry. While a path has merge conflicts it instead has up to three entries for its versions in the
+    // merge base (1), ours (2) and theirs (3).
+    stage: u8,
 }
 
 const ENTRY_BLOCK: usize = 8;
 impl IndexEntry {
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_oid(&self) -> &Digest {
+        &self.oid
+    }
+
+    pub fn get_mode(&self) -> u32 {
+        self.metadata.mode
+    }
+
+    pub fn get_stage(&self) -> u8 {
+        self.stage
+    }
+
+    // A cheap check (size and mode) for whether the given file stat could match this entry. If it doesn't, the file has definitely changed.
+    pub fn stat_match(&self, stat: &IndexMetadata) -> bool {
+        self.metadata.size == stat.size && self.metadata.mode == stat.mode
+    }
+
+    // If the timestamps match as well, we assume the file contents are unchanged without having to hash them.
+    pub fn times_match(&self, stat: &IndexMetadata) -> bool {
+        self.metadata.ctime == stat.ctime
+            && self.metadata.ctime_nsec == stat.ctime_nsec
+            && self.metadata.mtime == stat.mtime
+            && self.metadata.mtime_nsec == stat.mtime_nsec
+    }
+
     fn to_data(&self) -> Vec<u8> {
         // NOTE: each index entry is serialized using the format "N10H40nZ*" as follows:
         // - Ten 32-bit unsigned big-endian numbers (ctime sec, ctime nsec, mtime sec, mtime nsec, dev, ino, mode, uid, gid, size).
@@ -117,9 +176,10 @@ impl IndexEntry {
             .flat_map(|num| num.to_be_bytes())
             .collect::<Vec<_>>();
         v.extend_from_slice(&self.oid.bytes());
-        // NOTE: flags will only have the byte size and it has to fit in 16 bits.
+        // NOTE: flags hold the stage in bits 12-13 and the path's byte size (capped at 12 bits) below that.
         let path = self.path.to_string_lossy();
-        let flags = path.len().min(MAX_PATH_SIZE as usize) as u16;
+        let flags =
+            (self.stage as u16) << STAGE_SHIFT | path.len().min(MAX_PATH_SIZE as usize) as u16;
         v.extend_from_slice(&flags.to_be_bytes());
         v.extend_from_slice(path.as_bytes());
 
@@ -142,12 +202,14 @@ impl IndexEntry {
 
         let mut sha = [0; 20];
         data.read_exact(&mut sha).expect("Failed to read entry sha");
-        let oid = Sha1::from(sha).digest();
+        let oid = digest_from_bytes(&sha);
 
         let mut flags = [0; 2];
         data.read_exact(&mut flags)
             .expect("Failed to read entry flags");
-        let length = u16::from_be_bytes(flags) as usize;
+        let flags = u16::from_be_bytes(flags);
+        let stage = ((flags & STAGE_MASK) >> STAGE_SHIFT) as u8;
+        let length = (flags & MAX_PATH_SIZE as u16) as usize;
 
         // In order to read the path, either use the given length, or keep reading until we hit a null char.
         let path = if length < MAX_PATH_SIZE as usize {
@@ -190,14 +252,22 @@ impl IndexEntry {
             path,
             oid,
             metadata,
+            stage,
         }
     }
 }
 
+// The key of an entry in the index map.
+fn entry_key(path: &Path, stage: u8) -> (OsString, u8) {
+    (path.as_os_str().to_os_string(), stage)
+}
+
 #[derive(Debug, Default)]
 pub struct Index {
     path: PathBuf,
-    entries: BTreeMap<PathBuf, IndexEntry>,
+    // Keyed by path and stage, so that the stages of a conflicted path come one after another. Like git, paths are ordered by
+    // their bytes ("a-b" and "a.txt" before "a/b"), not by their components.
+    entries: BTreeMap<(OsString, u8), IndexEntry>,
     // This "parents_to_children" field maps each directory to all the paths (files) that it is a parent of. It's fully derived from "entries" and is used
     // as a faster way to access a given directory's children (e.g. remove_children).
     parents_to_children: HashMap<PathBuf, HashSet<PathBuf>>,
@@ -218,21 +288,14 @@ impl Index {
                 let entries: BTreeMap<_, _> = (0..length)
                     .map(|_| {
                         let entry = IndexEntry::read_entry(&mut cursor);
-                        (entry.path.clone(), entry)
+                        (entry_key(&entry.path, entry.stage), entry)
                     })
                     .collect();
 
                 // Read the last 20 bytes from the index file and compare them to the SHA formed by the rest of the file.
-                let mut sha = [0; 20];
-                cursor
-                    .read_exact(&mut sha)
-                    .expect("Failed to read SHA from index file");
-
-                // Without copying the original buf/cursor, read all the bytes except the last 20, and make sure their hash matches the sha we read.
-                let num_bytes_so_far = cursor.position();
-                cursor.set_position(0);
-                let mut x = cursor.take(num_bytes_so_far - 20);
-                let bytes_to_hash = x.fill_buf().unwrap();
+                // Anything between the entries and the SHA is an extension (e.g. git's cached trees), which we skip.
+                let buf = cursor.into_inner();
+                let (bytes_to_hash, sha) = buf.split_at(buf.len() - 20);
                 assert!(sha == Sha1::from(bytes_to_hash).digest().bytes());
 
                 // TODO consider only constructing this when it's needed (maybe using interior mutability to populate it behind the scenes when it's first needed and then reusing it on subsequent calls)
@@ -249,11 +312,11 @@ impl Index {
     }
 
     fn construct_parents_cache(
-        entries: &BTreeMap<PathBuf, IndexEntry>,
+        entries: &BTreeMap<(OsString, u8), IndexEntry>,
     ) -> HashMap<PathBuf, HashSet<PathBuf>> {
         let mut parents_to_children: HashMap<PathBuf, HashSet<PathBuf>> = HashMap::new();
-        for entry_path in entries.keys() {
-            for parent_dir in Tree::get_parent_directories(entry_path) {
+        for entry_path in entries.values().map(|entry| &entry.path) {
+            for parent_dir in Self::parent_directories(entry_path) {
                 match parents_to_children.get_mut(&parent_dir) {
                     Some(children) => {
                         children.insert(entry_path.clone());
@@ -270,14 +333,64 @@ impl Index {
         parents_to_children
     }
 
-    pub fn get_filepaths(&self) -> Vec<&PathBuf> {
-        self.entries.keys().collect()
+    // Returns the full path of every directory above the given path, outermost first (e.g. "a" and "a/b" for "a/b/c.txt").
+    pub fn parent_directories(path: &Path) -> Vec<PathBuf> {
+        let mut parents: Vec<PathBuf> = path
+            .ancestors()
+            .skip(1)
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .collect();
+        parents.reverse();
+        parents
+    }
+
+    // Whether any file in the index lives under the given directory.
+    pub fn is_tracked_directory(&self, path: &Path) -> bool {
+        self.parents_to_children.contains_key(path)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.values()
+    }
+
+    // The normal (stage 0) entry for a path, which a conflicted path doesn't have.
+    pub fn get_entry(&self, path: &Path) -> Option<&IndexEntry> {
+        self.entries.get(&entry_key(path, 0))
+    }
+
+    // Whether the index has the path at all, as a normal entry or as a conflict.
+    pub fn is_tracked_file(&self, path: &Path) -> bool {
+        (0..=3).any(|stage| self.entries.contains_key(&entry_key(path, stage)))
+    }
+
+    // The base, ours and theirs entries of a conflicted path (each None if that version doesn't exist), or None if the
+    // path isn't conflicted.
+    pub fn get_conflict(&self, path: &Path) -> Option<[Option<&IndexEntry>; 3]> {
+        let stages = [1, 2, 3].map(|stage| self.entries.get(&entry_key(path, stage)));
+        stages.iter().any(Option::is_some).then_some(stages)
+    }
+
+    // Every path that has unresolved merge conflicts, in index order.
+    pub fn conflicted_paths(&self) -> Vec<&Path> {
+        let mut paths: Vec<&Path> = self
+            .entries
+            .values()
+            .filter(|entry| entry.stage > 0)
+            .map(|entry| entry.path.as_path())
+            .collect();
+        paths.dedup();
+        paths
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.values().any(|entry| entry.stage > 0)
     }
 
     fn discard_conflicts(&mut self, conflicting_path: &Path) {
         // If an existing entry conflicts with this new one, remove the old entry.
         // This handles the case when the existing entry is just a file.
-        for parent_dir in Tree::get_parent_directories(conflicting_path) {
+        for parent_dir in Self::parent_directories(conflicting_path) {
             self.remove_entry(&parent_dir);
         }
         self.remove_children(conflicting_path);
@@ -286,7 +399,6 @@ impl Index {
     fn remove_children(&mut self, path: &Path) {
         // This handles the case when the existing entry is a directory (and we have to recursively remove its entries).
         if let Some(children) = self.parents_to_children.get(path) {
-            println!("removing children: {}", path.display());
             for child in children.clone() {
                 self.remove_entry(&child);
             }
@@ -294,45 +406,73 @@ impl Index {
     }
 
     fn remove_entry(&mut self, path: &Path) {
-        // If such an entry exists,
-        if let Some(entry) = self.entries.get(path) {
-            println!("removing entry: {}", path.display());
-            let entry_path = entry.path.clone();
+        // If such an entry exists (at any stage),
+        if self.is_tracked_file(path) {
+            let entry_path = path.to_path_buf();
             // Remove the entry from entries.
-            self.entries.remove(&entry_path);
+            for stage in 0..=3 {
+                self.entries.remove(&entry_key(&entry_path, stage));
+            }
             // Also remove the entry from the parents_to_children field. That means go over the parent dirs of this entry,
             // and for each such parent dir, remove its children. Finally, remove the parent dir itself.
-            for parent in Tree::get_parent_directories(&entry_path) {
+            for parent in Self::parent_directories(&entry_path) {
                 if let Some(children) = self.parents_to_children.get_mut(&parent) {
+                    children.remove(&entry_path);
                     if children.is_empty() {
                         self.parents_to_children.remove(&parent);
-                    } else {
-                        children.remove(&entry_path);
                     }
                 }
             }
         };
     }
 
+    // Removes a file from the index, or everything under it if it's a directory.
+    pub fn remove(&mut self, path: &Path) {
+        self.remove_entry(path);
+        self.remove_children(path);
+    }
+
+    // Adds (or replaces) the normal entry for a path, which also resolves any conflict it had.
     pub fn add(&mut self, path: PathBuf, oid: Digest, metadata: IndexMetadata) {
-        println!("Adding {} to index!", path.display());
         self.discard_conflicts(&path);
+        self.remove_entry(&path);
 
         self.store_entry(IndexEntry {
             path,
             oid,
             metadata,
+            stage: 0,
         });
     }
 
+    // Records a merge conflict at a path, replacing whatever the index had there with an entry for each version that
+    // exists: the merge base's (stage 1), ours (2) and theirs (3).
+    pub fn add_conflict(&mut self, path: PathBuf, versions: [Option<DatabaseEntry>; 3]) {
+        self.discard_conflicts(&path);
+        self.remove_entry(&path);
+
+        for (stage, version) in (1..).zip(versions) {
+            let Some(version) = version else {
+                continue;
+            };
+            self.store_entry(IndexEntry {
+                path: path.clone(),
+                oid: version.oid,
+                metadata: IndexMetadata::with_mode(version.mode),
+                stage,
+            });
+        }
+    }
+
     fn store_entry(&mut self, entry: IndexEntry) {
         let entry_path = entry.path.clone();
 
-        self.entries.insert(entry_path.clone(), entry);
+        self.entries
+            .insert(entry_key(&entry_path, entry.stage), entry);
 
         // TODO this whole block is repeated in construct_parents_cache(). Refactor it out by making a similar func to populate the parents_to_children for a single entry.
         // Now populate the parents_to_children for this new entry.
-        for parent_dir in Tree::get_parent_directories(&entry_path) {
+        for parent_dir in Self::parent_directories(&entry_path) {
             match self.parents_to_children.get_mut(&parent_dir) {
                 Some(children) => {
                     children.insert(entry_path.clone());
@@ -397,6 +537,7 @@ impl Index {
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_closure)]
 mod tests {
     use super::*;
 
@@ -418,9 +559,10 @@ mod tests {
             path: filepath.clone(),
             oid: fake_digest,
             metadata: IndexMetadata::default(),
+            stage: 0,
         };
         assert_eq!(index.entries.len(), 1);
-        assert_eq!(*index.entries.get(&filepath).unwrap(), expected_entry);
+        assert_eq!(*index.get_entry(&filepath).unwrap(), expected_entry);
     }
 
     #[test]
@@ -439,7 +581,7 @@ mod tests {
         // Also, the entries are ordered alphabetically.
         assert_eq!(index.entries.len(), 2);
         let expected_filepaths = ["alice.txt/nested.txt", "bob.txt"];
-        for (entry_key, expected_filepath) in index.entries.keys().zip(expected_filepaths) {
+        for ((entry_key, _), expected_filepath) in index.entries.keys().zip(expected_filepaths) {
             assert_eq!(entry_key.to_string_lossy(), expected_filepath);
         }
     }
@@ -465,8 +607,70 @@ mod tests {
         // Also, the entries are ordered alphabetically.
         assert_eq!(index.entries.len(), 2);
         let expected_filepaths = ["alice.txt", "nested"];
-        for (entry_key, expected_filepath) in index.entries.keys().zip(expected_filepaths) {
+        for ((entry_key, _), expected_filepath) in index.entries.keys().zip(expected_filepaths) {
             assert_eq!(entry_key.to_string_lossy(), expected_filepath);
         }
     }
+
+    #[test]
+    fn test_conflict_stages() {
+        let path = std::env::temp_dir().join(format!("index-test-{}", std::process::id()));
+        let mut index = Index {
+            path: path.clone(),
+            ..Default::default()
+        };
+        let filepath = PathBuf::from("dir/file.txt");
+        let version = |data: &str| {
+            Some(DatabaseEntry {
+                oid: Sha1::from(data).digest(),
+                mode: REGULAR_MODE,
+            })
+        };
+        index.add(
+            filepath.clone(),
+            Sha1::from("").digest(),
+            IndexMetadata::default(),
+        );
+        index.add_conflict(filepath.clone(), [version("base"), None, version("theirs")]);
+        assert!(index.get_entry(&filepath).is_none());
+        assert!(index.is_tracked_file(&filepath));
+        assert!(index.is_tracked_directory(Path::new("dir")));
+
+        // The stages survive being written out and read back in.
+        index.write_updates();
+        let mut index = Index::new(path.clone());
+        let stages = index.get_conflict(&filepath).unwrap();
+        assert_eq!(
+            stages.map(|entry| entry.map(IndexEntry::get_stage)),
+            [Some(1), None, Some(3)]
+        );
+        assert_eq!(
+            *stages[2].unwrap().get_oid(),
+            version("theirs").unwrap().oid
+        );
+
+        // Adding the path again resolves the conflict.
+        index.add(
+            filepath.clone(),
+            Sha1::from("").digest(),
+            IndexMetadata::default(),
+        );
+        assert!(!index.has_conflicts());
+        assert_eq!(index.entries.len(), 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_entries_in_byte_order() {
+        let mut index = empty_index();
+        for path in ["a/x", "a0", "a-b", "a.c"] {
+            index.add(
+                PathBuf::from(path),
+                Sha1::from("").digest(),
+                IndexMetadata::default(),
+            );
+        }
+        let paths: Vec<_> = index.entries().map(|entry| entry.get_path()).collect();
+        assert_eq!(paths, ["a-b", "a.c", "a/x", "a0"].map(Path::new));
+    }
 }
@@ -1,181 +1,129 @@
+mod attributes;
+mod binary_patch;
 mod blob;
+mod combined_diff;
+mod commands;
 mod commit;
+mod config;
 mod database;
+mod date;
+mod diff;
+mod diff3;
+mod diffstat;
+mod discovery;
+mod fatal;
+mod graph;
+mod ignore;
 mod index;
+mod merge;
+mod merge_base;
+mod migration;
 mod object;
+mod pathspec;
+mod pretty;
+mod rebase;
 mod refs;
+mod rename;
+mod repository;
+mod rev_list;
+mod revision;
+mod sequencer;
+mod tag;
 mod tree;
+mod wildmatch;
 mod workspace;
 
+use crate::fatal::fatal;
 use blob::Blob;
-use commit::Commit;
-use database::Database;
-use index::Index;
-use index::IndexMetadata;
-use object::Object;
-use refs::Refs;
+use repository::Repository;
 use std::env;
-use std::fs;
 use std::io;
-use std::io::stdin;
-use std::path::{Path, PathBuf};
-use std::time::SystemTime;
-use tree::Tree;
-use workspace::Workspace;
 
-// TODO eventually take these in from a config or args.
-const AUTHOR_NAME: &str = "bsubei";
-const AUTHOR_EMAIL: &str = "6508762+bsubei@users.noreply.github.com";
+const USAGE: &str =
+    "usage: git [-C <path>] [--git-dir=<path>] [--work-tree=<path>] <command> [<args>]";
 
 fn main() -> io::Result<()> {
     // TODO use something like clap for arg parsing.
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    parse_global_options(&mut args);
     let subcommand = args.get(1).expect("missing subcommand");
-    // TODO just assume root path is cwd. Will have to resolve this later.
-    let root_path = env::current_dir().expect("failed to get cwd");
-
-    let git_path = root_path.join(".git");
-    let db_path = git_path.join("objects");
-    let index_path = git_path.join("index");
-    println!("path to root is: {:?}", root_path);
-    println!("path to git is: {:?}", git_path);
-    println!("path to db is: {:?}", db_path);
+    let repo = || Repository::new(discovery::discover());
+    let workspace_repo = || {
+        let repo = repo();
+        repo.require_workspace();
+        repo
+    };
 
     match subcommand.as_str() {
-        "init" => {
-            fs::create_dir_all(git_path.join("objects")).expect("Could not create objects dir");
-            fs::create_dir_all(git_path.join("refs")).expect("Could not create refs dir");
-        }
-        "commit" => {
-            let ws = Workspace::new(root_path.clone());
-            let mut database = Database::new(db_path);
-            let mut refs = Refs::new(git_path.clone());
-            let index = Index::new(index_path);
-
-            // Store each file in the workspace as a Blob object on disk.
-            // Also create FileEntry for each file.
-            let files = index.get_filepaths();
-            println!("Committing these files: {:?}", files);
-            let entries: Vec<Blob> = files
-                .into_iter()
-                .map(|f| {
-                    Blob::new(
-                        ws.read_file(f).expect("Could not read file"),
-                        f.to_path_buf(),
-                    )
-                })
-                .collect();
-
-            // Make a Tree object and store it on disk.
-            let root_tree = Tree::new(entries);
-
-            root_tree.traverse(&mut |subtree| {
-                database.store(subtree);
-            });
-
-            let mut commit_message = String::new();
-            stdin().read_line(&mut commit_message)?;
-
-            // TODO currently, we can't read HEAD files that refer to branches (we assume hashes only). That means we can't test on this repo because we used git to create branches.
-            let parent_ref = refs.read_head();
-            let root_msg = match &parent_ref {
-                Some(_) => "",
-                _ => "(root-commit) ",
-            };
-
-            // TODO only go ahead and create a commit if there is something to commit. Likely have to compare the commit's root tree hash with the parent's tree hash.
-            // TODO FIX BUG where add is adding unrelated files to the index. Might be related to above.
-            // Make a Commit object and write it to disk.
-            let commit = Commit::new(
-                *root_tree.get_oid(),
-                parent_ref,
-                AUTHOR_NAME.to_string(),
-                AUTHOR_EMAIL.to_string(),
-                SystemTime::now(),
-                commit_message.to_string(),
-            );
-            database.store(&commit);
-
-            // Update the "HEAD" file to "point" to the new commit.
-            refs.update_head(commit.get_oid());
+        "init" => commands::init::run(&args[2..])?,
+        "add" => commands::add::run(&mut workspace_repo(), &args[2..])?,
+        "check-ignore" => commands::check_ignore::run(&workspace_repo(), &args[2..])?,
+        "checkout" => commands::checkout::run(&mut workspace_repo(), &args[2..])?,
+        "cherry-pick" => commands::cherry_pick::run(&mut workspace_repo(), &args[2..])?,
+        "commit" => commands::commit::run(&mut workspace_repo(), &args[2..])?,
+        "diff" => commands::diff::run(&repo(), &args[2..])?,
+        "log" => commands::log::run(&repo(), &args[2..])?,
+        "ls-files" => commands::ls_files::run(&repo(), &args[2..])?,
+        "merge" => commands::merge::run(&mut workspace_repo(), &args[2..])?,
+        "merge-file" => commands::merge_file::run(&args[2..])?,
+        "merge-base" => commands::merge_base::run(&repo(), &args[2..])?,
+        "rebase" => commands::rebase::run(&mut workspace_repo(), &args[2..])?,
+        "reset" => commands::reset::run(&mut repo(), &args[2..])?,
+        "restore" => commands::restore::run(&mut workspace_repo(), &args[2..])?,
+        "revert" => commands::revert::run(&mut workspace_repo(), &args[2..])?,
+        "rm" => commands::rm::run(&mut workspace_repo(), &args[2..])?,
+        "show" => commands::show::run(&repo(), &args[2..])?,
+        "stash" => commands::stash::run(&mut workspace_repo(), &args[2..])?,
+        "status" => commands::status::run(&workspace_repo(), &args[2..])?,
+        "switch" => commands::switch::run(&mut workspace_repo(), &args[2..])?,
+        "tag" => commands::tag::run(&mut repo(), &args[2..])?,
+        _ => panic!("Unsupported subcommand: {}", subcommand),
+    }
+    Ok(())
+}
 
-            let commit_hash = commit.get_oid();
-            println!(
-                "[{root_msg}{commit_hash} {}]",
-                commit_message.lines().take(1).collect::<String>()
-            );
+// Handles the options that come before the subcommand, removing them from the arguments. Like git, "-C <path>" changes
+// directory (each one relative to the last) and --git-dir and --work-tree work by setting $GIT_DIR and $GIT_WORK_TREE.
+fn parse_global_options(args: &mut Vec<String>) {
+    while let Some(arg) = args.get(1).cloned() {
+        if !arg.starts_with('-') {
+            break;
         }
-        // TODO we have to handle adding removed files (to support deleting files).
-        "add" => {
-            let mut input_filepaths = args
-                .into_iter()
-                .skip(2)
-                .map(PathBuf::from)
-                .collect::<Vec<_>>();
-            // Default to adding the root path.
-            if input_filepaths.is_empty() {
-                input_filepaths.push(root_path.clone());
+        args.remove(1);
+        let mut value = |name: &str| {
+            if args.len() < 2 {
+                eprintln!("error: no directory given for '{name}' option");
+                eprintln!("{USAGE}");
+                std::process::exit(129);
             }
-
-            let ws = Workspace::new(root_path);
-            let mut database = Database::new(db_path);
-
-            let mut index = Index::new(index_path);
-
-            // TODO don't try to add/write files that already exist in the index unless they have changes.
-            // For every user-given filepath, expand it (walk any directories), and add every resulting filepath.
-            let expanded_filepaths: walkdir::Result<Vec<PathBuf>> = input_filepaths
-                .iter()
-                .map(|input_filepath| ws.list_files(input_filepath))
-                .collect::<walkdir::Result<Vec<_>>>()
-                .map(|t| t.concat());
-
-            match expanded_filepaths {
-                Err(e) => {
-                    let path = e.path().unwrap_or(Path::new(""));
-                    if let Some(error) = e.io_error() {
-                        match error.kind() {
-                            io::ErrorKind::NotFound => {
-                                eprintln!(
-                                    "fatal: pathspec '{}' did not match any files",
-                                    path.display()
-                                );
-                            }
-                            _ => {
-                                eprintln!(
-                                    "fatal: pathspec {} had an unknown io error",
-                                    path.display()
-                                );
-                            }
-                        }
-                    } else {
-                        eprintln!("fatal: pathspec {} had an unknown error", path.display());
+            args.remove(1)
+        };
+        match arg.as_str() {
+            "-C" => {
+                let path = value("-C");
+                if !path.is_empty() {
+                    if let Err(e) = env::set_current_dir(&path) {
+                        let reason = match e.kind() {
+                            io::ErrorKind::NotFound => "No such file or directory".to_string(),
+                            _ => e.to_string(),
+                        };
+                        fatal(&format!("cannot change to '{path}': {reason}"));
                     }
-                    std::process::exit(128);
                 }
-                Ok(expanded_filepaths) => {
-                    for expanded_filepath in expanded_filepaths {
-                        let data = ws
-                            .read_file(&expanded_filepath)
-                            .expect("Could not read file in add");
-                        let fs_metadata = ws
-                            .stat_file(&expanded_filepath)
-                            .expect("Could not get file metadata");
-
-                        let blob = Blob::new(data, expanded_filepath.clone());
-                        database.store(&blob);
-                        index.add(
-                            expanded_filepath,
-                            *blob.get_oid(),
-                            IndexMetadata::from(fs_metadata),
-                        );
-                    }
-                }
-            };
-
-            index.write_updates();
+            }
+            "--git-dir" => env::set_var("GIT_DIR", value("--git-dir")),
+            "--work-tree" => env::set_var("GIT_WORK_TREE", value("--work-tree")),
+            _ if arg.starts_with("--git-dir=") => {
+                env::set_var("GIT_DIR", &arg["--git-dir=".len()..])
+            }
+            _ if arg.starts_with("--work-tree=") => {
+                env::set_var("GIT_WORK_TREE", &arg["--work-tree=".len()..])
+            }
+            _ => {
+                eprintln!("unknown option: {arg}");
+                eprintln!("{USAGE}");
+                std::process::exit(129);
+            }
         }
-        _ => panic!("Unsupported subcommand: {}", subcommand),
     }
-    Ok(())
 }
@@ -0,0 +1,627 @@
+use crate::blob::Blob;
+use crate::database::DatabaseEntry;
+use crate::diff::{self, FilePair, OriginKind, Side};
+use crate::diff3::{self, ConflictStyle, Labels, MergeOptions};
+use crate::index::SYMLINK_MODE;
+use crate::merge_base::MergeBase;
+use crate::object::Object;
+use crate::rename::{self, RenameOptions};
+use crate::repository::Repository;
+use crate::tree::Tree;
+use sha1_smol::Digest;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+// A path that didn't merge cleanly, with its version in the base and on each side (None where it doesn't exist).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub base: Option<DatabaseEntry>,
+    pub ours: Option<DatabaseEntry>,
+    pub theirs: Option<DatabaseEntry>,
+}
+
+#[derive(Debug, Default)]
+pub struct MergeOutcome {
+    // Every file the merge leaves in the workspace: the merged version of each path that merged cleanly, and for a conflict
+    // the version with conflict markers (or, if only one side still has the file, that side's version).
+    pub tree: BTreeMap<PathBuf, DatabaseEntry>,
+    pub conflicts: BTreeMap<PathBuf, Conflict>,
+    // What to tell the user about each path, e.g. "Auto-merging a" and "CONFLICT (content): Merge conflict in a". Like git,
+    // they're shown in the (byte) order of the paths they're about.
+    messages: BTreeMap<String, Vec<String>>,
+}
+
+impl MergeOutcome {
+    pub fn messages(&self) -> impl Iterator<Item = &String> {
+        self.messages.values().flatten()
+    }
+
+    // The list of conflicted paths that ends (as comments) the message prepared for committing the result.
+    pub fn conflicts_comment(&self) -> String {
+        let paths: String = self
+            .conflicts
+            .keys()
+            .map(|path| format!("#\t{}\n", path.display()))
+            .collect();
+        format!("# Conflicts:\n{paths}")
+    }
+
+    fn report(&mut self, path: &Path, message: String) {
+        self.messages
+            .entry(path.display().to_string())
+            .or_default()
+            .push(message);
+    }
+
+    fn conflict(&mut self, path: &Path, conflict: Conflict, message: String) {
+        self.conflicts.insert(path.to_path_buf(), conflict);
+        self.report(path, message);
+    }
+}
+
+// Merges the trees of two commits, path by path, against the tree of their best common ancestor. When there's more than one
+// of those (after criss-cross merges), they're merged together first into a virtual ancestor, like git's recursive and ort
+// strategies do.
+pub struct Merge<'a> {
+    repo: &'a mut Repository,
+    style: ConflictStyle,
+}
+
+impl<'a> Merge<'a> {
+    pub fn new(repo: &'a mut Repository, style: ConflictStyle) -> Self {
+        Merge { repo, style }
+    }
+
+    // Merges theirs into ours, given their merge bases. The names label the two sides in messages and conflict markers.
+    pub fn merge_commits(
+        &mut self,
+        bases: &[Digest],
+        ours: Digest,
+        theirs: Digest,
+        ours_name: &str,
+        theirs_name: &str,
+    ) -> MergeOutcome {
+        let base_tree = self.base_tree(bases);
+        let base_name = match bases {
+            [base] => self.repo.database.abbreviate(base),
+            [] => "empty tree".to_string(),
+            _ => "merged common ancestors".to_string(),
+        };
+        let labels = Labels {
+            ours: ours_name,
+            base: &base_name,
+            theirs: theirs_name,
+        };
+        let ours_tree = self.commit_tree(&ours);
+        let theirs_tree = self.commit_tree(&theirs);
+        self.merge_trees(base_tree, Some(ours_tree), Some(theirs_tree), &labels)
+    }
+
+    fn commit_tree(&self, oid: &Digest) -> Digest {
+        *self.repo.database.load_commit(oid).get_tree()
+    }
+
+    // The tree to use as the common ancestor. Several merge bases are merged one after another, each against the merge
+    // bases it has with the ones merged so far, and whatever that leaves (conflict markers included) becomes the ancestor.
+    fn base_tree(&mut self, bases: &[Digest]) -> Option<Digest> {
+        let (first, rest) = bases.split_first()?;
+        let mut tree = self.commit_tree(first);
+        let mut merged = vec![*first];
+        for next in rest {
+            let next_bases = MergeBase::new(self.repo).find(*next, &merged);
+            let base_tree = self.base_tree(&next_bases);
+            let labels = Labels {
+                ours: "Temporary merge branch 1",
+                base: "merged common ancestors",
+                theirs: "Temporary merge branch 2",
+            };
+            let next_tree = self.commit_tree(next);
+            let outcome = self.merge_trees(base_tree, Some(tree), Some(next_tree), &labels);
+            tree = store_tree(self.repo, outcome.tree);
+            merged.push(*next);
+        }
+        Some(tree)
+    }
+
+    // Merges two trees path by path against a base tree, any of which can be missing (an empty tree). Files renamed on either
+    // side are followed, so that their versions are merged together at the new path. Applying the change from one tree
+    // (base) to another (theirs) on top of ours is also how cherry-pick, revert and stash work.
+    pub fn merge_trees(
+        &mut self,
+        base: Option<Digest>,
+        ours: Option<Digest>,
+        theirs: Option<Digest>,
+        labels: &Labels,
+    ) -> MergeOutcome {
+        let entries = |oid: Option<Digest>| {
+            oid.map(|oid| self.repo.database.load_tree_entries(&oid))
+                .unwrap_or_default()
+        };
+        let (base, ours, theirs) = (entries(base), entries(ours), entries(theirs));
+        let (ours_found, theirs_found) = (self.renames(&base, &ours), self.renames(&base, &theirs));
+        // A rename onto a path where the other side has a different file of its own isn't followed, and the two files are
+        // merged as an add/add instead.
+        let unclaimed = |renames: &BTreeMap<PathBuf, PathBuf>,
+                         other_renames: &BTreeMap<PathBuf, PathBuf>,
+                         other: &BTreeMap<PathBuf, DatabaseEntry>| {
+            renames
+                .iter()
+                .filter(|(old, new)| {
+                    !other.contains_key(*new) || other_renames.get(*old) == Some(*new)
+                })
+                .map(|(old, new)| (old.clone(), new.clone()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let ours_renames = unclaimed(&ours_found, &theirs_found, &theirs);
+        let theirs_renames = unclaimed(&theirs_found, &ours_found, &ours);
+
+        let mut outcome = MergeOutcome::default();
+        let mut renamed = BTreeSet::new();
+        let mut sources: BTreeSet<&PathBuf> = ours_renames.keys().collect();
+        sources.extend(theirs_renames.keys());
+        for old in sources {
+            let ours_path = ours_renames.get(old).unwrap_or(old);
+            let theirs_path = theirs_renames.get(old).unwrap_or(old);
+            let conflict = Conflict {
+                base: base.get(old).copied(),
+                ours: ours.get(ours_path).copied(),
+                theirs: theirs.get(theirs_path).copied(),
+            };
+            self.merge_renamed(
+                [old, ours_path, theirs_path],
+                conflict,
+                labels,
+                &mut outcome,
+            );
+            renamed.extend([old, ours_path, theirs_path]);
+        }
+
+        let mut paths: BTreeSet<&PathBuf> = base.keys().collect();
+        paths.extend(ours.keys());
+        paths.extend(theirs.keys());
+        for path in paths.into_iter().filter(|path| !renamed.contains(path)) {
+            let conflict = Conflict {
+                base: base.get(path).copied(),
+                ours: ours.get(path).copied(),
+                theirs: theirs.get(path).copied(),
+            };
+            if let Some(entry) = self.merge_path(path, conflict, labels, &mut outcome) {
+                outcome.tree.insert(path.clone(), entry);
+            }
+        }
+        move_files_out_of_the_way(&mut outcome, &ours, labels);
+        outcome
+    }
+
+    // Finds the files a side renamed from the base, mapping their old paths to their new ones.
+    fn renames(
+        &self,
+        base: &BTreeMap<PathBuf, DatabaseEntry>,
+        side: &BTreeMap<PathBuf, DatabaseEntry>,
+    ) -> BTreeMap<PathBuf, PathBuf> {
+        let deleted: Vec<(&PathBuf, &DatabaseEntry)> = base
+            .iter()
+            .filter(|(path, _)| !side.contains_key(*path))
+            .collect();
+        let added: Vec<(&PathBuf, &DatabaseEntry)> = side
+            .iter()
+            .filter(|(path, _)| !base.contains_key(*path))
+            .collect();
+        if deleted.is_empty() || added.is_empty() {
+            return BTreeMap::new();
+        }
+        let load = |entry: &DatabaseEntry| Side {
+            oid: entry.oid,
+            mode: entry.mode,
+            data: self.repo.database.load_blob(&entry.oid),
+        };
+        let mut pairs: Vec<FilePair> = deleted
+            .into_iter()
+            .map(|(path, entry)| FilePair::new(path.clone(), Some(load(entry)), None))
+            .chain(
+                added
+                    .into_iter()
+                    .map(|(path, entry)| FilePair::new(path.clone(), None, Some(load(entry)))),
+            )
+            .collect();
+        pairs.sort_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+        rename::detect(pairs, &RenameOptions::default())
+            .into_iter()
+            .filter_map(|pair| {
+                let origin = pair.origin?;
+                (origin.kind == OriginKind::Rename).then_some((origin.path, pair.path))
+            })
+            .collect()
+    }
+
+    // Merges a file that was renamed on one or both sides, given its path in the base and on each side. Its versions are
+    // merged at the new path, with each side's path added to the conflict marker labels. A file renamed on one side and
+    // deleted on the other, or renamed to different paths on the two sides, is a conflict, like in git.
+    fn merge_renamed(
+        &mut self,
+        [old, ours_path, theirs_path]: [&PathBuf; 3],
+        conflict: Conflict,
+        labels: &Labels,
+        outcome: &mut MergeOutcome,
+    ) {
+        let Conflict { base, ours, theirs } = conflict.clone();
+        // Renamed to two different paths: both are kept, and the base version is left as a conflict at the old path.
+        if ours_path != old && theirs_path != old && ours_path != theirs_path {
+            let message = format!(
+                "CONFLICT (rename/rename): {} renamed to {} in {} and to {} in {}.",
+                old.display(),
+                ours_path.display(),
+                labels.ours,
+                theirs_path.display(),
+                labels.theirs
+            );
+            let conflict = Conflict {
+                base,
+                ours: None,
+                theirs: None,
+            };
+            outcome.conflict(old, conflict, message);
+            let ours = ours.expect("Renamed file is in our tree");
+            let theirs = theirs.expect("Renamed file is in their tree");
+            for (path, conflict) in [
+                (
+                    ours_path,
+                    Conflict {
+                        base: None,
+                        ours: Some(ours),
+                        theirs: None,
+                    },
+                ),
+                (
+                    theirs_path,
+                    Conflict {
+                        base: None,
+                        ours: None,
+                        theirs: Some(theirs),
+                    },
+                ),
+            ] {
+                outcome
+                    .tree
+                    .insert(path.clone(), conflict.ours.or(conflict.theirs).unwrap());
+                outcome.conflicts.insert(path.clone(), conflict);
+            }
+            return;
+        }
+
+        let path = if ours_path != old {
+            ours_path
+        } else {
+            theirs_path
+        };
+        if ours.is_none() || theirs.is_none() {
+            let (renamed_in, deleted_in) = match ours {
+                Some(_) => (labels.ours, labels.theirs),
+                None => (labels.theirs, labels.ours),
+            };
+            let message = format!(
+                "CONFLICT (rename/delete): {} renamed to {} in {renamed_in}, but deleted in {deleted_in}.",
+                old.display(),
+                path.display()
+            );
+            outcome.tree.insert(path.clone(), ours.or(theirs).unwrap());
+            outcome.conflict(path, conflict, message);
+            return;
+        }
+
+        let (ours_label, base_label, theirs_label);
+        let labels = if ours_path == theirs_path {
+            labels
+        } else {
+            ours_label = format!("{}:{}", labels.ours, ours_path.display());
+            base_label = format!("{}:{}", labels.base, old.display());
+            theirs_label = format!("{}:{}", labels.theirs, theirs_path.display());
+            &Labels {
+                ours: &ours_label,
+                base: &base_label,
+                theirs: &theirs_label,
+            }
+        };
+        if let Some(entry) = self.merge_path(path, conflict, labels, outcome) {
+            outcome.tree.insert(path.clone(), entry);
+        }
+    }
+
+    // Works out what a path ends up as: a side's version if only that side changed it, or the two sides' changes combined
+    // if both did. Returns what goes in the workspace, which for a conflict is the best that could be done.
+    fn merge_path(
+        &mut self,
+        path: &Path,
+        conflict: Conflict,
+        labels: &Labels,
+        outcome: &mut MergeOutcome,
+    ) -> Option<DatabaseEntry> {
+        let Conflict { base, ours, theirs } = conflict.clone();
+        if ours == theirs || base == theirs {
+            return ours;
+        }
+        if base == ours {
+            return theirs;
+        }
+        let name = path.display();
+        match (ours, theirs) {
+            (Some(ours), Some(theirs)) => {
+                outcome.report(path, format!("Auto-merging {name}"));
+                let (merged, clean) = self.merge_blobs(path, base, ours, theirs, labels);
+                if !clean {
+                    let kind = if base.is_some() { "content" } else { "add/add" };
+                    outcome.conflict(
+                        path,
+                        conflict,
+                        format!("CONFLICT ({kind}): Merge conflict in {name}"),
+                    );
+                }
+                Some(merged)
+            }
+            // One side deleted the file and the other changed it, so the changed version is kept.
+            (Some(_), None) => {
+                let message = format!(
+                    "CONFLICT (modify/delete): {name} deleted in {} and modified in {}.  Version {} of {name} left in tree.",
+                    labels.theirs, labels.ours, labels.ours
+                );
+                outcome.conflict(path, conflict, message);
+                ours
+            }
+            (None, Some(_)) => {
+                let message = format!(
+                    "CONFLICT (modify/delete): {name} deleted in {} and modified in {}.  Version {} of {name} left in tree.",
+                    labels.ours, labels.theirs, labels.theirs
+                );
+                outcome.conflict(path, conflict, message);
+                theirs
+            }
+            (None, None) => None,
+        }
+    }
+
+    // Merges two versions of a file line by line and stores the result, returning it and whether it merged cleanly. Binary
+    // files and symlinks can't be merged that way, so ours is kept as it is.
+    fn merge_blobs(
+        &mut self,
+        path: &Path,
+        base: Option<DatabaseEntry>,
+        ours: DatabaseEntry,
+        theirs: DatabaseEntry,
+        labels: &Labels,
+    ) -> (DatabaseEntry, bool) {
+        // Like the contents, the mode is whichever side changed it.
+        let mode = match base {
+            Some(base) if base.mode == ours.mode => theirs.mode,
+            _ => ours.mode,
+        };
+        if ours.mode == SYMLINK_MODE || theirs.mode == SYMLINK_MODE {
+            return (ours, false);
+        }
+        let database = &self.repo.database;
+        let base_data = base
+            .map(|base| database.load_blob(&base.oid))
+            .unwrap_or_default();
+        let ours_data = database.load_blob(&ours.oid);
+        let theirs_data = database.load_blob(&theirs.oid);
+        if [&base_data, &ours_data, &theirs_data]
+            .iter()
+            .any(|data| diff::is_binary(data))
+        {
+            eprintln!(
+                "warning: Cannot merge binary files: {} ({} vs. {})",
+                path.display(),
+                labels.ours,
+                labels.theirs
+            );
+            return (ours, false);
+        }
+
+        let options = MergeOptions {
+            style: self.style,
+            ..Default::default()
+        };
+        let result = diff3::merge(&base_data, &ours_data, &theirs_data, labels, &options);
+        let blob = Blob::new(result.data, path.to_path_buf());
+        self.repo.database.store(&blob);
+        let merged = DatabaseEntry {
+            oid: *blob.get_oid(),
+            mode,
+        };
+        (merged, result.conflicts == 0)
+    }
+}
+
+// A file can't be where the merge leaves a directory, so it's renamed to "<path>~<side>" (after the side it came from) and
+// left as a conflict.
+fn move_files_out_of_the_way(
+    outcome: &mut MergeOutcome,
+    ours: &BTreeMap<PathBuf, DatabaseEntry>,
+    labels: &Labels,
+) {
+    let directories: BTreeSet<PathBuf> = outcome
+        .tree
+        .keys()
+        .flat_map(|path| path.ancestors().skip(1).map(Path::to_path_buf))
+        .collect();
+    let in_the_way: Vec<PathBuf> = outcome
+        .tree
+        .keys()
+        .filter(|path| directories.contains(*path))
+        .cloned()
+        .collect();
+    for path in in_the_way {
+        let entry = outcome.tree.remove(&path).expect("Path is in the tree");
+        outcome.conflicts.remove(&path);
+        let ours_side = ours.get(&path) == Some(&entry);
+        let side = if ours_side {
+            labels.ours
+        } else {
+            labels.theirs
+        };
+        let new_path = PathBuf::from(format!("{}~{side}", path.display()));
+        let conflict = Conflict {
+            base: None,
+            ours: ours_side.then_some(entry),
+            theirs: (!ours_side).then_some(entry),
+        };
+        outcome.tree.insert(new_path.clone(), entry);
+        outcome.conflict(
+            &new_path,
+            conflict,
+            format!(
+                "CONFLICT (file/directory): directory in the way of {} from {side}; moving it to {} instead.",
+                path.display(),
+                new_path.display()
+            ),
+        );
+    }
+}
+
+// Puts every version of each conflicted path in the index, where they stay until the conflicts are resolved.
+pub fn stage_conflicts(repo: &mut Repository, outcome: &MergeOutcome) {
+    for (path, conflict) in &outcome.conflicts {
+        repo.index.add_conflict(
+            path.clone(),
+            [conflict.base, conflict.ours, conflict.theirs],
+        );
+    }
+    repo.index.write_updates();
+}
+
+// Stores the tree (and all the subtrees) of a set of files, returning its oid. The blobs must already be stored.
+pub fn store_tree(repo: &mut Repository, entries: BTreeMap<PathBuf, DatabaseEntry>) -> Digest {
+    let tree = Tree::new(entries);
+    tree.traverse(&mut |subtree| {
+        repo.database.store(subtree);
+    });
+    *tree.get_oid()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::Location;
+    use std::{env, fs};
+
+    fn empty_repo(name: &str) -> Repository {
+        let root = env::temp_dir().join(format!("merge-{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        Repository::new(Location {
+            git_path: root.join(".git"),
+            root_path: Some(root),
+            prefix: PathBuf::new(),
+        })
+    }
+
+    // Stores a tree of files, each given as its path and contents.
+    fn tree(repo: &mut Repository, files: &[(&str, &str)]) -> Digest {
+        let entries = files
+            .iter()
+            .map(|(path, data)| {
+                let blob = Blob::new(data.as_bytes().to_vec(), PathBuf::from(path));
+                repo.database.store(&blob);
+                let entry = DatabaseEntry {
+                    oid: *blob.get_oid(),
+                    mode: 0o100644,
+                };
+                (PathBuf::from(path), entry)
+            })
+            .collect();
+        store_tree(repo, entries)
+    }
+
+    fn numbers(changes: &[(usize, &str)]) -> String {
+        (1..=50)
+            .map(|i| match changes.iter().find(|(line, _)| *line == i) {
+                Some((_, text)) => format!("{text}\n"),
+                None => format!("{i}\n"),
+            })
+            .collect()
+    }
+
+    fn merge(repo: &mut Repository, trees: [Digest; 3]) -> MergeOutcome {
+        let labels = Labels {
+            ours: "ours",
+            base: "base",
+            theirs: "theirs",
+        };
+        Merge::new(repo, ConflictStyle::Merge).merge_trees(
+            Some(trees[0]),
+            Some(trees[1]),
+            Some(trees[2]),
+            &labels,
+        )
+    }
+
+    fn files(repo: &Repository, outcome: &MergeOutcome) -> Vec<(String, String)> {
+        outcome
+            .tree
+            .iter()
+            .map(|(path, entry)| {
+                let data = repo.database.load_blob(&entry.oid);
+                (path.display().to_string(), String::from_utf8(data).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_follows_renames() {
+        let mut repo = empty_repo("renames");
+        let base = tree(&mut repo, &[("big", &numbers(&[]))]);
+        let ours = tree(&mut repo, &[("big", &numbers(&[(10, "ten")]))]);
+        let theirs = tree(&mut repo, &[("big2", &numbers(&[(40, "forty")]))]);
+        // The edit and the rename both apply, from either side.
+        let merged = vec![("big2".to_string(), numbers(&[(10, "ten"), (40, "forty")]))];
+        let outcome = merge(&mut repo, [base, ours, theirs]);
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(files(&repo, &outcome), merged);
+        let outcome = merge(&mut repo, [base, theirs, ours]);
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(files(&repo, &outcome), merged);
+
+        // A conflict in the renamed file is reported at the new path, with each side's path in the markers.
+        let theirs = tree(&mut repo, &[("big2", &numbers(&[(10, "TEN")]))]);
+        let outcome = merge(&mut repo, [base, ours, theirs]);
+        assert_eq!(
+            outcome.conflicts.keys().collect::<Vec<_>>(),
+            [Path::new("big2")]
+        );
+        let (_, data) = &files(&repo, &outcome)[0];
+        assert!(data.contains("<<<<<<< ours:big\nten\n=======\nTEN\n>>>>>>> theirs:big2\n"));
+        assert_eq!(
+            outcome.messages().collect::<Vec<_>>(),
+            [
+                "Auto-merging big2",
+                "CONFLICT (content): Merge conflict in big2"
+            ]
+        );
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_rename_conflicts() {
+        let mut repo = empty_repo("rename-conflicts");
+        let base = tree(&mut repo, &[("big", &numbers(&[]))]);
+        let renamed = tree(&mut repo, &[("big2", &numbers(&[]))]);
+        let deleted = tree(&mut repo, &[]);
+        let outcome = merge(&mut repo, [base, deleted, renamed]);
+        assert_eq!(
+            outcome.messages().collect::<Vec<_>>(),
+            ["CONFLICT (rename/delete): big renamed to big2 in theirs, but deleted in ours."]
+        );
+        assert_eq!(files(&repo, &outcome), [("big2".to_string(), numbers(&[]))]);
+
+        let elsewhere = tree(&mut repo, &[("big3", &numbers(&[]))]);
+        let outcome = merge(&mut repo, [base, elsewhere, renamed]);
+        assert_eq!(
+            outcome.messages().collect::<Vec<_>>(),
+            ["CONFLICT (rename/rename): big renamed to big3 in ours and to big2 in theirs."]
+        );
+        assert_eq!(
+            outcome.conflicts.keys().collect::<Vec<_>>(),
+            [Path::new("big"), Path::new("big2"), Path::new("big3")]
+        );
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+}
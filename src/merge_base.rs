@@ -0,0 +1,221 @@
+use crate::repository::Repository;
+use sha1_smol::Digest;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+// The flags painted onto commits while walking down from the ones being compared: which side(s) they're reachable from,
+// whether they're a common ancestor already found, and whether everything below them is of no more interest.
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+
+// Finds common ancestors in the commit graph, loading each commit's date and parents (all the walks need) only once.
+pub struct MergeBase<'a> {
+    repo: &'a Repository,
+    commits: HashMap<Digest, (i64, Vec<Digest>)>,
+}
+
+impl<'a> MergeBase<'a> {
+    pub fn new(repo: &'a Repository) -> Self {
+        MergeBase {
+            repo,
+            commits: HashMap::new(),
+        }
+    }
+
+    fn load(&mut self, oid: &Digest) -> &(i64, Vec<Digest>) {
+        self.commits.entry(*oid).or_insert_with(|| {
+            let commit = self.repo.database.load_commit(oid);
+            (
+                commit.get_committer().timestamp,
+                commit.get_parents().to_vec(),
+            )
+        })
+    }
+
+    fn date(&mut self, oid: &Digest) -> i64 {
+        self.load(oid).0
+    }
+
+    // The best common ancestors of `one` and any of `others` (as if they were merged together first): those that aren't
+    // ancestors of another common ancestor. They're sorted newest first.
+    pub fn find(&mut self, one: Digest, others: &[Digest]) -> Vec<Digest> {
+        let (candidates, _) = self.paint_down(one, others);
+        if candidates.len() <= 1 {
+            return candidates;
+        }
+        let mut bases = self.remove_redundant(candidates);
+        self.sort_by_date(&mut bases);
+        bases
+    }
+
+    // Whether `ancestor` can be reached from `descendant` (a commit counts as its own ancestor).
+    pub fn is_ancestor(&mut self, ancestor: Digest, descendant: Digest) -> bool {
+        if ancestor == descendant {
+            return true;
+        }
+        let (_, flags) = self.paint_down(ancestor, &[descendant]);
+        flags
+            .get(&ancestor)
+            .is_some_and(|flags| flags & PARENT2 != 0)
+    }
+
+    // The best common ancestors for merging all of the given commits at once, found by taking the merge bases of the first
+    // two, then of each of those with the third, and so on.
+    pub fn octopus(&mut self, commits: &[Digest]) -> Vec<Digest> {
+        let Some((first, rest)) = commits.split_first() else {
+            return Vec::new();
+        };
+        let mut bases = vec![*first];
+        for commit in rest {
+            bases = bases
+                .into_iter()
+                .flat_map(|base| self.find(*commit, &[base]))
+                .collect();
+        }
+        bases
+    }
+
+    // Walks down from `one` (painted PARENT1) and `others` (PARENT2), newest commit first, spreading each commit's flags to
+    // its parents. A commit that ends up with both is a common ancestor, and everything below it is marked stale, since any
+    // common ancestor down there is older than it. The walk ends once only stale commits are left. The result includes every
+    // common ancestor found, some of which may be ancestors of others.
+    fn paint_down(&mut self, one: Digest, others: &[Digest]) -> (Vec<Digest>, HashMap<Digest, u8>) {
+        let mut flags = HashMap::new();
+        if others.contains(&one) {
+            return (vec![one], flags);
+        }
+
+        // The queue pops the most recently committed commit first, and the counter keeps ties in the order they were found.
+        let mut queue = BinaryHeap::new();
+        let mut counter = 0;
+        flags.insert(one, PARENT1);
+        queue.push((self.date(&one), Reverse(counter), one));
+        for other in others {
+            *flags.entry(*other).or_default() |= PARENT2;
+            counter += 1;
+            queue.push((self.date(other), Reverse(counter), *other));
+        }
+
+        let mut results = Vec::new();
+        while queue.iter().any(|(_, _, oid)| flags[oid] & STALE == 0) {
+            let Some((_, _, oid)) = queue.pop() else {
+                break;
+            };
+            let mut painted = flags[&oid] & (PARENT1 | PARENT2 | STALE);
+            if painted == PARENT1 | PARENT2 {
+                if flags[&oid] & RESULT == 0 {
+                    *flags.entry(oid).or_default() |= RESULT;
+                    results.push(oid);
+                }
+                painted |= STALE;
+            }
+            let parents = self.load(&oid).1.clone();
+            for parent in parents {
+                let parent_flags = flags.entry(parent).or_default();
+                if *parent_flags & painted == painted {
+                    continue;
+                }
+                *parent_flags |= painted;
+                counter += 1;
+                queue.push((self.date(&parent), Reverse(counter), parent));
+            }
+        }
+        self.sort_by_date(&mut results);
+        (results, flags)
+    }
+
+    // Drops the candidates that are ancestors of other candidates, by walking down from each one against all the rest.
+    fn remove_redundant(&mut self, candidates: Vec<Digest>) -> Vec<Digest> {
+        let mut redundant = vec![false; candidates.len()];
+        for (i, candidate) in candidates.iter().enumerate() {
+            if redundant[i] {
+                continue;
+            }
+            let others: Vec<Digest> = candidates
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i && !redundant[*j])
+                .map(|(_, oid)| *oid)
+                .collect();
+            let (_, flags) = self.paint_down(*candidate, &others);
+            if flags[candidate] & PARENT2 != 0 {
+                redundant[i] = true;
+            }
+            for (j, other) in candidates.iter().enumerate() {
+                if flags.get(other).is_some_and(|flags| flags & PARENT1 != 0) && j != i {
+                    redundant[j] = true;
+                }
+            }
+        }
+        candidates
+            .into_iter()
+            .zip(redundant)
+            .filter(|(_, redundant)| !redundant)
+            .map(|(oid, _)| oid)
+            .collect()
+    }
+
+    // Newest first, keeping commits with the same date in the order they were found.
+    fn sort_by_date(&mut self, oids: &mut [Digest]) {
+        oids.sort_by_cached_key(|oid| Reverse(self.date(oid)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commit::{Author, Commit};
+    use crate::discovery::Location;
+    use crate::object::Object;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn commit(repo: &mut Repository, parents: &[Digest], timestamp: i64) -> Digest {
+        let author = Author {
+            name: "A U Thor".to_string(),
+            email: "author@example.com".to_string(),
+            timestamp,
+            timezone: "+0000".to_string(),
+        };
+        let empty_tree = "4b825dc642cb6eb9a060e54bf8d69288fbee4904".parse().unwrap();
+        let commit = Commit::new(
+            empty_tree,
+            parents.to_vec(),
+            author.clone(),
+            author,
+            format!("{timestamp}\n"),
+        );
+        repo.database.store(&commit);
+        *commit.get_oid()
+    }
+
+    #[test]
+    fn test_criss_cross() {
+        let dir = std::env::temp_dir().join(format!("merge-base-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join(".git/objects")).unwrap();
+        let mut repo = Repository::new(Location {
+            git_path: dir.join(".git"),
+            root_path: Some(dir.clone()),
+            prefix: PathBuf::new(),
+        });
+        // Two merges of the same pair of branches have both branch tips as their best common ancestors.
+        let root = commit(&mut repo, &[], 1);
+        let x = commit(&mut repo, &[root], 2);
+        let y = commit(&mut repo, &[root], 3);
+        let m1 = commit(&mut repo, &[x, y], 4);
+        let m2 = commit(&mut repo, &[y, x], 5);
+        let tip = commit(&mut repo, &[m1], 6);
+
+        let mut merge_base = MergeBase::new(&repo);
+        assert_eq!(merge_base.find(m1, &[m2]), vec![y, x]);
+        assert_eq!(merge_base.find(tip, &[m2]), vec![y, x]);
+        assert_eq!(merge_base.find(tip, &[m1]), vec![m1]);
+        assert_eq!(merge_base.find(x, &[y]), vec![root]);
+        assert_eq!(merge_base.octopus(&[x, y, tip]), vec![root]);
+        assert!(merge_base.is_ancestor(root, tip));
+        assert!(!merge_base.is_ancestor(m2, tip));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
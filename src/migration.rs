@@ -0,0 +1,358 @@
+use crate::database::{DatabaseEntry, TreeDiff};
+use crate::index::{Index, IndexMetadata};
+use crate::repository::Repository;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The reasons a checkout refuses to touch a path, in the order git reports them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Conflict {
+    // A tracked file has changes (staged or not) that the checkout would overwrite.
+    StaleFile,
+    // A directory that has to be replaced by a file still has untracked files in it.
+    StaleDirectory,
+    // An untracked file is in the way of a file (or directory) the checkout wants to write.
+    UntrackedOverwritten,
+}
+
+impl Conflict {
+    // The message git shows before and after the list of paths, which names the operation ("checkout" or "merge").
+    fn message(&self, operation: &str) -> (String, String) {
+        let action = match operation {
+            "checkout" => "switch branches",
+            _ => operation,
+        };
+        match self {
+            Conflict::StaleFile => (
+                format!("Your local changes to the following files would be overwritten by {operation}:"),
+                format!("Please commit your changes or stash them before you {action}.\n"),
+            ),
+            Conflict::StaleDirectory => (
+                "Updating the following directories would lose untracked files in them:".to_string(),
+                "\n".to_string(),
+            ),
+            Conflict::UntrackedOverwritten => (
+                format!("The following untracked working tree files would be overwritten by {operation}:"),
+                format!("Please move or remove them before you {action}.\n"),
+            ),
+        }
+    }
+}
+
+// Moves the workspace and index from one tree to another: works out which files to write and delete, and checks beforehand
+// that none of that would throw away changes that aren't committed.
+pub struct Migration {
+    // Each path to change, with the entry it should end up with (None to delete it).
+    changes: BTreeMap<PathBuf, Option<DatabaseEntry>>,
+    conflicts: BTreeMap<Conflict, BTreeSet<PathBuf>>,
+}
+
+impl Migration {
+    // Plans the changes for a diff between the current tree and the target tree.
+    pub fn new(repo: &Repository, diff: TreeDiff) -> Self {
+        let mut migration = Migration {
+            changes: BTreeMap::new(),
+            conflicts: BTreeMap::new(),
+        };
+        for (path, (old, new)) in diff {
+            let staged = repo.index.get_entry(&path).map(|entry| DatabaseEntry {
+                oid: *entry.get_oid(),
+                mode: entry.get_mode(),
+            });
+            match staged {
+                // The target is already staged, so the index and workspace are kept as they are.
+                Some(staged) if Some(staged) == new => continue,
+                Some(staged) if Some(staged) != old => {
+                    migration.add_conflict(Conflict::StaleFile, &path)
+                }
+                Some(_) => migration.check_workspace_file(repo, &path),
+                // A file that's only in the old tree was untracked since, so it's left alone.
+                None if new.is_none() => continue,
+                // Removing the file from the index is a staged change of its own.
+                None if old.is_some() => migration.add_conflict(Conflict::StaleFile, &path),
+                None => migration.check_untracked(repo, &path),
+            }
+            if new.is_some() {
+                migration.check_untracked_parents(repo, &path);
+            }
+            migration.changes.insert(path, new);
+        }
+        migration
+    }
+
+    fn add_conflict(&mut self, conflict: Conflict, path: &Path) {
+        self.conflicts
+            .entry(conflict)
+            .or_default()
+            .insert(path.to_path_buf());
+    }
+
+    // A tracked file can only be replaced or deleted if it has no changes in the workspace. A missing file is fine.
+    fn check_workspace_file(&mut self, repo: &Repository, path: &Path) {
+        let entry = repo
+            .index
+            .get_entry(path)
+            .expect("Path is not in the index");
+        if let Ok(stat) = repo.workspace.stat_file(path) {
+            if stat.is_dir() || !repo.is_unchanged(entry, &IndexMetadata::from(stat)) {
+                self.add_conflict(Conflict::StaleFile, path);
+            }
+        }
+    }
+
+    // Something untracked at a path the target has a file at: an untracked file would be overwritten, and a directory is only
+    // safe to replace if everything in it is tracked or ignored (and so deleted as part of the checkout).
+    fn check_untracked(&mut self, repo: &Repository, path: &Path) {
+        let Ok(stat) = repo.workspace.stat_file(path) else {
+            return;
+        };
+        if !stat.is_dir() {
+            // Ignored files are expendable, so they're overwritten without complaint.
+            if !repo.workspace.is_ignored(path) {
+                self.add_conflict(Conflict::UntrackedOverwritten, path);
+            }
+            return;
+        }
+        let files = repo
+            .workspace
+            .list_files(&repo.workspace.root().join(path))
+            .unwrap_or_default();
+        if files.iter().any(|file| !repo.index.is_tracked_file(file)) {
+            self.add_conflict(Conflict::StaleDirectory, path);
+        }
+    }
+
+    // An untracked file where the target needs a directory would be overwritten as well.
+    fn check_untracked_parents(&mut self, repo: &Repository, path: &Path) {
+        for parent in path.ancestors().skip(1) {
+            if parent.as_os_str().is_empty() || repo.index.get_entry(parent).is_some() {
+                continue;
+            }
+            if repo
+                .workspace
+                .stat_file(parent)
+                .is_ok_and(|stat| !stat.is_dir())
+            {
+                self.add_conflict(Conflict::UntrackedOverwritten, parent);
+            }
+        }
+    }
+
+    // The errors to show if the migration can't go ahead, formatted like git's for the given operation. Empty if it's safe
+    // to apply.
+    pub fn errors(&self, operation: &str) -> Vec<String> {
+        self.conflicts
+            .iter()
+            .map(|(conflict, paths)| {
+                let (header, footer) = conflict.message(operation);
+                let paths: String = paths
+                    .iter()
+                    .map(|path| format!("\t{}\n", path.display()))
+                    .collect();
+                format!("{header}\n{paths}{footer}")
+            })
+            .collect()
+    }
+
+    // Writes the changes to the workspace and the index. Deletions come first, so that a file replacing a directory (or the
+    // other way around) has room to be written.
+    pub fn apply(&self, repo: &mut Repository) -> io::Result<()> {
+        let mut emptied_dirs = BTreeSet::new();
+        for (path, _) in self.changes.iter().filter(|(_, entry)| entry.is_none()) {
+            repo.workspace.remove_file(path)?;
+            repo.index.remove(path);
+            emptied_dirs.extend(Index::parent_directories(path));
+        }
+        // Deepest directories first, so each can be empty by the time we get to it.
+        for dir in emptied_dirs.iter().rev() {
+            repo.workspace.remove_directory(dir);
+        }
+
+        for (path, entry) in &self.changes {
+            let Some(entry) = entry else {
+                continue;
+            };
+            let stat = repo.write_to_workspace(path, entry)?;
+            repo.index.add(path.clone(), entry.oid, stat);
+        }
+        repo.index.write_updates();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob::Blob;
+    use crate::discovery::Location;
+    use crate::index::SYMLINK_MODE;
+    use crate::object::Object;
+    use std::{env, fs};
+
+    fn empty_repo(name: &str) -> Repository {
+        let root = env::temp_dir().join(format!("migration-{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        Repository::new(Location {
+            git_path: root.join(".git"),
+            root_path: Some(root),
+            prefix: PathBuf::new(),
+        })
+    }
+
+    fn store(repo: &mut Repository, data: &str, mode: u32) -> DatabaseEntry {
+        let blob = Blob::new(data.as_bytes().to_vec(), PathBuf::new());
+        repo.database.store(&blob);
+        DatabaseEntry {
+            oid: *blob.get_oid(),
+            mode,
+        }
+    }
+
+    // Writes a file and stages it, returning its entry.
+    fn track(repo: &mut Repository, path: &str, data: &str) -> DatabaseEntry {
+        let path = PathBuf::from(path);
+        for dir in Index::parent_directories(&path) {
+            repo.workspace.make_directory(&dir).unwrap();
+        }
+        fs::write(repo.workspace.root().join(&path), data).unwrap();
+        let entry = store(repo, data, 0o100644);
+        let stat = IndexMetadata::from(repo.workspace.stat_file(&path).unwrap());
+        repo.index.add(path, entry.oid, stat);
+        entry
+    }
+
+    fn conflicts(conflict: Conflict, paths: &[&str]) -> BTreeMap<Conflict, BTreeSet<PathBuf>> {
+        BTreeMap::from([(conflict, paths.iter().map(PathBuf::from).collect())])
+    }
+
+    #[test]
+    fn test_dirty_file() {
+        let mut repo = empty_repo("dirty");
+        let old = track(&mut repo, "a", "1\n");
+        let new = store(&mut repo, "2\n", 0o100644);
+        fs::write(repo.workspace.root().join("a"), "changed\n").unwrap();
+        let diff = TreeDiff::from([(PathBuf::from("a"), (Some(old), Some(new)))]);
+        let migration = Migration::new(&repo, diff);
+        assert_eq!(migration.conflicts, conflicts(Conflict::StaleFile, &["a"]));
+        assert_eq!(
+            migration.errors("checkout"),
+            ["Your local changes to the following files would be overwritten by checkout:\n\ta\n\
+              Please commit your changes or stash them before you switch branches.\n"]
+        );
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_untracked_file_in_the_way() {
+        let mut repo = empty_repo("untracked");
+        let new = store(&mut repo, "tracked\n", 0o100644);
+        fs::write(repo.workspace.root().join("a"), "untracked\n").unwrap();
+        let diff = TreeDiff::from([(PathBuf::from("a"), (None, Some(new)))]);
+        let migration = Migration::new(&repo, diff);
+        assert_eq!(
+            migration.conflicts,
+            conflicts(Conflict::UntrackedOverwritten, &["a"])
+        );
+
+        // An ignored file is overwritten instead.
+        fs::write(repo.workspace.root().join(".gitignore"), "a\n").unwrap();
+        let repo = Repository::new(Location {
+            git_path: repo.workspace.root().join(".git"),
+            root_path: Some(repo.workspace.root().to_path_buf()),
+            prefix: PathBuf::new(),
+        });
+        let diff = TreeDiff::from([(PathBuf::from("a"), (None, Some(new)))]);
+        assert!(Migration::new(&repo, diff).conflicts.is_empty());
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_untracked_files_in_replaced_directory() {
+        let mut repo = empty_repo("directory");
+        let old = track(&mut repo, "d/tracked", "1\n");
+        let new = store(&mut repo, "file\n", 0o100644);
+        fs::write(repo.workspace.root().join("d/untracked"), "untracked\n").unwrap();
+        let diff = TreeDiff::from([
+            (PathBuf::from("d"), (None, Some(new))),
+            (PathBuf::from("d/tracked"), (Some(old), None)),
+        ]);
+        let migration = Migration::new(&repo, diff.clone());
+        assert_eq!(
+            migration.conflicts,
+            conflicts(Conflict::StaleDirectory, &["d"])
+        );
+
+        // Once only tracked files are left, the directory makes way for the file.
+        fs::remove_file(repo.workspace.root().join("d/untracked")).unwrap();
+        let migration = Migration::new(&repo, diff);
+        assert!(migration.conflicts.is_empty());
+        migration.apply(&mut repo).unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.workspace.root().join("d")).unwrap(),
+            "file\n"
+        );
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_staged_changes_carried_over() {
+        let mut repo = empty_repo("staged");
+        let old = store(&mut repo, "1\n", 0o100644);
+        // One file has the target's contents staged already, and another a change the target doesn't touch.
+        let new = track(&mut repo, "a", "2\n");
+        track(&mut repo, "b", "staged\n");
+        let other = store(&mut repo, "other\n", 0o100644);
+        let diff = TreeDiff::from([
+            (PathBuf::from("a"), (Some(old), Some(new))),
+            (PathBuf::from("c"), (None, Some(other))),
+        ]);
+        let migration = Migration::new(&repo, diff);
+        assert!(migration.conflicts.is_empty());
+        migration.apply(&mut repo).unwrap();
+        let staged = |path: &str| *repo.index.get_entry(Path::new(path)).unwrap().get_oid();
+        assert_eq!(staged("a"), new.oid);
+        assert_eq!(repo.database.load_blob(&staged("b")), b"staged\n".to_vec());
+        assert_eq!(staged("c"), other.oid);
+
+        // A staged change the target doesn't have would be lost, though.
+        let newer = store(&mut repo, "3\n", 0o100644);
+        let diff = TreeDiff::from([(PathBuf::from("a"), (Some(old), Some(newer)))]);
+        let migration = Migration::new(&repo, diff);
+        assert_eq!(migration.conflicts, conflicts(Conflict::StaleFile, &["a"]));
+        fs::remove_dir_all(repo.workspace.root()).unwrap();
+    }
+
+    #[test]
+    fn test_symlinks() {
+        let mut repo = empty_repo("symlinks");
+        let root = repo.workspace.root().to_path_buf();
+        let link = store(&mut repo, "target", SYMLINK_MODE);
+        let diff = TreeDiff::from([(PathBuf::from("link"), (None, Some(link)))]);
+        Migration::new(&repo, diff).apply(&mut repo).unwrap();
+        assert_eq!(
+            fs::read_link(root.join("link")).unwrap(),
+            PathBuf::from("target")
+        );
+        let entry = repo.index.get_entry(Path::new("link")).unwrap();
+        assert_eq!(entry.get_mode(), SYMLINK_MODE);
+        let stat = IndexMetadata::from(repo.workspace.stat_file("link").unwrap());
+        assert!(repo.is_unchanged(entry, &stat));
+
+        // Replacing the symlink with a file doesn't write through it.
+        fs::write(root.join("target"), "untouched\n").unwrap();
+        let file = store(&mut repo, "file\n", 0o100644);
+        let diff = TreeDiff::from([(PathBuf::from("link"), (Some(link), Some(file)))]);
+        Migration::new(&repo, diff).apply(&mut repo).unwrap();
+        assert!(!fs::symlink_metadata(root.join("link"))
+            .unwrap()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(root.join("link")).unwrap(), "file\n");
+        assert_eq!(
+            fs::read_to_string(root.join("target")).unwrap(),
+            "untouched\n"
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
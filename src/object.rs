@@ -5,3 +5,13 @@ pub trait Object {
     fn get_oid(&self) -> &Digest;
     fn get_content(&self) -> &[u8];
 }
+
+// sha1_smol can only build a Digest from its hex form, so go through that when we read raw oid bytes (e.g. from trees or the index).
+pub fn digest_from_bytes(bytes: &[u8]) -> Digest {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>()
+        .parse()
+        .expect("Could not parse oid bytes into a digest")
+}
@@ -0,0 +1,359 @@
+use crate::fatal::fatal;
+use crate::wildmatch::{wildmatch, MatchFlags};
+use std::path::{Component, Path, PathBuf};
+
+// How a pattern matched a path, from weakest to strongest: by naming a directory the path is in, as a wildcard pattern, or
+// by naming the path itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Match {
+    Recursively,
+    Wildcard,
+    Exactly,
+}
+
+// One pattern of a pathspec along with its magic, which is given in front of it either in long form (":(icase,glob)*.c") or
+// in short form (":!docs", ":/src").
+#[derive(Debug, Default, Clone)]
+pub struct Item {
+    // The pattern as it was given, for error messages.
+    pub original: String,
+    // The pattern relative to the top of the workspace.
+    pub pattern: String,
+    // Where the first wildcard is, which is the end of the pattern if it has none (or is literal).
+    literal_length: usize,
+    // Wildcards don't match slashes, except for "**".
+    pub glob: bool,
+    pub icase: bool,
+    pub exclude: bool,
+}
+
+// The paths a command should work on, as given on its command line. Each pattern matches a file by its exact path, any file
+// in the directory it names, or (if it has wildcards) any path it matches as a glob, where "*" also matches slashes unless
+// the pattern has the "glob" magic. Patterns with the "exclude" magic take paths back out. An empty pathspec matches
+// everything.
+#[derive(Debug, Default)]
+pub struct Pathspec {
+    items: Vec<Item>,
+}
+
+impl Pathspec {
+    // Parses patterns given relative to `prefix` (where the command was run from) inside the workspace at `root`. Like git,
+    // invalid magic or a pattern leading outside the workspace is a fatal error.
+    pub fn new(patterns: &[String], root: &Path, prefix: &Path) -> Self {
+        let mut items: Vec<Item> = patterns
+            .iter()
+            .map(|pattern| parse_item(pattern, root, prefix))
+            .collect();
+        // Patterns that only exclude things exclude them from everything.
+        if !items.is_empty() && items.iter().all(|item| item.exclude) {
+            items.push(Item::default());
+        }
+        Pathspec { items }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    // Whether any of the patterns picks out paths, as opposed to only excluding them.
+    pub fn has_patterns(&self) -> bool {
+        self.items
+            .iter()
+            .any(|item| !item.exclude && !item.original.is_empty())
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        if self
+            .items
+            .iter()
+            .any(|item| item.exclude && item.matches(&path).is_some())
+        {
+            return false;
+        }
+        self.items.is_empty()
+            || self
+                .items
+                .iter()
+                .any(|item| !item.exclude && item.matches(&path).is_some())
+    }
+
+    // Each (non-exclude) pattern, along with the best way it matched any of the given paths. Paths that an exclude pattern
+    // takes out still count, like in git.
+    pub fn seen<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a Path>,
+    ) -> Vec<(&Item, Option<Match>)> {
+        let items: Vec<&Item> = self
+            .items
+            .iter()
+            .filter(|item| !item.exclude && !item.original.is_empty())
+            .collect();
+        let mut seen = vec![None; items.len()];
+        for path in paths {
+            let path = path.to_string_lossy();
+            for (best, item) in seen.iter_mut().zip(&items) {
+                *best = (*best).max(item.matches(&path));
+            }
+        }
+        items.into_iter().zip(seen).collect()
+    }
+
+    // The patterns that match none of the given paths, which commands usually report as errors.
+    pub fn unmatched<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> Vec<&str> {
+        self.seen(paths)
+            .into_iter()
+            .filter(|(_, seen)| seen.is_none())
+            .map(|(item, _)| item.original.as_str())
+            .collect()
+    }
+
+    // The pattern the given one resolved to, if it's the only pattern and has no wildcards, for following a single file.
+    pub fn single_path(&self) -> Option<&Path> {
+        match self.items.as_slice() {
+            [item] if !item.exclude && item.literal_length == item.pattern.len() => {
+                Some(Path::new(&item.pattern))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Item {
+    // The part of the pattern before its first wildcard.
+    pub fn literal_prefix(&self) -> &str {
+        &self.pattern[..self.literal_length]
+    }
+
+    fn matches(&self, path: &str) -> Option<Match> {
+        let pattern = self.pattern.as_str();
+        if pattern.is_empty() {
+            return Some(Match::Recursively);
+        }
+        let starts_with = |text: &str, prefix: &str| {
+            text.len() >= prefix.len()
+                && text.is_char_boundary(prefix.len())
+                && if self.icase {
+                    text[..prefix.len()].eq_ignore_ascii_case(prefix)
+                } else {
+                    text.starts_with(prefix)
+                }
+        };
+        if starts_with(path, pattern) {
+            if path.len() == pattern.len() {
+                return Some(Match::Exactly);
+            }
+            if pattern.ends_with('/') || path[pattern.len()..].starts_with('/') {
+                return Some(Match::Recursively);
+            }
+        }
+        let flags = MatchFlags {
+            pathname: self.glob,
+            casefold: self.icase,
+        };
+        (self.literal_length < pattern.len()
+            && starts_with(path, &pattern[..self.literal_length])
+            && wildmatch(pattern, path, flags))
+        .then_some(Match::Wildcard)
+    }
+}
+
+fn parse_item(original: &str, root: &Path, prefix: &Path) -> Item {
+    if original.is_empty() {
+        fatal("empty string is not a valid pathspec. please use . instead if you meant to match all paths");
+    }
+    let mut item = Item {
+        original: original.to_string(),
+        ..Default::default()
+    };
+    let mut top = false;
+    let mut literal = false;
+    let mut rest = original;
+    if let Some(magic) = original.strip_prefix(":(") {
+        let Some((names, pattern)) = magic.split_once(')') else {
+            fatal(&format!(
+                "Missing ')' at the end of pathspec magic in '{original}'"
+            ));
+        };
+        for name in names.split(',').filter(|name| !name.is_empty()) {
+            match name {
+                "top" => top = true,
+                "literal" => literal = true,
+                "glob" => item.glob = true,
+                "icase" => item.icase = true,
+                "exclude" => item.exclude = true,
+                _ => fatal(&format!("Invalid pathspec magic '{name}' in '{original}'")),
+            }
+        }
+        rest = pattern;
+    } else if let Some(magic) = original.strip_prefix(':') {
+        // Short magic is a run of punctuation, optionally ended by another ':'.
+        rest = magic;
+        while let Some(mnemonic) = rest.chars().next() {
+            match mnemonic {
+                ':' => {
+                    rest = &rest[1..];
+                    break;
+                }
+                '/' => top = true,
+                '!' | '^' => item.exclude = true,
+                _ if "\"#%&',-;<=>@_`~".contains(mnemonic) => fatal(&format!(
+                    "Unimplemented pathspec magic '{mnemonic}' in '{original}'"
+                )),
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+    }
+    if literal && item.glob {
+        fatal(&format!(
+            "{original}: 'literal' and 'glob' are incompatible"
+        ));
+    }
+
+    let base = if top { Path::new("") } else { prefix };
+    item.pattern = resolve(root, base, rest);
+    item.literal_length = if literal {
+        item.pattern.len()
+    } else {
+        item.pattern
+            .find(['*', '?', '[', '\\'])
+            .unwrap_or(item.pattern.len())
+    };
+    item
+}
+
+// Turns a path given relative to `prefix` into a path relative to the top of the workspace at `root`. Like git, a path that
+// leads outside the workspace is a fatal error.
+pub fn resolve(root: &Path, prefix: &Path, path: &str) -> String {
+    let mut resolved = PathBuf::new();
+    for component in root.join(prefix).join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    let Ok(relative) = resolved.strip_prefix(root) else {
+        fatal(&format!(
+            "{path}: '{path}' is outside repository at '{}'",
+            root.display()
+        ));
+    };
+    let mut relative = relative.to_string_lossy().into_owned();
+    // Keep a trailing slash, which says that the path is a directory.
+    if path.ends_with('/') && !relative.is_empty() {
+        relative.push('/');
+    }
+    relative
+}
+
+// A path from the top of the workspace as seen from a directory in it, going up with ".." where the path isn't inside it.
+pub fn relative_path(path: &Path, dir: &Path) -> PathBuf {
+    let mut path_components = path.components().peekable();
+    let mut dir_components = dir.components().peekable();
+    while path_components.peek().is_some() && path_components.peek() == dir_components.peek() {
+        path_components.next();
+        dir_components.next();
+    }
+    dir_components
+        .map(|_| Component::ParentDir)
+        .chain(path_components)
+        .collect()
+}
+
+// Whether a command line argument that isn't an existing file is still meant as a pathspec, because it has wildcards or
+// magic, so that commands taking both revisions and paths can tell which it is.
+pub fn looks_like_pathspec(arg: &str) -> bool {
+    arg.starts_with(":(")
+        || arg.starts_with(":/")
+        || arg.starts_with(":!")
+        || arg.starts_with(":^")
+        || arg.contains(['*', '?', '['])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pathspec(patterns: &[&str], prefix: &str) -> Pathspec {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Pathspec::new(&patterns, Path::new("/repo"), Path::new(prefix))
+    }
+
+    #[test]
+    fn test_matches() {
+        let spec = pathspec(&["src", "docs/", "*.md", "./a.txt"], "");
+        for path in [
+            "src/main.rs",
+            "src",
+            "docs/x/y",
+            "README.md",
+            "docs/deep/notes.md",
+            "a.txt",
+        ] {
+            assert!(spec.matches(Path::new(path)), "{path}");
+        }
+        for path in ["srcs/x", "a.txt.bak", "b.txt", "docs"] {
+            assert!(!spec.matches(Path::new(path)), "{path}");
+        }
+        assert!(pathspec(&["."], "").matches(Path::new("any/thing")));
+        assert!(pathspec(&[], "").matches(Path::new("any/thing")));
+    }
+
+    #[test]
+    fn test_magic() {
+        let spec = pathspec(&[":(glob)*.rs", ":(icase)README"], "");
+        assert!(spec.matches(Path::new("main.rs")));
+        assert!(!spec.matches(Path::new("src/main.rs")));
+        assert!(spec.matches(Path::new("readme")));
+        assert!(pathspec(&[":(glob)**/*.rs"], "").matches(Path::new("src/a/main.rs")));
+        assert!(pathspec(&[":(literal)a*"], "").matches(Path::new("a*")));
+        assert!(!pathspec(&[":(literal)a*"], "").matches(Path::new("ab")));
+
+        // Paths from a subdirectory are relative to it, unless they're from the top.
+        let spec = pathspec(&["*.c", ":/top.txt"], "sub");
+        assert!(spec.matches(Path::new("sub/deep/x.c")));
+        assert!(!spec.matches(Path::new("x.c")));
+        assert!(spec.matches(Path::new("top.txt")));
+
+        // Excluding patterns on their own exclude from everything.
+        let spec = pathspec(&[":!docs", ":(exclude)*.md"], "");
+        assert!(spec.matches(Path::new("src/main.rs")));
+        assert!(!spec.matches(Path::new("docs/x")));
+        assert!(!spec.matches(Path::new("README.md")));
+    }
+
+    #[test]
+    fn test_seen() {
+        let spec = pathspec(&["a", "nope", "*.c", "x.c", ":!a/b"], "");
+        let paths = [Path::new("a/b"), Path::new("x.c")];
+        let seen: Vec<_> = spec
+            .seen(paths)
+            .into_iter()
+            .map(|(item, seen)| (item.original.as_str(), seen))
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                ("a", Some(Match::Recursively)),
+                ("nope", None),
+                ("*.c", Some(Match::Wildcard)),
+                ("x.c", Some(Match::Exactly)),
+            ]
+        );
+        assert_eq!(spec.unmatched(paths), ["nope"]);
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let relative = |path: &str, dir: &str| relative_path(Path::new(path), Path::new(dir));
+        assert_eq!(relative("sub/f", "sub"), Path::new("f"));
+        assert_eq!(relative("top", "sub"), Path::new("../top"));
+        assert_eq!(relative("sub/f", "sub/deep"), Path::new("../f"));
+        assert_eq!(relative("subway/f", "sub"), Path::new("../subway/f"));
+        assert_eq!(relative("top", ""), Path::new("top"));
+    }
+}
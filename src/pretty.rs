@@ -0,0 +1,949 @@
+use crate::commit::{Author, Commit};
+use crate::date::{self, DateMode};
+use crate::fatal::fatal;
+use crate::graph::Graph;
+use crate::object::Object;
+use crate::repository::Repository;
+use crate::tag::Tag;
+use sha1_smol::Digest;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+
+const RESET: &str = "\x1b[m";
+const COMMIT_COLOR: &str = "\x1b[33m";
+
+// How each commit is printed, as chosen with --pretty, --format or --oneline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrettyFormat {
+    Oneline,
+    Short,
+    Medium,
+    Full,
+    Fuller,
+    Raw,
+    // A format string with placeholders. With `terminator` ("tformat:") every commit ends with a newline, otherwise ("format:")
+    // newlines only go between commits.
+    Custom { format: String, terminator: bool },
+}
+
+impl PrettyFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        let format = match name {
+            "oneline" => PrettyFormat::Oneline,
+            "short" => PrettyFormat::Short,
+            "medium" => PrettyFormat::Medium,
+            "full" => PrettyFormat::Full,
+            "fuller" => PrettyFormat::Fuller,
+            "raw" => PrettyFormat::Raw,
+            _ => {
+                let (format, terminator) = if let Some(format) = name.strip_prefix("format:") {
+                    (format, false)
+                } else if let Some(format) = name.strip_prefix("tformat:") {
+                    (format, true)
+                } else if name.is_empty() || name.contains('%') {
+                    (name, true)
+                } else {
+                    return None;
+                };
+                PrettyFormat::Custom {
+                    format: format.to_string(),
+                    terminator,
+                }
+            }
+        };
+        Some(format)
+    }
+}
+
+// Whether the commit header shows the refs that point at the commit, and if so by their short or full names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decorate {
+    No,
+    Short,
+    Full,
+}
+
+#[derive(Debug)]
+pub struct PrettyOptions {
+    pub format: PrettyFormat,
+    pub date_mode: DateMode,
+    // Show abbreviated oids instead of full ones in the commit header.
+    pub abbrev_commit: bool,
+    pub decorate: Decorate,
+    pub color: bool,
+}
+
+// Like git, decorations are shown by default only when writing to a terminal.
+fn auto_decorate() -> Decorate {
+    if io::stdout().is_terminal() {
+        Decorate::Short
+    } else {
+        Decorate::No
+    }
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        PrettyOptions {
+            format: PrettyFormat::Medium,
+            date_mode: DateMode::Default,
+            abbrev_commit: false,
+            decorate: auto_decorate(),
+            color: io::stdout().is_terminal(),
+        }
+    }
+}
+
+impl PrettyOptions {
+    // Consumes the argument if it's one of the commit formatting options and returns whether it was.
+    pub fn parse_arg(&mut self, arg: &str) -> bool {
+        match arg {
+            "--oneline" => {
+                self.format = PrettyFormat::Oneline;
+                self.abbrev_commit = true;
+            }
+            "--pretty" => self.format = PrettyFormat::Medium,
+            "--abbrev-commit" => self.abbrev_commit = true,
+            "--no-abbrev-commit" => self.abbrev_commit = false,
+            "--decorate" | "--decorate=short" => self.decorate = Decorate::Short,
+            "--decorate=full" => self.decorate = Decorate::Full,
+            "--no-decorate" | "--decorate=no" => self.decorate = Decorate::No,
+            "--decorate=auto" => self.decorate = auto_decorate(),
+            "--color" | "--color=always" => self.color = true,
+            "--no-color" | "--color=never" => self.color = false,
+            "--color=auto" => self.color = io::stdout().is_terminal(),
+            _ => {
+                if let Some(name) = arg
+                    .strip_prefix("--pretty=")
+                    .or_else(|| arg.strip_prefix("--format="))
+                {
+                    self.format = PrettyFormat::parse(name)
+                        .unwrap_or_else(|| fatal(&format!("invalid --pretty format: {name}")));
+                } else if let Some(mode) = arg.strip_prefix("--date=") {
+                    self.date_mode = DateMode::parse(mode)
+                        .unwrap_or_else(|| fatal(&format!("unknown date format {mode}")));
+                } else if let Some(value) = arg.strip_prefix("--decorate=") {
+                    fatal(&format!("invalid --decorate option: {value}"));
+                } else {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecorationKind {
+    Head,
+    Branch,
+    RemoteBranch,
+    Tag,
+    Stash,
+    Other,
+}
+
+// A ref name shown next to the commit it points to, e.g. "tag: v1.0".
+#[derive(Debug, Clone)]
+pub struct Decoration {
+    refname: String,
+    name: String,
+    kind: DecorationKind,
+}
+
+impl Decoration {
+    // Names are shortened (e.g. "refs/heads/main" to "main") unless `full` is set.
+    fn new(refname: &str, full: bool) -> Self {
+        let short = |prefix: &str| {
+            let name = refname.strip_prefix(prefix)?;
+            Some(if full { refname } else { name }.to_string())
+        };
+        let (name, kind) = if let Some(name) = short("refs/heads/") {
+            (name, DecorationKind::Branch)
+        } else if let Some(name) = short("refs/remotes/") {
+            (name, DecorationKind::RemoteBranch)
+        } else if let Some(name) = short("refs/tags/") {
+            (format!("tag: {name}"), DecorationKind::Tag)
+        } else if refname == "refs/stash" {
+            (refname.to_string(), DecorationKind::Stash)
+        } else if refname == "HEAD" {
+            (refname.to_string(), DecorationKind::Head)
+        } else {
+            (refname.to_string(), DecorationKind::Other)
+        };
+        Decoration {
+            refname: refname.to_string(),
+            name,
+            kind,
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self.kind {
+            DecorationKind::Head => "\x1b[1;36m",
+            DecorationKind::Branch => "\x1b[1;32m",
+            DecorationKind::RemoteBranch => "\x1b[1;31m",
+            DecorationKind::Tag => "\x1b[1;33m",
+            DecorationKind::Stash => "\x1b[1;35m",
+            DecorationKind::Other => COMMIT_COLOR,
+        }
+    }
+}
+
+// Maps each commit that refs point to onto the names to show for it. Like git, HEAD comes first and the rest are in reverse order
+// of their full ref names.
+pub fn load_decorations(repo: &Repository, full: bool) -> HashMap<Digest, Vec<Decoration>> {
+    let mut decorations: HashMap<Digest, Vec<Decoration>> = HashMap::new();
+    let head = repo.refs.read_head().map(|oid| ("HEAD".to_string(), oid));
+    for (refname, oid) in repo.refs.list_refs().into_iter().chain(head) {
+        // Annotated tags decorate the commit they tag rather than the tag object.
+        decorations
+            .entry(repo.database.peel(&oid))
+            .or_default()
+            .insert(0, Decoration::new(&refname, full));
+    }
+    decorations
+}
+
+// Joins decorations with ", ", showing the branch HEAD is on as "HEAD -> main". Each name gets its own color if asked for.
+fn format_decorations(
+    decorations: &[Decoration],
+    head_branch: Option<&str>,
+    color: bool,
+) -> String {
+    let paint = |text: &str, color_code: &str| {
+        if color {
+            format!("{color_code}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    };
+    let has_head = decorations.iter().any(|d| d.kind == DecorationKind::Head);
+    let is_head_branch = |decoration: &Decoration| {
+        has_head
+            && decoration.kind == DecorationKind::Branch
+            && decoration.refname.strip_prefix("refs/heads/") == head_branch
+    };
+    let head_branch = decorations.iter().find(|d| is_head_branch(d));
+
+    let names: Vec<String> = decorations
+        .iter()
+        .filter(|decoration| !is_head_branch(decoration))
+        .map(|decoration| match (decoration.kind, head_branch) {
+            (DecorationKind::Head, Some(branch)) => {
+                paint("HEAD -> ", decoration.color()) + &paint(&branch.name, branch.color())
+            }
+            _ => paint(&decoration.name, decoration.color()),
+        })
+        .collect();
+    names.join(&paint(", ", COMMIT_COLOR))
+}
+
+// The first paragraph of a commit message, joined into a single line.
+pub fn subject(message: &str) -> String {
+    message
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| !line.trim().is_empty())
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Everything in a commit message after its subject and the blank lines following it.
+fn body(message: &str) -> String {
+    let mut lines = message
+        .split_inclusive('\n')
+        .skip_while(|line| line.trim().is_empty());
+    let mut rest: Vec<&str> = lines
+        .by_ref()
+        .skip_while(|line| !line.trim().is_empty())
+        .collect();
+    let start = rest
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(rest.len());
+    rest.drain(..start);
+    rest.concat()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Truncate {
+    None,
+    Right,
+    Left,
+    Middle,
+}
+
+// A padding directive like "%<(20,trunc)", which applies to the placeholder right after it.
+#[derive(Debug, Clone, Copy)]
+struct Padding {
+    width: usize,
+    // For "%<|(N)", the width is up to column N of the current line rather than N characters.
+    to_column: bool,
+    align: Align,
+    truncate: Truncate,
+}
+
+impl Padding {
+    // Parses the directive at the start of `spec` (the text after '%'), returning it and how many bytes it took up.
+    fn parse(spec: &str) -> Option<(Self, usize)> {
+        let (align, rest) = if let Some(rest) = spec.strip_prefix("><") {
+            (Align::Center, rest)
+        } else if let Some(rest) = spec.strip_prefix(">>") {
+            (Align::Right, rest)
+        } else if let Some(rest) = spec.strip_prefix('>') {
+            (Align::Right, rest)
+        } else {
+            (Align::Left, spec.strip_prefix('<')?)
+        };
+        let (to_column, rest) = match rest.strip_prefix('|') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (args, _) = rest.strip_prefix('(')?.split_once(')')?;
+        let (width, truncate) = match args.split_once(',') {
+            Some((width, "trunc")) => (width, Truncate::Right),
+            Some((width, "ltrunc")) => (width, Truncate::Left),
+            Some((width, "mtrunc")) => (width, Truncate::Middle),
+            Some(_) => return None,
+            None => (args, Truncate::None),
+        };
+        let padding = Padding {
+            width: width.trim().parse().ok()?,
+            to_column,
+            align,
+            truncate,
+        };
+        let length = spec.len() - rest.len() + args.len() + 2;
+        Some((padding, length))
+    }
+
+    fn apply(&self, text: &str, column: usize) -> String {
+        let width = if self.to_column {
+            self.width.saturating_sub(column)
+        } else {
+            self.width
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let len = chars.len();
+        if len > width {
+            // Truncating leaves room for the ".." that marks where text was cut.
+            let keep = width.saturating_sub(2);
+            let kept: String = match self.truncate {
+                Truncate::None => return text.to_string(),
+                Truncate::Right => chars[..keep].iter().collect::<String>() + "..",
+                Truncate::Left => {
+                    "..".to_string() + &chars[len - keep..].iter().collect::<String>()
+                }
+                Truncate::Middle => {
+                    let left = (width / 2).saturating_sub(1);
+                    let right = keep - left;
+                    chars[..left].iter().collect::<String>()
+                        + ".."
+                        + &chars[len - right..].iter().collect::<String>()
+                }
+            };
+            return kept;
+        }
+        let fill = width - len;
+        match self.align {
+            Align::Left => format!("{text}{}", " ".repeat(fill)),
+            Align::Right => format!("{}{text}", " ".repeat(fill)),
+            Align::Center => format!(
+                "{}{text}{}",
+                " ".repeat(fill / 2),
+                " ".repeat(fill - fill / 2)
+            ),
+        }
+    }
+}
+
+// Turns a color spec like "bold red" or "yellow blue" (foreground then background) into its terminal escape sequence.
+fn parse_color(spec: &str) -> Option<String> {
+    let mut attributes = Vec::new();
+    let mut colors = Vec::new();
+    for word in spec.split_whitespace() {
+        let (bright, name) = match word.strip_prefix("bright") {
+            Some(name) => (true, name),
+            None => (false, word),
+        };
+        let basic = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ]
+        .iter()
+        .position(|color| *color == name);
+        // Each color is (code for a foreground, code for a background).
+        let color = match (basic, name) {
+            (Some(index), _) if bright => {
+                Some((format!("{}", 90 + index), format!("{}", 100 + index)))
+            }
+            (Some(index), _) => Some((format!("{}", 30 + index), format!("{}", 40 + index))),
+            (None, "normal") => Some((String::new(), String::new())),
+            (None, "default") => Some(("39".to_string(), "49".to_string())),
+            _ => None,
+        };
+        if let Some(color) = color {
+            colors.push(color);
+            continue;
+        }
+        if let Ok(number) = word.parse::<u8>() {
+            colors.push((format!("38;5;{number}"), format!("48;5;{number}")));
+            continue;
+        }
+        if let Some(hex) = word.strip_prefix('#').filter(|hex| hex.len() == 6) {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            let (r, g, b) = (channel(0)?, channel(2)?, channel(4)?);
+            colors.push((format!("38;2;{r};{g};{b}"), format!("48;2;{r};{g};{b}")));
+            continue;
+        }
+        let attribute = match word {
+            "bold" => 1,
+            "dim" => 2,
+            "italic" => 3,
+            "ul" => 4,
+            "blink" => 5,
+            "reverse" => 7,
+            "strike" => 9,
+            "nobold" | "nodim" | "no-bold" | "no-dim" => 22,
+            "noitalic" | "no-italic" => 23,
+            "noul" | "no-ul" => 24,
+            "noblink" | "no-blink" => 25,
+            "noreverse" | "no-reverse" => 27,
+            "nostrike" | "no-strike" => 29,
+            "reset" => return Some(RESET.to_string()),
+            _ => return None,
+        };
+        attributes.push(attribute);
+    }
+    if colors.len() > 2 {
+        return None;
+    }
+    attributes.sort();
+    attributes.dedup();
+    let mut codes: Vec<String> = attributes.iter().map(|a| a.to_string()).collect();
+    let mut colors = colors.into_iter();
+    codes.extend(colors.next().map(|(foreground, _)| foreground));
+    codes.extend(colors.next().map(|(_, background)| background));
+    codes.retain(|code| !code.is_empty());
+    if codes.is_empty() {
+        return Some(String::new());
+    }
+    Some(format!("\x1b[{}m", codes.join(";")))
+}
+
+// Prints commits in one of the builtin formats or a custom one, keeping track of the separators between them.
+pub struct Pretty<'a> {
+    repo: &'a Repository,
+    options: PrettyOptions,
+    decorations: HashMap<Digest, Vec<Decoration>>,
+    head_branch: Option<String>,
+    now: i64,
+    shown: usize,
+    // Whether the last commit's text didn't end with a newline, in which case there's no graph to draw before the separator.
+    missing_newline: bool,
+}
+
+impl<'a> Pretty<'a> {
+    pub fn new(repo: &'a Repository, options: PrettyOptions) -> Self {
+        Pretty {
+            repo,
+            decorations: load_decorations(repo, options.decorate == Decorate::Full),
+            head_branch: repo.refs.current_branch(),
+            options,
+            now: date::now(),
+            shown: 0,
+            missing_newline: false,
+        }
+    }
+
+    // Writes a commit, drawing the graph next to it if there is one. The graph must already have been updated with the commit.
+    pub fn write(
+        &mut self,
+        out: &mut impl Write,
+        commit: &Commit,
+        mut graph: Option<&mut Graph>,
+    ) -> io::Result<()> {
+        // Oneline and "tformat:" end every commit with a newline, the others only put one between commits.
+        let use_terminator = matches!(
+            self.options.format,
+            PrettyFormat::Oneline
+                | PrettyFormat::Custom {
+                    terminator: true,
+                    ..
+                }
+        );
+        if self.shown > 0 && !use_terminator {
+            if let Some(graph) = graph.as_deref_mut().filter(|_| !self.missing_newline) {
+                graph.show_padding(out)?;
+            }
+            writeln!(out)?;
+        }
+        self.shown += 1;
+        if let Some(graph) = graph.as_deref_mut() {
+            graph.show_commit(out)?;
+        }
+
+        let format = self.options.format.clone();
+        if !matches!(format, PrettyFormat::Custom { .. }) {
+            let prefix = if format == PrettyFormat::Oneline {
+                ""
+            } else {
+                "commit "
+            };
+            let header = format!("{prefix}{}", self.commit_id(commit));
+            write!(out, "{}", self.paint(&header, COMMIT_COLOR))?;
+            if self.options.decorate != Decorate::No {
+                write!(
+                    out,
+                    "{}",
+                    self.decorate(commit, " (", ")", self.options.color)
+                )?;
+            }
+            if format == PrettyFormat::Oneline {
+                write!(out, " ")?;
+            } else {
+                writeln!(out)?;
+                if let Some(graph) = graph.as_deref_mut() {
+                    graph.show_oneline(out)?;
+                }
+            }
+        }
+
+        let text = match &format {
+            PrettyFormat::Custom { format, .. } => self.expand(format, commit),
+            PrettyFormat::Oneline => subject(commit.get_message()),
+            format => self.builtin(format, commit).trim_end().to_string() + "\n",
+        };
+        self.missing_newline = !text.ends_with('\n');
+        match graph.as_deref_mut() {
+            Some(graph) => graph.show_commit_message(out, &text)?,
+            None => write!(out, "{text}")?,
+        }
+        if use_terminator && !self.is_empty() {
+            if let Some(graph) = graph.filter(|_| !self.missing_newline) {
+                graph.show_padding(out)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    // Writes the header and message of an annotated tag, which `show` prints before the object it tags.
+    pub fn write_tag(&mut self, out: &mut impl Write, tag: &Tag) -> io::Result<()> {
+        if self.shown_any() {
+            writeln!(out)?;
+        }
+        self.mark_shown();
+        let header = format!("tag {}", tag.get_name());
+        writeln!(out, "{}", self.paint(&header, COMMIT_COLOR))?;
+        if let Some(tagger) = tag.get_tagger() {
+            let ident = format!("{} <{}>", tagger.name, tagger.email);
+            match self.options.format {
+                PrettyFormat::Oneline => {}
+                PrettyFormat::Medium => writeln!(
+                    out,
+                    "Tagger: {ident}
+Date:   {}",
+                    self.format_date(tagger)
+                )?,
+                PrettyFormat::Fuller => writeln!(
+                    out,
+                    "Tagger:     {ident}
+TaggerDate: {}",
+                    self.format_date(tagger)
+                )?,
+                _ => writeln!(out, "Tagger: {ident}")?,
+            }
+        }
+        if !tag.get_message().is_empty() {
+            write!(out, "\n{}", tag.get_message())?;
+        }
+        Ok(())
+    }
+
+    // Whether anything was written yet, so that the next thing needs a separator before it.
+    pub fn shown_any(&self) -> bool {
+        self.shown > 0
+    }
+
+    // Counts something other than a commit (like a tree listing) as shown.
+    pub fn mark_shown(&mut self) {
+        self.shown += 1;
+    }
+
+    // Whether the format leaves out everything but the header line, in which case no blank line goes before a diff.
+    pub fn is_oneline(&self) -> bool {
+        self.options.format == PrettyFormat::Oneline
+    }
+
+    // Whether the format prints nothing at all for a commit (e.g. "--format=").
+    pub fn is_empty(&self) -> bool {
+        matches!(&self.options.format, PrettyFormat::Custom { format, .. } if format.is_empty())
+    }
+
+    fn paint(&self, text: &str, color: &str) -> String {
+        if self.options.color {
+            format!("{color}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+
+    // The names of the refs pointing at a commit between a prefix and suffix, or nothing if there aren't any. Like git, the
+    // prefix and suffix are painted even when they're empty.
+    fn decorate(&self, commit: &Commit, prefix: &str, suffix: &str, color: bool) -> String {
+        let Some(decorations) = self.decorations.get(commit.get_oid()) else {
+            return String::new();
+        };
+        let names = format_decorations(decorations, self.head_branch.as_deref(), color);
+        let paint = |text: &str| {
+            if color {
+                format!("{COMMIT_COLOR}{text}{RESET}")
+            } else {
+                text.to_string()
+            }
+        };
+        format!("{}{names}{}", paint(prefix), paint(suffix))
+    }
+
+    fn commit_id(&self, commit: &Commit) -> String {
+        if self.options.abbrev_commit {
+            self.repo.database.abbreviate(commit.get_oid())
+        } else {
+            commit.get_oid().to_string()
+        }
+    }
+
+    fn format_date(&self, person: &Author) -> String {
+        date::format(
+            person.timestamp,
+            &person.timezone,
+            &self.options.date_mode,
+            self.now,
+        )
+    }
+
+    // The multi-line formats after their "commit" line: a header (which depends on the format), a blank line, then the message
+    // indented by four spaces.
+    fn builtin(&self, format: &PrettyFormat, commit: &Commit) -> String {
+        let mut text = String::new();
+        let parents = commit.get_parents();
+        if parents.len() > 1 && *format != PrettyFormat::Raw {
+            let parents: Vec<String> = parents
+                .iter()
+                .map(|oid| self.repo.database.abbreviate(oid))
+                .collect();
+            text.push_str(&format!("Merge: {}\n", parents.join(" ")));
+        }
+        let (author, committer) = (commit.get_author(), commit.get_committer());
+        let ident = |person: &Author| format!("{} <{}>", person.name, person.email);
+        let header = match format {
+            PrettyFormat::Short => format!("Author: {}\n", ident(author)),
+            PrettyFormat::Medium => format!(
+                "Author: {}\nDate:   {}\n",
+                ident(author),
+                self.format_date(author)
+            ),
+            PrettyFormat::Full => {
+                format!("Author: {}\nCommit: {}\n", ident(author), ident(committer))
+            }
+            PrettyFormat::Fuller => format!(
+                "Author:     {}\nAuthorDate: {}\nCommit:     {}\nCommitDate: {}\n",
+                ident(author),
+                self.format_date(author),
+                ident(committer),
+                self.format_date(committer)
+            ),
+            _ => commit.get_headers(),
+        };
+        text.push_str(&header);
+        text.push('\n');
+
+        // Leading blank lines are skipped, and the short format only shows the first paragraph. Like git, the medium, full and
+        // fuller formats expand tabs, so that the indent doesn't throw off how the message lines up.
+        let expand = matches!(
+            format,
+            PrettyFormat::Medium | PrettyFormat::Full | PrettyFormat::Fuller
+        );
+        let lines = commit
+            .get_message()
+            .lines()
+            .skip_while(|line| line.trim().is_empty());
+        for line in lines {
+            if *format == PrettyFormat::Short && line.trim().is_empty() {
+                break;
+            }
+            let line = if expand {
+                expand_tabs(line)
+            } else {
+                line.to_string()
+            };
+            text.push_str(&format!("    {line}\n"));
+        }
+        text
+    }
+
+    // Expands the placeholders in a custom format string for one commit.
+    fn expand(&self, format: &str, commit: &Commit) -> String {
+        let mut out = String::new();
+        let mut padding: Option<Padding> = None;
+        let mut auto_color = false;
+        let mut rest = format;
+        while !rest.is_empty() {
+            let Some(after_percent) = rest.strip_prefix('%') else {
+                let c = rest.chars().next().expect("rest is not empty");
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            };
+
+            if let Some((directive, length)) = Padding::parse(after_percent) {
+                padding = Some(directive);
+                rest = &after_percent[length..];
+                continue;
+            }
+            if let Some((color, length)) =
+                self.color_placeholder(after_percent, &out, &mut auto_color)
+            {
+                out.push_str(&color);
+                rest = &after_percent[length..];
+                continue;
+            }
+
+            // "%+x" adds a newline before x if it's not empty, "% x" a space, and "%-x" removes the newlines before it if it is.
+            let magic = after_percent
+                .chars()
+                .next()
+                .filter(|c| ['+', '-', ' '].contains(c));
+            let spec = if magic.is_some() {
+                &after_percent[1..]
+            } else {
+                after_percent
+            };
+            let Some((mut text, length)) = self.placeholder(spec, commit, auto_color) else {
+                // Unknown placeholders are left as they are.
+                out.push('%');
+                rest = after_percent;
+                continue;
+            };
+            rest = &spec[length..];
+
+            if let Some(directive) = padding.take() {
+                let line_start = out.rfind('\n').map_or(0, |i| i + 1);
+                let column = out[line_start..].chars().count();
+                text = directive.apply(&text, column);
+            }
+            match magic {
+                Some('+') if !text.is_empty() => out.push('\n'),
+                Some(' ') if !text.is_empty() => out.push(' '),
+                Some('-') if text.is_empty() => out.truncate(out.trim_end_matches('\n').len()),
+                _ => {}
+            }
+            out.push_str(&text);
+        }
+        out
+    }
+
+    // Handles "%Cred", "%Cgreen", "%Cblue", "%Creset" and "%C(...)". Colors are only shown when color is on, unless the spec
+    // starts with "always,". "%C(auto)" instead colors the placeholders after it that have a color of their own.
+    fn color_placeholder(
+        &self,
+        spec: &str,
+        out: &str,
+        auto_color: &mut bool,
+    ) -> Option<(String, usize)> {
+        let spec = spec.strip_prefix('C')?;
+        if let Some(inner) = spec.strip_prefix('(') {
+            let (inner, _) = inner.split_once(')')?;
+            let length = inner.len() + 3;
+            if inner == "auto" {
+                *auto_color = self.options.color;
+                let reset = if *auto_color && !out.is_empty() {
+                    RESET
+                } else {
+                    ""
+                };
+                return Some((reset.to_string(), length));
+            }
+            let (always, inner) = match inner.strip_prefix("always,") {
+                Some(inner) => (true, inner),
+                None => (false, inner.strip_prefix("auto,").unwrap_or(inner)),
+            };
+            let color =
+                parse_color(inner).unwrap_or_else(|| fatal("unable to parse --pretty format"));
+            let show = always || self.options.color;
+            return Some((if show { color } else { String::new() }, length));
+        }
+        let (name, color) = [
+            ("red", "\x1b[31m"),
+            ("green", "\x1b[32m"),
+            ("blue", "\x1b[34m"),
+            ("reset", RESET),
+        ]
+        .into_iter()
+        .find(|(name, _)| spec.starts_with(name))?;
+        let color = if self.options.color { color } else { "" };
+        Some((color.to_string(), name.len() + 1))
+    }
+
+    // Expands a single placeholder at the start of `spec` (the text after '%'), returning its text and how many bytes it took up.
+    fn placeholder(
+        &self,
+        spec: &str,
+        commit: &Commit,
+        auto_color: bool,
+    ) -> Option<(String, usize)> {
+        let auto_paint = |text: String| {
+            if auto_color {
+                format!("{COMMIT_COLOR}{text}{RESET}")
+            } else {
+                text
+            }
+        };
+        let database = &self.repo.database;
+        let mut chars = spec.chars();
+        let text = match chars.next()? {
+            'n' => "\n".to_string(),
+            '%' => "%".to_string(),
+            'x' => {
+                let byte = u8::from_str_radix(spec.get(1..3)?, 16).ok()?;
+                return Some(((byte as char).to_string(), 3));
+            }
+            'H' => auto_paint(commit.get_oid().to_string()),
+            'h' => auto_paint(database.abbreviate(commit.get_oid())),
+            'T' => commit.get_tree().to_string(),
+            't' => database.abbreviate(commit.get_tree()),
+            'P' => commit
+                .get_parents()
+                .iter()
+                .map(|oid| oid.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            'p' => commit
+                .get_parents()
+                .iter()
+                .map(|oid| database.abbreviate(oid))
+                .collect::<Vec<_>>()
+                .join(" "),
+            's' => subject(commit.get_message()),
+            'b' => body(commit.get_message()),
+            'B' => commit.get_message().to_string(),
+            'd' => self.decorate(commit, " (", ")", auto_color),
+            'D' => self.decorate(commit, "", "", auto_color),
+            c @ ('a' | 'c') => {
+                let person = if c == 'a' {
+                    commit.get_author()
+                } else {
+                    commit.get_committer()
+                };
+                let (timestamp, timezone) = (person.timestamp, person.timezone.as_str());
+                let text = match chars.next()? {
+                    'n' => person.name.clone(),
+                    'e' => person.email.clone(),
+                    'd' => self.format_date(person),
+                    'r' => date::format_relative(timestamp, self.now),
+                    't' => timestamp.to_string(),
+                    'i' => date::format(timestamp, timezone, &DateMode::Iso, self.now),
+                    'I' => date::format(timestamp, timezone, &DateMode::IsoStrict, self.now),
+                    's' => date::format(timestamp, timezone, &DateMode::Short, self.now),
+                    'D' => date::format(timestamp, timezone, &DateMode::Rfc, self.now),
+                    _ => return None,
+                };
+                return Some((text, 2));
+            }
+            _ => return None,
+        };
+        Some((text, 1))
+    }
+}
+
+// Replaces tabs with spaces up to the next multiple of 8 columns.
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = 8 - column % 8;
+            expanded.push_str(&" ".repeat(spaces));
+            column += spaces;
+        } else {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subject_and_body() {
+        let message = "\nFirst line\nof subject\n\n\nBody one\n\nBody two\n";
+        assert_eq!(subject(message), "First line of subject");
+        assert_eq!(body(message), "Body one\n\nBody two\n");
+        assert_eq!(body("Only a subject\n"), "");
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        let message = "Subject\n\n\tindented\tbody\nab\tc\n";
+        let lines: Vec<String> = body(message).lines().map(expand_tabs).collect();
+        assert_eq!(lines, ["        indented        body", "ab      c"]);
+        assert_eq!(expand_tabs("no tabs"), "no tabs");
+    }
+
+    #[test]
+    fn test_padding() {
+        let parse = |spec: &str| Padding::parse(spec).unwrap().0;
+        assert_eq!(parse("<(8,trunc)").apply("touch renamed", 0), "touch ..");
+        assert_eq!(parse("<(8,ltrunc)").apply("touch renamed", 0), "..enamed");
+        assert_eq!(parse("<(9,mtrunc)").apply("touch renamed", 0), "tou..amed");
+        assert_eq!(parse(">(4)").apply("A", 0), "   A");
+        assert_eq!(parse("><(6)").apply("ab", 0), "  ab  ");
+        assert_eq!(parse("<|(5)").apply("ab", 2), "ab ");
+        assert_eq!(Padding::parse("<(8,trunc)%s").unwrap().1, 10);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("red").unwrap(), "\x1b[31m");
+        assert_eq!(parse_color("bold blue").unwrap(), "\x1b[1;34m");
+        assert_eq!(
+            parse_color("yellow brightblack ul").unwrap(),
+            "\x1b[4;33;100m"
+        );
+        assert_eq!(parse_color("#ff0000").unwrap(), "\x1b[38;2;255;0;0m");
+        assert_eq!(parse_color("reset").unwrap(), RESET);
+        assert!(parse_color("blurple").is_none());
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(PrettyFormat::parse("fuller"), Some(PrettyFormat::Fuller));
+        assert_eq!(
+            PrettyFormat::parse("%h %s"),
+            Some(PrettyFormat::Custom {
+                format: "%h %s".to_string(),
+                terminator: true
+            })
+        );
+        assert_eq!(
+            PrettyFormat::parse("format:%h"),
+            Some(PrettyFormat::Custom {
+                format: "%h".to_string(),
+                terminator: false
+            })
+        );
+        assert_eq!(PrettyFormat::parse("bogus"), None);
+    }
+}
@@ -0,0 +1,1289 @@
+use crate::commands::{commit, reset};
+use crate::commit::{Author, Commit};
+use crate::diff::{self, EditKind};
+use crate::diff3::Labels;
+use crate::fatal::fatal;
+use crate::merge::MergeOutcome;
+use crate::migration::Migration;
+use crate::object::Object;
+use crate::pretty;
+use crate::repository::Repository;
+use crate::rev_list::{RevList, RevListOptions};
+use crate::revision;
+use crate::sequencer;
+use sha1_smol::{Digest, Sha1};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+
+// What's shown below the todo list while it's being edited.
+const TODO_HELP: &str = "#
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# e, edit <commit> = use commit, but stop for amending
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\" but keep only the previous
+#                    commit's log message
+# x, exec <command> = run command (the rest of the line) using shell
+# b, break = stop here (continue rebase later with 'git rebase --continue')
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+#
+# If you remove a line here THAT COMMIT WILL BE LOST.
+#
+";
+
+// A command in the todo list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Pick,
+    // Pick, but let the user edit the message.
+    Reword,
+    // Pick, then stop so that the commit can be amended.
+    Edit,
+    // Meld into the previous commit, combining their messages.
+    Squash,
+    // Meld into the previous commit, keeping just its message.
+    Fixup,
+    // Run a shell command, stopping if it fails.
+    Exec,
+    // Stop, to be continued later.
+    Break,
+    // Leave the commit out.
+    Drop,
+}
+
+impl Command {
+    // Reads a command by its name or the one-letter abbreviation git accepts for it.
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "pick" | "p" => Some(Command::Pick),
+            "reword" | "r" => Some(Command::Reword),
+            "edit" | "e" => Some(Command::Edit),
+            "squash" | "s" => Some(Command::Squash),
+            "fixup" | "f" => Some(Command::Fixup),
+            "exec" | "x" => Some(Command::Exec),
+            "break" | "b" => Some(Command::Break),
+            "drop" | "d" => Some(Command::Drop),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Pick => "pick",
+            Command::Reword => "reword",
+            Command::Edit => "edit",
+            Command::Squash => "squash",
+            Command::Fixup => "fixup",
+            Command::Exec => "exec",
+            Command::Break => "break",
+            Command::Drop => "drop",
+        }
+    }
+
+    fn is_fixup(&self) -> bool {
+        matches!(self, Command::Squash | Command::Fixup)
+    }
+}
+
+// One line of the todo list: a command and the commit it works on, or for exec the shell command to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub command: Command,
+    pub oid: Option<Digest>,
+    pub exec: String,
+}
+
+impl Step {
+    pub fn pick(oid: Digest) -> Self {
+        Step {
+            command: Command::Pick,
+            oid: Some(oid),
+            exec: String::new(),
+        }
+    }
+}
+
+// What a new rebase is to do, worked out from its arguments.
+pub struct Plan {
+    // The branch being rebased (e.g. "refs/heads/topic"), or None if HEAD is detached.
+    pub head_name: Option<String>,
+    pub orig_head: Digest,
+    pub upstream: Digest,
+    pub onto: Digest,
+    // How the new base was given, e.g. "main", for the reflog.
+    pub onto_name: String,
+    pub todo: Vec<Step>,
+    // Whether the todo list is given to the user to edit first.
+    pub interactive: bool,
+}
+
+// Replays commits on top of another one, one todo list command at a time. Everything about the rebase in progress is kept in
+// .git/rebase-merge, so that after stopping (on a conflict, an edit or a break) it can be continued, skipped ahead or
+// aborted:
+//
+//   head-name       the branch being rebased, or "detached HEAD"
+//   onto            the commit the rebased commits go on top of
+//   orig-head       where HEAD was before the rebase started (where --abort goes back to)
+//   interactive     there if the todo list was edited by the user
+//   git-rebase-todo the commands still to do, the next one first
+//   done            the commands done so far, the last one being the one that stopped the rebase
+//   msgnum, end     how many commands have been started, and how many there are in all
+//   message         the message of the commit that stopped the rebase
+//   author-script   its author, as shell variable assignments
+//   stopped-sha     its oid
+//   amend           where HEAD was when an edit stopped, so that staged changes amend it
+//   current-fixups  the squash and fixup commands melded into the current commit so far
+//   message-squash  the combined messages of the commits melded together
+pub struct Rebase<'a> {
+    repo: &'a mut Repository,
+}
+
+impl<'a> Rebase<'a> {
+    pub fn new(repo: &'a mut Repository) -> Self {
+        Rebase { repo }
+    }
+
+    pub fn in_progress(repo: &Repository) -> bool {
+        repo.git_path.join("rebase-merge").exists()
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.repo.git_path.join("rebase-merge").join(name)
+    }
+
+    pub fn start(&mut self, plan: Plan) -> io::Result<()> {
+        fs::create_dir_all(self.path(""))?;
+        let head_name = plan.head_name.as_deref().unwrap_or("detached HEAD");
+        fs::write(self.path("head-name"), format!("{head_name}\n"))?;
+        fs::write(self.path("onto"), format!("{}\n", plan.onto))?;
+        fs::write(self.path("orig-head"), format!("{}\n", plan.orig_head))?;
+        if !plan.interactive {
+            self.save_todo(&plan.todo)?;
+            let onto = self.skip_unnecessary_picks()?;
+            self.check_out_onto(onto, &plan.onto_name)?;
+            return self.run();
+        }
+
+        fs::write(self.path("interactive"), "")?;
+        let repo = &*self.repo;
+        let header = format!(
+            "\n# Rebase {}..{} onto {} ({} command{})\n",
+            repo.database.abbreviate(&plan.upstream),
+            repo.database.abbreviate(&plan.orig_head),
+            repo.database.abbreviate(&plan.onto),
+            plan.todo.len(),
+            if plan.todo.len() == 1 { "" } else { "s" }
+        );
+        let help = format!(
+            "{header}{TODO_HELP}# However, if you remove everything, the rebase will be aborted.\n#\n"
+        );
+        // Like git, a todo list that needs fixing still starts the rebase, so that it can be continued once it's fixed.
+        let Some(todo) = self.edit_todo_list(&plan.todo, &help)? else {
+            self.check_out_onto(plan.onto, &plan.onto_name)?;
+            process::exit(1);
+        };
+        if todo.is_empty() {
+            eprintln!("error: nothing to do");
+            self.remove_state()?;
+            process::exit(1);
+        }
+        self.save_todo(&todo)?;
+        let onto = self.skip_unnecessary_picks()?;
+        self.check_out_onto(onto, &plan.onto_name)?;
+        self.run()
+    }
+
+    // Lets the user edit the rest of the todo list of a rebase that has stopped.
+    pub fn edit_todo(&mut self) -> io::Result<()> {
+        self.check_in_progress();
+        let help = format!(
+            "{TODO_HELP}# You are editing the todo file of an ongoing interactive rebase.
+# To continue rebase after editing, run:
+#     git rebase --continue
+#
+"
+        );
+        let Some(todo) = self.edit_todo_list(&self.load_todo(), &help)? else {
+            process::exit(1);
+        };
+        self.save_todo(&todo)?;
+        let started = self.read_number("msgnum");
+        fs::write(self.path("end"), format!("{}\n", started + todo.len()))
+    }
+
+    // Commits the changes made while the rebase was stopped (resolving a conflict or amending an edited commit), if they
+    // weren't committed already, and goes on with the rest of the todo list.
+    pub fn resume(&mut self) -> io::Result<()> {
+        self.check_in_progress();
+        if self.repo.index.has_conflicts() {
+            for path in self.repo.index.conflicted_paths() {
+                println!("{}: needs merge", path.display());
+            }
+        }
+        if self.repo.index.has_conflicts() || !self.repo.index_to_workspace().is_empty() {
+            println!("You must edit all merge conflicts and then");
+            println!("mark them as resolved using git add");
+            process::exit(1);
+        }
+        // A todo list that had to be fixed before the rebase could go on hasn't been counted yet.
+        if !self.path("end").exists() {
+            let total = self.load_done().len() + self.load_todo().len();
+            fs::write(self.path("end"), format!("{total}\n"))?;
+        }
+        let staged = !self.repo.head_to_index().is_empty();
+        if staged {
+            self.commit_staged()?;
+        }
+        self.clear_stop(staged)?;
+        self.run()
+    }
+
+    // Throws away the changes of the command that stopped the rebase, and goes on with the rest of the todo list.
+    pub fn skip(&mut self) -> io::Result<()> {
+        self.check_in_progress();
+        let head = self.repo.refs.read_head().expect("Rebasing without a HEAD");
+        reset::discard_changes(self.repo, head)?;
+        self.clear_stop(false)?;
+        self.run()
+    }
+
+    // Puts everything back the way it was before the rebase started.
+    pub fn abort(&mut self) -> io::Result<()> {
+        self.check_in_progress();
+        let orig_head = self.read_oid("orig-head");
+        reset::discard_changes(self.repo, orig_head)?;
+        let head_name = self.head_name();
+        let refs = &mut self.repo.refs;
+        let returning_to = match &head_name {
+            Some(name) => {
+                refs.set_head_branch(name.strip_prefix("refs/heads/").expect("Not a branch"));
+                name.clone()
+            }
+            None => orig_head.to_string(),
+        };
+        refs.update_head_logged(
+            &orig_head,
+            &format!("rebase (abort): returning to {returning_to}"),
+        );
+        let _ = fs::remove_file(self.repo.git_path.join("REBASE_HEAD"));
+        self.remove_state()
+    }
+
+    // Forgets about the rebase, leaving HEAD, the index and the workspace as they are.
+    pub fn quit(&mut self) -> io::Result<()> {
+        self.check_in_progress();
+        self.remove_state()
+    }
+
+    // Skips the picks at the start of the todo list that would just make the same commits again, by moving onto forward to the
+    // last of them instead (and any drops in between). Returns where the rebase starts from then.
+    fn skip_unnecessary_picks(&mut self) -> io::Result<Digest> {
+        let mut onto = self.read_oid("onto");
+        let mut todo = self.load_todo();
+        let mut done = Vec::new();
+        while let Some(step) = todo.first() {
+            match (step.command, step.oid) {
+                // Like git, dropped commits don't get in the way.
+                (Command::Drop, _) => {}
+                (Command::Pick, Some(oid))
+                    if self.repo.database.load_commit(&oid).get_parents() == [onto] =>
+                {
+                    onto = oid
+                }
+                _ => break,
+            }
+            done.push(todo.remove(0));
+        }
+        self.save_todo(&todo)?;
+        fs::write(self.path("done"), self.format_todo(&done, false))?;
+        fs::write(self.path("msgnum"), format!("{}\n", done.len()))?;
+        fs::write(self.path("end"), format!("{}\n", done.len() + todo.len()))?;
+        Ok(onto)
+    }
+
+    // Detaches HEAD at the commit the rebased commits go on top of, remembering where it was in ORIG_HEAD.
+    fn check_out_onto(&mut self, onto: Digest, onto_name: &str) -> io::Result<()> {
+        let orig_head = self.read_oid("orig-head");
+        self.repo.refs.set_orig_head(&orig_head);
+        self.check_out(onto, &format!("rebase (start): checkout {onto_name}"))
+    }
+
+    // Carries out the todo list, one command after another, until it's done or something stops it.
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let mut todo = self.load_todo();
+            if todo.is_empty() {
+                return self.finish();
+            }
+            // Each command is moved to the done list before it starts, so that if it stops the rebase, the rest can be
+            // continued.
+            let step = todo.remove(0);
+            self.save_todo(&todo)?;
+            let mut done = fs::read_to_string(self.path("done")).unwrap_or_default();
+            done.push_str(&self.format_todo(std::slice::from_ref(&step), false));
+            fs::write(self.path("done"), done)?;
+            let number = self.read_number("msgnum") + 1;
+            fs::write(self.path("msgnum"), format!("{number}\n"))?;
+            eprint!("Rebasing ({number}/{})\r", self.read_number("end"));
+
+            let next_is_fixup = todo.first().is_some_and(|next| next.command.is_fixup());
+            match (step.command, step.oid) {
+                (Command::Exec, _) => self.exec(&step.exec),
+                (Command::Break, _) => {
+                    let head = self.repo.refs.read_head().expect("Rebasing without a HEAD");
+                    let subject =
+                        pretty::subject(self.repo.database.load_commit(&head).get_message());
+                    eprintln!(
+                        "{}Stopped at {} ({subject})",
+                        clear_line(),
+                        self.repo.database.abbreviate(&head)
+                    );
+                    process::exit(0);
+                }
+                (Command::Drop, _) => {}
+                (Command::Squash | Command::Fixup, Some(oid)) => {
+                    self.meld(step.command, oid, next_is_fixup)?
+                }
+                (command, Some(oid)) => self.pick(command, oid)?,
+                (_, None) => panic!("Todo command has no commit"),
+            }
+        }
+    }
+
+    // Applies a commit's change on top of HEAD, keeping its author and (unless it's reworded) its message. A commit that's
+    // already on top of HEAD is just moved to, and one whose change is already there is dropped.
+    fn pick(&mut self, command: Command, oid: Digest) -> io::Result<()> {
+        let commit = self.repo.database.load_commit(&oid);
+        let head = self.repo.refs.read_head().expect("Rebasing without a HEAD");
+        let subject = pretty::subject(commit.get_message());
+        let fast_forward = commit.get_parents() == [head];
+        let tree = if fast_forward {
+            self.check_out(oid, "rebase: fast-forward")?;
+            *commit.get_tree()
+        } else {
+            let head_tree = *self.repo.database.load_commit(&head).get_tree();
+            let tree = self.apply(command, &commit)?;
+            if tree == head_tree {
+                return Ok(());
+            }
+            tree
+        };
+        // A commit that was fast-forwarded to is used as it is, unless its message changes.
+        if !fast_forward || command == Command::Reword {
+            let message = match command {
+                // After a fast-forward, the commit being reworded is HEAD, so it's shown as amended.
+                Command::Reword => commit::edit(
+                    self.repo,
+                    commit.get_message(),
+                    Some(commit.get_author()),
+                    fast_forward.then_some(&commit),
+                )?,
+                _ => commit.get_message().to_string(),
+            };
+            let new_commit = Commit::new(
+                tree,
+                vec![head],
+                commit.get_author().clone(),
+                Author::now(),
+                message,
+            );
+            self.repo.database.store(&new_commit);
+            let new_subject = first_line(new_commit.get_message());
+            self.repo.refs.update_head_logged(
+                new_commit.get_oid(),
+                &format!("rebase ({}): {new_subject}", command.name()),
+            );
+            if command == Command::Reword {
+                commit::print_summary(self.repo, &new_commit, true);
+            }
+        }
+
+        if command == Command::Edit {
+            self.save_stop(&commit)?;
+            let head = self.repo.refs.read_head().expect("Rebasing without a HEAD");
+            fs::write(self.path("amend"), format!("{head}\n"))?;
+            eprintln!(
+                "{}Stopped at {}...  {subject}",
+                clear_line(),
+                self.repo.database.abbreviate(&oid)
+            );
+            eprintln!("You can amend the commit now, with");
+            eprintln!();
+            eprintln!("  git commit --amend ");
+            eprintln!();
+            eprintln!("Once you are satisfied with your changes, run");
+            eprintln!();
+            eprintln!("  git rebase --continue");
+            process::exit(0);
+        }
+        Ok(())
+    }
+
+    // Melds a commit's change into HEAD, which is amended to include it. Its message is added to the ones of the commits melded
+    // so far, which (if any of them was squashed) the user gets to edit once the last one is melded in.
+    fn meld(&mut self, command: Command, oid: Digest, next_is_fixup: bool) -> io::Result<()> {
+        let commit = self.repo.database.load_commit(&oid);
+        self.add_to_chain(command, &commit)?;
+        let tree = self.apply(command, &commit)?;
+        self.amend_chain(command, tree, next_is_fixup)
+    }
+
+    // Records one more squash or fixup of HEAD, adding its message to the combined one like git does:
+    //
+    //   # This is a combination of 2 commits.
+    //   # This is the 1st commit message:
+    //
+    //   <HEAD's message>
+    //
+    //   # This is the commit message #2:
+    //
+    //   <the squashed commit's message>
+    //
+    // The message of a fixup is there too, but commented out, and so are "squash! " and "fixup! " subject lines.
+    fn add_to_chain(&mut self, command: Command, commit: &Commit) -> io::Result<()> {
+        let mut fixups = fs::read_to_string(self.path("current-fixups")).unwrap_or_default();
+        let combined = match fs::read_to_string(self.path("message-squash")) {
+            Ok(combined) => combined,
+            Err(_) => {
+                let head = self.repo.refs.read_head().expect("Rebasing without a HEAD");
+                let message = self
+                    .repo
+                    .database
+                    .load_commit(&head)
+                    .get_message()
+                    .to_string();
+                format!(
+                    "# This is a combination of 2 commits.\n# This is the 1st commit message:\n\n{}",
+                    comment_subject(&message)
+                )
+            }
+        };
+        fixups.push_str(&format!("{} {}\n", command.name(), commit.get_oid()));
+        let count = fixups.lines().count() + 1;
+        let rest = combined.split_once('\n').map_or("", |(_, rest)| rest);
+        let message = commit.get_message();
+        let added = match command {
+            Command::Squash => format!(
+                "\n# This is the commit message #{count}:\n\n{}",
+                comment_subject(message)
+            ),
+            _ => format!(
+                "\n# The commit message #{count} will be skipped:\n\n{}",
+                message
+                    .lines()
+                    .map(|line| comment(line) + "\n")
+                    .collect::<String>()
+            ),
+        };
+        fs::write(self.path("current-fixups"), fixups)?;
+        fs::write(
+            self.path("message-squash"),
+            format!("# This is a combination of {count} commits.\n{rest}{added}"),
+        )
+    }
+
+    // Amends HEAD to have the given tree and the combined message of the commits melded into it so far. Once the last one is
+    // in, the user gets to edit the message if any of them was squashed.
+    fn amend_chain(
+        &mut self,
+        command: Command,
+        tree: Digest,
+        next_is_fixup: bool,
+    ) -> io::Result<()> {
+        let head = self.repo.refs.read_head().expect("Rebasing without a HEAD");
+        let head_commit = self.repo.database.load_commit(&head);
+        let combined = fs::read_to_string(self.path("message-squash"))?;
+        let fixups = fs::read_to_string(self.path("current-fixups"))?;
+        let squashed = fixups.lines().any(|line| line.starts_with("squash "));
+        let edit = !next_is_fixup && squashed;
+        // Like git, the commits made along the way have the combined message as it is, comments and all.
+        let message = if edit {
+            commit::edit(
+                self.repo,
+                &combined,
+                Some(head_commit.get_author()),
+                Some(&head_commit),
+            )?
+        } else if next_is_fixup {
+            combined
+        } else {
+            commit::strip_message(&combined)
+        };
+        let new_commit = Commit::new(
+            tree,
+            head_commit.get_parents().to_vec(),
+            head_commit.get_author().clone(),
+            Author::now(),
+            message,
+        );
+        self.repo.database.store(&new_commit);
+        self.repo.refs.update_head_logged(
+            new_commit.get_oid(),
+            &format!(
+                "rebase ({}): {}",
+                command.name(),
+                first_line(new_commit.get_message())
+            ),
+        );
+        if edit {
+            commit::print_summary(self.repo, &new_commit, true);
+        }
+        if !next_is_fixup {
+            let _ = fs::remove_file(self.path("current-fixups"));
+            let _ = fs::remove_file(self.path("message-squash"));
+        }
+        Ok(())
+    }
+
+    // Merges a commit's change into HEAD, returning the tree it makes. On conflicts, the rebase stops for them to be resolved.
+    fn apply(&mut self, command: Command, commit: &Commit) -> io::Result<Digest> {
+        let oid = *commit.get_oid();
+        let short = self.repo.database.abbreviate(&oid);
+        let subject = pretty::subject(commit.get_message());
+        let name = format!("{short} ({subject})");
+        let parent_name = format!("parent of {name}");
+        let labels = Labels {
+            ours: "HEAD",
+            base: &parent_name,
+            theirs: &name,
+        };
+        let parent = commit.get_parents().first().copied();
+        let Some((outcome, tree)) =
+            sequencer::merge_into_head(self.repo, parent, Some(oid), &labels)?
+        else {
+            self.reschedule(command, oid);
+        };
+        // Like git, what happened to each path is only worth mentioning when something went wrong.
+        if !outcome.conflicts.is_empty() {
+            for message in outcome.messages() {
+                println!("{message}");
+            }
+            self.stop_on_conflicts(commit, &outcome)?;
+        }
+        Ok(tree)
+    }
+
+    // Puts a command that couldn't be started (because it would have overwritten local changes) back on the todo list.
+    fn reschedule(&mut self, command: Command, oid: Digest) -> ! {
+        let step = Step {
+            command,
+            oid: Some(oid),
+            exec: String::new(),
+        };
+        let mut todo = self.load_todo();
+        todo.insert(0, step.clone());
+        self.save_todo(&todo)
+            .expect("Could not write the todo list");
+        let number = self.read_number("msgnum");
+        let _ = fs::write(
+            self.path("msgnum"),
+            format!("{}\n", number.saturating_sub(1)),
+        );
+        let line = self.format_todo(&[step], true);
+        eprintln!("hint: Could not execute the todo command");
+        eprintln!("hint: ");
+        eprintln!("hint:     {}", line.trim_end());
+        eprintln!("hint: ");
+        eprintln!("hint: It has been rescheduled; To edit the command before continuing, please");
+        eprintln!("hint: edit the todo list first:");
+        eprintln!("hint: ");
+        eprintln!("hint:     git rebase --edit-todo");
+        eprintln!("hint:     git rebase --continue");
+        process::exit(1);
+    }
+
+    fn stop_on_conflicts(&mut self, commit: &Commit, outcome: &MergeOutcome) -> io::Result<()> {
+        self.save_stop(commit)?;
+        fs::write(
+            self.repo.git_path.join("MERGE_MSG"),
+            format!("{}\n{}", commit.get_message(), outcome.conflicts_comment()),
+        )?;
+        let stopped = format!(
+            "{}... {}",
+            self.repo.database.abbreviate(commit.get_oid()),
+            pretty::subject(commit.get_message())
+        );
+        eprintln!("error: could not apply {stopped}");
+        eprintln!("hint: Resolve all conflicts manually, mark them as resolved with");
+        eprintln!("hint: \"git add/rm <conflicted_files>\", then run \"git rebase --continue\".");
+        eprintln!("hint: You can instead skip this commit: run \"git rebase --skip\".");
+        eprintln!(
+            "hint: To abort and get back to the state before \"git rebase\", run \"git rebase --abort\"."
+        );
+        eprintln!("Could not apply {stopped}");
+        process::exit(1);
+    }
+
+    // Remembers the commit the rebase stopped at, to commit what the user makes of it when the rebase is continued.
+    fn save_stop(&self, commit: &Commit) -> io::Result<()> {
+        let oid = commit.get_oid();
+        // Like git, the message is kept with a blank line after it.
+        fs::write(self.path("message"), format!("{}\n", commit.get_message()))?;
+        fs::write(
+            self.path("author-script"),
+            author_script(commit.get_author()),
+        )?;
+        fs::write(self.path("stopped-sha"), format!("{oid}\n"))?;
+        fs::write(self.repo.git_path.join("REBASE_HEAD"), format!("{oid}\n"))
+    }
+
+    // Forgets about the commit the rebase stopped at, once it's been dealt with. Like git, REBASE_HEAD is kept around after a
+    // conflict (or a change to an edited commit) was committed, but not once an edited commit is left as it was.
+    fn clear_stop(&self, committed: bool) -> io::Result<()> {
+        if self.path("amend").exists() && !committed {
+            let _ = fs::remove_file(self.repo.git_path.join("REBASE_HEAD"));
+        }
+        for name in ["amend", "message", "author-script"] {
+            let _ = fs::remove_file(self.path(name));
+        }
+        self.repo.remove_merge_state();
+        Ok(())
+    }
+
+    // Commits what's staged after the rebase stopped: as an amendment to HEAD if an edit stopped it, as part of the current
+    // squash or fixup if it was one of those, or else as the commit that stopped it, with that commit's author and message.
+    fn commit_staged(&mut self) -> io::Result<()> {
+        let head = self.repo.refs.read_head().expect("Rebasing without a HEAD");
+        let last = self.load_done().pop().map(|step| step.command);
+        if let Some(amend) = self.optional_oid("amend") {
+            if amend != head {
+                eprintln!("error: ");
+                eprintln!("You have uncommitted changes in your working tree. Please, commit them");
+                eprintln!("first and then run 'git rebase --continue' again.");
+                process::exit(1);
+            }
+            let head_commit = self.repo.database.load_commit(&head);
+            let tree = *commit::write_tree(self.repo).get_oid();
+            let new_commit = Commit::new(
+                tree,
+                head_commit.get_parents().to_vec(),
+                head_commit.get_author().clone(),
+                Author::now(),
+                head_commit.get_message().to_string(),
+            );
+            return self.commit_continued(new_commit, true);
+        }
+        if let Some(command) = last.filter(Command::is_fixup) {
+            let tree = *commit::write_tree(self.repo).get_oid();
+            let next_is_fixup = self
+                .load_todo()
+                .first()
+                .is_some_and(|next| next.command.is_fixup());
+            return self.amend_chain(command, tree, next_is_fixup);
+        }
+
+        // Only a commit that failed to apply leaves an author behind; anything staged after an exec or a break has no commit to go
+        // into.
+        let Ok(script) = fs::read_to_string(self.path("author-script")) else {
+            eprintln!("error: could not open '.git/rebase-merge/author-script' for reading: No such file or directory");
+            eprintln!(
+                "error: you have staged changes in your working tree
+If these changes are meant to be squashed into the previous commit, run:
+
+  git commit --amend 
+
+If they are meant to go into a new commit, run:
+
+  git commit 
+
+In both cases, once you're done, continue with:
+
+  git rebase --continue
+"
+            );
+            eprintln!("error: could not commit staged changes.");
+            process::exit(1);
+        };
+        let author = parse_author_script(&script);
+        let message = fs::read_to_string(self.path("message"))?;
+        let mut message = format!("{}\n", message.trim_end_matches('\n'));
+        let reword = last == Some(Command::Reword);
+        if reword {
+            message = commit::edit(self.repo, &message, Some(&author), None)?;
+        }
+        let tree = *commit::write_tree(self.repo).get_oid();
+        let new_commit = Commit::new(tree, vec![head], author, Author::now(), message);
+        self.commit_continued(new_commit, reword)
+    }
+
+    fn commit_continued(&mut self, new_commit: Commit, show_date: bool) -> io::Result<()> {
+        self.repo.database.store(&new_commit);
+        self.repo.index.write_updates();
+        self.repo.refs.update_head_logged(
+            new_commit.get_oid(),
+            &format!(
+                "rebase (continue): {}",
+                first_line(new_commit.get_message())
+            ),
+        );
+        commit::print_summary(self.repo, &new_commit, show_date);
+        Ok(())
+    }
+
+    // Runs a shell command from the todo list in the top directory of the workspace, stopping the rebase if it fails.
+    fn exec(&self, command: &str) {
+        eprintln!("{}Executing: {command}", clear_line());
+        let status = process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(self.repo.workspace.root())
+            .status();
+        if !status.is_ok_and(|status| status.success()) {
+            eprintln!("warning: execution failed: {command}");
+            eprintln!("You can fix the problem, and then run");
+            eprintln!();
+            eprintln!("  git rebase --continue");
+            eprintln!();
+            eprintln!();
+            process::exit(1);
+        }
+    }
+
+    // Puts the branch (if there is one) where the rebase ended up, and HEAD back on it.
+    fn finish(&mut self) -> io::Result<()> {
+        let head = self.repo.refs.read_head().expect("Rebasing without a HEAD");
+        let head_name = self.head_name();
+        if let Some(name) = &head_name {
+            let onto = self.read_oid("onto");
+            let branch = name.strip_prefix("refs/heads/").expect("Not a branch");
+            let refs = &mut self.repo.refs;
+            refs.update_ref_logged(name, &head, &format!("rebase (finish): {name} onto {onto}"));
+            refs.set_head_branch(branch);
+            refs.update_head_logged(&head, &format!("rebase (finish): returning to {name}"));
+        }
+        self.remove_state()?;
+        eprintln!(
+            "{}Successfully rebased and updated {}.",
+            clear_line(),
+            head_name.as_deref().unwrap_or("detached HEAD")
+        );
+        Ok(())
+    }
+
+    // Moves a detached HEAD to a commit, bringing the index and workspace along.
+    fn check_out(&mut self, oid: Digest, message: &str) -> io::Result<()> {
+        let repo = &mut *self.repo;
+        let head = repo.refs.read_head().expect("Rebasing without a HEAD");
+        let tree = |oid: &Digest| *repo.database.load_commit(oid).get_tree();
+        let diff = repo
+            .database
+            .tree_diff(Some(&tree(&head)), Some(&tree(&oid)));
+        let migration = Migration::new(repo, diff);
+        let errors = migration.errors("checkout");
+        if !errors.is_empty() {
+            for error in errors {
+                eprint!("error: {error}");
+            }
+            eprintln!("Aborting");
+            process::exit(1);
+        }
+        migration.apply(repo)?;
+        repo.refs.detach_head(&head);
+        repo.refs.update_head_logged(&oid, message);
+        Ok(())
+    }
+
+    // Gives the user a todo list to edit (with the given help below it) in the sequence editor, and reads back what they
+    // made of it. Returns None (having said what's wrong) if that isn't a todo list that can be carried out.
+    fn edit_todo_list(&self, todo: &[Step], help: &str) -> io::Result<Option<Vec<Step>>> {
+        let path = self.path("git-rebase-todo");
+        fs::write(&path, format!("{}{help}", self.format_todo(todo, true)))?;
+        let editor = sequence_editor(self.repo);
+        if !commit::launch_editor(&editor, &path)? {
+            eprintln!("error: There was a problem with the editor '{editor}'.");
+            process::exit(1);
+        }
+        let text = fs::read_to_string(&path)?;
+        let done = self.path("done").exists() && !self.load_done().is_empty();
+        let error = match parse_todo(self.repo, &text) {
+            Err(error) => error,
+            // Squashing needs a commit to meld into, which isn't there if nothing has been picked before.
+            Ok(todo) => match todo.iter().find(|step| step.oid.is_some()) {
+                Some(step) if step.command.is_fixup() && !done => {
+                    format!("cannot '{}' without a previous commit", step.command.name())
+                }
+                _ => return Ok(Some(todo)),
+            },
+        };
+        eprintln!("error: {error}");
+        eprintln!(
+            "You can fix this with 'git rebase --edit-todo' and then run 'git rebase --continue'."
+        );
+        eprintln!("Or you can abort the rebase with 'git rebase --abort'.");
+        Ok(None)
+    }
+
+    fn load_todo(&self) -> Vec<Step> {
+        let text = fs::read_to_string(self.path("git-rebase-todo")).unwrap_or_default();
+        parse_todo(self.repo, &text).unwrap_or_else(|error| {
+            eprintln!("error: {error}");
+            process::exit(1);
+        })
+    }
+
+    fn load_done(&self) -> Vec<Step> {
+        let text = fs::read_to_string(self.path("done")).unwrap_or_default();
+        parse_todo(self.repo, &text).expect("The done list is invalid")
+    }
+
+    fn save_todo(&self, todo: &[Step]) -> io::Result<()> {
+        fs::write(self.path("git-rebase-todo"), self.format_todo(todo, false))
+    }
+
+    // Writes out todo list lines like "pick 1a2b3c4 Fix the build", with abbreviated oids for the user to read or full ones
+    // for keeping.
+    fn format_todo(&self, todo: &[Step], abbreviate: bool) -> String {
+        todo.iter()
+            .map(|step| match step.oid {
+                Some(oid) => {
+                    let commit = self.repo.database.load_commit(&oid);
+                    let oid = if abbreviate {
+                        self.repo.database.abbreviate(&oid)
+                    } else {
+                        oid.to_string()
+                    };
+                    format!(
+                        "{} {oid} {}\n",
+                        step.command.name(),
+                        pretty::subject(commit.get_message())
+                    )
+                }
+                None if step.command == Command::Exec => format!("exec {}\n", step.exec),
+                None => format!("{}\n", step.command.name()),
+            })
+            .collect()
+    }
+
+    // The branch being rebased, e.g. "refs/heads/topic", or None if it's a detached HEAD.
+    fn head_name(&self) -> Option<String> {
+        let name = fs::read_to_string(self.path("head-name")).expect("Could not read head-name");
+        let name = name.trim();
+        (name != "detached HEAD").then(|| name.to_string())
+    }
+
+    fn read_oid(&self, name: &str) -> Digest {
+        self.optional_oid(name)
+            .unwrap_or_else(|| panic!("Could not read {name}"))
+    }
+
+    fn optional_oid(&self, name: &str) -> Option<Digest> {
+        sequencer::read_oid(&self.path(name))
+    }
+
+    fn read_number(&self, name: &str) -> usize {
+        fs::read_to_string(self.path(name))
+            .ok()
+            .and_then(|number| number.trim().parse().ok())
+            .unwrap_or_default()
+    }
+
+    fn check_in_progress(&self) {
+        if !Rebase::in_progress(self.repo) {
+            fatal("No rebase in progress?");
+        }
+    }
+
+    fn remove_state(&self) -> io::Result<()> {
+        match fs::remove_dir_all(self.path("")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+// What the template of a commit message made during a rebase says about it instead of where HEAD is: the last commands done,
+// the next ones to do and which branch is being rebased, e.g.
+//
+//   # interactive rebase in progress; onto 1a2b3c4
+//   # Last command done (1 command done):
+//   #    reword 5d6e7f8 Fix the build
+//   # No commands remaining.
+//   # You are currently editing a commit while rebasing branch 'topic' on '1a2b3c4'.
+//   #
+pub fn status_comment(repo: &Repository) -> Option<String> {
+    let dir = repo.git_path.join("rebase-merge");
+    let onto = sequencer::read_oid(&dir.join("onto"))?;
+    let onto = repo.database.abbreviate(&onto);
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap_or_default();
+    // The todo lists have full oids, which are abbreviated here.
+    let commands = |text: &str| -> Vec<String> {
+        text.lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let mut words: Vec<&str> = line.splitn(3, ' ').collect();
+                let oid = words.get(1).and_then(|word| word.parse::<Digest>().ok());
+                let short = oid.map(|oid| repo.database.abbreviate(&oid));
+                if let Some(short) = &short {
+                    words[1] = short;
+                }
+                words.join(" ")
+            })
+            .collect()
+    };
+    let plural = |count: usize, word: &str| match count {
+        1 => format!("1 {word}"),
+        _ => format!("{count} {word}s"),
+    };
+
+    let kind = if dir.join("interactive").exists() {
+        "interactive rebase"
+    } else {
+        "rebase"
+    };
+    let mut comment = format!("# {kind} in progress; onto {onto}\n");
+    let done = commands(&read("done"));
+    if !done.is_empty() {
+        let last = if done.len() == 1 {
+            "command"
+        } else {
+            "commands"
+        };
+        comment.push_str(&format!(
+            "# Last {last} done ({} done):\n",
+            plural(done.len(), "command")
+        ));
+        for line in &done[done.len().saturating_sub(2)..] {
+            comment.push_str(&format!("#    {line}\n"));
+        }
+    }
+    let todo = commands(&read("git-rebase-todo"));
+    if todo.is_empty() {
+        comment.push_str("# No commands remaining.\n");
+    } else {
+        let next = if todo.len() == 1 {
+            "command"
+        } else {
+            "commands"
+        };
+        comment.push_str(&format!(
+            "# Next {next} to do ({}):\n",
+            plural(todo.len(), "remaining command")
+        ));
+        for line in todo.iter().take(2) {
+            comment.push_str(&format!("#    {line}\n"));
+        }
+    }
+    let editing = done
+        .last()
+        .is_some_and(|line| line.starts_with("reword ") || line.starts_with("edit "));
+    let doing = if editing {
+        "editing a commit while rebasing"
+    } else {
+        "rebasing"
+    };
+    let head_name = read("head-name");
+    let branch = head_name
+        .trim()
+        .strip_prefix("refs/heads/")
+        .unwrap_or("HEAD");
+    comment.push_str(&format!(
+        "# You are currently {doing} branch '{branch}' on '{onto}'.\n#\n"
+    ));
+    Some(comment)
+}
+
+// The commits from upstream to HEAD that a rebase replays, oldest first. Merge commits are left out, and so are commits
+// whose change upstream already has (with a warning, like git).
+pub fn commits_to_rebase(repo: &Repository, upstream: Digest, head: Digest) -> Vec<Digest> {
+    let walk = |include: Digest, exclude: Digest| {
+        let options = RevListOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let mut rev_list = RevList::new(repo, options);
+        rev_list.include(include);
+        rev_list.exclude(exclude);
+        rev_list.walk()
+    };
+    let applied: HashSet<Digest> = walk(upstream, head)
+        .iter()
+        .filter(|commit| commit.get_parents().len() <= 1)
+        .map(|commit| patch_id(repo, commit))
+        .collect();
+    let mut commits = Vec::new();
+    for commit in walk(head, upstream) {
+        if commit.get_parents().len() > 1 {
+            continue;
+        }
+        if !applied.is_empty() && applied.contains(&patch_id(repo, &commit)) {
+            eprintln!(
+                "warning: skipped previously applied commit {}",
+                repo.database.abbreviate(commit.get_oid())
+            );
+            eprintln!("hint: use --reapply-cherry-picks to include skipped commits");
+            eprintln!(
+                "hint: Disable this message with \"git config advice.skippedCherryPicks false\""
+            );
+            continue;
+        }
+        commits.push(*commit.get_oid());
+    }
+    commits
+}
+
+// Identifies the change a commit makes independently of where it was made: a hash of the paths it touches and the lines it
+// removes and adds in each, ignoring whitespace and line numbers. Commits with the same one make the same change.
+fn patch_id(repo: &Repository, commit: &Commit) -> Digest {
+    let parent_tree = commit
+        .get_parents()
+        .first()
+        .map(|oid| *repo.database.load_commit(oid).get_tree());
+    let changes = repo
+        .database
+        .tree_diff(parent_tree.as_ref(), Some(commit.get_tree()));
+    let mut hasher = Sha1::new();
+    for pair in diff::pairs_from_tree_diff(&repo.database, &changes) {
+        hasher.update(pair.display_name().as_bytes());
+        hasher.update(b"\0");
+        if pair.is_binary() {
+            for side in [&pair.a, &pair.b] {
+                let oid = side
+                    .as_ref()
+                    .map_or_else(String::new, |side| side.oid.to_string());
+                hasher.update(oid.as_bytes());
+            }
+            continue;
+        }
+        for edit in pair.edits() {
+            let sign = match edit.kind {
+                EditKind::Eql => continue,
+                EditKind::Ins => b"+",
+                EditKind::Del => b"-",
+            };
+            hasher.update(sign);
+            let text: Vec<u8> = edit
+                .text()
+                .iter()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .copied()
+                .collect();
+            hasher.update(&text);
+        }
+    }
+    hasher.digest()
+}
+
+// Moves each "fixup! <subject>" or "squash! <subject>" commit to just after the commit it's meant for (the first one whose
+// subject is, or starts with, the rest, or whose oid starts with it) and turns its pick into a fixup or squash. Each step
+// comes with the subject of its commit.
+pub fn autosquash(todo: Vec<(Step, String)>) -> Vec<Step> {
+    let target_of = |subject: &str, before: &[(Step, String)]| {
+        let (command, mut rest) = if let Some(rest) = subject.strip_prefix("fixup! ") {
+            (Command::Fixup, rest)
+        } else if let Some(rest) = subject.strip_prefix("squash! ") {
+            (Command::Squash, rest)
+        } else {
+            return None;
+        };
+        while let Some(inner) = rest
+            .strip_prefix("fixup! ")
+            .or_else(|| rest.strip_prefix("squash! "))
+        {
+            rest = inner;
+        }
+        let by = |matches: &dyn Fn(&(Step, String)) -> bool| before.iter().position(matches);
+        by(&|(_, subject)| subject == rest)
+            .or_else(|| {
+                by(&|(step, _)| {
+                    !rest.is_empty()
+                        && step
+                            .oid
+                            .is_some_and(|oid| oid.to_string().starts_with(rest))
+                })
+            })
+            .or_else(|| by(&|(_, subject)| !rest.is_empty() && subject.starts_with(rest)))
+            .map(|index| (command, index))
+    };
+
+    // Each commit gets the list of fixups meant for it, in their original order.
+    let mut fixups: Vec<Vec<Step>> = vec![Vec::new(); todo.len()];
+    let mut moved = vec![false; todo.len()];
+    for (index, (step, subject)) in todo.iter().enumerate() {
+        if let Some((command, target)) = target_of(subject, &todo[..index]) {
+            // A fixup of a fixup goes with the commit the first one is for.
+            let mut target = target;
+            while moved[target] {
+                target = fixups
+                    .iter()
+                    .position(|list| list.iter().any(|step| step.oid == todo[target].0.oid))
+                    .expect("Moved fixup has no target");
+            }
+            fixups[target].push(Step {
+                command,
+                ..step.clone()
+            });
+            moved[index] = true;
+        }
+    }
+    let mut rearranged = Vec::new();
+    for ((step, _), (fixups, moved)) in todo.into_iter().zip(fixups.into_iter().zip(moved)) {
+        if !moved {
+            rearranged.push(step);
+        }
+        rearranged.extend(fixups);
+    }
+    rearranged
+}
+
+// Reads a todo list as the user left it: one command per line, where blank lines and "#" comments don't count. Fails on the
+// first line that isn't a valid command, saying which one it is.
+fn parse_todo(repo: &Repository, text: &str) -> Result<Vec<Step>, String> {
+    let mut todo = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let invalid = || format!("invalid line {}: {line}", number + 1);
+        let (word, rest) = trimmed
+            .split_once(char::is_whitespace)
+            .unwrap_or((trimmed, ""));
+        let command = Command::parse(word).ok_or_else(invalid)?;
+        let rest = rest.trim();
+        let step = match command {
+            Command::Exec if rest.is_empty() => return Err(invalid()),
+            Command::Exec => Step {
+                command,
+                oid: None,
+                exec: rest.to_string(),
+            },
+            Command::Break => Step {
+                command,
+                oid: None,
+                exec: String::new(),
+            },
+            _ => {
+                let name = rest.split_whitespace().next().ok_or_else(invalid)?;
+                let oid = revision::resolve(repo, name).ok_or_else(invalid)?;
+                Step {
+                    command,
+                    oid: Some(oid),
+                    exec: String::new(),
+                }
+            }
+        };
+        todo.push(step);
+    }
+    Ok(todo)
+}
+
+// The editor for todo lists: $GIT_SEQUENCE_EDITOR, sequence.editor, or else the one used for commit messages.
+fn sequence_editor(repo: &Repository) -> String {
+    env::var("GIT_SEQUENCE_EDITOR")
+        .ok()
+        .filter(|editor| !editor.is_empty())
+        .or_else(|| repo.config.get("sequence.editor").map(str::to_string))
+        .unwrap_or_else(|| commit::editor(repo))
+}
+
+// Like git, an author is kept as shell variable assignments, with values quoted for the shell.
+fn author_script(author: &Author) -> String {
+    let quote = |value: &str| format!("'{}'", value.replace('\'', "'\\''"));
+    format!(
+        "GIT_AUTHOR_NAME={}\nGIT_AUTHOR_EMAIL={}\nGIT_AUTHOR_DATE={}\n",
+        quote(&author.name),
+        quote(&author.email),
+        quote(&format!("@{} {}", author.timestamp, author.timezone))
+    )
+}
+
+fn parse_author_script(script: &str) -> Author {
+    let value = |name: &str| {
+        script
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{name}=")))
+            .map(|value| {
+                let value = value.strip_prefix('\'').unwrap_or(value);
+                let value = value.strip_suffix('\'').unwrap_or(value);
+                value.replace("'\\''", "'")
+            })
+            .unwrap_or_else(|| panic!("The author script has no {name}"))
+    };
+    let date = value("GIT_AUTHOR_DATE");
+    Author::parse(&format!(
+        "{} <{}> {}",
+        value("GIT_AUTHOR_NAME"),
+        value("GIT_AUTHOR_EMAIL"),
+        date.trim_start_matches('@')
+    ))
+}
+
+// Comments out a "squash! " or "fixup! " subject, which is only there to say where the commit goes.
+fn comment_subject(message: &str) -> String {
+    if message.starts_with("squash! ") || message.starts_with("fixup! ") {
+        let (subject, rest) = message.split_once('\n').unwrap_or((message, ""));
+        return format!("{}\n{rest}", comment(subject));
+    }
+    message.to_string()
+}
+
+// How a commit is described in the reflog: by the first line of its message, even if its subject goes on.
+fn first_line(message: &str) -> &str {
+    message.lines().next().unwrap_or_default()
+}
+
+fn comment(line: &str) -> String {
+    if line.is_empty() {
+        "#".to_string()
+    } else {
+        format!("# {line}")
+    }
+}
+
+// What git prints to clear the progress line before saying something else: an escape code, or spaces on a dumb terminal.
+fn clear_line() -> String {
+    match env::var("TERM") {
+        Ok(term) if term != "dumb" => "\r\x1b[K".to_string(),
+        _ => format!("\r{}\r", " ".repeat(80)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_autosquash() {
+        let oid = |name: &str| Sha1::from(name).digest();
+        let step = |name: &str| (Step::pick(oid(name)), name.to_string());
+        let todo = vec![
+            step("one"),
+            step("two"),
+            step("fixup! one"),
+            step("squash! two"),
+            step("fixup! fixup! one"),
+            step("squash! nothing"),
+        ];
+        let commands: Vec<_> = autosquash(todo)
+            .into_iter()
+            .map(|step| (step.command, step.oid.unwrap()))
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                (Command::Pick, oid("one")),
+                (Command::Fixup, oid("fixup! one")),
+                (Command::Fixup, oid("fixup! fixup! one")),
+                (Command::Pick, oid("two")),
+                (Command::Squash, oid("squash! two")),
+                (Command::Pick, oid("squash! nothing")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_author_script() {
+        let author = Author::parse("O'Brien <ob@example.com> 1700000400 -0500");
+        let script = author_script(&author);
+        assert!(script.starts_with("GIT_AUTHOR_NAME='O'\\''Brien'\n"));
+        assert!(script.ends_with("GIT_AUTHOR_DATE='@1700000400 -0500'\n"));
+        assert_eq!(parse_author_script(&script), author);
+    }
+}
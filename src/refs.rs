@@ -1,36 +1,447 @@
+use crate::commit::Author;
 use sha1_smol::Digest;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::PathBuf;
 use std::{fs, io::Write};
 
+const SYMREF_PREFIX: &str = "ref: ";
+
 pub struct Refs {
     pathname: PathBuf,
 }
 
+// One move of a ref, as its reflog records it: from where (None if the ref didn't exist yet) to where, by whom and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    pub old: Option<Digest>,
+    pub new: Digest,
+    pub identity: String,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    // Parses a line of a reflog file, "<old oid> <new oid> <identity>\t<message>".
+    fn parse(line: &str) -> Option<Self> {
+        let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut fields = head.splitn(3, ' ');
+        let old: Digest = fields.next()?.parse().ok()?;
+        let new = fields.next()?.parse().ok()?;
+        Some(ReflogEntry {
+            old: (old.to_string() != "0".repeat(40)).then_some(old),
+            new,
+            identity: fields.next().unwrap_or_default().to_string(),
+            message: message.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ReflogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let old = self
+            .old
+            .map_or_else(|| "0".repeat(40), |oid| oid.to_string());
+        write!(f, "{old} {} {}\t{}", self.new, self.identity, self.message)
+    }
+}
+
 // TODO the book creates a "Lockfile" to make sure two processes don't have race conditions reading the HEAD file and others. I'll leave this out until it's needed.
 impl Refs {
     pub fn new(pathname: PathBuf) -> Self {
         Refs { pathname }
     }
 
-    pub fn update_head(&mut self, oid: &Digest) {
+    // Points HEAD at the given oid. If HEAD refers to a branch, the branch is what gets updated (and created if needed). The
+    // move (and why it happened, e.g. "reset: moving to HEAD~1") is recorded in the reflogs of HEAD and of the branch.
+    pub fn update_head_logged(&mut self, oid: &Digest, message: &str) {
+        let old = self.read_head();
+        let target = self.head_target();
+        self.write_ref(&target, oid);
+        let identity = Author::now();
+        // Like git, a ref that didn't actually move gets no entry, except for HEAD when it's on a branch.
+        if old == Some(*oid) && target == "HEAD" {
+            return;
+        }
+        if target != "HEAD" && old != Some(*oid) {
+            self.append_reflog(&target, old, oid, &identity, message);
+        }
+        self.append_reflog("HEAD", old, oid, &identity, message);
+    }
+
+    // Points a ref given by its full name (e.g. "refs/heads/main") at the given oid, recording the move (if it moved) in its
+    // reflog.
+    pub fn update_ref_logged(&mut self, name: &str, oid: &Digest, message: &str) {
+        let old = self.resolve(name);
+        self.write_ref(name, oid);
+        if old != Some(*oid) {
+            self.append_reflog(name, old, oid, &Author::now(), message);
+        }
+    }
+
+    // Records a move of HEAD that was made without updating it through update_head_logged, like switching branches.
+    pub fn log_head(&self, old: Option<Digest>, new: &Digest, message: &str) {
+        self.append_reflog("HEAD", old, new, &Author::now(), message);
+    }
+
+    // Remembers where HEAD was before an operation that moves it a long way (like reset), so that it can be undone.
+    pub fn set_orig_head(&mut self, oid: &Digest) {
+        self.write_ref("ORIG_HEAD", oid);
+    }
+
+    // Points HEAD directly at a commit, so that it no longer refers to any branch.
+    pub fn detach_head(&mut self, oid: &Digest) {
+        self.write_ref("HEAD", oid);
+    }
+
+    // Creates a branch (or moves it, if it already exists) to point at the given oid.
+    pub fn set_branch(&mut self, branch: &str, oid: &Digest) {
+        self.write_ref(&format!("refs/heads/{branch}"), oid);
+    }
+
+    pub fn read_branch(&self, branch: &str) -> Option<Digest> {
+        self.resolve(&format!("refs/heads/{branch}"))
+    }
+
+    // Creates a tag (or moves it, if it already exists) to point at the given oid. Like git, tags have no reflog.
+    pub fn set_tag(&mut self, tag: &str, oid: &Digest) {
+        self.write_ref(&format!("refs/tags/{tag}"), oid);
+    }
+
+    pub fn read_tag(&self, tag: &str) -> Option<Digest> {
+        self.resolve(&format!("refs/tags/{tag}"))
+    }
+
+    // Deletes a ref given by its full name, whether it's loose or packed, along with its reflog.
+    pub fn delete_ref(&mut self, name: &str) {
+        let _ = fs::remove_file(self.pathname.join(name));
+        let _ = fs::remove_file(self.pathname.join("logs").join(name));
+        let packed_path = self.pathname.join("packed-refs");
+        let Ok(packed) = fs::read_to_string(&packed_path) else {
+            return;
+        };
+        // A peeled line ("^<oid>") belongs to the ref on the line before it, so it goes too.
+        let mut lines: Vec<&str> = Vec::new();
+        let mut deleted = false;
+        for line in packed.lines() {
+            if line.starts_with('^') && deleted {
+                continue;
+            }
+            deleted = line
+                .split_once(' ')
+                .is_some_and(|(_, ref_name)| ref_name == name);
+            if !deleted {
+                lines.push(line);
+            }
+        }
+        let packed: String = lines.iter().map(|line| format!("{line}\n")).collect();
+        fs::write(packed_path, packed).expect("failed to write packed-refs");
+    }
+
+    fn write_ref(&self, name: &str, oid: &Digest) {
+        let path = self.pathname.join(name);
+        fs::create_dir_all(path.parent().expect("Ref has no parent dir"))
+            .expect("failed to create ref dir");
         fs::OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(self.get_head_path())
-            .expect("failed to open HEAD to update")
-            .write_all(oid.to_string().as_bytes())
-            .expect("failed to write to HEAD");
+            .open(path)
+            .expect("failed to open ref to update")
+            .write_all(format!("{oid}\n").as_bytes())
+            .expect("failed to write ref");
+    }
+
+    fn append_reflog(
+        &self,
+        name: &str,
+        old: Option<Digest>,
+        new: &Digest,
+        identity: &Author,
+        message: &str,
+    ) {
+        let path = self.pathname.join("logs").join(name);
+        fs::create_dir_all(path.parent().expect("Reflog has no parent dir"))
+            .expect("failed to create reflog dir");
+        let entry = ReflogEntry {
+            old,
+            new: *new,
+            identity: identity.to_string(),
+            message: message.to_string(),
+        };
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .expect("failed to open reflog")
+            .write_all(format!("{entry}\n").as_bytes())
+            .expect("failed to write reflog");
+    }
+
+    // The moves recorded in the reflog of a ref given by its full name, oldest first. Empty if it has no reflog.
+    pub fn read_reflog(&self, name: &str) -> Vec<ReflogEntry> {
+        fs::read_to_string(self.pathname.join("logs").join(name))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(ReflogEntry::parse)
+            .collect()
+    }
+
+    // Deletes the nth newest entry of a ref's reflog, like "git reflog delete --rewrite --updateref": the next entry is
+    // rewritten to start where the one before left off, and if the newest entry goes, the ref goes back to the one before. A
+    // ref whose reflog ends up empty is deleted along with it.
+    pub fn drop_reflog_entry(&mut self, name: &str, nth: usize) {
+        let mut entries = self.read_reflog(name);
+        let index = entries
+            .len()
+            .checked_sub(nth + 1)
+            .expect("Reflog entry out of range");
+        entries.remove(index);
+        let Some(newest) = entries.last().map(|entry| entry.new) else {
+            self.delete_ref(name);
+            return;
+        };
+        if index < entries.len() {
+            entries[index].old = index.checked_sub(1).map(|previous| entries[previous].new);
+        }
+        let log: String = entries.iter().map(|entry| format!("{entry}\n")).collect();
+        fs::write(self.pathname.join("logs").join(name), log).expect("failed to write reflog");
+        if nth == 0 {
+            self.write_ref(name, &newest);
+        }
+    }
+
+    // Makes HEAD a symbolic ref to the given branch, whether or not the branch exists yet.
+    pub fn set_head_branch(&mut self, branch: &str) {
+        fs::write(
+            self.get_head_path(),
+            format!("{SYMREF_PREFIX}refs/heads/{branch}\n"),
+        )
+        .expect("failed to write to HEAD");
     }
 
     pub fn read_head(&self) -> Option<Digest> {
-        // Return None if no HEAD file exists, but panic if we fail to parse the digest in it.
+        self.resolve("HEAD")
+    }
+
+    // The short name of the branch HEAD refers to (e.g. "main"), or None if HEAD is detached.
+    pub fn current_branch(&self) -> Option<String> {
+        self.head_target()
+            .strip_prefix("refs/heads/")
+            .map(str::to_string)
+    }
+
+    // Looks up a ref by the short names git accepts, e.g. "main" finds "refs/heads/main" and "v1.0" finds "refs/tags/v1.0".
+    pub fn read_ref(&self, name: &str) -> Option<Digest> {
+        self.full_name(name)
+            .and_then(|full_name| self.resolve(&full_name))
+    }
+
+    // The full name of the ref that read_ref finds for a short name, e.g. "refs/heads/main" for "main".
+    pub fn full_name(&self, name: &str) -> Option<String> {
+        // Only all-caps names like HEAD and ORIG_HEAD are looked up directly in the git dir, so that names like "index" or
+        // "config" don't resolve to some other file in there.
+        let toplevel =
+            name.starts_with("refs/") || name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+        let candidates = [
+            toplevel.then(|| name.to_string()),
+            Some(format!("refs/{name}")),
+            Some(format!("refs/tags/{name}")),
+            Some(format!("refs/heads/{name}")),
+            Some(format!("refs/remotes/{name}")),
+            Some(format!("refs/remotes/{name}/HEAD")),
+        ];
+        candidates
+            .into_iter()
+            .flatten()
+            .find(|candidate| self.resolve(candidate).is_some())
+    }
+
+    // Every ref under refs/ (loose or packed) and the oid it points to, sorted by name.
+    pub fn list_refs(&self) -> Vec<(String, Digest)> {
+        let mut refs = BTreeMap::new();
+        if let Ok(packed) = fs::read_to_string(self.pathname.join("packed-refs")) {
+            for (oid, name) in packed
+                .lines()
+                .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+                .filter_map(|line| line.split_once(' '))
+            {
+                refs.insert(
+                    name.to_string(),
+                    oid.parse().expect("Packed ref has an invalid oid"),
+                );
+            }
+        }
+        // Loose refs take precedence over packed ones with the same name.
+        for entry in walkdir::WalkDir::new(self.pathname.join("refs"))
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+        {
+            let name = entry
+                .path()
+                .strip_prefix(&self.pathname)
+                .expect("Ref is outside the git dir")
+                .to_string_lossy()
+                .to_string();
+            if let Some(oid) = self.resolve(&name) {
+                refs.insert(name, oid);
+            }
+        }
+        refs.into_iter().collect()
+    }
+
+    // Reads the oid a full ref name points to, following symbolic refs and falling back to the packed-refs file.
+    fn resolve(&self, name: &str) -> Option<Digest> {
+        match fs::read_to_string(self.pathname.join(name)) {
+            Ok(contents) => match contents.trim().strip_prefix(SYMREF_PREFIX) {
+                Some(target) => self.resolve(target),
+                // Panic if the ref exists but we fail to parse the digest in it.
+                None => Some(contents.trim().parse().expect("Ref has an invalid oid")),
+            },
+            Err(_) => self.read_packed_ref(name),
+        }
+    }
+
+    // Each line of packed-refs is "<oid> <name>". Comments start with '#' and peeled tags with '^', neither of which we need.
+    fn read_packed_ref(&self, name: &str) -> Option<Digest> {
+        let packed = fs::read_to_string(self.pathname.join("packed-refs")).ok()?;
+        packed
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+            .filter_map(|line| line.split_once(' '))
+            .find(|(_, ref_name)| *ref_name == name)
+            .map(|(oid, _)| oid.parse().expect("Packed ref has an invalid oid"))
+    }
+
+    // The ref that HEAD points to, or "HEAD" itself if it's detached (holds an oid directly).
+    fn head_target(&self) -> String {
         fs::read_to_string(self.get_head_path())
             .ok()
-            .map(|contents| contents.trim().parse().ok())?
+            .and_then(|contents| {
+                contents
+                    .trim()
+                    .strip_prefix(SYMREF_PREFIX)
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| "HEAD".to_string())
     }
 
     fn get_head_path(&self) -> PathBuf {
         self.pathname.join("HEAD")
     }
 }
+
+// Checks a branch name against git's rules for ref names (see git-check-ref-format), e.g. no "..", spaces or "~^:?*[\\"
+// anywhere, and no component starting with a dot or ending in ".lock".
+pub fn is_valid_branch_name(name: &str) -> bool {
+    name != "HEAD" && is_valid_tag_name(name)
+}
+
+// Tags follow the same rules as branches, except that one can be called HEAD.
+pub fn is_valid_tag_name(name: &str) -> bool {
+    if name.is_empty() || name == "@" || name.starts_with('-') {
+        return false;
+    }
+    if name.contains("..") || name.contains("@{") || name.ends_with('.') {
+        return false;
+    }
+    let bad_char = |c: char| c.is_ascii_control() || " ~^:?*[\\".contains(c);
+    if name.chars().any(bad_char) {
+        return false;
+    }
+    name.split('/').all(|component| {
+        !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbolic_head() {
+        let dir = std::env::temp_dir().join(format!("refs-test-{}", std::process::id()));
+        let mut refs = Refs::new(dir.clone());
+        fs::create_dir_all(&dir).unwrap();
+        refs.set_head_branch("main");
+        assert_eq!(refs.read_head(), None);
+        assert_eq!(refs.current_branch().as_deref(), Some("main"));
+
+        let oid = sha1_smol::Sha1::from("x").digest();
+        refs.update_head_logged(&oid, "commit (initial): x");
+        assert_eq!(refs.read_head(), Some(oid));
+        assert_eq!(refs.read_ref("main"), Some(oid));
+        assert_eq!(
+            fs::read_to_string(dir.join("refs/heads/main")).unwrap(),
+            format!("{oid}\n")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_head_logged() {
+        let dir = std::env::temp_dir().join(format!("refs-log-test-{}", std::process::id()));
+        let mut refs = Refs::new(dir.clone());
+        fs::create_dir_all(&dir).unwrap();
+        refs.set_head_branch("main");
+        let a = sha1_smol::Sha1::from("a").digest();
+        let b = sha1_smol::Sha1::from("b").digest();
+        refs.update_head_logged(&a, "commit (initial): a");
+        refs.update_head_logged(&b, "reset: moving to b");
+        refs.update_head_logged(&b, "reset: moving to HEAD");
+
+        let head_log = fs::read_to_string(dir.join("logs/HEAD")).unwrap();
+        let lines: Vec<_> = head_log.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(&format!("{} {a} ", "0".repeat(40))));
+        assert!(lines[1].starts_with(&format!("{a} {b} ")));
+        assert!(lines[1].ends_with("\treset: moving to b"));
+        // The branch didn't move the last time, so only HEAD's log has that entry.
+        let branch_log = fs::read_to_string(dir.join("logs/refs/heads/main")).unwrap();
+        assert_eq!(branch_log.lines().collect::<Vec<_>>(), lines[..2]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop_reflog_entry() {
+        let dir = std::env::temp_dir().join(format!("refs-drop-test-{}", std::process::id()));
+        let mut refs = Refs::new(dir.clone());
+        let [a, b, c] = ["a", "b", "c"].map(|name| sha1_smol::Sha1::from(name).digest());
+        for oid in [a, b, c] {
+            refs.update_ref_logged("refs/stash", &oid, "WIP");
+        }
+
+        // Dropping from the middle joins up the entries around it.
+        refs.drop_reflog_entry("refs/stash", 1);
+        let entries = refs.read_reflog("refs/stash");
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.old, entry.new))
+                .collect::<Vec<_>>(),
+            [(None, a), (Some(a), c)]
+        );
+        assert_eq!(refs.read_ref("stash"), Some(c));
+
+        // Dropping the newest moves the ref back, and dropping the last one deletes it.
+        refs.drop_reflog_entry("refs/stash", 0);
+        assert_eq!(refs.read_ref("stash"), Some(a));
+        refs.drop_reflog_entry("refs/stash", 0);
+        assert_eq!(refs.read_ref("stash"), None);
+        assert!(refs.read_reflog("refs/stash").is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_valid_branch_name() {
+        for name in ["main", "feature/x", "v1.0", "a-b_c"] {
+            assert!(is_valid_branch_name(name), "{name}");
+        }
+        for name in [
+            "", "-x", "a..b", "a b", "a~1", "x^", "a:b", "?", "a/", "/a", "a//b", ".a", "a/.b",
+            "a.", "a.lock", "a@{1}", "@", "HEAD",
+        ] {
+            assert!(!is_valid_branch_name(name), "{name}");
+        }
+    }
+}
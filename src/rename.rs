@@ -0,0 +1,283 @@
+use crate::diff::{FilePair, Origin, OriginKind};
+use std::collections::HashMap;
+
+// Similarity scores are fractions of this, like in git's diffcore.
+pub const MAX_SCORE: usize = 60000;
+// Files are split into lines, but no chunk is longer than this, so that binary files still get compared in pieces.
+const MAX_CHUNK: usize = 64;
+const DEFAULT_THRESHOLD: usize = MAX_SCORE / 2;
+// Comparing every deleted file with every added file is quadratic, so git gives up on inexact matching past this many files on
+// either side.
+const DEFAULT_RENAME_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameOptions {
+    pub renames: bool,
+    pub copies: bool,
+    // The minimum similarity (out of MAX_SCORE) for two files to be considered a rename or copy.
+    pub threshold: usize,
+    pub limit: usize,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        RenameOptions {
+            renames: true,
+            copies: false,
+            threshold: DEFAULT_THRESHOLD,
+            limit: DEFAULT_RENAME_LIMIT,
+        }
+    }
+}
+
+impl RenameOptions {
+    // Consumes the argument if it's one of the rename/copy detection options and returns whether it was.
+    pub fn parse_arg(&mut self, arg: &str) -> bool {
+        if arg == "--no-renames" {
+            self.renames = false;
+            self.copies = false;
+            return true;
+        }
+        if let Some(limit) = arg.strip_prefix("-l") {
+            if let Ok(limit) = limit.parse() {
+                self.limit = limit;
+                return true;
+            }
+            return false;
+        }
+        let (copies, score) = if let Some(score) = arg
+            .strip_prefix("--find-renames")
+            .or_else(|| arg.strip_prefix("-M"))
+        {
+            (false, score)
+        } else if let Some(score) = arg
+            .strip_prefix("--find-copies")
+            .or_else(|| arg.strip_prefix("-C"))
+        {
+            (true, score)
+        } else {
+            return false;
+        };
+        let score = score.strip_prefix('=').unwrap_or(score);
+        if !score.is_empty() {
+            match parse_score(score) {
+                Some(threshold) => self.threshold = threshold,
+                None => return false,
+            }
+        }
+        self.renames = true;
+        self.copies |= copies;
+        true
+    }
+}
+
+// Parses a similarity like git does: "90%" is a percentage, while plain digits are the fractional part of a number, so "5" and "50"
+// are both 50% and "95" is 95%.
+pub fn parse_score(score: &str) -> Option<usize> {
+    if let Some(percent) = score.strip_suffix('%') {
+        let percent: usize = percent.parse().ok()?;
+        return Some(MAX_SCORE.min(percent * MAX_SCORE / 100));
+    }
+    if score.is_empty() || !score.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let digits: usize = score.parse().ok()?;
+    let scale = 10_usize.checked_pow(score.len() as u32)?;
+    Some(digits * MAX_SCORE / scale)
+}
+
+// Counts how many bytes are in each distinct chunk of the data.
+fn chunk_counts(data: &[u8]) -> HashMap<&[u8], usize> {
+    let mut counts = HashMap::new();
+    let mut start = 0;
+    for (i, byte) in data.iter().enumerate() {
+        if *byte == b'\n' || i + 1 - start == MAX_CHUNK {
+            *counts.entry(&data[start..=i]).or_insert(0) += i + 1 - start;
+            start = i + 1;
+        }
+    }
+    if start < data.len() {
+        *counts.entry(&data[start..]).or_insert(0) += data.len() - start;
+    }
+    counts
+}
+
+// Estimates how similar two files are (out of MAX_SCORE), by how many of the bytes in the larger one can be found in the other.
+pub fn similarity(source: &[u8], target: &[u8]) -> usize {
+    let max_size = source.len().max(target.len());
+    if max_size == 0 {
+        return MAX_SCORE;
+    }
+    let source_counts = chunk_counts(source);
+    let copied: usize = chunk_counts(target)
+        .iter()
+        .map(|(chunk, count)| (*count).min(*source_counts.get(chunk).unwrap_or(&0)))
+        .sum();
+    copied * MAX_SCORE / max_size
+}
+
+// Pairs up added files with deleted (and, for copies, modified) files that have the same or similar contents, turning them into
+// renames and copies. The result is sorted by path, just like the input.
+pub fn detect(pairs: Vec<FilePair>, options: &RenameOptions) -> Vec<FilePair> {
+    if !options.renames {
+        return pairs;
+    }
+
+    let is_added = |pair: &FilePair| pair.a.is_none() && pair.b.is_some();
+    let is_source = |pair: &FilePair| pair.b.is_none() || (options.copies && pair.a.is_some());
+    let sources: Vec<usize> = (0..pairs.len()).filter(|i| is_source(&pairs[*i])).collect();
+    let targets: Vec<usize> = (0..pairs.len()).filter(|i| is_added(&pairs[*i])).collect();
+    if sources.is_empty() || targets.is_empty() {
+        return pairs;
+    }
+
+    // For each target, find the best source. Exact matches (same oid) win outright, then the most similar file above the threshold.
+    let mut matches: Vec<(usize, usize, usize)> = Vec::new();
+    let mut unmatched = Vec::new();
+    for &target in &targets {
+        let target_oid = pairs[target].b.as_ref().map(|side| side.oid);
+        let exact = sources
+            .iter()
+            .find(|source| pairs[**source].a.as_ref().map(|side| side.oid) == target_oid);
+        match exact {
+            Some(source) => matches.push((MAX_SCORE, *source, target)),
+            None => unmatched.push(target),
+        }
+    }
+    let within_limit = sources.len() <= options.limit && unmatched.len() <= options.limit;
+    if within_limit {
+        for &target in &unmatched {
+            let target_data = &pairs[target]
+                .b
+                .as_ref()
+                .expect("target has a new side")
+                .data;
+            let best = sources
+                .iter()
+                .filter_map(|source| {
+                    let source_data = &pairs[*source]
+                        .a
+                        .as_ref()
+                        .expect("source has an old side")
+                        .data;
+                    // Skip the expensive comparison when the sizes alone are too different to reach the threshold.
+                    let max_size = source_data.len().max(target_data.len());
+                    let size_difference = source_data.len().abs_diff(target_data.len());
+                    if max_size * (MAX_SCORE - options.threshold) < size_difference * MAX_SCORE {
+                        return None;
+                    }
+                    Some((similarity(source_data, target_data), *source))
+                })
+                .filter(|(score, _)| *score >= options.threshold)
+                // Prefer the highest score, then the earliest source for ties.
+                .min_by_key(|(score, source)| (MAX_SCORE - score, *source));
+            if let Some((score, source)) = best {
+                matches.push((score, source, target));
+            }
+        }
+    }
+
+    // Deleted files can only be renamed once, so hand them out to the best scoring targets first. Any further uses are copies.
+    matches.sort_by_key(|(score, source, target)| (MAX_SCORE - score, *source, *target));
+    let mut renamed = vec![false; pairs.len()];
+    let mut origins = HashMap::new();
+    for (score, source, target) in matches {
+        let source_deleted = pairs[source].b.is_none();
+        let kind = if source_deleted && !renamed[source] {
+            renamed[source] = true;
+            OriginKind::Rename
+        } else if options.copies {
+            OriginKind::Copy
+        } else {
+            continue;
+        };
+        let origin = Origin {
+            path: pairs[source].path.clone(),
+            similarity: score,
+            kind,
+        };
+        origins.insert(target, (origin, pairs[source].a.clone()));
+    }
+
+    // Renamed files no longer show up as deletions, and each target now compares against its source's old contents.
+    let mut result: Vec<FilePair> = pairs
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !renamed[*i])
+        .map(|(i, mut pair)| {
+            if let Some((origin, side)) = origins.remove(&i) {
+                pair.a = side;
+                pair.origin = Some(origin);
+            }
+            pair
+        })
+        .collect();
+    result.sort_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::Side;
+    use sha1_smol::Sha1;
+    use std::path::PathBuf;
+
+    fn side(data: &str) -> Option<Side> {
+        Some(Side {
+            oid: Sha1::from(data).digest(),
+            mode: 0o100644,
+            data: data.as_bytes().to_vec(),
+        })
+    }
+
+    fn numbers(range: std::ops::RangeInclusive<usize>) -> String {
+        range.map(|i| format!("{i}\n")).collect()
+    }
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(parse_score("50%"), Some(MAX_SCORE / 2));
+        assert_eq!(parse_score("5"), Some(MAX_SCORE / 2));
+        assert_eq!(parse_score("95"), Some(MAX_SCORE * 95 / 100));
+        assert_eq!(parse_score("x"), None);
+    }
+
+    #[test]
+    fn test_similarity() {
+        let a = numbers(1..=40);
+        let b = numbers(1..=41);
+        assert_eq!(similarity(a.as_bytes(), b.as_bytes()) * 100 / MAX_SCORE, 97);
+        assert_eq!(similarity(b"abc\n", b"xyz\n"), 0);
+    }
+
+    #[test]
+    fn test_detect_renames_and_copies() {
+        let pairs = vec![
+            FilePair::new(PathBuf::from("copy.txt"), None, side(&numbers(1..=20))),
+            FilePair::new(
+                PathBuf::from("m.txt"),
+                side(&numbers(1..=20)),
+                side(&numbers(1..=21)),
+            ),
+            FilePair::new(PathBuf::from("new.txt"), None, side(&numbers(1..=41))),
+            FilePair::new(PathBuf::from("old.txt"), side(&numbers(1..=40)), None),
+        ];
+
+        let renames = detect(pairs.clone(), &RenameOptions::default());
+        let paths: Vec<_> = renames.iter().map(|p| p.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["copy.txt", "m.txt", "new.txt"]);
+        let origin = renames[2].origin.as_ref().unwrap();
+        assert_eq!(origin.path, PathBuf::from("old.txt"));
+        assert_eq!(origin.kind, OriginKind::Rename);
+        assert!(renames[0].origin.is_none());
+
+        let mut options = RenameOptions::default();
+        assert!(options.parse_arg("-C"));
+        let copies = detect(pairs, &options);
+        let origin = copies[0].origin.as_ref().unwrap();
+        assert_eq!(origin.path, PathBuf::from("m.txt"));
+        assert_eq!(origin.kind, OriginKind::Copy);
+        assert_eq!(origin.similarity, MAX_SCORE);
+    }
+}
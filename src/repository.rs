@@ -0,0 +1,274 @@
+use crate::attributes::Attributes;
+use crate::config::Config;
+use crate::database::{Database, DatabaseEntry};
+use crate::diff::{FilePair, Side};
+use crate::discovery::Location;
+use crate::fatal::fatal;
+use crate::ignore::Ignore;
+use crate::index::{Index, IndexEntry, IndexMetadata};
+use crate::object::Object;
+use crate::pathspec::{self, Pathspec};
+use crate::refs::Refs;
+use crate::workspace::Workspace;
+use crate::Blob;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Bundles together all the pieces of a repository that subcommands need to work with.
+pub struct Repository {
+    // The .git directory, for the files commands keep there (like COMMIT_EDITMSG).
+    pub git_path: PathBuf,
+    // Where the command was run from, relative to the top of the workspace.
+    pub prefix: PathBuf,
+    pub bare: bool,
+    pub attributes: Attributes,
+    pub config: Config,
+    pub database: Database,
+    pub index: Index,
+    pub refs: Refs,
+    pub workspace: Workspace,
+}
+
+impl Repository {
+    pub fn new(location: Location) -> Self {
+        let Location {
+            git_path,
+            root_path,
+            prefix,
+        } = location;
+        let config = Config::load(&git_path);
+        // A bare repository has no workspace, so commands that need one refuse to run (see require_workspace).
+        let bare = root_path.is_none();
+        let root_path = root_path.unwrap_or_else(|| git_path.clone());
+        Repository {
+            bare,
+            attributes: Attributes::new(root_path.clone(), &git_path),
+            workspace: Workspace::new(
+                root_path.clone(),
+                Ignore::new(root_path, &git_path, &config),
+            ),
+            config,
+            database: Database::new(git_path.join("objects")),
+            index: Index::new(git_path.join("index")),
+            refs: Refs::new(git_path.clone()),
+            git_path,
+            prefix,
+        }
+    }
+
+    pub fn require_workspace(&self) {
+        if self.bare {
+            fatal("this operation must be run in a work tree");
+        }
+    }
+
+    // Turns paths given on the command line, which are relative to where the command was run from, into paths relative to the
+    // top of the workspace. Like git, a path that leads outside the workspace is a fatal error.
+    pub fn workspace_paths(&self, paths: &[String]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| pathspec::resolve(self.workspace.root(), &self.prefix, path))
+            .collect()
+    }
+
+    // A pathspec for patterns given on the command line, relative to where the command was run from.
+    pub fn pathspec(&self, patterns: &[String]) -> Pathspec {
+        Pathspec::new(patterns, self.workspace.root(), &self.prefix)
+    }
+
+    fn index_side(&self, path: &Path) -> Option<Side> {
+        self.index.get_entry(path).map(|entry| Side {
+            oid: *entry.get_oid(),
+            mode: entry.get_mode(),
+            data: self.database.load_blob(entry.get_oid()),
+        })
+    }
+
+    // Compares the tree of the HEAD commit with what's staged in the index.
+    pub fn head_to_index(&self) -> Vec<FilePair> {
+        self.tree_to_index(&self.head_tree_entries())
+    }
+
+    // Compares the entries of some tree (e.g. the HEAD commit's) with what's staged in the index. Paths with merge conflicts
+    // have nothing staged to compare, so they're left out.
+    pub fn tree_to_index(&self, head: &BTreeMap<PathBuf, DatabaseEntry>) -> Vec<FilePair> {
+        // Like git, paths are compared by their bytes rather than their components.
+        let mut paths: Vec<PathBuf> = head.keys().cloned().collect();
+        paths.extend(self.index.entries().map(|e| e.get_path().to_path_buf()));
+        paths.sort_by(|a, b| a.as_os_str().cmp(b.as_os_str()));
+        paths.dedup();
+        let conflicted = self.index.conflicted_paths();
+
+        paths
+            .into_iter()
+            .filter(|path| !conflicted.contains(&path.as_path()))
+            .filter(|path| {
+                let in_head = head.get(path).map(|entry| (entry.oid, entry.mode));
+                let in_index = self
+                    .index
+                    .get_entry(path)
+                    .map(|entry| (*entry.get_oid(), entry.get_mode()));
+                in_head != in_index
+            })
+            .map(|path| {
+                let a = head.get(&path).map(|entry| Side {
+                    oid: entry.oid,
+                    mode: entry.mode,
+                    data: self.database.load_blob(&entry.oid),
+                });
+                let b = self.index_side(&path);
+                FilePair::new(path, a, b)
+            })
+            .collect()
+    }
+
+    // Compares what's staged in the index with the files in the workspace. Untracked files and paths with merge conflicts are
+    // not included.
+    pub fn index_to_workspace(&self) -> Vec<FilePair> {
+        self.index
+            .entries()
+            .filter(|entry| entry.get_stage() == 0)
+            .filter_map(|entry| {
+                let path = entry.get_path().to_path_buf();
+                let stat = match self.workspace.stat_file(&path) {
+                    Ok(stat) => IndexMetadata::from(stat),
+                    Err(_) => {
+                        return Some(FilePair::new(path.clone(), self.index_side(&path), None))
+                    }
+                };
+                if self.is_unchanged(entry, &stat) {
+                    return None;
+                }
+                let data = self
+                    .workspace
+                    .read_file(&path)
+                    .expect("Could not read file to compare with the index");
+                let b = Some(Side {
+                    oid: *Blob::new(data.clone(), path.clone()).get_oid(),
+                    mode: stat.get_mode(),
+                    data,
+                });
+                Some(FilePair::new(path.clone(), self.index_side(&path), b))
+            })
+            .collect()
+    }
+
+    // Whether a workspace file (given its stat) still matches its index entry. The contents are only hashed if the file's
+    // timestamps changed but its size and mode didn't.
+    pub fn is_unchanged(&self, entry: &IndexEntry, stat: &IndexMetadata) -> bool {
+        if !entry.stat_match(stat) {
+            return false;
+        }
+        if entry.times_match(stat) {
+            return true;
+        }
+        self.workspace
+            .read_file(entry.get_path())
+            .is_ok_and(|data| {
+                Blob::new(data, entry.get_path().to_path_buf()).get_oid() == entry.get_oid()
+            })
+    }
+
+    // Writes a blob from the database to the workspace, making room for it (creating its parent directories, or removing a
+    // directory where the file goes), and returns the new file's stat data for the index.
+    pub fn write_to_workspace(
+        &self,
+        path: &Path,
+        entry: &DatabaseEntry,
+    ) -> io::Result<IndexMetadata> {
+        for dir in Index::parent_directories(path) {
+            self.workspace.make_directory(&dir)?;
+        }
+        let full_path = self.workspace.root().join(path);
+        if full_path.is_dir() {
+            fs::remove_dir_all(&full_path)?;
+        }
+        let data = self.database.load_blob(&entry.oid);
+        self.workspace.write_file(path, &data, entry.mode)?;
+        let mut stat = IndexMetadata::from(self.workspace.stat_file(path)?);
+        stat.set_mode(entry.mode);
+        Ok(stat)
+    }
+
+    // Whether the workspace file at a path has exactly the contents and mode of the given entry.
+    pub fn workspace_matches(&self, path: &Path, entry: &DatabaseEntry) -> bool {
+        let Ok(stat) = self.workspace.stat_file(path) else {
+            return false;
+        };
+        if stat.is_dir() || IndexMetadata::from(stat).get_mode() != entry.mode {
+            return false;
+        }
+        self.workspace
+            .read_file(path)
+            .is_ok_and(|data| *Blob::new(data, path.to_path_buf()).get_oid() == entry.oid)
+    }
+
+    // The stat data to store in the index along with a blob that's staged without writing it out: the workspace file's if it
+    // already has the blob's contents, and otherwise just the mode, so that the file shows up as changed.
+    pub fn index_metadata_for(&self, path: &Path, entry: &DatabaseEntry) -> IndexMetadata {
+        if self.workspace_matches(path, entry) {
+            if let Ok(stat) = self.workspace.stat_file(path) {
+                return IndexMetadata::from(stat);
+            }
+        }
+        IndexMetadata::with_mode(entry.mode)
+    }
+
+    // Lists the files in the workspace that aren't in the index. Like git, a directory with nothing tracked in it is listed once
+    // (with a trailing slash) instead of listing everything inside it.
+    pub fn untracked_files(&self) -> BTreeSet<String> {
+        self.untracked_files_in(&Pathspec::default())
+    }
+
+    // The untracked files (see untracked_files) that match a pathspec.
+    pub fn untracked_files_in(&self, pathspec: &Pathspec) -> BTreeSet<String> {
+        let files = self
+            .workspace
+            .list_files(self.workspace.root())
+            .expect("Could not list workspace files");
+
+        let mut untracked = BTreeSet::new();
+        for file in files {
+            if self.index.is_tracked_file(&file) || !pathspec.matches(&file) {
+                continue;
+            }
+            let untracked_dir = file
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .find(|dir| !self.index.is_tracked_directory(dir));
+            match untracked_dir {
+                Some(dir) => untracked.insert(format!("{}/", dir.display())),
+                None => untracked.insert(file.display().to_string()),
+            };
+        }
+        untracked
+    }
+
+    // Maps each path in the HEAD commit's tree to its blob oid and mode, or returns an empty map if there are no commits yet.
+    pub fn head_tree_entries(&self) -> BTreeMap<PathBuf, DatabaseEntry> {
+        self.refs
+            .read_head()
+            .map(|oid| self.database.load_commit(&oid))
+            .map(|commit| self.database.load_tree_entries(commit.get_tree()))
+            .unwrap_or_default()
+    }
+
+    // Forgets about a merge, cherry-pick or revert that was waiting to be committed, once it's been committed or reset away.
+    pub fn remove_merge_state(&self) {
+        for file in [
+            "MERGE_HEAD",
+            "MERGE_MODE",
+            "MERGE_MSG",
+            "CHERRY_PICK_HEAD",
+            "REVERT_HEAD",
+        ] {
+            let _ = fs::remove_file(self.git_path.join(file));
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use crate::commit::Commit;
+use crate::date;
+use crate::diff::{self, OriginKind};
+use crate::fatal::fatal;
+use crate::object::Object;
+use crate::pathspec::Pathspec;
+use crate::rename::{self, RenameOptions};
+use crate::repository::Repository;
+use crate::revision;
+use sha1_smol::Digest;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Options that control which commits a walk visits, which of them are shown, and in what order.
+#[derive(Debug, Default)]
+pub struct RevListOptions {
+    pub max_count: Option<usize>,
+    // Never show a parent before all of its children, instead of going strictly by commit date. Otherwise each line of history
+    // is shown in one go, unless `date_order` is set, in which case the most recent commit goes first.
+    pub topo_order: bool,
+    pub date_order: bool,
+    pub reverse: bool,
+    pub first_parent: bool,
+    // Only show commits whose author (name and email) or message contain one of these.
+    pub authors: Vec<String>,
+    pub greps: Vec<String>,
+    // Commit dates (in seconds since the epoch) to show commits between. Nothing older than `since` is walked at all.
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    // Keep following the single path in the pathspec back through renames.
+    pub follow: bool,
+}
+
+impl RevListOptions {
+    // Consumes the argument if it's one of the walk options and returns whether it was. Options that take a value accept it
+    // either after an '=' or as the next argument.
+    pub fn parse_arg<'a>(
+        &mut self,
+        arg: &str,
+        rest: &mut impl Iterator<Item = &'a String>,
+    ) -> bool {
+        match arg {
+            "--topo-order" => {
+                self.topo_order = true;
+                self.date_order = false;
+            }
+            "--date-order" => {
+                self.topo_order = true;
+                self.date_order = true;
+            }
+            "--reverse" => self.reverse = true,
+            "--first-parent" => self.first_parent = true,
+            "--follow" => self.follow = true,
+            _ => {
+                let (name, value) = match arg.split_once('=') {
+                    Some((name, value)) if name.starts_with("--") => {
+                        (name, Some(value.to_string()))
+                    }
+                    _ => (arg, None),
+                };
+                let takes_value = matches!(
+                    name,
+                    "-n" | "--max-count"
+                        | "--author"
+                        | "--grep"
+                        | "--since"
+                        | "--after"
+                        | "--until"
+                        | "--before"
+                );
+                if !takes_value {
+                    return self.parse_count(arg);
+                }
+                let Some(value) = value.or_else(|| rest.next().cloned()) else {
+                    fatal(&format!("option '{name}' requires a value"));
+                };
+                match name {
+                    "-n" | "--max-count" => self.max_count = Some(parse_number(&value)),
+                    "--author" => self.authors.push(value),
+                    "--grep" => self.greps.push(value),
+                    "--since" | "--after" => self.since = Some(parse_date(&value)),
+                    _ => self.until = Some(parse_date(&value)),
+                }
+            }
+        }
+        true
+    }
+
+    // Handles the short forms of --max-count, "-5" and "-n5".
+    fn parse_count(&mut self, arg: &str) -> bool {
+        let digits = arg
+            .strip_prefix("-n")
+            .or_else(|| arg.strip_prefix('-'))
+            .filter(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()));
+        match digits {
+            Some(digits) => {
+                self.max_count = Some(parse_number(digits));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn parse_number(value: &str) -> usize {
+    value.parse().unwrap_or_else(|_| {
+        fatal(&format!("'{value}': not an integer"));
+    })
+}
+
+fn parse_date(value: &str) -> i64 {
+    date::parse_approximate(value, date::now())
+        .unwrap_or_else(|| fatal(&format!("invalid date '{value}'")))
+}
+
+// A walk over the commit graph, starting from some commits and stopping at the ancestors of others.
+pub struct RevList<'a> {
+    repo: &'a Repository,
+    options: RevListOptions,
+    tips: Vec<Digest>,
+    exclude_tips: Vec<Digest>,
+    excluded: HashSet<Digest>,
+    commits: HashMap<Digest, Rc<Commit>>,
+    // Each commit the walk went through, whether it's visible after simplification and which parents were walked from it.
+    walked: HashMap<Digest, (bool, Vec<Digest>)>,
+    // Only show commits that change paths matching this.
+    pathspec: Pathspec,
+    // The file being followed through renames, which takes the place of the pathspec.
+    followed: Option<PathBuf>,
+    // The path the followed file had in each commit that was shown for it.
+    followed_paths: HashMap<Digest, PathBuf>,
+}
+
+impl<'a> RevList<'a> {
+    pub fn new(repo: &'a Repository, options: RevListOptions) -> Self {
+        RevList {
+            repo,
+            options,
+            tips: Vec::new(),
+            exclude_tips: Vec::new(),
+            excluded: HashSet::new(),
+            commits: HashMap::new(),
+            walked: HashMap::new(),
+            pathspec: Pathspec::default(),
+            followed: None,
+            followed_paths: HashMap::new(),
+        }
+    }
+
+    // Limits the walk to commits that change paths matching the pathspec. When following a file, the pathspec has to name just
+    // that file (a pattern with wildcards is used as it is, without following renames).
+    pub fn set_pathspec(&mut self, pathspec: Pathspec) {
+        if self.options.follow {
+            self.followed = pathspec.single_path().map(Path::to_path_buf);
+        }
+        self.pathspec = pathspec;
+    }
+
+    pub fn pathspec(&self) -> &Pathspec {
+        &self.pathspec
+    }
+
+    // The path the followed file had in a commit the walk showed, if a file is being followed.
+    pub fn followed_path(&self, oid: &Digest) -> Option<&Path> {
+        self.followed_paths.get(oid).map(PathBuf::as_path)
+    }
+
+    pub fn include(&mut self, oid: Digest) {
+        self.tips.push(oid);
+    }
+
+    // Leaves out the given commit and all of its ancestors.
+    pub fn exclude(&mut self, oid: Digest) {
+        self.exclude_tips.push(oid);
+    }
+
+    // Adds a revision argument: "B", "^A" (exclude A and its ancestors), "A..B" (same as "^A B") or "A...B" (commits reachable
+    // from either but not both). A missing side of a range means HEAD. Returns false if any revision doesn't resolve.
+    pub fn add_arg(&mut self, arg: &str) -> bool {
+        let repo = self.repo;
+        let resolve = |expression: &str| {
+            let expression = if expression.is_empty() {
+                "HEAD"
+            } else {
+                expression
+            };
+            revision::resolve(repo, expression)
+        };
+
+        if let Some((a, b)) = arg.split_once("...") {
+            let (Some(a), Some(b)) = (resolve(a), resolve(b)) else {
+                return false;
+            };
+            let common = &self.ancestors(&[a]) & &self.ancestors(&[b]);
+            self.excluded.extend(common);
+            self.tips.extend([a, b]);
+        } else if let Some((a, b)) = arg.split_once("..") {
+            let (Some(a), Some(b)) = (resolve(a), resolve(b)) else {
+                return false;
+            };
+            self.exclude_tips.push(a);
+            self.tips.push(b);
+        } else if let Some(excluded) = arg.strip_prefix('^') {
+            let Some(oid) = resolve(excluded) else {
+                return false;
+            };
+            self.exclude_tips.push(oid);
+        } else {
+            let Some(oid) = resolve(arg) else {
+                return false;
+            };
+            self.tips.push(oid);
+        }
+        true
+    }
+
+    fn load(&mut self, oid: &Digest) -> Rc<Commit> {
+        self.commits
+            .entry(*oid)
+            .or_insert_with(|| Rc::new(self.repo.database.load_commit(oid)))
+            .clone()
+    }
+
+    // Every commit reachable from the given ones (including themselves), through all parents.
+    fn ancestors(&mut self, tips: &[Digest]) -> HashSet<Digest> {
+        let mut reachable = HashSet::new();
+        let mut pending = tips.to_vec();
+        while let Some(oid) = pending.pop() {
+            if reachable.insert(oid) {
+                pending.extend_from_slice(self.load(&oid).get_parents());
+            }
+        }
+        reachable
+    }
+
+    // Whether anything under the pathspecs differs between two trees.
+    fn touches_paths(&self, a: Option<&Digest>, b: &Digest) -> bool {
+        self.repo
+            .database
+            .tree_diff(a, Some(b))
+            .keys()
+            .any(|path| match &self.followed {
+                Some(followed) => path.starts_with(followed),
+                None => self.pathspec.matches(path),
+            })
+    }
+
+    // Decides whether a commit is shown and which of its parents to walk next. Without paths every commit is shown. With paths,
+    // like git's default history simplification, a commit that leaves them unchanged compared to one of its parents is hidden,
+    // and only that parent is followed.
+    fn simplify(&mut self, commit: &Commit) -> (bool, Vec<Digest>) {
+        let mut parents = commit.get_parents().to_vec();
+        if self.options.first_parent {
+            parents.truncate(1);
+        }
+        if self.pathspec.is_empty() {
+            return (true, parents);
+        }
+        if parents.is_empty() {
+            return (self.touches_paths(None, commit.get_tree()), parents);
+        }
+        // Like git, following a file doesn't simplify the history: every parent is walked, and merges are never shown, since
+        // only a change against a single parent can be followed.
+        if self.followed.is_some() {
+            let visible = match parents.as_slice() {
+                [parent] => {
+                    let parent_tree = *self.load(parent).get_tree();
+                    self.touches_paths(Some(&parent_tree), commit.get_tree())
+                }
+                _ => false,
+            };
+            return (visible, parents);
+        }
+        for parent in &parents {
+            let parent_tree = *self.load(parent).get_tree();
+            if !self.touches_paths(Some(&parent_tree), commit.get_tree()) {
+                return (false, vec![*parent]);
+            }
+        }
+        (true, parents)
+    }
+
+    // When following a file, a commit that adds it might really have renamed it from somewhere else. If so, keep following the
+    // old path from here on.
+    fn follow_rename(&mut self, commit: &Commit) {
+        let parent_tree = commit
+            .get_parents()
+            .first()
+            .map(|parent| *self.load(parent).get_tree());
+        let database = &self.repo.database;
+        let changes = database.tree_diff(parent_tree.as_ref(), Some(commit.get_tree()));
+        let Some(path) = &self.followed else {
+            return;
+        };
+        if !matches!(changes.get(path), Some((None, Some(_)))) {
+            return;
+        }
+        let pairs = rename::detect(
+            diff::pairs_from_tree_diff(database, &changes),
+            &RenameOptions::default(),
+        );
+        let origin = pairs
+            .into_iter()
+            .find(|pair| &pair.path == path)
+            .and_then(|pair| pair.origin)
+            .filter(|origin| origin.kind == OriginKind::Rename);
+        if let Some(origin) = origin {
+            self.followed = Some(origin.path);
+        }
+    }
+
+    fn matches_filters(&self, commit: &Commit) -> bool {
+        let author = commit.get_author();
+        let author_line = format!("{} <{}>", author.name, author.email);
+        let contains_any = |patterns: &[String], text: &str| {
+            patterns.is_empty()
+                || patterns
+                    .iter()
+                    .any(|pattern| text.contains(pattern.as_str()))
+        };
+        let date = commit.get_committer().timestamp;
+        contains_any(&self.options.authors, &author_line)
+            && contains_any(&self.options.greps, commit.get_message())
+            && self.options.until.is_none_or(|until| date <= until)
+    }
+
+    // Walks the graph newest commit first and returns the commits to show, in the order they should be shown.
+    pub fn walk(&mut self) -> Vec<Rc<Commit>> {
+        let exclude_tips = std::mem::take(&mut self.exclude_tips);
+        let excluded = self.ancestors(&exclude_tips);
+        self.excluded.extend(excluded);
+
+        // The queue pops the most recently committed commit first, and the counter keeps ties in the order they were found.
+        let mut queue = BinaryHeap::new();
+        let mut counter = 0;
+        let mut seen = HashSet::new();
+        for tip in std::mem::take(&mut self.tips) {
+            if !self.excluded.contains(&tip) && seen.insert(tip) {
+                let date = self.load(&tip).get_committer().timestamp;
+                queue.push((date, Reverse(counter), tip));
+                counter += 1;
+            }
+        }
+
+        // Topological order needs the whole graph before anything can be shown, but otherwise we can stop once we have enough.
+        let stop_early = !self.options.topo_order;
+        let mut walked = Vec::new();
+        let mut shown = Vec::new();
+        while let Some((_, _, oid)) = queue.pop() {
+            if stop_early && Some(shown.len()) == self.options.max_count {
+                break;
+            }
+            let commit = self.load(&oid);
+            if self
+                .options
+                .since
+                .is_some_and(|since| commit.get_committer().timestamp < since)
+            {
+                continue;
+            }
+            let (visible, parents) = self.simplify(&commit);
+            if let Some(path) = self.followed.as_ref().filter(|_| visible) {
+                self.followed_paths.insert(oid, path.clone());
+                self.follow_rename(&commit);
+            }
+            for parent in &parents {
+                if !self.excluded.contains(parent) && seen.insert(*parent) {
+                    let date = self.load(parent).get_committer().timestamp;
+                    queue.push((date, Reverse(counter), *parent));
+                    counter += 1;
+                }
+            }
+            self.walked.insert(oid, (visible, parents.clone()));
+            if stop_early {
+                if visible && self.matches_filters(&commit) {
+                    shown.push(commit);
+                }
+            } else {
+                walked.push((commit, visible, parents));
+            }
+        }
+
+        if self.options.topo_order {
+            shown = topo_sort(walked, self.options.date_order)
+                .into_iter()
+                .filter(|commit| self.matches_filters(commit))
+                .take(self.options.max_count.unwrap_or(usize::MAX))
+                .collect();
+        }
+        if self.options.reverse {
+            shown.reverse();
+        }
+        shown
+    }
+
+    // The parents a shown commit's lines lead to in a graph. Like git's parent rewriting, a parent that's hidden because it leaves
+    // the paths unchanged is replaced by its nearest shown ancestor, and parents that aren't shown at all are left out.
+    pub fn graph_parents(&self, commit: &Commit) -> Vec<Digest> {
+        let mut graph_parents = Vec::new();
+        let Some((_, parents)) = self.walked.get(commit.get_oid()) else {
+            return graph_parents;
+        };
+        for parent in parents {
+            let mut oid = *parent;
+            while let Some((visible, parents)) = self.walked.get(&oid) {
+                if *visible {
+                    if self.matches_filters(&self.commits[&oid]) && !graph_parents.contains(&oid) {
+                        graph_parents.push(oid);
+                    }
+                    break;
+                }
+                // Hidden commits only ever have the one parent they're treesame to.
+                match parents.first() {
+                    Some(next) => oid = *next,
+                    None => break,
+                }
+            }
+        }
+        graph_parents
+    }
+}
+
+// Orders the walked commits so that no parent comes before any of its children. Like git, commits wait in a queue once all of
+// their children have been shown, starting with the tips in the order they were walked. The queue is a stack, which keeps each
+// line of history together, unless `by_date` asks for the most recently committed one first.
+fn topo_sort(walked: Vec<(Rc<Commit>, bool, Vec<Digest>)>, by_date: bool) -> Vec<Rc<Commit>> {
+    let mut children: HashMap<Digest, usize> = walked
+        .iter()
+        .map(|(commit, _, _)| (*commit.get_oid(), 0))
+        .collect();
+    for (_, _, parents) in &walked {
+        for parent in parents {
+            if let Some(count) = children.get_mut(parent) {
+                *count += 1;
+            }
+        }
+    }
+
+    let by_oid: HashMap<Digest, &(Rc<Commit>, bool, Vec<Digest>)> = walked
+        .iter()
+        .map(|entry| (*entry.0.get_oid(), entry))
+        .collect();
+    let mut queue = BinaryHeap::new();
+    let mut counter = 0;
+    let mut push = |queue: &mut BinaryHeap<_>, commit: &Commit| {
+        counter += 1;
+        let priority = if by_date {
+            (commit.get_committer().timestamp, -counter)
+        } else {
+            (0, counter)
+        };
+        queue.push((priority, *commit.get_oid()));
+    };
+    let mut tips: Vec<&Rc<Commit>> = walked
+        .iter()
+        .map(|(commit, _, _)| commit)
+        .filter(|commit| children[commit.get_oid()] == 0)
+        .collect();
+    if !by_date {
+        tips.reverse();
+    }
+    for tip in tips {
+        push(&mut queue, tip);
+    }
+
+    let mut sorted = Vec::new();
+    while let Some((_, oid)) = queue.pop() {
+        let (commit, visible, parents) = by_oid[&oid];
+        for parent in parents {
+            if let Some(count) = children.get_mut(parent) {
+                *count -= 1;
+                if *count == 0 {
+                    push(&mut queue, &by_oid[parent].0);
+                }
+            }
+        }
+        if *visible {
+            sorted.push(commit.clone());
+        }
+    }
+    sorted
+}
@@ -0,0 +1,195 @@
+use crate::database::{DatabaseEntry, TREE_MODE};
+use crate::repository::Repository;
+use sha1_smol::Digest;
+use std::path::{Component, Path};
+
+// Resolves a revision expression like "HEAD", "main~2", "v1.0^2", "stash@{1}" or an (abbreviated) oid to the commit it names, following tags.
+// Returns None if any part of it doesn't exist or it doesn't name a commit.
+pub fn resolve(repo: &Repository, expression: &str) -> Option<Digest> {
+    let oid = repo.database.peel(&resolve_object(repo, expression)?);
+    (repo.database.read_object(&oid).0 == "commit").then_some(oid)
+}
+
+// Resolves an expression naming any kind of object. Besides revisions, "<rev>:<path>" names a blob or tree within a commit (or
+// tree), and ":<path>" names a staged blob. Tags are left as they are, unless a parent (^), ancestor (~) or peeling (^{<type>})
+// step follows them.
+pub fn resolve_object(repo: &Repository, expression: &str) -> Option<Digest> {
+    if let Some((revision, path)) = expression.split_once(':') {
+        if revision.is_empty() {
+            return repo
+                .index
+                .get_entry(Path::new(path))
+                .map(|entry| *entry.get_oid());
+        }
+        let mut oid = repo.database.peel(&resolve_object(repo, revision)?);
+        let (object_type, _) = repo.database.read_object(&oid);
+        if object_type == "commit" {
+            oid = *repo.database.load_commit(&oid).get_tree();
+        } else if object_type != "tree" {
+            return None;
+        }
+        return lookup_path(repo, oid, Path::new(path));
+    }
+
+    // Find where the name ends and the chain of parent (^), ancestor (~) and peeling (^{<type>}) steps begins.
+    let name_end = expression.find(['^', '~']).unwrap_or(expression.len());
+    let (name, mut steps) = expression.split_at(name_end);
+    let mut oid = resolve_name(repo, name)?;
+
+    while let Some(step) = steps.chars().next() {
+        if !matches!(step, '^' | '~') {
+            return None;
+        }
+        steps = &steps[1..];
+        // "^{<type>}" peels the object until it gets to one of that type, and "^{}" peels tags to what they tag.
+        if step == '^' && steps.starts_with('{') {
+            let (object_type, rest) = steps[1..].split_once('}')?;
+            steps = rest;
+            let peeled = &expression[..expression.len() - steps.len()];
+            oid = peel_to_type(repo, peeled, oid, object_type)?;
+            continue;
+        }
+        let digits_end = steps
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(steps.len());
+        let (digits, rest) = steps.split_at(digits_end);
+        steps = rest;
+        let count = if digits.is_empty() {
+            1
+        } else {
+            digits.parse().ok()?
+        };
+        oid = repo.database.peel(&oid);
+        let object_type = repo.database.read_object(&oid).0;
+        if object_type != "commit" {
+            eprintln!("error: object {oid} is a {object_type}, not a commit");
+            return None;
+        }
+        match step {
+            // "^N" is the Nth parent, and "^0" is the commit itself.
+            '^' if count == 0 => {}
+            '^' => {
+                oid = *repo
+                    .database
+                    .load_commit(&oid)
+                    .get_parents()
+                    .get(count - 1)?
+            }
+            // "~N" follows the first parent N times.
+            _ => {
+                for _ in 0..count {
+                    oid = *repo.database.load_commit(&oid).get_parents().first()?;
+                }
+            }
+        }
+    }
+    Some(oid)
+}
+
+// Follows tags (and commits, to their tree) from an object until it gets to one of the given type, where "object" is any type
+// and an empty type is whatever isn't a tag. Like git, getting stuck on some other type is an error.
+fn peel_to_type(
+    repo: &Repository,
+    name: &str,
+    mut oid: Digest,
+    object_type: &str,
+) -> Option<Digest> {
+    if !["", "object", "commit", "tree", "blob", "tag"].contains(&object_type) {
+        return None;
+    }
+    loop {
+        let actual_type = repo.database.read_object(&oid).0;
+        if actual_type == object_type
+            || object_type == "object"
+            || (object_type.is_empty() && actual_type != "tag")
+        {
+            return Some(oid);
+        }
+        match actual_type.as_str() {
+            "tag" => oid = *repo.database.load_tag(&oid).get_object(),
+            "commit" if object_type == "tree" || object_type == "blob" => {
+                oid = *repo.database.load_commit(&oid).get_tree()
+            }
+            _ => {
+                eprintln!("error: {name}: expected {object_type} type, but the object dereferences to {actual_type} type");
+                return None;
+            }
+        }
+    }
+}
+
+// Finds the object at a path within a tree. An empty path is the tree itself.
+fn lookup_path(repo: &Repository, tree: Digest, path: &Path) -> Option<Digest> {
+    let mut entry = DatabaseEntry {
+        oid: tree,
+        mode: TREE_MODE,
+    };
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        if !entry.is_tree() {
+            return None;
+        }
+        entry = *repo.database.load_tree(&entry.oid).get(Path::new(name))?;
+    }
+    Some(entry.oid)
+}
+
+// The branch (or, if HEAD was detached, the commit) that "@{-N}" names: what was checked out N checkouts ago, as HEAD's reflog
+// remembers it. None if the name isn't of that form or there weren't that many checkouts.
+pub fn previous_checkout(repo: &Repository, name: &str) -> Option<String> {
+    let nth: usize = name.strip_prefix("@{-")?.strip_suffix('}')?.parse().ok()?;
+    repo.refs
+        .read_reflog("HEAD")
+        .iter()
+        .rev()
+        .filter_map(|entry| entry.message.strip_prefix("checkout: moving from "))
+        .nth(nth.checked_sub(1)?)
+        .and_then(|moved| moved.split_once(" to "))
+        .map(|(from, _)| from.to_string())
+}
+
+fn resolve_name(repo: &Repository, name: &str) -> Option<Digest> {
+    let name = match name {
+        "@" => "HEAD",
+        _ => name,
+    };
+    if let Some(previous) = previous_checkout(repo, name) {
+        return repo
+            .refs
+            .read_branch(&previous)
+            .or_else(|| resolve_name(repo, &previous));
+    }
+    // "<ref>@{N}" is where the ref was N moves ago, as its reflog remembers it. A missing ref means the current branch.
+    if let Some((name, rest)) = name.split_once("@{") {
+        let nth: usize = rest.strip_suffix('}')?.parse().ok()?;
+        let full_name = match (name, repo.refs.current_branch()) {
+            ("", Some(branch)) => format!("refs/heads/{branch}"),
+            ("", None) => "HEAD".to_string(),
+            _ => repo.refs.full_name(name)?,
+        };
+        return repo
+            .refs
+            .read_reflog(&full_name)
+            .iter()
+            .rev()
+            .nth(nth)
+            .map(|entry| entry.new);
+    }
+    if let Some(oid) = repo.refs.read_ref(name) {
+        return Some(oid);
+    }
+    // Otherwise it has to be an oid, which can be abbreviated as long as it's unambiguous.
+    if name.len() < 4 || name.len() > 40 || !name.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match repo.database.find_by_prefix(name).as_slice() {
+        [oid] => Some(*oid),
+        [] => None,
+        _ => {
+            eprintln!("error: short object ID {name} is ambiguous");
+            None
+        }
+    }
+}
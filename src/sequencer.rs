@@ -0,0 +1,526 @@
+use crate::commands::{commit, reset, status};
+use crate::commit::{Author, Commit};
+use crate::diff3::{ConflictStyle, Labels};
+use crate::fatal::fatal;
+use crate::merge::{self, Merge, MergeOutcome};
+use crate::migration::Migration;
+use crate::object::Object;
+use crate::pretty;
+use crate::repository::Repository;
+use crate::revision;
+use sha1_smol::Digest;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Whether a commit's change is applied (cherry-pick) or undone (revert).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Pick,
+    Revert,
+}
+
+impl Action {
+    pub fn command(&self) -> &'static str {
+        match self {
+            Action::Pick => "cherry-pick",
+            Action::Revert => "revert",
+        }
+    }
+
+    // The file that records the commit being applied while its conflicts are resolved.
+    fn head_file(&self) -> &'static str {
+        match self {
+            Action::Pick => "CHERRY_PICK_HEAD",
+            Action::Revert => "REVERT_HEAD",
+        }
+    }
+
+    // How the action is written in the todo list.
+    fn todo_word(&self) -> &'static str {
+        match self {
+            Action::Pick => "pick",
+            Action::Revert => "revert",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    // Add "(cherry picked from commit ...)" to the message of each picked commit (-x).
+    pub record_origin: bool,
+    // Let the user edit each message before committing.
+    pub edit: bool,
+}
+
+// Applies or undoes commits one after another. When there's more than one, what's left to do is kept in .git/sequencer, so
+// that after stopping on a conflict the sequence can be continued, skipped ahead or aborted:
+//
+//   head          where HEAD was before the sequence started (where --abort goes back to)
+//   abort-safety  where HEAD was after the last commit made, to notice if something else moved it since
+//   todo          the remaining commits, one per line like "pick 1a2b3c4 Fix the build", the current one first
+//   opts          the options given when starting, if any
+pub struct Sequencer<'a> {
+    repo: &'a mut Repository,
+    action: Action,
+    options: Options,
+}
+
+impl<'a> Sequencer<'a> {
+    pub fn new(repo: &'a mut Repository, action: Action, options: Options) -> Self {
+        Sequencer {
+            repo,
+            action,
+            options,
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.repo.git_path.join("sequencer")
+    }
+
+    // Starts applying the commits. A single commit is applied on its own, without keeping any state beyond what's needed to
+    // commit it once its conflicts are resolved.
+    pub fn start(&mut self, commits: Vec<Digest>, single: bool) -> io::Result<()> {
+        self.check_can_start();
+        if single {
+            return self.apply(commits[0]);
+        }
+        if self.dir().exists() {
+            self.fail_in_progress();
+        }
+        fs::create_dir_all(self.dir())?;
+        // On an unborn branch, there's no commit to go back to or check against.
+        if let Some(head) = self.repo.refs.read_head() {
+            fs::write(self.dir().join("head"), format!("{head}\n"))?;
+            fs::write(self.dir().join("abort-safety"), format!("{head}\n"))?;
+        }
+        let mut opts = String::new();
+        if self.options.edit {
+            opts.push_str("\tedit = true\n");
+        }
+        if self.options.record_origin {
+            opts.push_str("\trecord-origin = true\n");
+        }
+        if !opts.is_empty() {
+            fs::write(self.dir().join("opts"), format!("[options]\n{opts}"))?;
+        }
+        let todo = commits.into_iter().map(|oid| (self.action, oid)).collect();
+        self.run_todo(todo)
+    }
+
+    // Commits the resolved conflicts of the commit that stopped the sequence (if they weren't committed already), and goes
+    // on with the rest.
+    pub fn resume(&mut self) -> io::Result<()> {
+        let stopped = self.stopped_at();
+        if stopped.is_none() && !self.dir().exists() {
+            self.fail("no cherry-pick or revert in progress");
+        }
+        if stopped.is_some() {
+            let args = ["--no-edit", "--cleanup=strip"].map(String::from);
+            commit::run(self.repo, &args)?;
+        } else if !self.repo.head_to_index().is_empty() {
+            self.fail_dirty_index();
+        }
+        self.run_remaining()
+    }
+
+    // Throws away the changes of the commit that stopped the sequence, and goes on with the rest.
+    pub fn skip(&mut self) -> io::Result<()> {
+        let stopped = self.repo.git_path.join(self.action.head_file()).exists();
+        if !stopped {
+            if !self.dir().exists() {
+                self.fail(&format!("no {} in progress", self.action.command()));
+            }
+            if !self.is_rollback_safe() {
+                eprintln!("error: there is nothing to skip");
+                eprintln!("hint: have you committed already?");
+                eprintln!("hint: try \"git {} --continue\"", self.action.command());
+                self.fail_quietly();
+            }
+        }
+        if let Some(head) = self.repo.refs.read_head() {
+            reset::reset_hard(self.repo, head)?;
+        }
+        self.run_remaining()
+    }
+
+    // Goes back to where HEAD was before the sequence (or the single commit) was started.
+    pub fn abort(&mut self) -> io::Result<()> {
+        if !self.dir().exists() {
+            if self.stopped_at().is_none() {
+                self.fail("no cherry-pick or revert in progress");
+            }
+            return match self.repo.refs.read_head() {
+                Some(head) => reset::reset_hard(self.repo, head),
+                None => Ok(()),
+            };
+        }
+        if !self.is_rollback_safe() {
+            eprintln!("warning: You seem to have moved HEAD. Not rewinding, check your HEAD!");
+            return self.remove_state();
+        }
+        if let Some(start) = read_oid(&self.dir().join("head")) {
+            reset::reset_hard(self.repo, start)?;
+        }
+        self.remove_state()
+    }
+
+    // Forgets about the sequence, leaving HEAD, the index and the workspace as they are.
+    pub fn quit(&mut self) -> io::Result<()> {
+        self.remove_state()?;
+        self.repo.remove_merge_state();
+        Ok(())
+    }
+
+    // The commit whose conflicts are being resolved, if any.
+    fn stopped_at(&self) -> Option<Digest> {
+        [Action::Pick, Action::Revert]
+            .iter()
+            .map(|action| self.repo.git_path.join(action.head_file()))
+            .find_map(|path| read_oid(&path))
+    }
+
+    // Whether HEAD is still where the sequence left it, so that going back won't lose anything committed since.
+    fn is_rollback_safe(&self) -> bool {
+        read_oid(&self.dir().join("abort-safety")) == self.repo.refs.read_head()
+    }
+
+    fn check_can_start(&self) {
+        if self.repo.index.has_conflicts() {
+            let doing = match self.action {
+                Action::Pick => "Cherry-picking",
+                Action::Revert => "Reverting",
+            };
+            eprintln!("error: {doing} is not possible because you have unmerged files.");
+            eprintln!("hint: Fix them up in the work tree, and then use 'git add/rm <file>'");
+            eprintln!("hint: as appropriate to mark resolution and make a commit.");
+            self.fail_quietly();
+        }
+    }
+
+    // Goes on with the todo list after the commit that stopped it, if there is one.
+    fn run_remaining(&mut self) -> io::Result<()> {
+        if !self.dir().exists() {
+            return Ok(());
+        }
+        let opts = fs::read_to_string(self.dir().join("opts")).unwrap_or_default();
+        self.options = Options {
+            record_origin: opts.contains("record-origin = true"),
+            edit: opts.contains("edit = true"),
+        };
+        let mut todo = self.load_todo();
+        if !todo.is_empty() {
+            todo.remove(0);
+        }
+        self.run_todo(todo)
+    }
+
+    fn run_todo(&mut self, mut todo: Vec<(Action, Digest)>) -> io::Result<()> {
+        while let Some(&(action, oid)) = todo.first() {
+            // The todo list is saved before each commit is applied, so that if it stops there the rest can be continued.
+            self.save_todo(&todo)?;
+            self.action = action;
+            self.apply(oid)?;
+            if let Some(head) = self.repo.refs.read_head() {
+                fs::write(self.dir().join("abort-safety"), format!("{head}\n"))?;
+            }
+            todo.remove(0);
+        }
+        self.remove_state()
+    }
+
+    fn load_todo(&self) -> Vec<(Action, Digest)> {
+        let todo = fs::read_to_string(self.dir().join("todo")).unwrap_or_default();
+        todo.lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let mut words = line.split_whitespace();
+                let action = match words.next() {
+                    Some("pick" | "p") => Action::Pick,
+                    Some("revert") => Action::Revert,
+                    _ => self.fail(&format!("invalid line in todo: {line}")),
+                };
+                let oid = words
+                    .next()
+                    .and_then(|name| revision::resolve(self.repo, name))
+                    .unwrap_or_else(|| self.fail(&format!("invalid line in todo: {line}")));
+                (action, oid)
+            })
+            .collect()
+    }
+
+    fn save_todo(&self, todo: &[(Action, Digest)]) -> io::Result<()> {
+        let lines: String = todo
+            .iter()
+            .map(|(action, oid)| {
+                let commit = self.repo.database.load_commit(oid);
+                format!(
+                    "{} {} {}\n",
+                    action.todo_word(),
+                    self.repo.database.abbreviate(oid),
+                    pretty::subject(commit.get_message())
+                )
+            })
+            .collect();
+        fs::write(self.dir().join("todo"), lines)
+    }
+
+    fn remove_state(&self) -> io::Result<()> {
+        match fs::remove_dir_all(self.dir()) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // Applies (or undoes) one commit on top of HEAD and commits the result. On conflicts, the index and workspace are left
+    // with them to resolve, and the process exits.
+    fn apply(&mut self, oid: Digest) -> io::Result<()> {
+        let repo = &mut *self.repo;
+        let commit = repo.database.load_commit(&oid);
+        if commit.get_parents().len() > 1 {
+            eprintln!("error: commit {oid} is a merge but no -m option was given.");
+            self.fail_quietly();
+        }
+        if !repo.head_to_index().is_empty() {
+            self.fail_dirty_index();
+        }
+        let head = repo.refs.read_head();
+        let parent = commit.get_parents().first().copied();
+        let subject = pretty::subject(commit.get_message());
+        let short = repo.database.abbreviate(&oid);
+        let name = format!("{short} ({subject})");
+        let parent_name = format!("parent of {name}");
+        // A revert is a cherry-pick of the opposite change: from the commit back to its parent.
+        let (base, theirs, base_name, theirs_name) = match self.action {
+            Action::Pick => (parent, Some(oid), &parent_name, &name),
+            Action::Revert => (Some(oid), parent, &name, &parent_name),
+        };
+        let labels = Labels {
+            ours: "HEAD",
+            base: base_name,
+            theirs: theirs_name,
+        };
+        let head_tree = head.map(|head| *repo.database.load_commit(&head).get_tree());
+        let Some((outcome, merged_tree)) = merge_into_head(repo, base, theirs, &labels)? else {
+            self.fail_quietly();
+        };
+        for message in outcome.messages() {
+            println!("{message}");
+        }
+
+        let message = self.message(&commit);
+        let repo = &mut *self.repo;
+        if !outcome.conflicts.is_empty() {
+            fs::write(
+                repo.git_path.join(self.action.head_file()),
+                format!("{oid}\n"),
+            )?;
+            fs::write(
+                repo.git_path.join("MERGE_MSG"),
+                format!("{message}\n{}", outcome.conflicts_comment()),
+            )?;
+            let command = self.action.command();
+            let verb = match self.action {
+                Action::Pick => "apply",
+                Action::Revert => "revert",
+            };
+            eprintln!("error: could not {verb} {short}... {subject}");
+            eprintln!("hint: After resolving the conflicts, mark them with");
+            eprintln!("hint: \"git add/rm <pathspec>\", then run");
+            eprintln!("hint: \"git {command} --continue\".");
+            eprintln!("hint: You can instead skip this commit with \"git {command} --skip\".");
+            eprintln!("hint: To abort and get back to the state before \"git {command}\",");
+            eprintln!("hint: run \"git {command} --abort\".");
+            std::process::exit(1);
+        }
+
+        // A change that's already there leaves nothing to commit, which is left for the user to sort out.
+        if head_tree == Some(merged_tree) {
+            fs::write(
+                repo.git_path.join(self.action.head_file()),
+                format!("{oid}\n"),
+            )?;
+            fs::write(repo.git_path.join("MERGE_MSG"), &message)?;
+            if self.action == Action::Pick {
+                eprintln!(
+                    "The previous cherry-pick is now empty, possibly due to conflict resolution."
+                );
+                eprintln!("If you wish to commit it anyway, use:");
+                eprintln!();
+                eprintln!("    git commit --allow-empty");
+                eprintln!();
+                eprintln!("Otherwise, please use 'git cherry-pick --skip'");
+            }
+            status::run(repo, &[])?;
+            std::process::exit(1);
+        }
+
+        // A cherry-picked commit keeps its author, while a revert is the work of whoever does it.
+        let author = match self.action {
+            Action::Pick => commit.get_author().clone(),
+            Action::Revert => Author::now(),
+        };
+        let message = if self.options.edit {
+            let borrowed = (self.action == Action::Pick).then_some(&author);
+            commit::edit(repo, &message, borrowed, None)?
+        } else {
+            message
+        };
+        let new_commit = Commit::new(
+            merged_tree,
+            head.into_iter().collect(),
+            author,
+            Author::now(),
+            message,
+        );
+        repo.database.store(&new_commit);
+        let new_subject = pretty::subject(new_commit.get_message());
+        repo.refs.update_head_logged(
+            new_commit.get_oid(),
+            &format!("{}: {new_subject}", self.action.command()),
+        );
+        // Like git, the date is shown unless it's a revert whose message was just written.
+        let show_date = self.action == Action::Pick || !self.options.edit;
+        commit::print_summary(repo, &new_commit, show_date);
+        Ok(())
+    }
+
+    // The message for the new commit: the original one when cherry-picking, and one saying what was reverted otherwise.
+    fn message(&self, commit: &Commit) -> String {
+        match self.action {
+            Action::Pick if self.options.record_origin => {
+                let message = commit.get_message();
+                let separator = if ends_with_trailers(message) {
+                    ""
+                } else {
+                    "\n"
+                };
+                format!(
+                    "{message}{separator}(cherry picked from commit {})\n",
+                    commit.get_oid()
+                )
+            }
+            Action::Pick => commit.get_message().to_string(),
+            Action::Revert => format!(
+                "Revert \"{}\"\n\nThis reverts commit {}.\n",
+                pretty::subject(commit.get_message()),
+                commit.get_oid()
+            ),
+        }
+    }
+
+    fn fail_dirty_index(&self) -> ! {
+        let command = self.action.command();
+        eprintln!("error: your local changes would be overwritten by {command}.");
+        eprintln!("hint: commit your changes or stash them to proceed.");
+        self.fail_quietly();
+    }
+
+    fn fail_in_progress(&self) -> ! {
+        let command = self.action.command();
+        eprintln!("error: {command} is already in progress");
+        let skip = if self.stopped_at().is_some() {
+            "--skip | "
+        } else {
+            ""
+        };
+        eprintln!("hint: try \"git {command} (--continue | {skip}--abort | --quit)\"");
+        self.fail_quietly();
+    }
+
+    fn fail(&self, message: &str) -> ! {
+        eprintln!("error: {message}");
+        self.fail_quietly();
+    }
+
+    fn fail_quietly(&self) -> ! {
+        fatal(&format!("{} failed", self.action.command()));
+    }
+}
+
+// Merges the change from base to theirs (commits, where None stands for an empty tree) into HEAD, bringing the index and
+// workspace along. Conflicts are staged to be resolved. Returns the outcome and the tree the merge made, or None (having said
+// why) if it would overwrite local changes, in which case nothing was touched.
+pub fn merge_into_head(
+    repo: &mut Repository,
+    base: Option<Digest>,
+    theirs: Option<Digest>,
+    labels: &Labels,
+) -> io::Result<Option<(MergeOutcome, Digest)>> {
+    let tree = |oid: Digest| *repo.database.load_commit(&oid).get_tree();
+    let head = repo.refs.read_head().map(tree);
+    let (base, theirs) = (base.map(tree), theirs.map(tree));
+    merge_into_index(repo, base, head, theirs, labels)
+}
+
+// Like merge_into_head, but with trees, and merging into ours: the tree the index holds (or HEAD's, when nothing is staged).
+pub fn merge_into_index(
+    repo: &mut Repository,
+    base: Option<Digest>,
+    ours: Option<Digest>,
+    theirs: Option<Digest>,
+    labels: &Labels,
+) -> io::Result<Option<(MergeOutcome, Digest)>> {
+    let style = repo
+        .config
+        .get("merge.conflictStyle")
+        .and_then(ConflictStyle::parse)
+        .unwrap_or_default();
+    let outcome = Merge::new(repo, style).merge_trees(base, ours, theirs, labels);
+    let merged_tree = merge::store_tree(repo, outcome.tree.clone());
+    let diff = repo.database.tree_diff(ours.as_ref(), Some(&merged_tree));
+    let migration = Migration::new(repo, diff);
+    let errors = migration.errors("merge");
+    if !errors.is_empty() {
+        for error in errors {
+            eprint!("error: {error}");
+        }
+        eprintln!("Aborting");
+        return Ok(None);
+    }
+    migration.apply(repo)?;
+    if !outcome.conflicts.is_empty() {
+        merge::stage_conflicts(repo, &outcome);
+    }
+    Ok(Some((outcome, merged_tree)))
+}
+
+// Reads a file holding an oid, like CHERRY_PICK_HEAD or the sequencer's head, if it's there.
+pub fn read_oid(path: &Path) -> Option<Digest> {
+    let oid = fs::read_to_string(path).ok()?;
+    Some(oid.trim().parse().expect("Not an oid"))
+}
+
+// Whether the last paragraph of a message is made of trailers like "Signed-off-by: A U Thor <a@u.thor>", which a
+// "(cherry picked from commit ...)" line joins without a blank line in between.
+fn ends_with_trailers(message: &str) -> bool {
+    let Some(last) = message.trim_end().rsplit("\n\n").next() else {
+        return false;
+    };
+    let is_trailer = |line: &str| {
+        line.starts_with("(cherry picked from commit ")
+            || line.split_once(": ").is_some_and(|(token, _)| {
+                !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+    };
+    // A one-paragraph message is just a subject, whatever it looks like.
+    message.trim_end().contains("\n\n") && last.lines().all(is_trailer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ends_with_trailers() {
+        assert!(!ends_with_trailers("Fix: the build\n"));
+        assert!(!ends_with_trailers("Subject\n\nSome body text.\n"));
+        assert!(ends_with_trailers(
+            "Subject\n\nBody\n\nSigned-off-by: A U Thor <a@u.thor>\n"
+        ));
+        assert!(ends_with_trailers(
+            "Subject\n\n(cherry picked from commit 1234)\n"
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::commit::Author;
+use crate::object::Object;
+use sha1_smol::{Digest, Sha1};
+
+// An annotated tag: a named pointer to another object (usually a commit), along with who made it and a message.
+pub struct Tag {
+    oid: Digest,
+    content: Vec<u8>,
+    object: Digest,
+    name: String,
+    tagger: Option<Author>,
+    message: String,
+}
+
+impl Tag {
+    // Makes a new tag, giving a name (and a message) to an object of the given type.
+    pub fn new(
+        object: Digest,
+        object_type: &str,
+        name: &str,
+        tagger: Author,
+        message: String,
+    ) -> Self {
+        let data = format!(
+            "object {object}\ntype {object_type}\ntag {name}\ntagger {tagger}\n\n{message}"
+        );
+        let content = format!("tag {}\0{data}", data.len()).into_bytes();
+        Tag {
+            oid: Sha1::from(&content).digest(),
+            content,
+            object,
+            name: name.to_string(),
+            tagger: Some(tagger),
+            message,
+        }
+    }
+
+    // Builds a Tag from the data of a tag object that was read back from the database.
+    pub fn parse(oid: Digest, data: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(data);
+        // Like commits, the headers end at the first blank line and everything after it is the message.
+        let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+
+        let mut object = None;
+        let mut name = String::new();
+        let mut tagger = None;
+        for line in headers.lines() {
+            match line.split_once(' ') {
+                Some(("object", value)) => {
+                    object = Some(value.parse().expect("Tag has an invalid object oid"))
+                }
+                Some(("tag", value)) => name = value.to_string(),
+                Some(("tagger", value)) => tagger = Some(Author::parse(value)),
+                _ => {}
+            }
+        }
+
+        Tag {
+            oid,
+            content: [format!("tag {}\0", data.len()).as_bytes(), data].concat(),
+            object: object.expect("Tag is missing its object"),
+            name,
+            tagger,
+            message: message.to_string(),
+        }
+    }
+
+    // The object this tag points to.
+    pub fn get_object(&self) -> &Digest {
+        &self.object
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    // Very old tags were made without a tagger.
+    pub fn get_tagger(&self) -> Option<&Author> {
+        self.tagger.as_ref()
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Object for Tag {
+    fn get_oid(&self) -> &Digest {
+        &self.oid
+    }
+    fn get_content(&self) -> &[u8] {
+        &self.content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tag() {
+        let data = "object 0000000000000000000000000000000000000001\n\
+                    type commit\n\
+                    tag v1.0\n\
+                    tagger T A Gger <tagger@example.com> 1700000000 +0100\n\
+                    \n\
+                    Release\n";
+        let tag = Tag::parse(Sha1::new().digest(), data.as_bytes());
+        assert_eq!(
+            tag.get_object().to_string(),
+            "0000000000000000000000000000000000000001"
+        );
+        assert_eq!(tag.get_name(), "v1.0");
+        assert_eq!(tag.get_tagger().map(|t| t.name.as_str()), Some("T A Gger"));
+        assert_eq!(tag.get_message(), "Release\n");
+    }
+
+    #[test]
+    fn test_new_tag() {
+        let object = Sha1::from("commit").digest();
+        let tagger = Author::parse("T A Gger <tagger@example.com> 1700000000 +0100");
+        let tag = Tag::new(object, "commit", "v1.0", tagger, "Release\n".to_string());
+        let data = format!(
+            "object {object}\ntype commit\ntag v1.0\ntagger T A Gger <tagger@example.com> 1700000000 +0100\n\nRelease\n"
+        );
+        assert_eq!(
+            tag.get_content(),
+            format!("tag {}\0{data}", data.len()).as_bytes()
+        );
+
+        // It reads back the same.
+        let parsed = Tag::parse(*tag.get_oid(), data.as_bytes());
+        assert_eq!(parsed.get_content(), tag.get_content());
+        assert_eq!(parsed.get_object(), &object);
+        assert_eq!(parsed.get_name(), "v1.0");
+    }
+}
@@ -1,18 +1,17 @@
+use crate::database::DatabaseEntry;
 use crate::object::Object;
-use crate::Blob;
 use sha1_smol::{Digest, Sha1};
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::path::{Path, PathBuf};
 
-const EXECUTABLE_MODE: &str = "100755";
-const NON_EXECUTABLE_MODE: &str = "100644";
 const DIRECTORY_MODE: &str = "40000";
 
 enum TreeEntry {
     T(Tree),
-    B(Blob),
+    // A file, whose blob is already in the database.
+    B(DatabaseEntry),
 }
 
 impl Debug for TreeEntry {
@@ -21,7 +20,7 @@ impl Debug for TreeEntry {
             TreeEntry::T(t) => fmt.write_fmt(format_args!("\n{t:?}")),
             TreeEntry::B(b) => fmt
                 .debug_struct("Blob")
-                .field("oid", &b.get_oid().to_string())
+                .field("oid", &b.oid.to_string())
                 .finish(),
         }
     }
@@ -44,12 +43,11 @@ impl Debug for Tree {
 }
 
 impl Tree {
-    fn add_entry(&mut self, parents: Vec<PathBuf>, blob: Blob) {
+    fn add_entry(&mut self, parents: Vec<PathBuf>, path: &Path, blob: DatabaseEntry) {
         // Insert the blob at this point since we've bottomed out while recursing this subtree.
         if parents.is_empty() {
             self.entries.insert(
-                blob.get_path()
-                    .file_name()
+                path.file_name()
                     .expect("could not get base filename in add_entry")
                     .into(),
                 TreeEntry::B(blob),
@@ -66,7 +64,7 @@ impl Tree {
             // Recurse into the tree.
             match self.entries.get_mut(base_dir).unwrap() {
                 TreeEntry::T(ref mut tree) => {
-                    tree.add_entry(parents.into_iter().skip(1).collect(), blob);
+                    tree.add_entry(parents.into_iter().skip(1).collect(), path, blob);
                 }
                 _ => panic!("supposed to be a tree here!"),
             }
@@ -78,7 +76,16 @@ impl Tree {
         // let mut entries_data : Vec<String> = Vec::new();
         let mut entries_data: Vec<Vec<u8>> = Vec::new();
 
-        for (path, entry) in &mut self.entries {
+        // git sorts a tree's entries as if directory names ended in a slash, so "a.txt" comes before a directory "a".
+        let mut entries: Vec<_> = self.entries.iter_mut().collect();
+        entries.sort_by_key(|(path, entry)| {
+            let mut name = path.as_os_str().to_os_string();
+            if matches!(entry, TreeEntry::T(_)) {
+                name.push("/");
+            }
+            name
+        });
+        for (path, entry) in entries {
             match entry {
                 TreeEntry::T(ref mut tree) => {
                     let entry_data = tree.build();
@@ -99,13 +106,8 @@ impl Tree {
                 }
                 TreeEntry::B(blob) => {
                     // Each entry is is represented as a string with the mode, a space, the filename, a null byte, and 20 bytes for the oid.
-                    let mode = if blob.is_executable() {
-                        EXECUTABLE_MODE
-                    } else {
-                        NON_EXECUTABLE_MODE
-                    };
-                    let prefix = format!("{mode} {}\0", path.to_string_lossy());
-                    let oid_bytes = blob.get_oid().bytes();
+                    let prefix = format!("{:o} {}\0", blob.mode, path.to_string_lossy());
+                    let oid_bytes = blob.oid.bytes();
                     entries_data.push([prefix.as_bytes(), &oid_bytes[..]].concat());
                 }
             }
@@ -114,14 +116,12 @@ impl Tree {
         entries_data.into_iter().flatten().collect()
     }
 
-    pub fn new(mut blobs: Vec<Blob>) -> Self {
-        // TODO possibly don't need to sort this because these come from the index, which sorts them.
-        blobs.sort();
-
+    // Builds the tree (and subtrees) holding the given files, e.g. everything in the index.
+    pub fn new(blobs: BTreeMap<PathBuf, DatabaseEntry>) -> Self {
         // Create a tree filled with entries.
         let mut root = Tree::default();
-        for blob in blobs {
-            root.add_entry(Self::get_parent_directories(blob.get_path()), blob);
+        for (path, blob) in blobs {
+            root.add_entry(Self::get_parent_directories(&path), &path, blob);
         }
 
         // Traverse those entries and fill out each Tree's oid and content on the way back from the recursion.
@@ -137,19 +137,15 @@ impl Tree {
         root
     }
 
+    // Visits every subtree (and then this tree). The blobs aren't visited, since they're stored as soon as they're added.
     pub fn traverse<F>(&self, f: &mut F)
     where
         // TODO find a way so we avoid this virtual/dynamic dispatch
         F: FnMut(&dyn Object),
     {
         for entry in self.entries.values() {
-            match entry {
-                TreeEntry::T(tree) => {
-                    tree.traverse(f);
-                }
-                TreeEntry::B(blob) => {
-                    f(blob);
-                }
+            if let TreeEntry::T(tree) = entry {
+                tree.traverse(f);
             }
         }
         f(self);
@@ -0,0 +1,314 @@
+// A port of git's wildmatch.c, which implements the glob matching used by .gitignore, .gitattributes and pathspecs.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchFlags {
+    // When set, wildcards other than "**" don't match "/".
+    pub pathname: bool,
+    pub casefold: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MatchResult {
+    Match,
+    NoMatch,
+    // These two let an outer "*" or "**" know that retrying with more text can never succeed.
+    AbortAll,
+    AbortToStarStar,
+}
+
+pub fn wildmatch(pattern: &str, text: &str, flags: MatchFlags) -> bool {
+    dowild(pattern.as_bytes(), 0, text.as_bytes(), 0, flags) == MatchResult::Match
+}
+
+// Reading past the end of either string gives a NUL, mirroring the C code this came from.
+fn at(s: &[u8], i: usize) -> u8 {
+    s.get(i).copied().unwrap_or(0)
+}
+
+fn is_glob_special(c: u8) -> bool {
+    matches!(c, b'*' | b'?' | b'[' | b'\\')
+}
+
+fn fold(c: u8, flags: MatchFlags) -> u8 {
+    if flags.casefold {
+        c.to_ascii_lowercase()
+    } else {
+        c
+    }
+}
+
+fn dowild(
+    pattern: &[u8],
+    mut p: usize,
+    text: &[u8],
+    mut t: usize,
+    flags: MatchFlags,
+) -> MatchResult {
+    while at(pattern, p) != 0 {
+        let mut p_ch = fold(at(pattern, p), flags);
+        let mut t_ch = fold(at(text, t), flags);
+        if t_ch == 0 && p_ch != b'*' {
+            return MatchResult::AbortAll;
+        }
+        match p_ch {
+            b'?' => {
+                if flags.pathname && t_ch == b'/' {
+                    return MatchResult::NoMatch;
+                }
+            }
+            b'*' => {
+                p += 1;
+                let match_slash;
+                if at(pattern, p) == b'*' {
+                    let prev_is_boundary = p < 2 || pattern[p - 2] == b'/';
+                    while at(pattern, p) == b'*' {
+                        p += 1;
+                    }
+                    let next_is_boundary = at(pattern, p) == 0
+                        || at(pattern, p) == b'/'
+                        || (at(pattern, p) == b'\\' && at(pattern, p + 1) == b'/');
+                    if prev_is_boundary && next_is_boundary {
+                        // "foo/**/bar" should match "foo/bar" too, so first try letting "**/" match nothing.
+                        if at(pattern, p) == b'/'
+                            && dowild(pattern, p + 1, text, t, flags) == MatchResult::Match
+                        {
+                            return MatchResult::Match;
+                        }
+                        match_slash = true;
+                    } else {
+                        match_slash = false;
+                    }
+                } else {
+                    // Without the pathname flag, "*" behaves just like "**".
+                    match_slash = !flags.pathname;
+                }
+
+                if at(pattern, p) == 0 {
+                    // A trailing "**" matches everything, and a trailing "*" only matches if there are no more slashes.
+                    if !match_slash && text[t..].contains(&b'/') {
+                        return MatchResult::NoMatch;
+                    }
+                    return MatchResult::Match;
+                } else if !match_slash && at(pattern, p) == b'/' {
+                    // A single "*" followed by a slash matches up to the next directory.
+                    match text[t..].iter().position(|c| *c == b'/') {
+                        Some(offset) => {
+                            t += offset;
+                            // The slash itself is consumed below along with the pattern's slash.
+                            p += 1;
+                            t += 1;
+                            continue;
+                        }
+                        None => return MatchResult::NoMatch,
+                    }
+                }
+
+                loop {
+                    if t_ch == 0 {
+                        break;
+                    }
+                    // Skip ahead quickly when the "*" is followed by a literal character.
+                    if !is_glob_special(at(pattern, p)) {
+                        let literal = fold(at(pattern, p), flags);
+                        loop {
+                            t_ch = fold(at(text, t), flags);
+                            if t_ch == 0 || (!match_slash && t_ch == b'/') || t_ch == literal {
+                                break;
+                            }
+                            t += 1;
+                        }
+                        if t_ch != literal {
+                            return MatchResult::NoMatch;
+                        }
+                    }
+                    let matched = dowild(pattern, p, text, t, flags);
+                    if matched != MatchResult::NoMatch {
+                        if !match_slash || matched != MatchResult::AbortToStarStar {
+                            return matched;
+                        }
+                    } else if !match_slash && t_ch == b'/' {
+                        return MatchResult::AbortToStarStar;
+                    }
+                    t += 1;
+                    t_ch = fold(at(text, t), flags);
+                }
+                return MatchResult::AbortAll;
+            }
+            b'[' => {
+                p += 1;
+                p_ch = at(pattern, p);
+                if p_ch == b'^' {
+                    p_ch = b'!';
+                }
+                let negated = p_ch == b'!';
+                if negated {
+                    p += 1;
+                    p_ch = at(pattern, p);
+                }
+                let mut prev_ch = 0;
+                let mut matched = false;
+                loop {
+                    if p_ch == 0 {
+                        return MatchResult::AbortAll;
+                    }
+                    if p_ch == b'\\' {
+                        p += 1;
+                        p_ch = at(pattern, p);
+                        if p_ch == 0 {
+                            return MatchResult::AbortAll;
+                        }
+                        if t_ch == p_ch {
+                            matched = true;
+                        }
+                    } else if p_ch == b'-'
+                        && prev_ch != 0
+                        && at(pattern, p + 1) != 0
+                        && at(pattern, p + 1) != b']'
+                    {
+                        p += 1;
+                        p_ch = at(pattern, p);
+                        if p_ch == b'\\' {
+                            p += 1;
+                            p_ch = at(pattern, p);
+                            if p_ch == 0 {
+                                return MatchResult::AbortAll;
+                            }
+                        }
+                        if (prev_ch..=p_ch).contains(&t_ch)
+                            || (flags.casefold
+                                && (prev_ch..=p_ch).contains(&t_ch.to_ascii_uppercase()))
+                        {
+                            matched = true;
+                        }
+                        // This makes prev_ch get reset, so "a-c-e" doesn't form a second range.
+                        p_ch = 0;
+                    } else if p_ch == b'[' && at(pattern, p + 1) == b':' {
+                        let class_start = p + 2;
+                        let Some(class_len) =
+                            pattern[class_start..].iter().position(|c| *c == b']')
+                        else {
+                            return MatchResult::AbortAll;
+                        };
+                        let class_end = class_start + class_len;
+                        if class_len == 0 || pattern[class_end - 1] != b':' {
+                            // There's no ":]", so treat the "[" like a normal character in the set.
+                            if t_ch == b'[' {
+                                matched = true;
+                            }
+                        } else {
+                            let class = &pattern[class_start..class_end - 1];
+                            match char_class_matches(class, t_ch) {
+                                Some(true) => matched = true,
+                                Some(false) => {}
+                                None => return MatchResult::AbortAll,
+                            }
+                            p = class_end;
+                            p_ch = 0;
+                        }
+                    } else if t_ch == p_ch {
+                        matched = true;
+                    }
+                    prev_ch = p_ch;
+                    p += 1;
+                    p_ch = at(pattern, p);
+                    if p_ch == b']' {
+                        break;
+                    }
+                }
+                if matched == negated || (flags.pathname && t_ch == b'/') {
+                    return MatchResult::NoMatch;
+                }
+            }
+            _ => {
+                if p_ch == b'\\' {
+                    p += 1;
+                    p_ch = fold(at(pattern, p), flags);
+                }
+                if t_ch != p_ch {
+                    return MatchResult::NoMatch;
+                }
+            }
+        }
+        p += 1;
+        t += 1;
+    }
+
+    if t < text.len() {
+        MatchResult::NoMatch
+    } else {
+        MatchResult::Match
+    }
+}
+
+// Returns None for an unknown class name, which makes the whole pattern fail like in git.
+fn char_class_matches(class: &[u8], c: u8) -> Option<bool> {
+    Some(match class {
+        b"alnum" => c.is_ascii_alphanumeric(),
+        b"alpha" => c.is_ascii_alphabetic(),
+        b"blank" => c == b' ' || c == b'\t',
+        b"cntrl" => c.is_ascii_control(),
+        b"digit" => c.is_ascii_digit(),
+        b"graph" => c.is_ascii_graphic(),
+        b"lower" => c.is_ascii_lowercase(),
+        b"print" => c.is_ascii_graphic() || c == b' ',
+        b"punct" => c.is_ascii_punctuation(),
+        b"space" => c.is_ascii_whitespace() || c == 0x0b,
+        b"upper" => c.is_ascii_uppercase(),
+        b"xdigit" => c.is_ascii_hexdigit(),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATHNAME: MatchFlags = MatchFlags {
+        pathname: true,
+        casefold: false,
+    };
+
+    #[test]
+    fn test_basic_wildcards() {
+        assert!(wildmatch("*.rs", "main.rs", PATHNAME));
+        assert!(!wildmatch("*.rs", "src/main.rs", PATHNAME));
+        assert!(wildmatch("*.rs", "src/main.rs", MatchFlags::default()));
+        assert!(wildmatch("ma?n.rs", "main.rs", PATHNAME));
+        assert!(!wildmatch("foo?bar", "foo/bar", PATHNAME));
+        assert!(wildmatch("\\*literal", "*literal", PATHNAME));
+        assert!(!wildmatch("\\*literal", "xliteral", PATHNAME));
+    }
+
+    #[test]
+    fn test_double_star() {
+        assert!(wildmatch("**/foo", "foo", PATHNAME));
+        assert!(wildmatch("**/foo", "a/b/foo", PATHNAME));
+        assert!(wildmatch("a/**/b", "a/b", PATHNAME));
+        assert!(wildmatch("a/**/b", "a/x/y/b", PATHNAME));
+        assert!(wildmatch("a/**", "a/x/y", PATHNAME));
+        assert!(!wildmatch("a/**", "b/x", PATHNAME));
+        assert!(wildmatch("*/b", "a/b", PATHNAME));
+        assert!(!wildmatch("*/b", "a/x/b", PATHNAME));
+    }
+
+    #[test]
+    fn test_brackets() {
+        assert!(wildmatch("[abc].txt", "b.txt", PATHNAME));
+        assert!(!wildmatch("[!abc].txt", "b.txt", PATHNAME));
+        assert!(wildmatch("[a-c]x", "cx", PATHNAME));
+        assert!(wildmatch("[[:digit:]]*", "1abc", PATHNAME));
+        assert!(!wildmatch("[[:digit:]]*", "abc", PATHNAME));
+        assert!(wildmatch("[]]", "]", PATHNAME));
+    }
+
+    #[test]
+    fn test_casefold() {
+        let flags = MatchFlags {
+            pathname: true,
+            casefold: true,
+        };
+        assert!(wildmatch("*.RS", "main.rs", flags));
+        assert!(wildmatch("[A-C].txt", "b.txt", flags));
+        assert!(!wildmatch("*.RS", "main.rs", PATHNAME));
+    }
+}
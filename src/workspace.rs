@@ -1,16 +1,20 @@
+use crate::ignore::{Ignore, IgnoreRule};
+use crate::index::SYMLINK_MODE;
+use std::ffi::OsStr;
 use std::fs::{self, Metadata};
 use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{symlink, PermissionsExt};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
-// TODO use the .gitignore file instead of this.
-const IGNORE: [&str; 2] = [".git", "target"];
 pub struct Workspace {
     workspace_dir: PathBuf,
+    ignore: Ignore,
 }
 
 impl Workspace {
-    pub fn new(workspace_dir: PathBuf) -> Self {
+    pub fn new(workspace_dir: PathBuf, ignore: Ignore) -> Self {
         if !workspace_dir.is_dir() {
             panic!(
                 "Workspace dir provided should not be a dir: {:?}",
@@ -18,34 +22,46 @@ impl Workspace {
             );
         }
 
-        Workspace { workspace_dir }
+        Workspace {
+            workspace_dir,
+            ignore,
+        }
     }
 
     pub fn list_files(&self, filepath: &Path) -> walkdir::Result<Vec<PathBuf>> {
+        self.walk(filepath, true)
+    }
+
+    // Like list_files, but including the files that are ignored.
+    pub fn list_all_files(&self, filepath: &Path) -> walkdir::Result<Vec<PathBuf>> {
+        self.walk(filepath, false)
+    }
+
+    fn walk(&self, filepath: &Path, skip_ignored: bool) -> walkdir::Result<Vec<PathBuf>> {
+        // A path that fails to canonicalize (e.g. doesn't exist) is handed to WalkDir as-is so that it reports the error with the path.
         let canonicalized = filepath
-            .to_path_buf()
-            .clone()
             .canonicalize()
-            .map_err(|err| walkdir::Error::from(err));
+            .unwrap_or_else(|_| filepath.to_path_buf());
 
         // Swallows errors when accessing dir entries and only shows the entries we can access.
 
-        // Return all entries in dir except for ignored ones. If a file is given, WalkDir yields only that file in the iterator.
+        // Return all entries in dir except for .git (and ignored ones if we're skipping them, without looking inside ignored
+        // directories). If a file is given, WalkDir yields only that file in the iterator.
         Ok(WalkDir::new(canonicalized)
             .into_iter()
             .filter_entry(|entry| {
-                !IGNORE.contains(
-                    &entry
-                        .path()
-                        .strip_prefix(&self.workspace_dir)
-                        .expect("failed to strip prefix in ignore filter")
-                        .to_string_lossy()
-                        .as_ref(),
-                )
+                let path = entry
+                    .path()
+                    .strip_prefix(&self.workspace_dir)
+                    .expect("failed to strip prefix in ignore filter");
+                path.as_os_str().is_empty()
+                    || (path != Path::new(".git")
+                        && !(skip_ignored
+                            && self.ignore.is_ignored(path, entry.file_type().is_dir())))
             })
             .collect::<walkdir::Result<Vec<_>>>()?
             .iter()
-            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| !entry.file_type().is_dir())
             .map(|entry| {
                 entry
                     .path()
@@ -56,11 +72,81 @@ impl Workspace {
             .collect())
     }
 
+    // The rule deciding whether a workspace path is ignored (see Ignore::matching_rule).
+    pub fn ignore_rule(&self, path: &Path) -> Option<IgnoreRule> {
+        // A path given with a trailing slash is taken to be a directory, whether or not it exists.
+        let is_dir =
+            path.to_string_lossy().ends_with('/') || self.workspace_dir.join(path).is_dir();
+        self.ignore.matching_rule(path, is_dir)
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.ignore_rule(path).is_some_and(|rule| !rule.negated)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.workspace_dir
+    }
+
+    // Reads a file's contents, or for a symlink, the path it points to (which is what git stores for it).
     pub fn read_file<P: AsRef<Path>>(&self, filepath: P) -> io::Result<Vec<u8>> {
-        fs::read(self.workspace_dir.join(filepath))
+        let path = self.workspace_dir.join(filepath);
+        if fs::symlink_metadata(&path)?.is_symlink() {
+            return Ok(fs::read_link(path)?.into_os_string().into_vec());
+        }
+        fs::read(path)
     }
 
+    // Stats a file without following symlinks, so that a symlink is tracked as itself.
     pub fn stat_file<P: AsRef<Path>>(&self, path: P) -> io::Result<Metadata> {
-        fs::metadata(self.workspace_dir.join(path))
+        fs::symlink_metadata(self.workspace_dir.join(path))
+    }
+
+    // Writes a file, making it executable or not to match the given mode, or a symlink to the path in data if the mode is a
+    // symlink's. Any existing file is replaced.
+    pub fn write_file(&self, path: &Path, data: &[u8], mode: u32) -> io::Result<()> {
+        // Writing through a symlink would change the file it points to instead.
+        if mode == SYMLINK_MODE || self.stat_file(path).is_ok_and(|stat| stat.is_symlink()) {
+            self.remove_file(path)?;
+        }
+        let path = self.workspace_dir.join(path);
+        if mode == SYMLINK_MODE {
+            return symlink(OsStr::from_bytes(data), path);
+        }
+        fs::write(&path, data)?;
+        let permissions = if mode & 0o111 != 0 { 0o755 } else { 0o644 };
+        fs::set_permissions(path, fs::Permissions::from_mode(permissions))
+    }
+
+    // Removes a file, doing nothing if it's already gone (or a file has taken the place of one of its directories).
+    pub fn remove_file(&self, path: &Path) -> io::Result<()> {
+        match fs::remove_file(self.workspace_dir.join(path)) {
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+                ) =>
+            {
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    // Creates a directory, replacing a file that's in the way.
+    pub fn make_directory(&self, path: &Path) -> io::Result<()> {
+        let path = self.workspace_dir.join(path);
+        if fs::symlink_metadata(&path).is_ok_and(|stat| !stat.is_dir()) {
+            fs::remove_file(&path)?;
+        }
+        match fs::create_dir(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+            result => result,
+        }
+    }
+
+    // Removes a directory if it's empty. Directories that still hold (e.g. untracked) files are left alone.
+    pub fn remove_directory(&self, path: &Path) {
+        let _ = fs::remove_dir(self.workspace_dir.join(path));
     }
 }